@@ -0,0 +1,30 @@
+//! Compiles the conformance test ROMs, and the extra ROMs in `tests/roms`,
+//! so the trace tests can run the generated code against the interpreter.
+
+use std::path::Path;
+
+use chip_core::aot::compile;
+use chip_core::harness::parse_listing;
+
+fn main() {
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let out_dir = std::env::var("OUT_DIR").unwrap();
+
+    for roms in [
+        manifest_dir.join("../chip-core/tests/roms"),
+        manifest_dir.join("tests/roms"),
+    ] {
+        println!("cargo:rerun-if-changed={}", roms.display());
+        for entry in std::fs::read_dir(&roms).unwrap() {
+            compile_listing(&entry.unwrap().path(), Path::new(&out_dir));
+        }
+    }
+}
+
+/// Compiles a hex listing to `<name>.rs` in the output directory.
+fn compile_listing(path: &Path, out_dir: &Path) {
+    let listing = std::fs::read_to_string(path).unwrap();
+    let rom = parse_listing(&listing).unwrap();
+    let name = path.file_stem().unwrap().to_str().unwrap();
+    std::fs::write(out_dir.join(format!("{}.rs", name)), compile(&rom)).unwrap();
+}
@@ -0,0 +1,84 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use chip_core::aot::{analyze, compile};
+
+/// Usage message printed on argument errors.
+const USAGE: &str = "usage: chip-aot <ROM> [--output PATH]";
+
+/// Command line options.
+struct Options {
+    /// Path to the ROM to compile.
+    rom: PathBuf,
+    /// Where to write the Rust module, standard output if not given.
+    output: Option<PathBuf>,
+}
+
+impl Options {
+    /// Parses the command line arguments (without the program name).
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut rom = None;
+        let mut output = None;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--output" => {
+                    output = Some(args.next().ok_or("missing value for --output")?.into())
+                }
+                _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+                _ if rom.is_none() => rom = Some(PathBuf::from(arg)),
+                _ => return Err(format!("unexpected argument {}", arg)),
+            }
+        }
+
+        Ok(Options {
+            rom: rom.ok_or("missing ROM path")?,
+            output,
+        })
+    }
+}
+
+/// Compiles the ROM and writes out the module.
+fn run(options: &Options) -> Result<(), String> {
+    let rom =
+        std::fs::read(&options.rom).map_err(|e| format!("{}: {}", options.rom.display(), e))?;
+    let source = compile(&rom);
+
+    match &options.output {
+        Some(path) => {
+            std::fs::write(path, source).map_err(|e| format!("{}: {}", path.display(), e))?;
+            let analysis = analyze(&rom);
+            let compiled = analysis
+                .code
+                .keys()
+                .filter(|address| analysis.is_compiled(**address))
+                .count();
+            eprintln!(
+                "{}: compiled {} of {} reachable instructions",
+                path.display(),
+                compiled,
+                analysis.code.len()
+            );
+        }
+        None => print!("{}", source),
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("error: {}\n{}", e, USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match run(&options) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,144 @@
+//! Runs each compiled test ROM next to the interpreter, one CPU cycle at a
+//! time, and checks the machine state matches after every cycle.
+
+use chip_core::aot::{analyze, compile};
+use chip_core::harness::{parse_listing, State};
+use chip_core::quirks::Quirks;
+use chip_core::Emulator;
+
+/// Includes the module `build.rs` generated for a ROM.
+macro_rules! compiled {
+    ($($name:ident),*) => {
+        $(#[allow(dead_code)]
+        mod $name {
+            include!(concat!(env!("OUT_DIR"), "/", stringify!($name), ".rs"));
+        })*
+    };
+}
+
+compiled!(
+    alu_arith,
+    alu_logic,
+    draw,
+    flow,
+    keys,
+    memory,
+    quirks,
+    random,
+    timers,
+    self_modifying,
+    subroutines
+);
+
+/// Runs a compiled ROM and the interpreter side by side for 60 frames
+/// under every quirk preset, with no keys held and then a few held.
+fn check_trace(rom: &[u8], run: fn(&mut Emulator, usize)) {
+    let presets = [
+        Quirks::default(),
+        Quirks::chip8(),
+        Quirks::schip(),
+        Quirks::xochip(),
+    ];
+    for quirks in presets {
+        for keys in [0u16, 0x0480] {
+            let new = || {
+                let mut emulator = Emulator::new();
+                emulator.set_quirks(quirks);
+                emulator.seed_rng(0xA07);
+                emulator.load_rom(rom).unwrap();
+                emulator.set_keys(keys);
+                emulator
+            };
+            let (mut interpreter, mut compiled) = (new(), new());
+
+            for cycle in 0..600 {
+                interpreter.tick();
+                run(&mut compiled, 1);
+                if cycle % 10 == 9 {
+                    interpreter.timer_tick();
+                    compiled.timer_tick();
+                }
+                assert_eq!(
+                    State::capture(&compiled),
+                    State::capture(&interpreter),
+                    "cycle {} with {:?} and keys {:04X}",
+                    cycle,
+                    quirks,
+                    keys
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn compiled_roms_match_interpreter() {
+    check_trace(&alu_arith::ROM, alu_arith::run);
+    check_trace(&alu_logic::ROM, alu_logic::run);
+    check_trace(&draw::ROM, draw::run);
+    check_trace(&flow::ROM, flow::run);
+    check_trace(&keys::ROM, keys::run);
+    check_trace(&memory::ROM, memory::run);
+    check_trace(&quirks::ROM, quirks::run);
+    check_trace(&random::ROM, random::run);
+    check_trace(&timers::ROM, timers::run);
+    check_trace(&self_modifying::ROM, self_modifying::run);
+    check_trace(&subroutines::ROM, subroutines::run);
+}
+
+#[test]
+fn whole_frames_match_interpreter() {
+    let mut interpreter = Emulator::new();
+    let mut compiled = Emulator::new();
+    for emulator in [&mut interpreter, &mut compiled] {
+        emulator.seed_rng(1);
+        emulator.load_rom(&draw::ROM).unwrap();
+    }
+    for _ in 0..60 {
+        interpreter.run_frame();
+        draw::run_frame(&mut compiled);
+    }
+    assert_eq!(State::capture(&compiled), State::capture(&interpreter));
+}
+
+#[test]
+fn self_modifying_code() {
+    let listing = include_str!("roms/self_modifying.hex");
+    let analysis = analyze(&parse_listing(listing).unwrap());
+    // The write through ANNN is found, the one through FX1E isn't.
+    assert!(!analysis.is_compiled(0x204));
+    assert!(analysis.is_compiled(0x206));
+
+    let mut emulator = Emulator::new();
+    emulator.load_rom(&self_modifying::ROM).unwrap();
+    self_modifying::run(&mut emulator, 100);
+    assert_eq!(emulator.registers()[0xB], 0x22);
+    assert_eq!(emulator.registers()[0xC], 0x22);
+}
+
+#[test]
+fn subroutines_get_their_own_functions() {
+    let rom = parse_listing(include_str!("roms/subroutines.hex")).unwrap();
+    let analysis = analyze(&rom);
+    assert_eq!(
+        analysis.subroutines.iter().copied().collect::<Vec<_>>(),
+        [0x200, 0x210, 0x218]
+    );
+    // The tail belongs to the first subroutine that jumps to it.
+    let source = compile(&rom);
+    assert!(source.contains("0x210 | 0x212 | 0x214 | 0x21C | 0x21E => sub_210("));
+    assert!(source.contains("0x218 | 0x21A => sub_218("));
+
+    let mut emulator = Emulator::new();
+    emulator.load_rom(&subroutines::ROM).unwrap();
+    subroutines::run(&mut emulator, 1000);
+    assert_eq!(emulator.program_counter(), 0x20A);
+    assert_eq!(emulator.registers()[0], 9);
+}
+
+#[test]
+fn jump_tables_fall_back_to_interpreter() {
+    let rom = parse_listing(include_str!("../../chip-core/tests/roms/flow.hex")).unwrap();
+    let analysis = analyze(&rom);
+    assert_eq!(analysis.dynamic_jumps.len(), 1);
+}
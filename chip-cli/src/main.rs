@@ -0,0 +1,347 @@
+use std::net::TcpListener;
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+
+use chip_core::cheat::CheatDatabase;
+use chip_core::coverage::Coverage;
+use chip_core::display::Palette;
+use chip_core::gif::GifRecorder;
+use chip_core::hash::sha1;
+use chip_core::profiler::Profiler;
+use chip_core::romdb::RomDatabase;
+use chip_core::rpc::Server;
+use chip_core::screenshot::{to_pbm, to_png, to_svg};
+use chip_core::script;
+use chip_core::Emulator;
+
+/// Usage message printed on argument errors.
+const USAGE: &str = "usage: chip-cli <ROM> [--frames N] [--ticks-per-frame N] [--scale N] \
+[--screenshot PATH] [--record PATH] [--record-start FRAME] [--record-stop FRAME] [--cheats PATH] \
+[--profile PATH] [--flamegraph PATH] [--coverage PATH] [--coverage-dump PATH] [--rom-database PATH] \
+[--script PATH]
+       chip-cli --serve ADDRESS [ROM] [--ticks-per-frame N] [--rom-database PATH]";
+
+/// Command line options for a headless run.
+struct Options {
+    /// Path to the ROM to run, which a server can do without.
+    rom: Option<PathBuf>,
+    /// Number of frames to run.
+    frames: u32,
+    /// CPU cycles per frame, instead of the ROM database's or the default.
+    ticks_per_frame: Option<usize>,
+    /// Image pixels per display pixel.
+    scale: usize,
+    /// Where to save a screenshot of the final frame.
+    screenshot: Option<PathBuf>,
+    /// Where to save the GIF recording.
+    record: Option<PathBuf>,
+    /// Frame the recording starts on.
+    record_start: u32,
+    /// Frame the recording stops on.
+    record_stop: Option<u32>,
+    /// Cheat file to apply every frame.
+    cheats: Option<PathBuf>,
+    /// Where to save the profiler's text report.
+    profile: Option<PathBuf>,
+    /// Where to save the profiler's call stacks for a flame graph.
+    flamegraph: Option<PathBuf>,
+    /// Coverage file to merge this run's RAM coverage into.
+    coverage: Option<PathBuf>,
+    /// Where to save the RAM hex dump annotated with coverage.
+    coverage_dump: Option<PathBuf>,
+    /// ROM database whose entries are added to the embedded one.
+    rom_database: Option<PathBuf>,
+    /// Rhai script that drives the emulator instead of the fixed run.
+    script: Option<PathBuf>,
+    /// Serve the emulator with JSON-RPC on this TCP address, or Unix
+    /// socket path after `unix:`, instead of running it.
+    serve: Option<String>,
+}
+
+impl Options {
+    /// Parses the command line arguments (without the program name).
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut options = Options {
+            rom: None,
+            frames: 600,
+            ticks_per_frame: None,
+            scale: 8,
+            screenshot: None,
+            record: None,
+            record_start: 0,
+            record_stop: None,
+            cheats: None,
+            profile: None,
+            flamegraph: None,
+            coverage: None,
+            coverage_dump: None,
+            rom_database: None,
+            script: None,
+            serve: None,
+        };
+
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("missing value for {}", arg));
+            match arg.as_str() {
+                "--frames" => options.frames = parse_number(&value()?)?,
+                "--ticks-per-frame" => options.ticks_per_frame = Some(parse_number(&value()?)?),
+                "--scale" => options.scale = parse_number(&value()?)?,
+                "--screenshot" => options.screenshot = Some(value()?.into()),
+                "--record" => options.record = Some(value()?.into()),
+                "--record-start" => options.record_start = parse_number(&value()?)?,
+                "--record-stop" => options.record_stop = Some(parse_number(&value()?)?),
+                "--cheats" => options.cheats = Some(value()?.into()),
+                "--profile" => options.profile = Some(value()?.into()),
+                "--flamegraph" => options.flamegraph = Some(value()?.into()),
+                "--coverage" => options.coverage = Some(value()?.into()),
+                "--coverage-dump" => options.coverage_dump = Some(value()?.into()),
+                "--rom-database" => options.rom_database = Some(value()?.into()),
+                "--script" => options.script = Some(value()?.into()),
+                "--serve" => options.serve = Some(value()?),
+                _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+                _ if options.rom.is_none() => options.rom = Some(PathBuf::from(arg)),
+                _ => return Err(format!("unexpected argument {}", arg)),
+            }
+        }
+
+        if options.rom.is_none() && options.serve.is_none() {
+            return Err("missing ROM path".to_string());
+        }
+        // These watch every frame of the fixed run, which a script replaces.
+        let per_frame = [
+            options.record.is_some(),
+            options.cheats.is_some(),
+            options.profile.is_some(),
+            options.flamegraph.is_some(),
+            options.coverage.is_some(),
+            options.coverage_dump.is_some(),
+        ];
+        if options.script.is_some() && per_frame.contains(&true) {
+            return Err(
+                "--script can't be combined with recording, cheats, profiling or coverage"
+                    .to_string(),
+            );
+        }
+        Ok(options)
+    }
+}
+
+/// Parses a numeric option value.
+fn parse_number<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid number {}", value))
+}
+
+/// Saves a screenshot of the display, picking the format from the file
+/// extension.
+fn save_screenshot(
+    emulator: &Emulator,
+    path: &Path,
+    scale: usize,
+    palette: &Palette,
+) -> Result<(), String> {
+    let data = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("png") => to_png(emulator, scale, palette),
+        Some("pbm") => to_pbm(emulator, scale, palette),
+        Some("svg") => to_svg(emulator, scale, palette).into_bytes(),
+        _ => return Err(format!("unsupported image format {}", path.display())),
+    };
+    std::fs::write(path, data).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Creates the emulator with the ROM loaded, if there is one.
+///
+/// #### Returns:
+/// - The emulator and the ROM.
+///
+fn load(options: &Options) -> Result<(Emulator, Vec<u8>), String> {
+    let mut emulator = Emulator::new();
+    if let Some(path) = &options.rom_database {
+        let text =
+            std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let mut database = (*RomDatabase::embedded()).clone();
+        database
+            .extend(RomDatabase::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))?);
+        emulator.set_rom_database(Arc::new(database));
+    }
+    let mut rom = Vec::new();
+    if let Some(path) = &options.rom {
+        rom = std::fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        emulator
+            .load_rom(&rom)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+    if let Some(ticks_per_frame) = options.ticks_per_frame {
+        emulator.set_ticks_per_frame(ticks_per_frame);
+    }
+    Ok((emulator, rom))
+}
+
+/// Runs the ROM for the requested number of frames.
+fn run(options: &Options) -> Result<(), String> {
+    let (mut emulator, rom) = load(options)?;
+
+    let cheats = match &options.cheats {
+        Some(path) => {
+            let text =
+                std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            CheatDatabase::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))?
+        }
+        None => CheatDatabase::new(),
+    };
+    let hash = sha1(&rom);
+    // Screenshots and recordings use the colours the ROM was made with.
+    let palette = emulator
+        .rom_info()
+        .and_then(|info| info.palette)
+        .unwrap_or_default();
+
+    let mut recorder = GifRecorder::new(options.scale, palette);
+    let record_stop = options.record_stop.unwrap_or(options.frames);
+    let mut profiler =
+        (options.profile.is_some() || options.flamegraph.is_some()).then(Profiler::new);
+    let mut coverage =
+        (options.coverage.is_some() || options.coverage_dump.is_some()).then(Coverage::new);
+
+    let frames = match &options.script {
+        Some(path) => {
+            let source =
+                std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            script::run(&mut emulator, &source)
+                .map_err(|e| format!("{}: {}", path.display(), e))?;
+            0
+        }
+        None => options.frames,
+    };
+    for frame in 0..frames {
+        if options.record.is_some() && frame == options.record_start {
+            recorder.start();
+        }
+        if frame == record_stop {
+            save_recording(&mut recorder, options)?;
+        }
+        cheats
+            .apply(&hash, &mut emulator)
+            .map_err(|e| format!("cheat: {}", e))?;
+        match (&mut profiler, &mut coverage) {
+            (None, None) => emulator.run_frame(),
+            (profiler, coverage) => emulator.run_frame_observed(&mut (profiler, coverage)),
+        }
+        recorder
+            .capture(&emulator)
+            .map_err(|e| format!("record: {}", e))?;
+    }
+    save_recording(&mut recorder, options)?;
+
+    // The outputs still show the screen the ROM stopped on, so a fault is
+    // only reported.
+    if let Some(fault) = emulator.fault() {
+        eprintln!("warning: the ROM stopped: {}", fault);
+    }
+    if let Some(path) = &options.screenshot {
+        save_screenshot(&emulator, path, options.scale, &palette)?;
+    }
+    if let Some(profiler) = &profiler {
+        if let Some(path) = &options.profile {
+            std::fs::write(path, profiler.report())
+                .map_err(|e| format!("{}: {}", path.display(), e))?;
+        }
+        if let Some(path) = &options.flamegraph {
+            std::fs::write(path, profiler.collapsed_stacks())
+                .map_err(|e| format!("{}: {}", path.display(), e))?;
+        }
+    }
+    if let Some(coverage) = &mut coverage {
+        if let Some(path) = &options.coverage_dump {
+            std::fs::write(path, coverage.to_hex_dump(&emulator))
+                .map_err(|e| format!("{}: {}", path.display(), e))?;
+        }
+        if let Some(path) = &options.coverage {
+            save_coverage(coverage, path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Serves the emulator to one client at a time until the process is
+/// killed. Clients share the emulator, so a client can pick up where the
+/// last one left off.
+fn serve(options: &Options, address: &str) -> Result<(), String> {
+    let (emulator, _) = load(options)?;
+    let mut server = Server::new(emulator);
+    let error = |e: std::io::Error| format!("{}: {}", address, e);
+
+    if let Some(path) = address.strip_prefix("unix:") {
+        #[cfg(unix)]
+        {
+            // A socket left behind by an earlier server would stop the bind.
+            let _ = std::fs::remove_file(path);
+            let listener = UnixListener::bind(path).map_err(error)?;
+            eprintln!("listening on {}", address);
+            for stream in listener.incoming() {
+                if let Err(e) = stream.and_then(|stream| server.serve(stream)) {
+                    eprintln!("warning: {}", e);
+                }
+            }
+            return Ok(());
+        }
+        #[cfg(not(unix))]
+        return Err(format!("{}: Unix sockets aren't supported here", path));
+    }
+
+    let listener = TcpListener::bind(address).map_err(error)?;
+    eprintln!("listening on {}", listener.local_addr().map_err(error)?);
+    for stream in listener.incoming() {
+        if let Err(e) = stream.and_then(|stream| server.serve(stream)) {
+            eprintln!("warning: {}", e);
+        }
+    }
+    Ok(())
+}
+
+/// Merges the coverage from earlier runs saved at the path, if any, into
+/// this run's and saves the total back.
+fn save_coverage(coverage: &mut Coverage, path: &Path) -> Result<(), String> {
+    if path.exists() {
+        let text =
+            std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let previous =
+            Coverage::from_json(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+        coverage.merge(&previous);
+    }
+    std::fs::write(path, coverage.to_json()).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Stops the recording (if one is running) and writes it out.
+fn save_recording(recorder: &mut GifRecorder, options: &Options) -> Result<(), String> {
+    if let (Some(path), Some(gif)) = (&options.record, recorder.stop()) {
+        std::fs::write(path, gif).map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("error: {}\n{}", e, USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let result = match &options.serve {
+        Some(address) => serve(&options, address),
+        None => run(&options),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,63 @@
+//! Compares the interpreter with the recompiler on a tight ALU loop with a
+//! BCD write every time round, the kind of code long headless runs spend
+//! their time in. Run with `cargo bench -p chip-core`.
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+
+use chip_core::harness::parse_listing;
+use chip_core::recompiler::Recompiler;
+use chip_core::Emulator;
+
+/// Cycles run per iteration.
+const CYCLES: usize = 100_000;
+
+const ROM: &str = "
+200: 6000   ; V0 = 0
+202: 6101   ; V1 = 1
+204: 8014   ; V0 += V1
+206: 8204   ; V2 += V0
+208: 8326   ; V3 = V2 >> 1
+20A: 7401   ; V4 += 1
+20C: 8543   ; V5 ^= V4
+20E: 8651   ; V6 |= V5
+210: 8762   ; V7 &= V6
+212: 3400   ; skip if V4 == 0
+214: 1218   ; jump 218
+216: 1204   ; jump 204
+218: A300   ; I = 300
+21A: F233   ; BCD of V2 at 300
+21C: 1204   ; jump 204
+";
+
+fn emulator() -> Emulator {
+    let mut emulator = Emulator::with_seed(0);
+    emulator.load_rom(&parse_listing(ROM).unwrap()).unwrap();
+    emulator
+}
+
+fn run(c: &mut Criterion) {
+    let mut group = c.benchmark_group("run");
+    group.throughput(Throughput::Elements(CYCLES as u64));
+    group.bench_function("interpreter", |b| {
+        b.iter_batched_ref(
+            emulator,
+            |emulator| {
+                for _ in 0..CYCLES {
+                    emulator.tick();
+                }
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    group.bench_function("recompiler", |b| {
+        b.iter_batched_ref(
+            || (emulator(), Recompiler::new()),
+            |(emulator, recompiler)| recompiler.run(emulator, CYCLES),
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, run);
+criterion_main!(benches);
@@ -0,0 +1,6 @@
+/* Memory layout of the microcontroller, change to match the badge. */
+MEMORY
+{
+  FLASH : ORIGIN = 0x08000000, LENGTH = 256K
+  RAM : ORIGIN = 0x20000000, LENGTH = 64K
+}
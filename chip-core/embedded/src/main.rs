@@ -0,0 +1,97 @@
+//! Runs the emulator on a Cortex-M microcontroller driving a 64x32 OLED,
+//! without `std` or a heap. SysTick paces the 60 Hz frames and the
+//! hardware specific parts (the panel, the keypad, the buzzer and an
+//! entropy source) are in `board`, to be filled in with the badge's HAL.
+
+#![no_std]
+#![no_main]
+
+use chip_core::{Emulator, NUM_KEYS, SCREEN_HEIGHT, SCREEN_WIDTH};
+use cortex_m::peripheral::syst::SystClkSource;
+use cortex_m_rt::entry;
+use panic_halt as _;
+
+/// Core clock of the microcontroller, SysTick counts at this rate.
+const CORE_CLOCK_HZ: u32 = 64_000_000;
+/// SSD1306 style panels take the display as pages of 8 rows, one byte per
+/// column with the top row in the lowest bit.
+const PAGES: usize = SCREEN_HEIGHT / 8;
+
+/// Rolls a die every half second: shows a random hex digit in the middle
+/// of the screen.
+const ROM: [u8; 24] = [
+    0x00, 0xE0, // 200: CLS
+    0xC0, 0x0F, // 202: V0 = random digit
+    0xF0, 0x29, // 204: I = font sprite for V0
+    0x61, 0x1E, // 206: V1 = 30
+    0x62, 0x0D, // 208: V2 = 13
+    0xD1, 0x25, // 20A: draw it at (V1, V2)
+    0x63, 0x1E, // 20C: V3 = 30 frames
+    0xF3, 0x15, // 20E: DT = V3
+    0xF4, 0x07, // 210: V4 = DT
+    0x34, 0x00, // 212: skip if V4 == 0
+    0x12, 0x10, // 214: jump to 210
+    0x12, 0x00, // 216: jump to 200
+];
+
+/// Hooks for the badge's peripherals. These are stand-ins so the example
+/// builds on its own, replace them with calls into the board's HAL.
+mod board {
+    /// Returns a seed for CXNN, such as a hardware RNG reading or the noise
+    /// in a floating ADC pin.
+    pub fn entropy() -> u64 {
+        0
+    }
+
+    /// Returns the held keys, bit N is set when key N is down.
+    pub fn read_keys() -> u16 {
+        0
+    }
+
+    /// Sends a frame to the panel, in its page layout.
+    pub fn flush(_pages: &[u8]) {}
+
+    /// Turns the buzzer on or off.
+    pub fn buzzer(_on: bool) {}
+}
+
+/// Converts the row major display into the panel's page layout.
+///
+/// #### Parameters:
+/// - display: The emulator's pixels.
+/// - pages: Receives `PAGES` rows of `SCREEN_WIDTH` bytes.
+///
+fn pack_pages(display: &[bool], pages: &mut [u8; SCREEN_WIDTH * PAGES]) {
+    for (page, columns) in pages.chunks_mut(SCREEN_WIDTH).enumerate() {
+        for (x, byte) in columns.iter_mut().enumerate() {
+            *byte = (0..8).fold(0, |byte, bit| {
+                let y = page * 8 + bit;
+                byte | (display[x + SCREEN_WIDTH * y] as u8) << bit
+            });
+        }
+    }
+}
+
+#[entry]
+fn main() -> ! {
+    let mut core = cortex_m::Peripherals::take().unwrap();
+    core.SYST.set_clock_source(SystClkSource::Core);
+    core.SYST.set_reload(CORE_CLOCK_HZ / 60 - 1);
+    core.SYST.clear_current();
+    core.SYST.enable_counter();
+
+    // The emulator is a few KB of arrays and lives on the stack.
+    let mut emulator = Emulator::with_seed(board::entropy());
+    emulator.load_rom(&ROM).unwrap();
+    let mut pages = [0; SCREEN_WIDTH * PAGES];
+
+    loop {
+        let keys = board::read_keys();
+        emulator.set_keys(keys);
+        emulator.run_frame();
+        pack_pages(emulator.get_display(), &mut pages);
+        board::flush(&pages);
+        board::buzzer(emulator.sound_timer() > 0);
+        while !core.SYST.has_wrapped() {}
+    }
+}
@@ -0,0 +1,12 @@
+#!/bin/sh
+# Rebuilds the seed corpus from the conformance test ROMs. The execute
+# target gets each ROM under the default quirks (shifting only), the
+# default speed and no keys held.
+set -e
+cd "$(dirname "$0")"
+for listing in ../tests/roms/*.hex; do
+    name=seed_$(basename "$listing" .hex)
+    # Strip comments and address prefixes, leaving the hex bytes.
+    sed -e 's/;.*//' -e 's/^[^:]*://' "$listing" | xxd -r -p > "corpus/load_rom/$name"
+    { printf '\020\012\000\000'; cat "corpus/load_rom/$name"; } > "corpus/execute/$name"
+done
@@ -0,0 +1,59 @@
+//! Runs arbitrary byte images as ROMs and checks the machine state stays
+//! valid after every instruction.
+//!
+//! The input starts with a four byte header followed by the ROM:
+//! 1. Quirk flags: bit 0 `vf_reset`, 1 `memory`, 2 `display_wait`,
+//!    3 `clipping`, 4 `shifting` and 5 `jumping`.
+//! 2. CPU cycles per frame.
+//! 3. and 4. The held keys, one bit per key (big endian).
+
+#![no_main]
+
+use chip_core::quirks::Quirks;
+use chip_core::{Emulator, NUM_KEYS, RAM_SIZE, STACK_SIZE, START_ADDRESS};
+use libfuzzer_sys::fuzz_target;
+
+/// Number of CPU cycles to run each input for.
+const TICKS: usize = 5000;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((header, rom)) = data.split_first_chunk::<4>() else {
+        return;
+    };
+    let [flags, ticks_per_frame, keys_high, keys_low] = *header;
+
+    let mut emulator = Emulator::new();
+    emulator.seed_rng(0);
+    emulator.set_quirks(Quirks {
+        vf_reset: flags & 0x01 != 0,
+        memory: flags & 0x02 != 0,
+        display_wait: flags & 0x04 != 0,
+        clipping: flags & 0x08 != 0,
+        shifting: flags & 0x10 != 0,
+        jumping: flags & 0x20 != 0,
+    });
+    let keys = u16::from_be_bytes([keys_high, keys_low]);
+    emulator.set_keys(keys);
+    // Anything that doesn't fit is cut off rather than rejected so large
+    // inputs still run.
+    let rom = &rom[..rom.len().min(RAM_SIZE - START_ADDRESS as usize)];
+    emulator.load_rom(rom).unwrap();
+
+    let ticks_per_frame = ticks_per_frame.max(1) as usize;
+    for tick in 1..=TICKS {
+        emulator.tick();
+        if tick % ticks_per_frame == 0 {
+            emulator.timer_tick();
+        }
+
+        assert!((emulator.program_counter() as usize) < RAM_SIZE);
+        assert!(emulator.stack().len() <= STACK_SIZE);
+        assert!(emulator
+            .stack()
+            .iter()
+            .all(|address| (*address as usize) < RAM_SIZE));
+        if emulator.fault().is_some() {
+            break;
+        }
+    }
+});
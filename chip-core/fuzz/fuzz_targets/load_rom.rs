@@ -0,0 +1,27 @@
+//! Loads arbitrary byte images as ROMs.
+
+#![no_main]
+
+use chip_core::{Emulator, RAM_SIZE, START_ADDRESS};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let mut emulator = Emulator::new();
+    let fits = data.len() <= RAM_SIZE - START_ADDRESS as usize;
+
+    match emulator.load_rom(data) {
+        Ok(()) => {
+            assert!(fits);
+            let loaded = emulator
+                .read_ram(START_ADDRESS as usize, data.len())
+                .unwrap();
+            assert_eq!(loaded, data);
+        }
+        Err(_) => {
+            assert!(!fits);
+            // Nothing is loaded when the ROM is too big.
+            let rest = emulator.read_ram(START_ADDRESS as usize, RAM_SIZE - START_ADDRESS as usize);
+            assert!(rest.unwrap().iter().all(|byte| *byte == 0));
+        }
+    }
+});
@@ -0,0 +1,586 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use crate::hash::sha1;
+use crate::instruction::Instruction;
+use crate::{Emulator, ADDRESS_MASK, NUM_REGS, RAM_SIZE, START_ADDRESS};
+
+/// Runtime support for code generated by `compile`, not meant for any
+/// other use. These skip the checks of the `memory` accessors, the
+/// generated code only passes values that the interpreter could produce
+/// itself.
+#[doc(hidden)]
+pub trait Runtime {
+    /// Whether `tick` would run an instruction, i.e. the CPU isn't waiting
+    /// for the next frame and hasn't stopped on a fault.
+    fn is_running(&self) -> bool;
+
+    /// Whether RAM still holds an opcode at an address, i.e. the code
+    /// hasn't been changed since it was compiled.
+    ///
+    /// #### Parameters:
+    /// - address: The address of the instruction.
+    /// - opcode: The opcode that was compiled.
+    ///
+    fn code_matches(&self, address: u16, opcode: u16) -> bool;
+
+    /// Returns the V registers for writing.
+    fn registers_mut(&mut self) -> &mut [u8; NUM_REGS];
+
+    /// Moves the program counter, wrapping around at the end of RAM.
+    ///
+    /// #### Parameters:
+    /// - address: The address of the next instruction to run.
+    ///
+    fn jump(&mut self, address: u16);
+
+    /// Runs one already decoded instruction as the next CPU cycle, the same
+    /// as `tick` when RAM holds that instruction at the program counter.
+    ///
+    /// #### Parameters:
+    /// - instruction: The instruction to run.
+    ///
+    fn step(&mut self, instruction: Instruction);
+}
+
+impl Runtime for Emulator {
+    fn is_running(&self) -> bool {
+        !self.waiting_for_frame && self.fault.is_none()
+    }
+
+    fn code_matches(&self, address: u16, opcode: u16) -> bool {
+        let [high, low] = opcode.to_be_bytes();
+        self.ram[(address & ADDRESS_MASK) as usize] == high
+            && self.ram[((address + 1) & ADDRESS_MASK) as usize] == low
+    }
+
+    fn registers_mut(&mut self) -> &mut [u8; NUM_REGS] {
+        &mut self.registers
+    }
+
+    fn jump(&mut self, address: u16) {
+        self.program_counter = address & ADDRESS_MASK;
+    }
+
+    fn step(&mut self, instruction: Instruction) {
+        if !self.is_running() {
+            return;
+        }
+        self.skip();
+        self.execute(instruction);
+    }
+}
+
+/// What control flow analysis found out about a ROM.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Analysis {
+    /// Reachable instructions by address, as opcodes.
+    pub code: BTreeMap<u16, u16>,
+    /// Addresses FX33 and FX55 can be shown to write to. Instructions over
+    /// them are left to the interpreter.
+    pub written: BTreeSet<u16>,
+    /// BNNN instructions, whose targets depend on a register.
+    pub dynamic_jumps: BTreeSet<u16>,
+    /// Where subroutines start: the start address and every 2NNN target
+    /// found in the code.
+    pub subroutines: BTreeSet<u16>,
+}
+
+impl Analysis {
+    /// Whether the instruction at an address is compiled to native code.
+    pub fn is_compiled(&self, address: u16) -> bool {
+        self.code.contains_key(&address)
+            && !self.written.contains(&address)
+            && !self.written.contains(&((address + 1) & ADDRESS_MASK))
+    }
+}
+
+/// What's known about the index register at an instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Index {
+    /// Set by an ANNN on every path to the instruction.
+    Known(u16),
+    /// Anything else.
+    Unknown,
+}
+
+impl Index {
+    /// Combines the values from two paths.
+    fn meet(self, other: Index) -> Index {
+        if self == other {
+            self
+        } else {
+            Index::Unknown
+        }
+    }
+}
+
+/// Finds the code reachable from the start address by following every
+/// branch, and which parts of the ROM the ROM writes over. Only code inside
+/// the ROM is followed, BNNN targets aren't followed at all.
+///
+/// #### Parameters:
+/// - rom: The raw ROM bytes.
+///
+pub fn analyze(rom: &[u8]) -> Analysis {
+    let start = START_ADDRESS as usize;
+    let end = (start + rom.len()).min(RAM_SIZE);
+    let opcode_at = |address: u16| {
+        let offset = address as usize - start;
+        u16::from_be_bytes([rom[offset], rom[offset + 1]])
+    };
+
+    let mut analysis = Analysis::default();
+    let mut index: BTreeMap<u16, Index> = BTreeMap::new();
+    let mut worklist = vec![(START_ADDRESS, Index::Unknown)];
+
+    while let Some((address, incoming)) = worklist.pop() {
+        // Only whole instructions inside the ROM are compiled.
+        if (address as usize) < start || address as usize + 1 >= end {
+            continue;
+        }
+        let i = match index.get(&address) {
+            Some(known) if known.meet(incoming) == *known => continue,
+            Some(known) => known.meet(incoming),
+            None => incoming,
+        };
+        index.insert(address, i);
+
+        let opcode = opcode_at(address);
+        analysis.code.insert(address, opcode);
+        let instruction = Instruction::decode(opcode);
+
+        if let (Index::Known(i), len @ 1..) = (i, instruction.ram_write_len()) {
+            for offset in 0..len as u16 {
+                analysis.written.insert((i + offset) & ADDRESS_MASK);
+            }
+        }
+
+        match instruction {
+            Instruction::Call(nnn) => {
+                analysis.subroutines.insert(nnn);
+                worklist.push((nnn, i));
+            }
+            Instruction::JumpV0 { .. } => {
+                analysis.dynamic_jumps.insert(address);
+            }
+            _ => (),
+        }
+        let after = match instruction {
+            Instruction::SetI(nnn) => Index::Known(nnn),
+            // The subroutine may change I before returning, the others
+            // change I themselves (FX55 and FX65 only with the memory
+            // quirk).
+            Instruction::Call(_)
+            | Instruction::AddI { .. }
+            | Instruction::Font { .. }
+            | Instruction::Store { .. }
+            | Instruction::Load { .. } => Index::Unknown,
+            _ => i,
+        };
+        for next in successors(address, instruction) {
+            worklist.push((next, after));
+        }
+    }
+    if analysis.code.contains_key(&START_ADDRESS) {
+        analysis.subroutines.insert(START_ADDRESS);
+    }
+    analysis
+        .subroutines
+        .retain(|address| analysis.code.contains_key(address));
+    analysis
+}
+
+/// Returns where the CPU can go after an instruction without leaving its
+/// subroutine: 2NNN continues with the next instruction once the call
+/// returns, 00EE and BNNN leave.
+fn successors(address: u16, instruction: Instruction) -> Vec<u16> {
+    let next = (address + 2) & ADDRESS_MASK;
+    let skip = (address + 4) & ADDRESS_MASK;
+    match instruction {
+        Instruction::Jump(nnn) => vec![nnn],
+        Instruction::SkipEq { .. }
+        | Instruction::SkipNeq { .. }
+        | Instruction::SkipEqV { .. }
+        | Instruction::SkipNeqV { .. }
+        | Instruction::SkipKey { .. }
+        | Instruction::SkipNkey { .. } => vec![next, skip],
+        Instruction::JumpV0 { .. } | Instruction::Ret | Instruction::Unknown(_) => Vec::new(),
+        _ => vec![next],
+    }
+}
+
+/// Splits the compiled code into subroutines by entry address. Code shared
+/// by several subroutines, such as a common tail jumped to from each, goes
+/// to the first one that reaches it.
+fn partition(analysis: &Analysis) -> BTreeMap<u16, Vec<(u16, u16)>> {
+    let mut owned = BTreeSet::new();
+    let mut subroutines = BTreeMap::new();
+    for entry in &analysis.subroutines {
+        let mut code = BTreeMap::new();
+        let mut worklist = vec![*entry];
+        while let Some(address) = worklist.pop() {
+            let Some(opcode) = analysis.code.get(&address) else {
+                continue;
+            };
+            if !owned.insert(address) {
+                continue;
+            }
+            if analysis.is_compiled(address) {
+                code.insert(address, *opcode);
+            }
+            worklist.extend(successors(address, Instruction::decode(*opcode)));
+        }
+        if !code.is_empty() {
+            subroutines.insert(*entry, code.into_iter().collect());
+        }
+    }
+    subroutines
+}
+
+/// Compiles a ROM to the source of a Rust module with a `run` function that
+/// emulates CPU cycles exactly like `Emulator::tick`, but with the
+/// reachable code compiled ahead of time.
+///
+/// Each subroutine becomes a function that runs cycles for as long as the
+/// program counter stays in its code, `run` picks the function from the
+/// program counter. Instructions that work on registers and the program
+/// counter become native Rust, the rest call `Runtime::step` with the
+/// instruction already decoded. Every compiled instruction checks RAM
+/// still holds it before running, so code the ROM rewrites, code that was
+/// never found (such as BNNN jump tables) and anything outside the ROM
+/// runs on the interpreter.
+///
+/// #### Parameters:
+/// - rom: The raw ROM bytes.
+///
+/// #### Returns:
+/// - The module source, using the `chip_core` crate.
+///
+pub fn compile(rom: &[u8]) -> String {
+    let analysis = analyze(rom);
+    let subroutines = partition(&analysis);
+    let compiled: usize = subroutines.values().map(Vec::len).sum();
+
+    // Writing to a String can't fail so the results are ignored.
+    let mut source = String::new();
+    let _ = write!(
+        source,
+        "// Generated by chip-aot from a {} byte ROM (SHA-1 ",
+        rom.len()
+    );
+    for byte in sha1(rom) {
+        let _ = write!(source, "{:02x}", byte);
+    }
+    let _ = writeln!(source, "), do not edit.");
+    let _ = writeln!(
+        source,
+        "// {} of {} reachable instructions are compiled, in {} subroutines.",
+        compiled,
+        analysis.code.len(),
+        subroutines.len()
+    );
+    if !analysis.written.is_empty() {
+        let _ = writeln!(
+            source,
+            "// Written by the ROM, left to the interpreter: {}",
+            ranges(&analysis.written)
+        );
+    }
+    if !analysis.dynamic_jumps.is_empty() {
+        let _ = writeln!(
+            source,
+            "// BNNN jumps, targets left to the interpreter: {}",
+            ranges(&analysis.dynamic_jumps)
+        );
+    }
+    source.push('\n');
+
+    // The functions go last, the imports depend on what they use.
+    let functions: Vec<String> = subroutines
+        .iter()
+        .map(|(entry, code)| subroutine(*entry, code))
+        .collect();
+
+    if !functions.is_empty() {
+        source.push_str("use chip_core::aot::Runtime;\n");
+    }
+    if functions
+        .iter()
+        .any(|function| function.contains("Instruction::"))
+    {
+        source.push_str("use chip_core::instruction::Instruction;\n");
+    }
+    source.push_str("use chip_core::Emulator;\n\n");
+
+    let _ = writeln!(source, "/// The ROM this module was compiled from.");
+    let _ = writeln!(source, "pub const ROM: [u8; {}] = [", rom.len());
+    for chunk in rom.chunks(16) {
+        let bytes: Vec<String> = chunk
+            .iter()
+            .map(|byte| format!("0x{:02X},", byte))
+            .collect();
+        let _ = writeln!(source, "    {}", bytes.join(" "));
+    }
+    source.push_str("];\n\n");
+
+    source.push_str(
+        "/// Runs CPU cycles, the same as calling `Emulator::tick` that many times.\n\
+         pub fn run(emulator: &mut Emulator, ticks: usize) {\n",
+    );
+    if subroutines.is_empty() {
+        source.push_str(
+            "    for _ in 0..ticks {\n\
+             \x20       emulator.tick();\n\
+             \x20   }\n",
+        );
+    } else {
+        source.push_str(
+            "    let mut ticks = ticks;\n\
+             \x20   while ticks > 0 && emulator.is_running() {\n\
+             \x20       let compiled = match emulator.program_counter() {\n",
+        );
+        for (entry, code) in &subroutines {
+            let addresses: Vec<String> = code
+                .iter()
+                .map(|(address, _)| format!("0x{:03X}", address))
+                .collect();
+            let lines: Vec<String> = addresses.chunks(8).map(|line| line.join(" | ")).collect();
+            let _ = writeln!(
+                source,
+                "            {} => sub_{:03x}(emulator, &mut ticks),",
+                lines.join("\n            | "),
+                entry
+            );
+        }
+        source.push_str(
+            "            _ => false,\n\
+             \x20       };\n\
+             \x20       if !compiled {\n\
+             \x20           emulator.tick();\n\
+             \x20           ticks -= 1;\n\
+             \x20       }\n\
+             \x20   }\n",
+        );
+    }
+    source.push_str(
+        "}\n\n\
+         /// Emulates one 60 Hz frame, the same as `Emulator::run_frame`.\n\
+         pub fn run_frame(emulator: &mut Emulator) {\n\
+         \x20   run(emulator, emulator.ticks_per_frame());\n\
+         \x20   emulator.timer_tick();\n\
+         }\n",
+    );
+    for function in functions {
+        source.push('\n');
+        source.push_str(&function);
+    }
+    source
+}
+
+/// Returns the function for a subroutine, which runs cycles while the
+/// program counter is on its compiled code and returns whether any ran.
+///
+/// #### Parameters:
+/// - entry: The address the subroutine starts at.
+/// - code: The subroutine's compiled instructions, as addresses and
+///   opcodes.
+///
+fn subroutine(entry: u16, code: &[(u16, u16)]) -> String {
+    // The match arms go first, the setup before them depends on what they
+    // use.
+    let mut arms = String::new();
+    for (address, opcode) in code {
+        let _ = writeln!(
+            arms,
+            "            0x{:03X} if emulator.code_matches(0x{:03X}, 0x{:04X}) => {{",
+            address, address, opcode
+        );
+        match native(*address, *opcode) {
+            Some(lines) => {
+                for line in lines {
+                    let _ = writeln!(arms, "                {}", line);
+                }
+            }
+            None => {
+                let _ = writeln!(
+                    arms,
+                    "                emulator.step(Instruction::{});",
+                    literal(Instruction::decode(*opcode))
+                );
+            }
+        }
+        arms.push_str("            }\n");
+    }
+
+    let mut function = String::new();
+    if entry == START_ADDRESS {
+        let _ = writeln!(function, "/// The code run from the start address.");
+    } else {
+        let _ = writeln!(function, "/// The subroutine at 0x{:03X}.", entry);
+    }
+    let _ = writeln!(
+        function,
+        "fn sub_{:03x}(emulator: &mut Emulator, ticks: &mut usize) -> bool {{",
+        entry
+    );
+    if arms.contains("quirks.") {
+        function.push_str("    let quirks = emulator.quirks();\n");
+    }
+    function.push_str(
+        "    let start = *ticks;\n\
+         \x20   while *ticks > 0 && emulator.is_running() {\n\
+         \x20       match emulator.program_counter() {\n",
+    );
+    function.push_str(&arms);
+    function.push_str(
+        "            _ => break,\n\
+         \x20       }\n\
+         \x20       *ticks -= 1;\n\
+         \x20   }\n\
+         \x20   *ticks != start\n\
+         }\n",
+    );
+    function
+}
+
+/// Returns native Rust for an instruction, or `None` if it's left to
+/// `Runtime::step`.
+fn native(address: u16, opcode: u16) -> Option<Vec<String>> {
+    let next = format!("0x{:03X}", (address + 2) & ADDRESS_MASK);
+    let skip = format!("0x{:03X}", (address + 4) & ADDRESS_MASK);
+    let branch = |condition: String| {
+        vec![
+            format!("let taken = {};", condition),
+            format!(
+                "emulator.jump(if taken {{ {} }} else {{ {} }});",
+                skip, next
+            ),
+        ]
+    };
+    let register = |idx: u8| format!("v[0x{:X}]", idx);
+    // Code on the registers, followed by the move to the next instruction.
+    let alu = |lines: Vec<String>| {
+        let mut body = vec!["let v = emulator.registers_mut();".to_string()];
+        body.extend(lines);
+        body.push(format!("emulator.jump({});", next));
+        body
+    };
+    let bitwise = |x: u8, y: u8, operator: &str| {
+        alu(vec![
+            format!("{} {}= {};", register(x), operator, register(y)),
+            "if quirks.vf_reset {".to_string(),
+            "    v[0xF] = 0;".to_string(),
+            "}".to_string(),
+        ])
+    };
+    let shift = |x: u8, y: u8, operator: &str, flag: &str| {
+        let value = if x == y {
+            format!("let value = {};", register(x))
+        } else {
+            format!(
+                "let value = if quirks.shifting {{ {} }} else {{ {} }};",
+                register(x),
+                register(y)
+            )
+        };
+        alu(vec![
+            value,
+            format!("{} = value {} 1;", register(x), operator),
+            format!("v[0xF] = {};", flag),
+        ])
+    };
+    let subtract = |x: u8, from: u8, to: u8| {
+        alu(vec![
+            format!(
+                "let (value, borrow) = {}.overflowing_sub({});",
+                register(from),
+                register(to)
+            ),
+            format!("{} = value;", register(x)),
+            "v[0xF] = !borrow as u8;".to_string(),
+        ])
+    };
+    let compare = |x: u8, operator: &str, value: String| {
+        branch(format!(
+            "emulator.registers()[0x{:X}] {} {}",
+            x, operator, value
+        ))
+    };
+
+    let lines = match Instruction::decode(opcode) {
+        Instruction::Nop => vec![format!("emulator.jump({});", next)],
+        Instruction::Jump(nnn) => vec![format!("emulator.jump(0x{:03X});", nnn)],
+        Instruction::SkipEq { x, nn } => compare(x, "==", format!("0x{:02X}", nn)),
+        Instruction::SkipNeq { x, nn } => compare(x, "!=", format!("0x{:02X}", nn)),
+        Instruction::SkipEqV { x, y } => {
+            compare(x, "==", format!("emulator.registers()[0x{:X}]", y))
+        }
+        Instruction::SkipNeqV { x, y } => {
+            compare(x, "!=", format!("emulator.registers()[0x{:X}]", y))
+        }
+        Instruction::Set { x, nn } => alu(vec![format!("{} = 0x{:02X};", register(x), nn)]),
+        Instruction::Add { x, nn } => alu(vec![format!(
+            "{} = {}.wrapping_add(0x{:02X});",
+            register(x),
+            register(x),
+            nn
+        )]),
+        Instruction::SetV { x, y } if x == y => vec![format!("emulator.jump({});", next)],
+        Instruction::SetV { x, y } => alu(vec![format!("{} = {};", register(x), register(y))]),
+        Instruction::Or { x, y } => bitwise(x, y, "|"),
+        Instruction::And { x, y } => bitwise(x, y, "&"),
+        Instruction::Xor { x, y } => bitwise(x, y, "^"),
+        Instruction::AddV { x, y } => alu(vec![
+            format!(
+                "let (value, carry) = {}.overflowing_add({});",
+                register(x),
+                register(y)
+            ),
+            format!("{} = value;", register(x)),
+            "v[0xF] = carry as u8;".to_string(),
+        ]),
+        Instruction::SubV { x, y } => subtract(x, x, y),
+        Instruction::SubX { x, y } => subtract(x, y, x),
+        Instruction::ShiftRight { x, y } => shift(x, y, ">>", "value & 1"),
+        Instruction::ShiftLeft { x, y } => shift(x, y, "<<", "value >> 7"),
+        _ => return None,
+    };
+    Some(lines)
+}
+
+/// Formats an instruction as a Rust expression with hex operands, e.g.
+/// `Draw { x: 0xA, y: 0xB, n: 0x5 }`.
+fn literal(instruction: Instruction) -> String {
+    // The alternate debug format gives hex numbers but spreads them over
+    // several lines.
+    format!("{:#X?}", instruction)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace("( ", "(")
+        .replace(", )", ")")
+        .replace(", }", " }")
+}
+
+/// Formats a set of addresses as ranges, e.g. `200-203, 2F0`.
+fn ranges(addresses: &BTreeSet<u16>) -> String {
+    let mut ranges: Vec<(u16, u16)> = Vec::new();
+    for address in addresses {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == *address => *end = *address,
+            _ => ranges.push((*address, *address)),
+        }
+    }
+    ranges
+        .iter()
+        .map(|(start, end)| {
+            if start == end {
+                format!("{:03X}", start)
+            } else {
+                format!("{:03X}-{:03X}", start, end)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -0,0 +1,418 @@
+use crate::error::{ExecutionError, MemoryError};
+use crate::quirks::Quirks;
+use crate::rng::Rng;
+use crate::romdb::RomDatabase;
+use crate::{
+    Cpu, Emulator, DEFAULT_TICKS_PER_FRAME, FONTSET, FONTSET_SIZE, NUM_KEYS, NUM_REGS, RAM_SIZE,
+    SCREEN_HEIGHT, SCREEN_WIDTH, STACK_SIZE, START_ADDRESS,
+};
+
+/// Number of pixels in one display.
+const SCREEN_SIZE: usize = SCREEN_WIDTH * SCREEN_HEIGHT;
+
+/// Many machines stepped together, for training agents on thousands of
+/// copies of a game at once.
+///
+/// The state is stored as a structure of arrays: the displays of every
+/// machine are one contiguous array, as are the keys, the RAM and so on,
+/// so they can be handed to ML code without copying each machine out.
+/// Every machine runs the same instructions as `Emulator`, so a batch gives
+/// exactly the same results as the same number of emulators. The tick rate
+/// and quirks are shared by the whole batch.
+///
+/// With the `rayon` feature, `par_run_frame` runs the machines on a thread
+/// pool.
+pub struct Batch {
+    program_counters: Vec<u16>,
+    ram: Vec<[u8; RAM_SIZE]>,
+    screens: Vec<[bool; SCREEN_SIZE]>,
+    registers: Vec<[u8; NUM_REGS]>,
+    i_registers: Vec<u16>,
+    stack_pointers: Vec<u16>,
+    stacks: Vec<[u16; STACK_SIZE]>,
+    keys: Vec<[bool; NUM_KEYS]>,
+    delay_timers: Vec<u8>,
+    sound_timers: Vec<u8>,
+    waiting_for_frame: Vec<bool>,
+    rngs: Vec<Rng>,
+    faults: Vec<Option<ExecutionError>>,
+    /// Number of CPU cycles run by each call to `run_frame`.
+    ticks_per_frame: usize,
+    /// The interpreter behaviours to emulate.
+    quirks: Quirks,
+}
+
+impl Batch {
+    /// Constructor. Each machine starts the same as `Emulator::new`, with
+    /// its own random seed from the operating system.
+    ///
+    /// #### Parameters:
+    /// - len: The number of machines.
+    ///
+    pub fn new(len: usize) -> Self {
+        let mut ram = [0; RAM_SIZE];
+        ram[..FONTSET_SIZE].copy_from_slice(&FONTSET);
+        Self {
+            program_counters: vec![START_ADDRESS; len],
+            ram: vec![ram; len],
+            screens: vec![[false; SCREEN_SIZE]; len],
+            registers: vec![[0; NUM_REGS]; len],
+            i_registers: vec![0; len],
+            stack_pointers: vec![0; len],
+            stacks: vec![[0; STACK_SIZE]; len],
+            keys: vec![[false; NUM_KEYS]; len],
+            delay_timers: vec![0; len],
+            sound_timers: vec![0; len],
+            waiting_for_frame: vec![false; len],
+            rngs: (0..len).map(|_| Rng::from_entropy()).collect(),
+            faults: vec![None; len],
+            ticks_per_frame: DEFAULT_TICKS_PER_FRAME,
+            quirks: Quirks::default(),
+        }
+    }
+
+    /// Returns the number of machines.
+    pub fn len(&self) -> usize {
+        self.program_counters.len()
+    }
+
+    /// Whether the batch has no machines.
+    pub fn is_empty(&self) -> bool {
+        self.program_counters.is_empty()
+    }
+
+    /// Reset one machine, the same as `Emulator::reset`.
+    ///
+    /// #### Parameters:
+    /// - instance: The machine to reset.
+    ///
+    /// #### Panics
+    /// - If there is no machine with that index.
+    ///
+    pub fn reset(&mut self, instance: usize) {
+        self.set_emulator(instance, &Emulator::new());
+    }
+
+    /// Load a ROM image into every machine, see `Emulator::load_rom`. Like
+    /// a new emulator, the batch takes the quirks and tick rate the embedded
+    /// ROM database has for the ROM.
+    ///
+    /// #### Parameters:
+    /// - data: The raw ROM bytes.
+    ///
+    /// #### Returns:
+    /// - An error if the ROM doesn't fit in the memory after the start
+    ///   address, in which case nothing is loaded.
+    ///
+    pub fn load_rom(&mut self, data: &[u8]) -> Result<(), MemoryError> {
+        for instance in 0..self.len() {
+            self.write_ram(instance, START_ADDRESS as usize, data)?;
+        }
+        if let Some(info) = RomDatabase::embedded().lookup(data) {
+            self.quirks = info.quirks;
+            if let Some(tick_rate) = info.tick_rate {
+                self.ticks_per_frame = tick_rate;
+            }
+        }
+        Ok(())
+    }
+
+    /// Read a range of one machine's RAM.
+    ///
+    /// #### Parameters:
+    /// - instance: The machine to read from.
+    /// - address: The first address to read.
+    /// - len: The number of bytes to read.
+    ///
+    /// #### Returns:
+    /// - The bytes, or an error if the range doesn't fit in RAM.
+    ///
+    /// #### Panics
+    /// - If there is no machine with that index.
+    ///
+    pub fn read_ram(
+        &self,
+        instance: usize,
+        address: usize,
+        len: usize,
+    ) -> Result<&[u8], MemoryError> {
+        match address.checked_add(len) {
+            Some(end) if end <= RAM_SIZE => Ok(&self.ram[instance][address..end]),
+            _ => Err(MemoryError::AddressOutOfRange { address, len }),
+        }
+    }
+
+    /// Write a range of one machine's RAM.
+    ///
+    /// #### Parameters:
+    /// - instance: The machine to write to.
+    /// - address: The first address to write.
+    /// - data: The bytes to write.
+    ///
+    /// #### Returns:
+    /// - An error if the range doesn't fit in RAM, in which case nothing
+    ///   is written.
+    ///
+    /// #### Panics
+    /// - If there is no machine with that index.
+    ///
+    pub fn write_ram(
+        &mut self,
+        instance: usize,
+        address: usize,
+        data: &[u8],
+    ) -> Result<(), MemoryError> {
+        match address.checked_add(data.len()) {
+            Some(end) if end <= RAM_SIZE => {
+                self.ram[instance][address..end].copy_from_slice(data);
+                Ok(())
+            }
+            _ => Err(MemoryError::AddressOutOfRange {
+                address,
+                len: data.len(),
+            }),
+        }
+    }
+
+    /// Returns one machine's V registers.
+    ///
+    /// #### Panics
+    /// - If there is no machine with that index.
+    ///
+    pub fn registers(&self, instance: usize) -> &[u8; NUM_REGS] {
+        &self.registers[instance]
+    }
+
+    /// Returns one machine's error that stopped the ROM, if it has stopped.
+    ///
+    /// #### Panics
+    /// - If there is no machine with that index.
+    ///
+    pub fn fault(&self, instance: usize) -> Option<ExecutionError> {
+        self.faults[instance]
+    }
+
+    /// Seed one machine's random number generator, see
+    /// `Emulator::seed_rng`.
+    ///
+    /// #### Parameters:
+    /// - instance: The machine to seed.
+    /// - seed: Any value, each seed gives a different sequence.
+    ///
+    /// #### Panics
+    /// - If there is no machine with that index.
+    ///
+    pub fn seed_rng(&mut self, instance: usize, seed: u64) {
+        self.rngs[instance] = Rng::new(seed);
+    }
+
+    /// Returns the keys of every machine, 16 per machine in machine order.
+    pub fn keys(&self) -> &[bool] {
+        self.keys.as_flattened()
+    }
+
+    /// Returns the keys of every machine for writing, laid out the same as
+    /// `keys`.
+    pub fn keys_mut(&mut self) -> &mut [bool] {
+        self.keys.as_flattened_mut()
+    }
+
+    /// Set which keys one machine has held down.
+    ///
+    /// #### Parameters:
+    /// - instance: The machine.
+    /// - keys: Bit N is set when key N is held.
+    ///
+    /// #### Panics
+    /// - If there is no machine with that index.
+    ///
+    pub fn set_keys(&mut self, instance: usize, keys: u16) {
+        for (key, pressed) in self.keys[instance].iter_mut().enumerate() {
+            *pressed = keys & (1 << key) != 0;
+        }
+    }
+
+    /// Returns the displays of every machine in machine order, each a row
+    /// major array of pixels the same as `Emulator::get_display`.
+    pub fn screens(&self) -> &[bool] {
+        self.screens.as_flattened()
+    }
+
+    /// Returns one machine's display as a row major array of pixels.
+    ///
+    /// #### Panics
+    /// - If there is no machine with that index.
+    ///
+    pub fn screen(&self, instance: usize) -> &[bool] {
+        &self.screens[instance]
+    }
+
+    /// Returns the number of CPU cycles run per frame.
+    pub fn ticks_per_frame(&self) -> usize {
+        self.ticks_per_frame
+    }
+
+    /// Set the number of CPU cycles every machine runs per frame.
+    ///
+    /// #### Parameters:
+    /// - ticks: The number of cycles per frame.
+    ///
+    pub fn set_ticks_per_frame(&mut self, ticks: usize) {
+        self.ticks_per_frame = ticks;
+    }
+
+    /// Returns the interpreter behaviours being emulated.
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    /// Set the interpreter behaviours every machine emulates.
+    ///
+    /// #### Parameters:
+    /// - quirks: The behaviours, usually one of the `Quirks` presets.
+    ///
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    /// Copies one machine out into an emulator, e.g. to inspect it or to
+    /// keep playing it on its own.
+    ///
+    /// #### Panics
+    /// - If there is no machine with that index.
+    ///
+    pub fn emulator(&self, instance: usize) -> Emulator {
+        Emulator {
+            program_counter: self.program_counters[instance],
+            ram: self.ram[instance],
+            screen: self.screens[instance],
+            registers: self.registers[instance],
+            i_register: self.i_registers[instance],
+            stack_pointer: self.stack_pointers[instance],
+            stack: self.stacks[instance],
+            keys: self.keys[instance],
+            delay_timer: self.delay_timers[instance],
+            sound_timer: self.sound_timers[instance],
+            ticks_per_frame: self.ticks_per_frame,
+            quirks: self.quirks,
+            waiting_for_frame: self.waiting_for_frame[instance],
+            rng: self.rngs[instance],
+            fault: self.faults[instance],
+            rom_database: RomDatabase::embedded(),
+            rom_info: None,
+        }
+    }
+
+    /// Replace one machine with a copy of an emulator's state. The
+    /// emulator's tick rate and quirks are ignored in favour of the
+    /// batch's.
+    ///
+    /// #### Parameters:
+    /// - instance: The machine to replace.
+    /// - emulator: The state to copy.
+    ///
+    /// #### Panics
+    /// - If there is no machine with that index.
+    ///
+    pub fn set_emulator(&mut self, instance: usize, emulator: &Emulator) {
+        self.program_counters[instance] = emulator.program_counter;
+        self.ram[instance] = emulator.ram;
+        self.screens[instance] = emulator.screen;
+        self.registers[instance] = emulator.registers;
+        self.i_registers[instance] = emulator.i_register;
+        self.stack_pointers[instance] = emulator.stack_pointer;
+        self.stacks[instance] = emulator.stack;
+        self.keys[instance] = emulator.keys;
+        self.delay_timers[instance] = emulator.delay_timer;
+        self.sound_timers[instance] = emulator.sound_timer;
+        self.waiting_for_frame[instance] = emulator.waiting_for_frame;
+        self.rngs[instance] = emulator.rng;
+        self.faults[instance] = emulator.fault;
+    }
+
+    /// Runs one CPU cycle on every machine, the same as `Emulator::tick`.
+    pub fn tick(&mut self) {
+        for mut cpu in self.cpus() {
+            cpu.tick();
+        }
+    }
+
+    /// Ticks the timers of every machine, the same as
+    /// `Emulator::timer_tick`.
+    pub fn timer_tick(&mut self) {
+        for mut cpu in self.cpus() {
+            cpu.timer_tick();
+        }
+    }
+
+    /// Emulates one 60 Hz frame on every machine, the same as
+    /// `Emulator::run_frame`.
+    pub fn run_frame(&mut self) {
+        let ticks = self.ticks_per_frame;
+        for mut cpu in self.cpus() {
+            run_frame(&mut cpu, ticks);
+        }
+    }
+
+    /// Emulates one 60 Hz frame on every machine, spread over the rayon
+    /// thread pool. The result is the same as `run_frame`.
+    #[cfg(feature = "rayon")]
+    pub fn par_run_frame(&mut self) {
+        use rayon::prelude::*;
+
+        let ticks = self.ticks_per_frame;
+        let cpus: Vec<Cpu> = self.cpus().collect();
+        cpus.into_par_iter()
+            .for_each(|mut cpu| run_frame(&mut cpu, ticks));
+    }
+
+    /// Borrows each machine's state in turn for running instructions.
+    fn cpus(&mut self) -> impl Iterator<Item = Cpu<'_>> {
+        let quirks = self.quirks;
+        let mut program_counters = self.program_counters.iter_mut();
+        let mut ram = self.ram.iter_mut();
+        let mut screens = self.screens.iter_mut();
+        let mut registers = self.registers.iter_mut();
+        let mut i_registers = self.i_registers.iter_mut();
+        let mut stack_pointers = self.stack_pointers.iter_mut();
+        let mut stacks = self.stacks.iter_mut();
+        let mut keys = self.keys.iter();
+        let mut delay_timers = self.delay_timers.iter_mut();
+        let mut sound_timers = self.sound_timers.iter_mut();
+        let mut waiting_for_frame = self.waiting_for_frame.iter_mut();
+        let mut rngs = self.rngs.iter_mut();
+        let mut faults = self.faults.iter_mut();
+        std::iter::from_fn(move || {
+            Some(Cpu {
+                program_counter: program_counters.next()?,
+                ram: ram.next()?,
+                screen: screens.next()?,
+                registers: registers.next()?,
+                i_register: i_registers.next()?,
+                stack_pointer: stack_pointers.next()?,
+                stack: stacks.next()?,
+                keys: keys.next()?,
+                delay_timer: delay_timers.next()?,
+                sound_timer: sound_timers.next()?,
+                quirks,
+                waiting_for_frame: waiting_for_frame.next()?,
+                rng: rngs.next()?,
+                fault: faults.next()?,
+                observer: (),
+            })
+        })
+    }
+}
+
+/// Emulates one frame on a machine, the same as `Emulator::run_frame`.
+///
+/// #### Parameters:
+/// - cpu: The machine's state.
+/// - ticks: The number of CPU cycles in the frame.
+///
+fn run_frame(cpu: &mut Cpu, ticks: usize) {
+    for _ in 0..ticks {
+        cpu.tick();
+    }
+    cpu.timer_tick();
+}
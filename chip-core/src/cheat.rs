@@ -0,0 +1,268 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::error::{MemoryError, ParseError};
+use crate::hash::parse_sha1;
+use crate::{Emulator, NUM_REGS, RAM_SIZE};
+
+/// How a RAM search narrows down its candidate addresses, comparing the
+/// current RAM against the snapshot taken by the previous search step.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SearchFilter {
+    /// The byte currently holds this value.
+    Equal(u8),
+    /// The byte is different from the snapshot.
+    Changed,
+    /// The byte is the same as the snapshot.
+    Unchanged,
+    /// The byte is larger than in the snapshot.
+    Increased,
+    /// The byte is smaller than in the snapshot.
+    Decreased,
+}
+
+impl SearchFilter {
+    /// Whether an address with the old and new values stays a candidate.
+    fn matches(&self, old: u8, new: u8) -> bool {
+        match self {
+            SearchFilter::Equal(value) => new == *value,
+            SearchFilter::Changed => new != old,
+            SearchFilter::Unchanged => new == old,
+            SearchFilter::Increased => new > old,
+            SearchFilter::Decreased => new < old,
+        }
+    }
+}
+
+/// Classic cheat finder: starts with every RAM address as a candidate and
+/// narrows them down one filter at a time, e.g. take a snapshot, lose a
+/// life, filter by `Decreased`, repeat until only the lives counter is left.
+pub struct RamSearch {
+    /// RAM at the last search step.
+    snapshot: [u8; RAM_SIZE],
+    /// Addresses that matched every filter so far.
+    candidates: Vec<usize>,
+}
+
+impl RamSearch {
+    /// Starts a new search with every address as a candidate.
+    ///
+    /// #### Parameters:
+    /// - emulator: The emulator to take the first snapshot from.
+    ///
+    pub fn new(emulator: &Emulator) -> Self {
+        Self {
+            snapshot: emulator.ram,
+            candidates: (0..RAM_SIZE).collect(),
+        }
+    }
+
+    /// Keeps only the candidates matching the filter and takes a new
+    /// snapshot for the next step.
+    ///
+    /// #### Parameters:
+    /// - emulator: The emulator to compare against the snapshot.
+    /// - filter: The condition candidates must meet.
+    ///
+    /// #### Returns:
+    /// - The number of candidates left.
+    ///
+    pub fn filter(&mut self, emulator: &Emulator, filter: SearchFilter) -> usize {
+        let ram = &emulator.ram;
+        let snapshot = &self.snapshot;
+        self.candidates
+            .retain(|address| filter.matches(snapshot[*address], ram[*address]));
+        self.snapshot = *ram;
+        self.candidates.len()
+    }
+
+    /// Returns the addresses still matching every filter.
+    pub fn candidates(&self) -> &[usize] {
+        &self.candidates
+    }
+
+    /// Returns the value a candidate had in the last snapshot.
+    pub fn snapshot_value(&self, address: usize) -> Option<u8> {
+        self.snapshot.get(address).copied()
+    }
+}
+
+/// What a cheat holds at a fixed value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CheatTarget {
+    /// A RAM address.
+    Ram(u16),
+    /// A V register.
+    Register(u8),
+}
+
+/// A freeze code: the target is set back to the value every frame.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cheat {
+    /// Where the value is written.
+    pub target: CheatTarget,
+    /// The value to hold.
+    pub value: u8,
+    /// Disabled cheats are kept in the file but not applied.
+    pub enabled: bool,
+    /// Free text description, e.g. "Infinite lives".
+    pub description: String,
+}
+
+impl Cheat {
+    /// Writes the value to the target.
+    fn apply(&self, emulator: &mut Emulator) -> Result<(), MemoryError> {
+        match self.target {
+            CheatTarget::Ram(address) => emulator.write_ram(address as usize, &[self.value]),
+            CheatTarget::Register(idx) => emulator.set_register(idx as usize, self.value),
+        }
+    }
+}
+
+/// Cheats for any number of ROMs, keyed by the SHA-1 of the ROM
+/// (`hash::sha1`) like the ROM database and keymaps.
+///
+/// The text format has one section per ROM, headed by the hash in square
+/// brackets, followed by one cheat per line: `on` or `off`, the target and
+/// value joined by `=` and an optional description. Targets are a RAM
+/// address or a V register, all numbers are hexadecimal. Blank lines and
+/// lines starting with `#` are ignored.
+///
+/// ```text
+/// # Space Invaders
+/// [0d3a1a2b3c4d5e6f708192a3b4c5d6e7f8091a2b]
+/// on 02F0=09 Infinite lives
+/// off VE=00 No enemy fire
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CheatDatabase {
+    roms: BTreeMap<[u8; 20], Vec<Cheat>>,
+}
+
+impl CheatDatabase {
+    /// Creates an empty database.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the cheats for a ROM.
+    pub fn cheats(&self, rom_hash: &[u8; 20]) -> &[Cheat] {
+        self.roms
+            .get(rom_hash)
+            .map_or(&[], |cheats| cheats.as_slice())
+    }
+
+    /// Returns the cheats for a ROM for editing, creating an empty list if
+    /// the ROM has none yet.
+    pub fn cheats_mut(&mut self, rom_hash: &[u8; 20]) -> &mut Vec<Cheat> {
+        self.roms.entry(*rom_hash).or_default()
+    }
+
+    /// Writes every enabled cheat of a ROM into the emulator. Should be
+    /// called once per frame to keep the values frozen.
+    ///
+    /// #### Parameters:
+    /// - rom_hash: The SHA-1 of the running ROM.
+    /// - emulator: The emulator to modify.
+    ///
+    /// #### Returns:
+    /// - An error if a cheat targets memory that doesn't exist, the cheats
+    ///   before it are still applied.
+    ///
+    pub fn apply(&self, rom_hash: &[u8; 20], emulator: &mut Emulator) -> Result<(), MemoryError> {
+        self.cheats(rom_hash)
+            .iter()
+            .filter(|cheat| cheat.enabled)
+            .try_for_each(|cheat| cheat.apply(emulator))
+    }
+
+    /// Parses a cheat file.
+    ///
+    /// #### Parameters:
+    /// - text: The file contents.
+    ///
+    /// #### Returns:
+    /// - The database, or the first malformed line.
+    ///
+    pub fn parse(text: &str) -> Result<Self, ParseError> {
+        let mut database = Self::new();
+        let mut section = None;
+
+        for (idx, line) in text.lines().enumerate() {
+            let error = |reason| ParseError {
+                line: idx + 1,
+                reason,
+            };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if let Some(hash) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                let hash = parse_sha1(hash).ok_or(error("invalid ROM hash"))?;
+                database.roms.entry(hash).or_default();
+                section = Some(hash);
+                continue;
+            }
+
+            let hash = section.ok_or(error("cheat before the first ROM section"))?;
+            let (state, rest) = line.split_once(' ').ok_or(error("missing cheat"))?;
+            let enabled = match state {
+                "on" => true,
+                "off" => false,
+                _ => return Err(error("expected `on` or `off`")),
+            };
+            let rest = rest.trim_start();
+            let (code, description) = rest.split_once(' ').unwrap_or((rest, ""));
+            let (target, value) = code
+                .split_once('=')
+                .ok_or(error("expected `target=value`"))?;
+
+            let target = match target.strip_prefix(['V', 'v']) {
+                Some(idx) => match u8::from_str_radix(idx, 16) {
+                    Ok(idx) if (idx as usize) < NUM_REGS => CheatTarget::Register(idx),
+                    _ => return Err(error("invalid register")),
+                },
+                None => match u16::from_str_radix(target, 16) {
+                    Ok(address) if (address as usize) < RAM_SIZE => CheatTarget::Ram(address),
+                    _ => return Err(error("invalid RAM address")),
+                },
+            };
+            let value = u8::from_str_radix(value, 16).map_err(|_| error("invalid value"))?;
+
+            database.cheats_mut(&hash).push(Cheat {
+                target,
+                value,
+                enabled,
+                description: description.trim().to_string(),
+            });
+        }
+        Ok(database)
+    }
+
+    /// Formats the database in the text format read by `parse`.
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        // Writing to a String can't fail so the results are ignored.
+        for (hash, cheats) in &self.roms {
+            text.push('[');
+            for byte in hash {
+                let _ = write!(text, "{:02x}", byte);
+            }
+            text.push_str("]\n");
+            for cheat in cheats {
+                let state = if cheat.enabled { "on" } else { "off" };
+                let _ = match cheat.target {
+                    CheatTarget::Ram(address) => write!(text, "{} {:04X}", state, address),
+                    CheatTarget::Register(idx) => write!(text, "{} V{:X}", state, idx),
+                };
+                let _ = write!(text, "={:02X}", cheat.value);
+                if !cheat.description.is_empty() {
+                    let _ = write!(text, " {}", cheat.description);
+                }
+                text.push('\n');
+            }
+        }
+        text
+    }
+}
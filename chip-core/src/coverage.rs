@@ -0,0 +1,245 @@
+use std::fmt::Write;
+
+use crate::error::ParseError;
+use crate::json::{Document, Value};
+use crate::observer::Observer;
+use crate::{Emulator, ADDRESS_MASK, RAM_SIZE};
+
+/// Bytes per row of the hex dump.
+const DUMP_ROW: usize = 16;
+
+/// How a byte of RAM has been used.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Access {
+    /// Fetched as part of an instruction.
+    pub executed: bool,
+    /// Read as data, a sprite row for DXYN or a value for FX65.
+    pub read: bool,
+    /// Written by FX33 or FX55.
+    pub written: bool,
+}
+
+impl Access {
+    /// Returns whether the byte was never used.
+    pub fn untouched(&self) -> bool {
+        !(self.executed || self.read || self.written)
+    }
+
+    fn mark_executed(&mut self) {
+        self.executed = true;
+    }
+
+    fn mark_read(&mut self) {
+        self.read = true;
+    }
+
+    fn mark_written(&mut self) {
+        self.written = true;
+    }
+
+    /// The hex dump's marker for the access: `x` executed, `r` read, `w`
+    /// written, `*` more than one and `.` untouched.
+    fn marker(&self) -> char {
+        match (self.executed, self.read, self.written) {
+            (false, false, false) => '.',
+            (true, false, false) => 'x',
+            (false, true, false) => 'r',
+            (false, false, true) => 'w',
+            _ => '*',
+        }
+    }
+}
+
+/// Tags every byte of RAM with how it was used, as an observer passed to
+/// `Emulator::run_frame_observed`. Coverage from several runs can be saved
+/// as JSON and merged, to see what a whole test session reached.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Coverage {
+    access: Vec<Access>,
+}
+
+impl Coverage {
+    /// Constructor, with every byte untouched.
+    pub fn new() -> Self {
+        Self {
+            access: vec![Access::default(); RAM_SIZE],
+        }
+    }
+
+    /// Returns how a byte has been used.
+    ///
+    /// #### Parameters:
+    /// - address: The RAM address.
+    ///
+    pub fn access(&self, address: u16) -> Access {
+        self.access[(address & ADDRESS_MASK) as usize]
+    }
+
+    /// Adds the coverage of another run.
+    ///
+    /// #### Parameters:
+    /// - other: The coverage to add.
+    ///
+    pub fn merge(&mut self, other: &Coverage) {
+        for (access, other) in self.access.iter_mut().zip(&other.access) {
+            access.executed |= other.executed;
+            access.read |= other.read;
+            access.written |= other.written;
+        }
+    }
+
+    /// Saves the coverage as JSON: the RAM size and, for each kind of
+    /// access, the `[start, end)` address ranges with it.
+    ///
+    /// ```json
+    /// {"ram_size":4096,"executed":[[512,540]],"read":[[0,5],[768,771]],"written":[[768,771]]}
+    /// ```
+    pub fn to_json(&self) -> String {
+        let ranges = |kind: fn(&Access) -> bool| {
+            let mut ranges = Vec::new();
+            let mut start = None;
+            for address in 0..=RAM_SIZE {
+                let covered = address < RAM_SIZE && kind(&self.access[address]);
+                match (covered, start) {
+                    (true, None) => start = Some(address),
+                    (false, Some(first)) => {
+                        ranges.push(Value::Array(vec![
+                            Value::Number(first as f64),
+                            Value::Number(address as f64),
+                        ]));
+                        start = None;
+                    }
+                    _ => (),
+                }
+            }
+            Value::Array(ranges)
+        };
+        let json = Value::Object(vec![
+            ("ram_size".to_string(), Value::Number(RAM_SIZE as f64)),
+            ("executed".to_string(), ranges(|access| access.executed)),
+            ("read".to_string(), ranges(|access| access.read)),
+            ("written".to_string(), ranges(|access| access.written)),
+        ]);
+        format!("{}\n", json)
+    }
+
+    /// Loads coverage saved by `to_json`.
+    ///
+    /// #### Parameters:
+    /// - text: The JSON.
+    ///
+    /// #### Returns:
+    /// - The coverage, or an error if the JSON is invalid or doesn't
+    ///   describe coverage of this emulator's RAM.
+    ///
+    pub fn from_json(text: &str) -> Result<Self, ParseError> {
+        let document = Document::parse(text)?;
+        let json = &document.value;
+        let invalid = |value, reason| ParseError {
+            line: document.line(value),
+            reason,
+        };
+        match json.get("ram_size") {
+            Some(size) if size.as_u64() == Some(RAM_SIZE as u64) => (),
+            size => {
+                return Err(invalid(
+                    size.unwrap_or(json),
+                    "ram_size doesn't match the emulator's RAM",
+                ))
+            }
+        }
+        let mut coverage = Coverage::new();
+        for (key, mark) in [
+            ("executed", Access::mark_executed as fn(&mut Access)),
+            ("read", Access::mark_read),
+            ("written", Access::mark_written),
+        ] {
+            let ranges = json.get(key).and_then(Value::as_array).ok_or_else(|| {
+                invalid(
+                    json.get(key).unwrap_or(json),
+                    "missing a list of address ranges",
+                )
+            })?;
+            for range in ranges {
+                let bounds: Option<Vec<u64>> = range
+                    .as_array()
+                    .map(|bounds| bounds.iter().map(Value::as_u64).collect())
+                    .unwrap_or(None);
+                let (start, end) = match bounds.as_deref() {
+                    Some(&[start, end]) if start < end && end <= RAM_SIZE as u64 => {
+                        (start as usize, end as usize)
+                    }
+                    _ => return Err(invalid(range, "invalid address range")),
+                };
+                coverage.access[start..end].iter_mut().for_each(mark);
+            }
+        }
+        Ok(coverage)
+    }
+
+    /// Formats the RAM as a hex dump with each byte's access marked beside
+    /// it (see `Access`). Runs of rows that are all zero and untouched are
+    /// left out and shown as `*`.
+    ///
+    /// #### Parameters:
+    /// - emulator: The emulator whose RAM is dumped.
+    ///
+    pub fn to_hex_dump(&self, emulator: &Emulator) -> String {
+        let ram = &emulator.ram;
+        let count = |kind: fn(&Access) -> bool| self.access.iter().filter(|a| kind(a)).count();
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "; {} bytes executed, {} read, {} written, {} untouched",
+            count(|access| access.executed),
+            count(|access| access.read),
+            count(|access| access.written),
+            count(Access::untouched)
+        );
+        let _ = writeln!(
+            out,
+            "; x executed, r read, w written, * more than one, . untouched"
+        );
+        let mut skipping = false;
+        for row in (0..RAM_SIZE).step_by(DUMP_ROW) {
+            let bytes = &ram[row..row + DUMP_ROW];
+            let access = &self.access[row..row + DUMP_ROW];
+            if bytes.iter().all(|byte| *byte == 0) && access.iter().all(Access::untouched) {
+                if !skipping {
+                    let _ = writeln!(out, "*");
+                    skipping = true;
+                }
+                continue;
+            }
+            skipping = false;
+            let _ = write!(out, "{:03X}: ", row);
+            for byte in bytes {
+                let _ = write!(out, " {:02X}", byte);
+            }
+            let markers: String = access.iter().map(Access::marker).collect();
+            let _ = writeln!(out, "  {}", markers);
+        }
+        out
+    }
+}
+
+impl Default for Coverage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Observer for Coverage {
+    fn on_fetch(&mut self, address: u16, _opcode: u16) {
+        self.access[address as usize].executed = true;
+        self.access[((address + 1) & ADDRESS_MASK) as usize].executed = true;
+    }
+
+    fn on_ram_read(&mut self, address: u16, _value: u8) {
+        self.access[address as usize].read = true;
+    }
+
+    fn on_ram_write(&mut self, address: u16, _value: u8) {
+        self.access[address as usize].written = true;
+    }
+}
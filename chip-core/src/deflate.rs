@@ -0,0 +1,161 @@
+use crate::hash::adler32;
+
+/// Shortest back reference worth encoding.
+const MIN_MATCH: usize = 3;
+/// Longest back reference the format allows.
+const MAX_MATCH: usize = 258;
+/// Largest distance a back reference can reach.
+const MAX_DISTANCE: usize = 32768;
+
+/// Base match length of each length code (257 to 285).
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+/// Number of extra bits following each length code.
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+/// Base distance of each distance code (0 to 29).
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+/// Number of extra bits following each distance code.
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+/// Packs values into a byte stream least significant bit first, as
+/// required by DEFLATE.
+struct BitWriter {
+    out: Vec<u8>,
+    buffer: u32,
+    count: u32,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        Self {
+            out: Vec::new(),
+            buffer: 0,
+            count: 0,
+        }
+    }
+
+    /// Writes the lowest `count` bits of `value`.
+    fn write_bits(&mut self, value: u32, count: u32) {
+        self.buffer |= value << self.count;
+        self.count += count;
+        while self.count >= 8 {
+            self.out.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// Writes a Huffman code, which DEFLATE stores most significant bit
+    /// first unlike every other field.
+    fn write_code(&mut self, code: u32, len: u32) {
+        let reversed = code.reverse_bits() >> (32 - len);
+        self.write_bits(reversed, len);
+    }
+
+    /// Flushes any partial byte and returns the stream.
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.out.push(self.buffer as u8);
+        }
+        self.out
+    }
+}
+
+/// Writes a literal/length symbol using the fixed Huffman table.
+fn write_symbol(writer: &mut BitWriter, symbol: u16) {
+    let symbol = symbol as u32;
+    match symbol {
+        0..=143 => writer.write_code(0x30 + symbol, 8),
+        144..=255 => writer.write_code(0x190 + symbol - 144, 9),
+        256..=279 => writer.write_code(symbol - 256, 7),
+        _ => writer.write_code(0xC0 + symbol - 280, 8),
+    }
+}
+
+/// Writes a back reference of `length` bytes, `distance` bytes back.
+fn write_match(writer: &mut BitWriter, length: usize, distance: usize) {
+    let code = LENGTH_BASE
+        .iter()
+        .rposition(|base| *base as usize <= length)
+        .unwrap();
+    write_symbol(writer, 257 + code as u16);
+    writer.write_bits(
+        (length - LENGTH_BASE[code] as usize) as u32,
+        LENGTH_EXTRA[code] as u32,
+    );
+
+    let code = DISTANCE_BASE
+        .iter()
+        .rposition(|base| *base as usize <= distance)
+        .unwrap();
+    writer.write_code(code as u32, 5);
+    writer.write_bits(
+        (distance - DISTANCE_BASE[code] as usize) as u32,
+        DISTANCE_EXTRA[code] as u32,
+    );
+}
+
+/// Compresses data into a zlib stream using a single fixed Huffman block.
+///
+/// Rather than a general purpose match finder, only two candidate
+/// distances are tried: the previous byte and the previous row. Upscaled
+/// pixel art is almost entirely runs and repeated rows, so this gets close
+/// to a full encoder at a fraction of the complexity.
+///
+/// #### Parameters:
+/// - data: The bytes to compress.
+/// - stride: The row length of the image data in bytes.
+///
+/// #### Returns:
+/// - The zlib stream (header, DEFLATE data and Adler-32 trailer).
+///
+pub fn zlib_compress(data: &[u8], stride: usize) -> Vec<u8> {
+    let mut writer = BitWriter::new();
+    // BFINAL = 1, BTYPE = 01 (fixed Huffman codes).
+    writer.write_bits(1, 1);
+    writer.write_bits(1, 2);
+
+    let mut pos = 0;
+    while pos < data.len() {
+        let max_len = MAX_MATCH.min(data.len() - pos);
+        let mut best = (0, 0);
+        for distance in [1, stride] {
+            if distance == 0 || distance > pos || distance > MAX_DISTANCE {
+                continue;
+            }
+            let len = (0..max_len)
+                .take_while(|i| data[pos + i] == data[pos + i - distance])
+                .count();
+            if len > best.0 {
+                best = (len, distance);
+            }
+        }
+
+        if best.0 >= MIN_MATCH {
+            write_match(&mut writer, best.0, best.1);
+            pos += best.0;
+        } else {
+            write_symbol(&mut writer, data[pos] as u16);
+            pos += 1;
+        }
+    }
+    // End of block.
+    write_symbol(&mut writer, 256);
+
+    // CMF: deflate with a 32K window, FLG: no dictionary and a check value
+    // making the header a multiple of 31.
+    let mut out = vec![0x78, 0x01];
+    out.extend(writer.finish());
+    out.extend(adler32(data).to_be_bytes());
+    out
+}
@@ -0,0 +1,109 @@
+use crate::{Emulator, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+/// Maximum number of colours in a palette. Four bitplanes give sixteen
+/// distinct pixel values, which covers every planned display mode.
+pub const PALETTE_SIZE: usize = 16;
+
+/// A 24-bit RGB colour.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    /// Constructor.
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+
+    /// Perceived brightness of the colour (ITU-R BT.601 weights), 0 to 255.
+    pub fn luma(&self) -> u8 {
+        ((self.r as u32 * 299 + self.g as u32 * 587 + self.b as u32 * 114) / 1000) as u8
+    }
+}
+
+/// Maps pixel values to colours. Index 0 is the background, index 1 is the
+/// foreground of a monochrome display and the remaining entries are used by
+/// bitplane displays where each plane contributes one bit of the index.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Palette {
+    colors: [Rgb; PALETTE_SIZE],
+}
+
+impl Palette {
+    /// Creates a two colour palette for monochrome displays.
+    ///
+    /// #### Parameters:
+    /// - background: The colour of unset pixels.
+    /// - foreground: The colour of set pixels.
+    ///
+    pub const fn monochrome(background: Rgb, foreground: Rgb) -> Self {
+        let mut colors = [foreground; PALETTE_SIZE];
+        colors[0] = background;
+        Self { colors }
+    }
+
+    /// Creates a palette from a list of colours. Missing entries repeat the
+    /// last colour given and extra entries are ignored.
+    ///
+    /// #### Parameters:
+    /// - colors: The colours in pixel value order.
+    ///
+    pub fn from_colors(colors: &[Rgb]) -> Self {
+        let mut palette = Self::default();
+        for idx in 0..PALETTE_SIZE {
+            if let Some(color) = colors.get(idx).or(colors.last()) {
+                palette.colors[idx] = *color;
+            }
+        }
+        palette
+    }
+
+    /// Returns the colour for a pixel value, values outside the palette wrap.
+    pub fn color(&self, index: usize) -> Rgb {
+        self.colors[index % PALETTE_SIZE]
+    }
+
+    /// Returns all palette entries.
+    pub fn colors(&self) -> &[Rgb; PALETTE_SIZE] {
+        &self.colors
+    }
+}
+
+impl Default for Palette {
+    /// White pixels on a black background.
+    fn default() -> Self {
+        Self::monochrome(Rgb::new(0, 0, 0), Rgb::new(0xFF, 0xFF, 0xFF))
+    }
+}
+
+/// A display surface that can be exported or rendered. The monochrome
+/// CHIP-8 screen is one implementation, hi-res and bitplane displays only
+/// need to report their own dimensions and pixel values.
+pub trait Framebuffer {
+    /// Display width in pixels.
+    fn width(&self) -> usize;
+
+    /// Display height in pixels.
+    fn height(&self) -> usize;
+
+    /// Returns the palette index of the pixel at `(x, y)`. Monochrome
+    /// displays return 0 or 1, bitplane displays combine one bit per plane.
+    fn pixel(&self, x: usize, y: usize) -> usize;
+}
+
+impl Framebuffer for Emulator {
+    fn width(&self) -> usize {
+        SCREEN_WIDTH
+    }
+
+    fn height(&self) -> usize {
+        SCREEN_HEIGHT
+    }
+
+    fn pixel(&self, x: usize, y: usize) -> usize {
+        self.screen[x + SCREEN_WIDTH * y] as usize
+    }
+}
@@ -0,0 +1,500 @@
+use crate::error::{EnvError, ParseError};
+use crate::quirks::Quirks;
+use crate::rng::Rng;
+use crate::{
+    Emulator, DEFAULT_TICKS_PER_FRAME, NUM_KEYS, NUM_REGS, RAM_SIZE, SCREEN_HEIGHT, SCREEN_WIDTH,
+};
+
+/// The most digits a BCD value can have, so it fits in a `u32`.
+const MAX_BCD_DIGITS: usize = 9;
+
+/// A number read from the machine, such as a score or a lives counter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Source {
+    /// The byte at a RAM address.
+    Ram(u16),
+    /// A V register.
+    Register(u8),
+    /// Decimal digits stored one per byte starting at an address, most
+    /// significant first, the way FX33 stores them.
+    Bcd { address: u16, digits: u8 },
+}
+
+impl Source {
+    /// Whether the source can be read: the address is in RAM, the register
+    /// exists and a BCD value has 1 to 9 digits, all in RAM.
+    pub fn is_valid(&self) -> bool {
+        match *self {
+            Source::Ram(address) => (address as usize) < RAM_SIZE,
+            Source::Register(idx) => (idx as usize) < NUM_REGS,
+            Source::Bcd { address, digits } => {
+                (1..=MAX_BCD_DIGITS).contains(&(digits as usize))
+                    && address as usize + digits as usize <= RAM_SIZE
+            }
+        }
+    }
+
+    /// Reads the current value. The source must be valid.
+    fn read(&self, emulator: &Emulator) -> u32 {
+        match *self {
+            Source::Ram(address) => emulator.ram[address as usize] as u32,
+            Source::Register(idx) => emulator.registers[idx as usize] as u32,
+            Source::Bcd { address, digits } => {
+                let start = address as usize;
+                // Bytes that aren't decimal digits, such as RAM the game
+                // hasn't written yet, count as 0.
+                emulator.ram[start..start + digits as usize]
+                    .iter()
+                    .map(|digit| if *digit <= 9 { *digit as u32 } else { 0 })
+                    .fold(0, |value, digit| value * 10 + digit)
+            }
+        }
+    }
+}
+
+/// How a `Condition` compares its source against the value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+/// Ends an episode when a value read from the machine compares true.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Condition {
+    /// Where the value is read from.
+    pub source: Source,
+    /// How the value read is compared.
+    pub comparison: Comparison,
+    /// The value compared against.
+    pub value: u32,
+}
+
+impl Condition {
+    /// Whether the condition currently holds.
+    fn holds(&self, emulator: &Emulator) -> bool {
+        let current = self.source.read(emulator);
+        match self.comparison {
+            Comparison::Equal => current == self.value,
+            Comparison::NotEqual => current != self.value,
+            Comparison::Less => current < self.value,
+            Comparison::LessOrEqual => current <= self.value,
+            Comparison::Greater => current > self.value,
+            Comparison::GreaterOrEqual => current >= self.value,
+        }
+    }
+}
+
+/// Part of the reward: how much a value read from the machine changed
+/// since the last frame, multiplied by a weight.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Reward {
+    /// Where the value is read from.
+    pub source: Source,
+    /// Multiplies the change, negative for things like lost lives. Must be
+    /// finite.
+    pub weight: f32,
+}
+
+/// How to play one game as an environment: what the actions are, how
+/// rewards are counted and when an episode ends.
+///
+/// The text format has one setting per line, written `name = value`. All
+/// settings are optional and `action`, `reward` and `done` can be given
+/// more than once. Addresses and registers are hexadecimal like in cheat
+/// files, every other number is decimal. Blank lines and lines starting
+/// with `#` are ignored.
+///
+/// - `ticks_per_frame`: CPU cycles per frame.
+/// - `quirks`: `default`, `chip8`, `schip` or `xochip`.
+/// - `frame_skip`: frames each step repeats the action for.
+/// - `sticky`: the chance (0 to 1) that a frame repeats the previous
+///   action instead of the new one.
+/// - `max_frames`: frames after which an episode always ends.
+/// - `action`: the keys held by the next action, or `none`. Without any,
+///   action 0 holds no key and action N + 1 holds key N.
+/// - `reward`: a source and an optional `* weight`, the reward is the
+///   weighted sum of how much each source increased.
+/// - `done`: a source, a comparison (`==`, `!=`, `<`, `<=`, `>`, `>=`)
+///   and a value, the episode ends when any of them holds. It also ends
+///   when the ROM stops with an error.
+///
+/// A source is a RAM address, a V register or `bcd` with an address and
+/// a number of digits:
+///
+/// ```text
+/// # Brix: 3 digit score at 02F0, lives in VE.
+/// quirks = chip8
+/// frame_skip = 4
+/// action = none
+/// action = 4
+/// action = 6
+/// reward = bcd 02F0 3
+/// reward = VE * 10
+/// done = VE == 0
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct EnvConfig {
+    /// Number of CPU cycles run per frame.
+    pub ticks_per_frame: usize,
+    /// The interpreter behaviours the game needs.
+    pub quirks: Quirks,
+    /// Frames each step repeats the action for.
+    pub frame_skip: usize,
+    /// The chance that a frame repeats the previous action.
+    pub sticky: f32,
+    /// Frames after which an episode always ends.
+    pub max_frames: Option<usize>,
+    /// The keys held by each action, as a bit mask (bit N is key N).
+    pub actions: Vec<u16>,
+    /// Summed to give the reward.
+    pub rewards: Vec<Reward>,
+    /// The episode ends when any of these holds.
+    pub done: Vec<Condition>,
+}
+
+impl Default for EnvConfig {
+    fn default() -> Self {
+        Self {
+            ticks_per_frame: DEFAULT_TICKS_PER_FRAME,
+            quirks: Quirks::default(),
+            frame_skip: 1,
+            sticky: 0.0,
+            max_frames: None,
+            actions: Self::default_actions(),
+            rewards: Vec::new(),
+            done: Vec::new(),
+        }
+    }
+}
+
+impl EnvConfig {
+    /// No key, then each key on its own.
+    fn default_actions() -> Vec<u16> {
+        std::iter::once(0)
+            .chain((0..NUM_KEYS).map(|key| 1 << key))
+            .collect()
+    }
+
+    /// Parses a config file.
+    ///
+    /// #### Parameters:
+    /// - text: The file contents.
+    ///
+    /// #### Returns:
+    /// - The config, or the first malformed line.
+    ///
+    pub fn parse(text: &str) -> Result<Self, ParseError> {
+        let mut config = Self {
+            actions: Vec::new(),
+            ..Self::default()
+        };
+
+        for (idx, line) in text.lines().enumerate() {
+            let error = |reason| ParseError {
+                line: idx + 1,
+                reason,
+            };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (name, value) = line
+                .split_once('=')
+                .ok_or(error("expected `name = value`"))?;
+            let value = value.trim();
+            let number = |reason| value.parse::<usize>().map_err(|_| error(reason));
+            match name.trim() {
+                "ticks_per_frame" => config.ticks_per_frame = number("invalid tick count")?,
+                "quirks" => {
+                    config.quirks = match value {
+                        "default" => Quirks::default(),
+                        "chip8" => Quirks::chip8(),
+                        "schip" => Quirks::schip(),
+                        "xochip" => Quirks::xochip(),
+                        _ => return Err(error("unknown quirks preset")),
+                    }
+                }
+                "frame_skip" => match number("invalid frame skip")? {
+                    0 => return Err(error("frame skip must be at least 1")),
+                    frames => config.frame_skip = frames,
+                },
+                "sticky" => match value.parse::<f32>() {
+                    Ok(chance) if (0.0..=1.0).contains(&chance) => config.sticky = chance,
+                    _ => return Err(error("sticky must be between 0 and 1")),
+                },
+                "max_frames" => config.max_frames = Some(number("invalid frame count")?),
+                "action" => config
+                    .actions
+                    .push(parse_action(value).ok_or(error("invalid key"))?),
+                "reward" => {
+                    let (source, weight) = match value.split_once('*') {
+                        Some((source, weight)) => match weight.trim().parse::<f32>() {
+                            Ok(weight) if weight.is_finite() => (source, weight),
+                            _ => return Err(error("invalid weight")),
+                        },
+                        None => (value, 1.0),
+                    };
+                    let source = parse_source(source).ok_or(error("invalid source"))?;
+                    config.rewards.push(Reward { source, weight });
+                }
+                "done" => {
+                    let words: Vec<&str> = value.split_whitespace().collect();
+                    let condition = match words[..] {
+                        [ref source @ .., comparison, value] if !source.is_empty() => Condition {
+                            source: parse_source(&source.join(" "))
+                                .ok_or(error("invalid source"))?,
+                            comparison: parse_comparison(comparison)
+                                .ok_or(error("invalid comparison"))?,
+                            value: value.parse().map_err(|_| error("invalid value"))?,
+                        },
+                        _ => return Err(error("expected `source comparison value`")),
+                    };
+                    config.done.push(condition);
+                }
+                _ => return Err(error("unknown setting")),
+            }
+        }
+
+        if config.actions.is_empty() {
+            config.actions = Self::default_actions();
+        }
+        Ok(config)
+    }
+
+    /// Checks the settings `parse` checks, for configs built in code.
+    fn validate(&self) -> Result<(), EnvError> {
+        let error = |reason| Err(EnvError::InvalidConfig(reason));
+        if self.frame_skip == 0 {
+            return error("frame skip must be at least 1");
+        }
+        if !(0.0..=1.0).contains(&self.sticky) {
+            return error("sticky must be between 0 and 1");
+        }
+        if self.actions.is_empty() {
+            return error("there must be at least one action");
+        }
+        let mut sources = self.rewards.iter().map(|term| term.source);
+        if !sources.all(|source| source.is_valid()) {
+            return error("invalid reward source");
+        }
+        // One NaN weight would make every reward NaN.
+        if !self.rewards.iter().all(|term| term.weight.is_finite()) {
+            return error("reward weights must be finite");
+        }
+        let mut sources = self.done.iter().map(|condition| condition.source);
+        if !sources.all(|source| source.is_valid()) {
+            return error("invalid done source");
+        }
+        Ok(())
+    }
+}
+
+/// Parses the keys held by an action.
+fn parse_action(text: &str) -> Option<u16> {
+    if text == "none" {
+        return Some(0);
+    }
+    text.split_whitespace()
+        .try_fold(0, |keys, key| match u8::from_str_radix(key, 16) {
+            Ok(key) if (key as usize) < NUM_KEYS => Some(keys | 1 << key),
+            _ => None,
+        })
+}
+
+/// Parses a RAM address, a V register or a BCD value.
+fn parse_source(text: &str) -> Option<Source> {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let source = match words[..] {
+        ["bcd", address, digits] => Source::Bcd {
+            address: u16::from_str_radix(address, 16).ok()?,
+            digits: digits.parse().ok()?,
+        },
+        [word] => match word.strip_prefix(['V', 'v']) {
+            Some(idx) => Source::Register(u8::from_str_radix(idx, 16).ok()?),
+            None => Source::Ram(u16::from_str_radix(word, 16).ok()?),
+        },
+        _ => return None,
+    };
+    source.is_valid().then_some(source)
+}
+
+/// Parses the comparison of a done condition.
+fn parse_comparison(text: &str) -> Option<Comparison> {
+    match text {
+        "==" => Some(Comparison::Equal),
+        "!=" => Some(Comparison::NotEqual),
+        "<" => Some(Comparison::Less),
+        "<=" => Some(Comparison::LessOrEqual),
+        ">" => Some(Comparison::Greater),
+        ">=" => Some(Comparison::GreaterOrEqual),
+        _ => None,
+    }
+}
+
+/// What the agent sees after each step.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Observation {
+    /// The display as a row major array of pixels.
+    pub screen: [bool; SCREEN_WIDTH * SCREEN_HEIGHT],
+}
+
+/// A game wrapped up for reinforcement learning, in the style of an OpenAI
+/// Gym environment: `reset` starts an episode and `step` plays an action.
+///
+/// Everything random (CXNN and sticky actions) comes from the seed, so the
+/// same seed and actions always play out the same way.
+pub struct Env {
+    emulator: Emulator,
+    rom: Vec<u8>,
+    config: EnvConfig,
+    /// Seeds each episode and decides sticky actions.
+    rng: Rng,
+    /// The action held during the last frame.
+    held: usize,
+    /// The value of each reward source at the end of the last frame.
+    scores: Vec<u32>,
+    /// Frames played this episode.
+    frames: usize,
+}
+
+impl Env {
+    /// Constructor, starts the first episode.
+    ///
+    /// #### Parameters:
+    /// - rom: The raw ROM bytes.
+    /// - config: How to play the game.
+    /// - seed: Any value, each seed gives a different run.
+    ///
+    /// #### Returns:
+    /// - An error if the ROM doesn't fit in the memory after the start
+    ///   address, or the config has a setting `EnvConfig::parse` would
+    ///   reject, such as a source outside RAM.
+    ///
+    pub fn new(rom: &[u8], config: EnvConfig, seed: u64) -> Result<Self, EnvError> {
+        config.validate()?;
+        let mut emulator = Emulator::new();
+        emulator.load_rom(rom)?;
+        let mut env = Self {
+            emulator,
+            rom: rom.to_vec(),
+            config,
+            rng: Rng::new(seed),
+            held: 0,
+            scores: Vec::new(),
+            frames: 0,
+        };
+        env.reset();
+        Ok(env)
+    }
+
+    /// Returns the number of actions, which are numbered from 0.
+    pub fn action_count(&self) -> usize {
+        self.config.actions.len()
+    }
+
+    /// Returns the emulator running the game.
+    pub fn emulator(&self) -> &Emulator {
+        &self.emulator
+    }
+
+    /// Returns how the game is played.
+    pub fn config(&self) -> &EnvConfig {
+        &self.config
+    }
+
+    /// Starts a new episode from a freshly loaded ROM.
+    ///
+    /// #### Returns:
+    /// - The first observation.
+    ///
+    pub fn reset(&mut self) -> Observation {
+        self.emulator.reset();
+        self.emulator.seed_rng(self.rng.next_u64());
+        // The ROM was checked to fit when the environment was created.
+        let _ = self.emulator.load_rom(&self.rom);
+        // After loading, so the config's settings win over the ROM
+        // database's.
+        self.emulator
+            .set_ticks_per_frame(self.config.ticks_per_frame);
+        self.emulator.set_quirks(self.config.quirks);
+
+        self.held = 0;
+        self.frames = 0;
+        self.scores = self.read_scores();
+        self.observe()
+    }
+
+    /// Plays an action for `frame_skip` frames, stopping early if the
+    /// episode ends.
+    ///
+    /// #### Parameters:
+    /// - action: The action to play, below `action_count`.
+    ///
+    /// #### Returns:
+    /// - The observation after the last frame, the reward earned over all
+    ///   the frames and whether the episode has ended.
+    ///
+    /// #### Panics
+    /// - If there is no action with that number.
+    ///
+    pub fn step(&mut self, action: usize) -> (Observation, f32, bool) {
+        assert!(action < self.action_count(), "no action {}", action);
+        let mut reward = 0.0;
+        let mut done = false;
+
+        for _ in 0..self.config.frame_skip {
+            if self.config.sticky == 0.0 || self.rng.next_f32() >= self.config.sticky {
+                self.held = action;
+            }
+            let keys = self.config.actions[self.held];
+            self.emulator.set_keys(keys);
+            self.emulator.run_frame();
+            self.frames += 1;
+
+            let scores = self.read_scores();
+            for ((term, old), new) in self.config.rewards.iter().zip(&self.scores).zip(&scores) {
+                reward += term.weight * (*new as f32 - *old as f32);
+            }
+            self.scores = scores;
+
+            done = self.is_done();
+            if done {
+                break;
+            }
+        }
+        (self.observe(), reward, done)
+    }
+
+    /// Whether the episode has ended.
+    fn is_done(&self) -> bool {
+        self.emulator.fault.is_some()
+            || self.config.max_frames.is_some_and(|max| self.frames >= max)
+            || self
+                .config
+                .done
+                .iter()
+                .any(|condition| condition.holds(&self.emulator))
+    }
+
+    /// Reads the current value of each reward source.
+    fn read_scores(&self) -> Vec<u32> {
+        self.config
+            .rewards
+            .iter()
+            .map(|term| term.source.read(&self.emulator))
+            .collect()
+    }
+
+    /// Captures what the agent sees.
+    fn observe(&self) -> Observation {
+        Observation {
+            screen: self.emulator.screen,
+        }
+    }
+}
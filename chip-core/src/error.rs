@@ -0,0 +1,248 @@
+use core::fmt;
+
+/// Errors returned when reading or writing the machine state from outside
+/// the emulator.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryError {
+    /// The range starting at `address` and `len` bytes long doesn't fit in
+    /// RAM.
+    AddressOutOfRange { address: usize, len: usize },
+    /// There is no V register with this index.
+    InvalidRegister(usize),
+    /// More addresses were given than the stack can hold.
+    StackOverflow(usize),
+    /// There is no key with this index.
+    InvalidKey(usize),
+}
+
+impl fmt::Display for MemoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MemoryError::AddressOutOfRange { address, len } => write!(
+                f,
+                "address range {:#05X} (+{} bytes) is outside of RAM",
+                address, len
+            ),
+            MemoryError::InvalidRegister(idx) => write!(f, "no V register with index {}", idx),
+            MemoryError::StackOverflow(len) => {
+                write!(f, "{} addresses don't fit on the stack", len)
+            }
+            MemoryError::InvalidKey(idx) => write!(f, "no key with index {}", idx),
+        }
+    }
+}
+
+impl core::error::Error for MemoryError {}
+
+/// Errors raised by a ROM while it runs. The emulator stops on the first
+/// one, with the program counter left on the instruction that caused it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExecutionError {
+    /// The opcode at `address` isn't a CHIP-8 instruction.
+    UnknownOpcode { address: u16, opcode: u16 },
+    /// 2NNN at `address` was called with every stack entry in use.
+    StackOverflow { address: u16 },
+    /// 00EE at `address` returned with nothing on the stack.
+    StackUnderflow { address: u16 },
+}
+
+impl fmt::Display for ExecutionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExecutionError::UnknownOpcode { address, opcode } => {
+                write!(f, "unknown opcode {:04X} at {:#05X}", opcode, address)
+            }
+            ExecutionError::StackOverflow { address } => {
+                write!(f, "stack overflow at {:#05X}", address)
+            }
+            ExecutionError::StackUnderflow { address } => {
+                write!(f, "return with an empty stack at {:#05X}", address)
+            }
+        }
+    }
+}
+
+impl core::error::Error for ExecutionError {}
+
+/// Errors returned when parsing a text format, such as a cheat file or a
+/// ROM listing.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    /// The 1-based line number the error was found on.
+    pub line: usize,
+    /// What was wrong with the line.
+    pub reason: &'static str,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.reason)
+    }
+}
+
+impl core::error::Error for ParseError {}
+
+/// Errors returned when recording a GIF.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GifError {
+    /// The scaled display is larger than the 65535 by 65535 pixels a GIF
+    /// can hold.
+    TooLarge {
+        /// The scaled width in pixels.
+        width: usize,
+        /// The scaled height in pixels.
+        height: usize,
+    },
+}
+
+impl fmt::Display for GifError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GifError::TooLarge { width, height } => write!(
+                f,
+                "a {}x{} recording is larger than a GIF can hold",
+                width, height
+            ),
+        }
+    }
+}
+
+impl core::error::Error for GifError {}
+
+/// Errors returned when creating an environment.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EnvError {
+    /// The ROM doesn't fit in memory.
+    Rom(MemoryError),
+    /// A config setting holds a value the environment can't use.
+    InvalidConfig(&'static str),
+}
+
+impl fmt::Display for EnvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EnvError::Rom(error) => write!(f, "can't load the ROM: {}", error),
+            EnvError::InvalidConfig(reason) => write!(f, "invalid environment config: {}", reason),
+        }
+    }
+}
+
+impl core::error::Error for EnvError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            EnvError::Rom(error) => Some(error),
+            EnvError::InvalidConfig(_) => None,
+        }
+    }
+}
+
+impl From<MemoryError> for EnvError {
+    fn from(error: MemoryError) -> Self {
+        EnvError::Rom(error)
+    }
+}
+
+/// Errors returned when loading a save state.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StateError {
+    /// The data isn't the size of a save state.
+    WrongSize(usize),
+    /// The data doesn't start with the save state signature.
+    NotAState,
+    /// The state was saved in a layout this version can't read.
+    UnsupportedVersion(u8),
+    /// A field holds a value the machine can't be in.
+    InvalidField(&'static str),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::WrongSize(len) => write!(f, "a save state can't be {} bytes long", len),
+            StateError::NotAState => write!(f, "not a save state"),
+            StateError::UnsupportedVersion(version) => {
+                write!(f, "unsupported save state version {}", version)
+            }
+            StateError::InvalidField(field) => write!(f, "invalid {} in save state", field),
+        }
+    }
+}
+
+impl core::error::Error for StateError {}
+
+/// Errors that end a netplay session.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub enum NetplayError {
+    /// Reading from or writing to the connection failed, including timing
+    /// out while waiting for the other player.
+    Io(std::io::Error),
+    /// The other player closed the connection.
+    Disconnected,
+    /// The other player sent something that isn't part of the protocol.
+    Protocol(&'static str),
+    /// The two emulators can't play together, such as when they have
+    /// different ROMs loaded.
+    Mismatch(&'static str),
+    /// The machine states differed at the start of this frame.
+    Desync { frame: u32 },
+}
+
+#[cfg(feature = "std")]
+impl fmt::Display for NetplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetplayError::Io(error) => write!(f, "connection error: {}", error),
+            NetplayError::Disconnected => write!(f, "the other player disconnected"),
+            NetplayError::Protocol(reason) => write!(f, "protocol error: {}", reason),
+            NetplayError::Mismatch(reason) => write!(f, "can't play together: {}", reason),
+            NetplayError::Desync { frame } => write!(f, "desynced at frame {}", frame),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for NetplayError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            NetplayError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<std::io::Error> for NetplayError {
+    fn from(error: std::io::Error) -> Self {
+        match error.kind() {
+            std::io::ErrorKind::UnexpectedEof
+            | std::io::ErrorKind::BrokenPipe
+            | std::io::ErrorKind::ConnectionReset
+            | std::io::ErrorKind::ConnectionAborted => NetplayError::Disconnected,
+            _ => NetplayError::Io(error),
+        }
+    }
+}
+
+/// Errors that stop a script.
+#[cfg(feature = "scripting")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScriptError {
+    /// The 1-based line the error was raised on, if it's known.
+    pub line: Option<usize>,
+    /// What went wrong.
+    pub message: String,
+}
+
+#[cfg(feature = "scripting")]
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {}: {}", line, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+#[cfg(feature = "scripting")]
+impl std::error::Error for ScriptError {}
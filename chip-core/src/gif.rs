@@ -0,0 +1,313 @@
+use crate::display::{Framebuffer, Palette, PALETTE_SIZE};
+use crate::error::GifError;
+
+/// Number of emulated frames per second.
+const FRAME_RATE: u64 = 60;
+/// Shortest frame delay (in hundredths of a second) that browsers and
+/// image viewers honour, shorter delays are commonly slowed down to 10.
+const MIN_DELAY: u64 = 2;
+/// Longest frame delay a graphic control extension holds.
+const MAX_DELAY: u64 = u16::MAX as u64;
+/// Bits per palette index, the palette always has 16 entries.
+const MIN_CODE_SIZE: u32 = 4;
+/// Largest LZW code, GIF codes are at most 12 bits.
+const MAX_CODE: u16 = 4095;
+/// Largest image width or height a GIF can store.
+const MAX_SIZE: usize = u16::MAX as usize;
+
+/// A recorded frame and how many emulated frames it stayed on screen.
+struct GifFrame {
+    pixels: Vec<u8>,
+    duration: u32,
+}
+
+/// Records emulated frames into an animated GIF.
+///
+/// Frames are captured once per 60 Hz emulated frame and identical
+/// consecutive frames are merged into a single longer frame. GIF delays are
+/// stored in hundredths of a second, so frame start times are rounded from
+/// the exact 60 Hz timeline rather than rounding each delay on its own,
+/// which keeps the clip from drifting.
+pub struct GifRecorder {
+    /// The width and height of each display pixel in image pixels.
+    scale: usize,
+    /// The colours used for each pixel value.
+    palette: Palette,
+    /// The display dimensions, taken from the first captured frame.
+    size: Option<(usize, usize)>,
+    /// The frames captured so far.
+    frames: Vec<GifFrame>,
+    /// Whether `capture` currently records frames.
+    recording: bool,
+}
+
+impl GifRecorder {
+    /// Constructor.
+    ///
+    /// #### Parameters:
+    /// - scale: The width and height of each display pixel in image pixels
+    ///   (0 is treated as 1).
+    /// - palette: The colours to use for each pixel value.
+    ///
+    pub fn new(scale: usize, palette: Palette) -> Self {
+        Self {
+            scale: scale.max(1),
+            palette,
+            size: None,
+            frames: Vec::new(),
+            recording: false,
+        }
+    }
+
+    /// Start a new recording, discarding any frames from a previous one.
+    pub fn start(&mut self) {
+        self.frames.clear();
+        self.size = None;
+        self.recording = true;
+    }
+
+    /// Returns whether a recording is in progress.
+    pub fn is_recording(&self) -> bool {
+        self.recording
+    }
+
+    /// Returns the number of emulated frames recorded so far.
+    pub fn frame_count(&self) -> u32 {
+        self.frames.iter().map(|frame| frame.duration).sum()
+    }
+
+    /// Capture the current display. Should be called once after every
+    /// emulated frame, calls while not recording are ignored.
+    ///
+    /// #### Parameters:
+    /// - fb: The display to capture.
+    ///
+    /// #### Returns:
+    /// - An error if the first frame of a recording is too large for a GIF
+    ///   once scaled, in which case nothing is recorded.
+    ///
+    pub fn capture<F: Framebuffer + ?Sized>(&mut self, fb: &F) -> Result<(), GifError> {
+        if !self.recording {
+            return Ok(());
+        }
+        let (width, height) = match self.size {
+            Some(size) => size,
+            None => {
+                let (width, height) = (fb.width(), fb.height());
+                let scaled = |len: usize| len.saturating_mul(self.scale);
+                if scaled(width) > MAX_SIZE || scaled(height) > MAX_SIZE {
+                    return Err(GifError::TooLarge {
+                        width: scaled(width),
+                        height: scaled(height),
+                    });
+                }
+                *self.size.insert((width, height))
+            }
+        };
+        // A display mode switch mid-recording can't change the GIF size, so
+        // pixels outside the recorded size are dropped and missing pixels
+        // are left as background.
+        let mut pixels = vec![0; width * height];
+        for y in 0..height.min(fb.height()) {
+            for x in 0..width.min(fb.width()) {
+                pixels[x + width * y] = (fb.pixel(x, y) % PALETTE_SIZE) as u8;
+            }
+        }
+
+        match self.frames.last_mut() {
+            Some(last) if last.pixels == pixels => last.duration += 1,
+            _ => self.frames.push(GifFrame {
+                pixels,
+                duration: 1,
+            }),
+        }
+        Ok(())
+    }
+
+    /// Stop recording and encode the captured frames.
+    ///
+    /// #### Returns:
+    /// - The GIF file contents, or `None` if no recording was in progress
+    ///   or no frames were captured.
+    ///
+    pub fn stop(&mut self) -> Option<Vec<u8>> {
+        if !self.recording {
+            return None;
+        }
+        self.recording = false;
+        let gif = self.encode();
+        self.frames.clear();
+        gif
+    }
+
+    /// Encodes the captured frames as a looping GIF89a image.
+    fn encode(&self) -> Option<Vec<u8>> {
+        // `capture` checked the scaled size fits.
+        let (width, height) = self.size?;
+        let image_width = (width * self.scale) as u16;
+        let image_height = (height * self.scale) as u16;
+
+        let mut gif = b"GIF89a".to_vec();
+        // Logical screen descriptor with a 16 entry global colour table
+        // (2 ^ (3 + 1) entries, 8 bits per primary).
+        gif.extend(image_width.to_le_bytes());
+        gif.extend(image_height.to_le_bytes());
+        gif.extend([0xF3, 0, 0]);
+        for color in self.palette.colors() {
+            gif.extend([color.r, color.g, color.b]);
+        }
+        // NETSCAPE2.0 application extension, loop forever.
+        gif.extend([0x21, 0xFF, 0x0B]);
+        gif.extend(b"NETSCAPE2.0");
+        gif.extend([0x03, 0x01, 0x00, 0x00, 0x00]);
+
+        let mut elapsed = 0;
+        let mut shown_at = 0;
+        for (idx, frame) in self.frames.iter().enumerate() {
+            elapsed += frame.duration as u64;
+            let end = centiseconds(elapsed);
+            let is_last = idx == self.frames.len() - 1;
+            // Frames too short to be displayed reliably are skipped, the next
+            // frame takes over their start time so the total length is kept.
+            if end - shown_at < MIN_DELAY && !is_last {
+                continue;
+            }
+            let mut delay = (end - shown_at).max(MIN_DELAY);
+            shown_at = end;
+
+            let scaled = self.scale_pixels(&frame.pixels, width, height);
+            let data = lzw_compress(&scaled);
+            // A delay only has 16 bits, longer ones repeat the image. Each
+            // part leaves enough for the last to be shown.
+            while delay > 0 {
+                let part = match delay > MAX_DELAY {
+                    true => (delay - MIN_DELAY).min(MAX_DELAY),
+                    false => delay,
+                };
+                delay -= part;
+
+                // Graphic control extension: no disposal, no transparency.
+                gif.extend([0x21, 0xF9, 0x04, 0x04]);
+                gif.extend((part as u16).to_le_bytes());
+                gif.extend([0x00, 0x00]);
+                // Image descriptor covering the whole image, no local colour
+                // table.
+                gif.push(0x2C);
+                gif.extend([0, 0, 0, 0]);
+                gif.extend(image_width.to_le_bytes());
+                gif.extend(image_height.to_le_bytes());
+                gif.push(0x00);
+
+                gif.push(MIN_CODE_SIZE as u8);
+                for block in data.chunks(255) {
+                    gif.push(block.len() as u8);
+                    gif.extend(block);
+                }
+                gif.push(0x00);
+            }
+        }
+        // Trailer.
+        gif.push(0x3B);
+        Some(gif)
+    }
+
+    /// Upscales a frame by the recorder's scale factor.
+    fn scale_pixels(&self, pixels: &[u8], width: usize, height: usize) -> Vec<u8> {
+        let scaled_width = width * self.scale;
+        let mut scaled = Vec::with_capacity(scaled_width * height * self.scale);
+        for y in 0..height * self.scale {
+            for x in 0..scaled_width {
+                scaled.push(pixels[x / self.scale + width * (y / self.scale)]);
+            }
+        }
+        scaled
+    }
+}
+
+/// Converts a count of 60 Hz frames to hundredths of a second, rounded to
+/// the nearest value.
+fn centiseconds(frames: u64) -> u64 {
+    (frames * 100 + FRAME_RATE / 2) / FRAME_RATE
+}
+
+/// Packs variable width codes least significant bit first.
+struct CodeWriter {
+    out: Vec<u8>,
+    buffer: u32,
+    count: u32,
+}
+
+impl CodeWriter {
+    fn write(&mut self, code: u16, size: u32) {
+        self.buffer |= (code as u32) << self.count;
+        self.count += size;
+        while self.count >= 8 {
+            self.out.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+}
+
+/// Compresses 4-bit palette indices with GIF flavoured LZW.
+///
+/// #### Parameters:
+/// - pixels: The palette indices, each below 16.
+///
+/// #### Returns:
+/// - The packed code stream, not yet split into sub-blocks.
+///
+fn lzw_compress(pixels: &[u8]) -> Vec<u8> {
+    let clear_code: u16 = 1 << MIN_CODE_SIZE;
+    let end_code = clear_code + 1;
+
+    // The string table as a trie, `children[code * 16 + symbol]` is the code
+    // for the string `code` followed by `symbol`, or 0 if there isn't one.
+    let mut children = vec![0u16; (MAX_CODE as usize + 1) * PALETTE_SIZE];
+    let mut next_code = end_code + 1;
+    let mut code_size = MIN_CODE_SIZE + 1;
+
+    let mut writer = CodeWriter {
+        out: Vec::new(),
+        buffer: 0,
+        count: 0,
+    };
+    writer.write(clear_code, code_size);
+
+    let Some((first, rest)) = pixels.split_first() else {
+        writer.write(end_code, code_size);
+        return writer.out;
+    };
+    let mut prefix = *first as u16;
+    for symbol in rest {
+        let child = children[prefix as usize * PALETTE_SIZE + *symbol as usize];
+        if child != 0 {
+            prefix = child;
+            continue;
+        }
+
+        writer.write(prefix, code_size);
+        if next_code > MAX_CODE {
+            // The table is full, start again from single symbols.
+            writer.write(clear_code, code_size);
+            children.fill(0);
+            next_code = end_code + 1;
+            code_size = MIN_CODE_SIZE + 1;
+        } else {
+            children[prefix as usize * PALETTE_SIZE + *symbol as usize] = next_code;
+            next_code += 1;
+            // Decoders add their table entry one code later than we do, so
+            // the code size grows once the table has passed the limit.
+            if next_code > (1 << code_size) && code_size < 12 {
+                code_size += 1;
+            }
+        }
+        prefix = *symbol as u16;
+    }
+    writer.write(prefix, code_size);
+    writer.write(end_code, code_size);
+    if writer.count > 0 {
+        writer.out.push(writer.buffer as u8);
+    }
+    writer.out
+}
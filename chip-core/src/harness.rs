@@ -0,0 +1,380 @@
+use std::fmt;
+
+use crate::display::{Framebuffer, Palette, Rgb};
+use crate::error::{MemoryError, ParseError};
+use crate::hash::crc32_update;
+use crate::quirks::Quirks;
+use crate::screenshot::to_pbm;
+use crate::{Emulator, DEFAULT_TICKS_PER_FRAME, RAM_SIZE, START_ADDRESS};
+
+/// A key press or release applied at the start of a frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyEvent {
+    /// The frame (counting from 0) the event happens before.
+    pub frame: u32,
+    /// The CHIP-8 key (0x0 to 0xF).
+    pub key: usize,
+    /// Whether the key goes down or up.
+    pub pressed: bool,
+}
+
+/// Settings for running a ROM without a frontend. Everything that affects
+/// the result is fixed up front, so a run always produces the same screen.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HeadlessRun {
+    /// Number of frames to run.
+    pub frames: u32,
+    /// CPU cycles per frame.
+    pub ticks_per_frame: usize,
+    /// The interpreter behaviours to emulate.
+    pub quirks: Quirks,
+    /// Seed for the CXNN random number generator.
+    pub seed: u64,
+    /// Scripted key presses, in any order.
+    pub input: Vec<KeyEvent>,
+}
+
+impl Default for HeadlessRun {
+    fn default() -> Self {
+        Self {
+            frames: 60,
+            ticks_per_frame: DEFAULT_TICKS_PER_FRAME,
+            quirks: Quirks::default(),
+            seed: 0,
+            input: Vec::new(),
+        }
+    }
+}
+
+impl HeadlessRun {
+    /// Runs a ROM from a fresh emulator.
+    ///
+    /// #### Parameters:
+    /// - rom: The raw ROM bytes.
+    ///
+    /// #### Returns:
+    /// - The emulator in its state after the last frame, or an error if
+    ///   the ROM doesn't fit in memory or an input names no key.
+    ///
+    pub fn run(&self, rom: &[u8]) -> Result<Emulator, MemoryError> {
+        let mut emulator = Emulator::new();
+        emulator.set_ticks_per_frame(self.ticks_per_frame);
+        emulator.set_quirks(self.quirks);
+        emulator.seed_rng(self.seed);
+        emulator.load_rom(rom)?;
+
+        for frame in 0..self.frames {
+            for event in self.input.iter().filter(|event| event.frame == frame) {
+                emulator.keypress(event.key, event.pressed)?;
+            }
+            emulator.run_frame();
+        }
+        Ok(emulator)
+    }
+}
+
+/// Returns a CRC-32 of the display contents, a cheap way to check a screen
+/// against a known good result without storing the image.
+pub fn screen_hash<F: Framebuffer + ?Sized>(fb: &F) -> u32 {
+    let mut crc = crc32_update(0, &(fb.width() as u32).to_be_bytes());
+    crc = crc32_update(crc, &(fb.height() as u32).to_be_bytes());
+    for y in 0..fb.height() {
+        let row: Vec<u8> = (0..fb.width()).map(|x| fb.pixel(x, y) as u8).collect();
+        crc = crc32_update(crc, &row);
+    }
+    crc
+}
+
+/// A known good screen, stored as a plain PBM image with one image pixel
+/// per display pixel and set pixels in black.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Golden {
+    width: usize,
+    height: usize,
+    pixels: Vec<bool>,
+}
+
+impl Golden {
+    /// Captures a display as a golden image.
+    pub fn capture<F: Framebuffer + ?Sized>(fb: &F) -> Self {
+        let pixels = (0..fb.width() * fb.height())
+            .map(|idx| fb.pixel(idx % fb.width(), idx / fb.width()) != 0)
+            .collect();
+        Self {
+            width: fb.width(),
+            height: fb.height(),
+            pixels,
+        }
+    }
+
+    /// Parses a plain (P1) PBM image.
+    ///
+    /// #### Parameters:
+    /// - text: The PBM file contents.
+    ///
+    /// #### Returns:
+    /// - The image, or an error describing the first problem found.
+    ///
+    pub fn parse_pbm(text: &str) -> Result<Self, ParseError> {
+        // Problems found at the end of the file are reported on its last
+        // line.
+        let last_line = text.lines().count().max(1);
+        // Comments run from `#` to the end of the line, everything else is
+        // whitespace separated apart from the pixels, which may be packed.
+        let mut tokens = text.lines().enumerate().flat_map(|(idx, line)| {
+            let line_no = idx + 1;
+            line.split('#')
+                .next()
+                .unwrap_or("")
+                .split_whitespace()
+                .map(move |token| (line_no, token))
+        });
+
+        match tokens.next() {
+            Some((_, "P1")) => {}
+            next => {
+                return Err(ParseError {
+                    line: next.map_or(last_line, |(line, _)| line),
+                    reason: "not a plain PBM image",
+                })
+            }
+        }
+        let mut dimension = || match tokens.next() {
+            Some((line, token)) => match token.parse::<usize>() {
+                Ok(value) => Ok((line, value)),
+                Err(_) => Err(ParseError {
+                    line,
+                    reason: "invalid image size",
+                }),
+            },
+            None => Err(ParseError {
+                line: last_line,
+                reason: "invalid image size",
+            }),
+        };
+        let (_, width) = dimension()?;
+        let (line, height) = dimension()?;
+        let len = width.checked_mul(height).ok_or(ParseError {
+            line,
+            reason: "invalid image size",
+        })?;
+
+        let mut pixels = Vec::new();
+        for (line, token) in tokens {
+            for c in token.chars() {
+                let error = |reason| ParseError { line, reason };
+                if pixels.len() == len {
+                    return Err(error("pixel count doesn't match the image size"));
+                }
+                match c {
+                    '0' => pixels.push(false),
+                    '1' => pixels.push(true),
+                    _ => return Err(error("invalid pixel")),
+                }
+            }
+        }
+        if pixels.len() != len {
+            return Err(ParseError {
+                line: last_line,
+                reason: "pixel count doesn't match the image size",
+            });
+        }
+        Ok(Self {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    /// Formats the image as a plain PBM file.
+    pub fn to_pbm(&self) -> Vec<u8> {
+        let palette = Palette::monochrome(Rgb::new(0xFF, 0xFF, 0xFF), Rgb::new(0, 0, 0));
+        to_pbm(self, 1, &palette)
+    }
+
+    /// Compares a display against the image.
+    ///
+    /// #### Parameters:
+    /// - fb: The display to check.
+    ///
+    /// #### Returns:
+    /// - An error describing every mismatched pixel if the display differs.
+    ///
+    pub fn compare<F: Framebuffer + ?Sized>(&self, fb: &F) -> Result<(), PixelDiff> {
+        let actual = Golden::capture(fb);
+        if actual == *self {
+            return Ok(());
+        }
+        Err(PixelDiff {
+            expected: self.clone(),
+            actual,
+        })
+    }
+}
+
+impl Framebuffer for Golden {
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn height(&self) -> usize {
+        self.height
+    }
+
+    fn pixel(&self, x: usize, y: usize) -> usize {
+        self.pixels[x + self.width * y] as usize
+    }
+}
+
+/// The difference between a display and its golden image.
+///
+/// Displays as a map of the screen where `#` is a correctly set pixel, `.`
+/// a correctly clear pixel, `+` a pixel that is set but shouldn't be and
+/// `-` a pixel that should be set but isn't.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PixelDiff {
+    expected: Golden,
+    actual: Golden,
+}
+
+impl PixelDiff {
+    /// Returns the number of pixels that don't match, or `None` if the
+    /// display and the image aren't the same size.
+    pub fn mismatched(&self) -> Option<usize> {
+        if (self.expected.width, self.expected.height) != (self.actual.width, self.actual.height) {
+            return None;
+        }
+        let count = self
+            .expected
+            .pixels
+            .iter()
+            .zip(&self.actual.pixels)
+            .filter(|(expected, actual)| expected != actual)
+            .count();
+        Some(count)
+    }
+}
+
+impl fmt::Display for PixelDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(count) = self.mismatched() else {
+            return write!(
+                f,
+                "display is {}x{} but the golden image is {}x{}",
+                self.actual.width, self.actual.height, self.expected.width, self.expected.height
+            );
+        };
+
+        writeln!(f, "{} pixels differ from the golden image:", count)?;
+        for y in 0..self.expected.height {
+            let row: String = (0..self.expected.width)
+                .map(|x| {
+                    let idx = x + self.expected.width * y;
+                    match (self.expected.pixels[idx], self.actual.pixels[idx]) {
+                        (true, true) => '#',
+                        (false, false) => '.',
+                        (false, true) => '+',
+                        (true, false) => '-',
+                    }
+                })
+                .collect();
+            writeln!(f, "{}", row)?;
+        }
+        Ok(())
+    }
+}
+
+/// Everything a ROM can observe or change, for checking two ways of running
+/// the same ROM end up in the same state. Only compared, by the interpreter
+/// and recompiler tests, so it isn't part of the documented API.
+#[doc(hidden)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct State {
+    ram: Vec<u8>,
+    registers: Vec<u8>,
+    i_register: u16,
+    program_counter: u16,
+    stack: Vec<u16>,
+    delay_timer: u8,
+    sound_timer: u8,
+    display: Vec<bool>,
+    fault: Option<String>,
+}
+
+impl State {
+    /// Captures the state of an emulator.
+    pub fn capture(emulator: &Emulator) -> Self {
+        Self {
+            ram: emulator.ram.to_vec(),
+            registers: emulator.registers().to_vec(),
+            i_register: emulator.i_register(),
+            program_counter: emulator.program_counter(),
+            stack: emulator.stack().to_vec(),
+            delay_timer: emulator.delay_timer(),
+            sound_timer: emulator.sound_timer(),
+            display: emulator.get_display().to_vec(),
+            fault: emulator.fault().map(|fault| fault.to_string()),
+        }
+    }
+}
+
+/// Assembles a hex listing into a ROM image.
+///
+/// Listings make hand-written test programs readable. Each line holds
+/// whitespace separated bytes (two hex digits) or instructions (four hex
+/// digits), anything after a `;` is a comment. A line may start with the
+/// address of its first byte followed by `:`, which is checked against the
+/// bytes before it to catch mistakes when editing.
+///
+/// ```text
+/// ; Draw the digit 5 in the top left corner.
+/// 200: 6005   ; V0 = 5
+/// 202: F029   ; I = font(V0)
+/// 204: D005   ; draw 8x5 at (V0, V0)
+/// ```
+///
+/// #### Parameters:
+/// - text: The listing.
+///
+/// #### Returns:
+/// - The ROM bytes, or the first malformed line.
+///
+pub fn parse_listing(text: &str) -> Result<Vec<u8>, ParseError> {
+    let mut rom = Vec::new();
+    for (idx, line) in text.lines().enumerate() {
+        let error = |reason| ParseError {
+            line: idx + 1,
+            reason,
+        };
+        let mut code = line.split(';').next().unwrap_or("").trim();
+
+        if let Some((address, rest)) = code.split_once(':') {
+            let address =
+                usize::from_str_radix(address.trim(), 16).map_err(|_| error("invalid address"))?;
+            if address != START_ADDRESS as usize + rom.len() {
+                return Err(error("address doesn't match the bytes before it"));
+            }
+            code = rest;
+        }
+
+        for token in code.split_whitespace() {
+            if token.len() != 2 && token.len() != 4 {
+                return Err(error("expected a byte or a two byte instruction"));
+            }
+            let value = u16::from_str_radix(token, 16).map_err(|_| error("invalid hex"))?;
+            if token.len() == 4 {
+                rom.extend(value.to_be_bytes());
+            } else {
+                rom.push(value as u8);
+            }
+        }
+    }
+
+    if rom.len() > RAM_SIZE - START_ADDRESS as usize {
+        return Err(ParseError {
+            line: text.lines().count(),
+            reason: "ROM doesn't fit in memory",
+        });
+    }
+    Ok(rom)
+}
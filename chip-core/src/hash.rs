@@ -0,0 +1,147 @@
+/// Computes the CRC-32 (ISO-HDLC, as used by PNG and zip) of the data.
+///
+/// #### Parameters:
+/// - data: The bytes to checksum.
+///
+/// #### Returns:
+/// - The 32-bit checksum.
+///
+pub fn crc32(data: &[u8]) -> u32 {
+    crc32_update(0, data)
+}
+
+/// Continues a CRC-32 computation from a previous checksum, allowing the
+/// input to be fed in several pieces.
+///
+/// #### Parameters:
+/// - crc: The checksum of the data seen so far (0 for no data).
+/// - data: The next bytes to checksum.
+///
+/// #### Returns:
+/// - The checksum of all the data seen so far.
+///
+pub fn crc32_update(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            // Reflected polynomial 0x04C11DB7.
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+/// Computes the Adler-32 checksum (as used by zlib) of the data.
+///
+/// #### Parameters:
+/// - data: The bytes to checksum.
+///
+/// #### Returns:
+/// - The 32-bit checksum.
+///
+pub fn adler32(data: &[u8]) -> u32 {
+    const MOD_ADLER: u32 = 65521;
+    let mut a: u32 = 1;
+    let mut b: u32 = 0;
+    // 5552 is the largest block that can't overflow the u32 sums.
+    for chunk in data.chunks(5552) {
+        for byte in chunk {
+            a += *byte as u32;
+            b += a;
+        }
+        a %= MOD_ADLER;
+        b %= MOD_ADLER;
+    }
+    (b << 16) | a
+}
+
+/// Computes the SHA-1 digest of the data, the hash ROM databases are keyed
+/// by.
+///
+/// #### Parameters:
+/// - data: The bytes to hash.
+///
+/// #### Returns:
+/// - The 20-byte digest.
+///
+pub fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut state: [u32; 5] = [
+        0x6745_2301,
+        0xEFCD_AB89,
+        0x98BA_DCFE,
+        0x1032_5476,
+        0xC3D2_E1F0,
+    ];
+    // The message is padded with a 1 bit, zeros and its length in bits to a
+    // multiple of 64 bytes.
+    let length = (data.len() as u64).wrapping_mul(8);
+    let mut tail = [0u8; 128];
+    let remainder = data.len() % 64;
+    tail[..remainder].copy_from_slice(&data[data.len() - remainder..]);
+    tail[remainder] = 0x80;
+    let tail_len = if remainder < 56 { 64 } else { 128 };
+    tail[tail_len - 8..tail_len].copy_from_slice(&length.to_be_bytes());
+
+    let blocks = data[..data.len() - remainder].chunks_exact(64);
+    for block in blocks.chain(tail[..tail_len].chunks_exact(64)) {
+        let mut words = [0u32; 80];
+        for (word, bytes) in words.iter_mut().zip(block.chunks_exact(4)) {
+            *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        for idx in 16..80 {
+            words[idx] = (words[idx - 3] ^ words[idx - 8] ^ words[idx - 14] ^ words[idx - 16])
+                .rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = state;
+        for (idx, word) in words.iter().enumerate() {
+            let (f, k) = match idx {
+                0..=19 => ((b & c) | (!b & d), 0x5A82_7999),
+                20..=39 => (b ^ c ^ d, 0x6ED9_EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1B_BCDC),
+                _ => (b ^ c ^ d, 0xCA62_C1D6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (value, new) in state.iter_mut().zip([a, b, c, d, e]) {
+            *value = value.wrapping_add(new);
+        }
+    }
+
+    let mut digest = [0u8; 20];
+    for (bytes, value) in digest.chunks_exact_mut(4).zip(state) {
+        bytes.copy_from_slice(&value.to_be_bytes());
+    }
+    digest
+}
+
+/// Parses a SHA-1 digest written as 40 hex digits.
+///
+/// #### Parameters:
+/// - text: The hex digits, in either case.
+///
+/// #### Returns:
+/// - The digest, or `None` if the text isn't one.
+///
+pub fn parse_sha1(text: &str) -> Option<[u8; 20]> {
+    if text.len() != 40 || !text.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return None;
+    }
+    let mut digest = [0u8; 20];
+    for (byte, digits) in digest.iter_mut().zip(text.as_bytes().chunks_exact(2)) {
+        *byte = u8::from_str_radix(core::str::from_utf8(digits).ok()?, 16).ok()?;
+    }
+    Some(digest)
+}
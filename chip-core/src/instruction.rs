@@ -0,0 +1,173 @@
+/// A decoded CHIP-8 instruction. The variants follow the opcode table in
+/// the README, register operands are the X and Y nibbles (0x0 to 0xF).
+///
+/// Decoding once and executing the result is what lets the recompiler skip
+/// the decode step for code it has already seen.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    /// 0000; do nothing.
+    Nop,
+    /// 00E0; clear the screen.
+    Cls,
+    /// 00EE; return from a subroutine.
+    Ret,
+    /// 1NNN; jump to NNN.
+    Jump(u16),
+    /// 2NNN; call the subroutine at NNN.
+    Call(u16),
+    /// 3XNN; skip the next instruction if VX == NN.
+    SkipEq { x: u8, nn: u8 },
+    /// 4XNN; skip the next instruction if VX != NN.
+    SkipNeq { x: u8, nn: u8 },
+    /// 5XY0; skip the next instruction if VX == VY.
+    SkipEqV { x: u8, y: u8 },
+    /// 6XNN; VX = NN.
+    Set { x: u8, nn: u8 },
+    /// 7XNN; VX += NN, without touching VF.
+    Add { x: u8, nn: u8 },
+    /// 8XY0; VX = VY.
+    SetV { x: u8, y: u8 },
+    /// 8XY1; VX |= VY.
+    Or { x: u8, y: u8 },
+    /// 8XY2; VX &= VY.
+    And { x: u8, y: u8 },
+    /// 8XY3; VX ^= VY.
+    Xor { x: u8, y: u8 },
+    /// 8XY4; VX += VY, VF is the carry.
+    AddV { x: u8, y: u8 },
+    /// 8XY5; VX -= VY, VF is 0 on a borrow.
+    SubV { x: u8, y: u8 },
+    /// 8XY6; single right shift, VF is the dropped bit.
+    ShiftRight { x: u8, y: u8 },
+    /// 8XY7; VX = VY - VX, VF is 0 on a borrow.
+    SubX { x: u8, y: u8 },
+    /// 8XYE; single left shift, VF is the dropped bit.
+    ShiftLeft { x: u8, y: u8 },
+    /// 9XY0; skip the next instruction if VX != VY.
+    SkipNeqV { x: u8, y: u8 },
+    /// ANNN; I = NNN.
+    SetI(u16),
+    /// BNNN; jump to NNN + V0 (or + VX with the jumping quirk).
+    JumpV0 { x: u8, nnn: u16 },
+    /// CXNN; VX = a random byte AND NN.
+    Rand { x: u8, nn: u8 },
+    /// DXYN; draw an N row sprite from I at (VX, VY).
+    Draw { x: u8, y: u8, n: u8 },
+    /// EX9E; skip the next instruction if the key in VX is pressed.
+    SkipKey { x: u8 },
+    /// EXA1; skip the next instruction if the key in VX isn't pressed.
+    SkipNkey { x: u8 },
+    /// FX07; VX = delay timer.
+    GetDt { x: u8 },
+    /// FX0A; wait for a key press and store the key in VX.
+    WaitKey { x: u8 },
+    /// FX15; delay timer = VX.
+    SetDt { x: u8 },
+    /// FX18; sound timer = VX.
+    SetSt { x: u8 },
+    /// FX1E; I += VX.
+    AddI { x: u8 },
+    /// FX29; I = address of the font character for VX.
+    Font { x: u8 },
+    /// FX33; store the BCD representation of VX at I, I + 1 and I + 2.
+    Bcd { x: u8 },
+    /// FX55; store V0 to VX in RAM starting at I.
+    Store { x: u8 },
+    /// FX65; load V0 to VX from RAM starting at I.
+    Load { x: u8 },
+    /// Anything else, which stops the ROM when run.
+    Unknown(u16),
+}
+
+impl Instruction {
+    /// Decodes an opcode.
+    ///
+    /// #### Parameters:
+    /// - opcode: The two instruction bytes in big endian order.
+    ///
+    pub fn decode(opcode: u16) -> Self {
+        // We need to separate out each hex digit in the 2 byte opcode.
+        // We'll do this by bitwise AND'ing to retrieve the relevant
+        // bits and then right shifting them by the offset amount.
+        let hex_1 = ((opcode & 0xF000) >> 12) as u8;
+        let hex_2 = ((opcode & 0x0F00) >> 8) as u8;
+        let hex_3 = ((opcode & 0x00F0) >> 4) as u8;
+        let hex_4 = (opcode & 0x000F) as u8;
+        let (x, y, n) = (hex_2, hex_3, hex_4);
+        let nn = (opcode & 0xFF) as u8;
+        let nnn = opcode & 0xFFF;
+
+        match (hex_1, hex_2, hex_3, hex_4) {
+            (0, 0, 0, 0) => Instruction::Nop,
+            (0, 0, 0xE, 0) => Instruction::Cls,
+            (0, 0, 0xE, 0xE) => Instruction::Ret,
+            (1, _, _, _) => Instruction::Jump(nnn),
+            (2, _, _, _) => Instruction::Call(nnn),
+            (3, _, _, _) => Instruction::SkipEq { x, nn },
+            (4, _, _, _) => Instruction::SkipNeq { x, nn },
+            (5, _, _, 0) => Instruction::SkipEqV { x, y },
+            (6, _, _, _) => Instruction::Set { x, nn },
+            (7, _, _, _) => Instruction::Add { x, nn },
+            (8, _, _, 0) => Instruction::SetV { x, y },
+            (8, _, _, 1) => Instruction::Or { x, y },
+            (8, _, _, 2) => Instruction::And { x, y },
+            (8, _, _, 3) => Instruction::Xor { x, y },
+            (8, _, _, 4) => Instruction::AddV { x, y },
+            (8, _, _, 5) => Instruction::SubV { x, y },
+            (8, _, _, 6) => Instruction::ShiftRight { x, y },
+            (8, _, _, 7) => Instruction::SubX { x, y },
+            (8, _, _, 0xE) => Instruction::ShiftLeft { x, y },
+            (9, _, _, 0) => Instruction::SkipNeqV { x, y },
+            (0xA, _, _, _) => Instruction::SetI(nnn),
+            (0xB, _, _, _) => Instruction::JumpV0 { x, nnn },
+            (0xC, _, _, _) => Instruction::Rand { x, nn },
+            (0xD, _, _, _) => Instruction::Draw { x, y, n },
+            (0xE, _, 9, 0xE) => Instruction::SkipKey { x },
+            (0xE, _, 0xA, 1) => Instruction::SkipNkey { x },
+            (0xF, _, 0, 7) => Instruction::GetDt { x },
+            (0xF, _, 0, 0xA) => Instruction::WaitKey { x },
+            (0xF, _, 1, 5) => Instruction::SetDt { x },
+            (0xF, _, 1, 8) => Instruction::SetSt { x },
+            (0xF, _, 1, 0xE) => Instruction::AddI { x },
+            (0xF, _, 2, 9) => Instruction::Font { x },
+            (0xF, _, 3, 3) => Instruction::Bcd { x },
+            (0xF, _, 5, 5) => Instruction::Store { x },
+            (0xF, _, 6, 5) => Instruction::Load { x },
+            (_, _, _, _) => Instruction::Unknown(opcode),
+        }
+    }
+
+    /// Whether the instruction can change the program counter other than
+    /// moving on to the next instruction, stop the CPU or write to RAM.
+    /// Straight line code runs until one of these.
+    pub fn ends_block(&self) -> bool {
+        matches!(
+            self,
+            Instruction::Ret
+                | Instruction::Jump(_)
+                | Instruction::Call(_)
+                | Instruction::SkipEq { .. }
+                | Instruction::SkipNeq { .. }
+                | Instruction::SkipEqV { .. }
+                | Instruction::SkipNeqV { .. }
+                | Instruction::JumpV0 { .. }
+                | Instruction::Draw { .. }
+                | Instruction::SkipKey { .. }
+                | Instruction::SkipNkey { .. }
+                | Instruction::WaitKey { .. }
+                | Instruction::Bcd { .. }
+                | Instruction::Store { .. }
+                | Instruction::Unknown(_)
+        )
+    }
+
+    /// Returns the number of bytes the instruction writes to RAM starting at
+    /// I (the addresses wrap around at the end of RAM).
+    pub fn ram_write_len(&self) -> usize {
+        match self {
+            Instruction::Bcd { .. } => 3,
+            Instruction::Store { x } => *x as usize + 1,
+            _ => 0,
+        }
+    }
+}
@@ -0,0 +1,417 @@
+use std::fmt;
+
+use crate::error::ParseError;
+
+/// How deep objects and arrays can be nested.
+pub const MAX_DEPTH: usize = 128;
+
+/// A JSON value, for the file formats other tools read and write. Objects
+/// keep their keys in order.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// Parses a JSON document.
+    ///
+    /// #### Parameters:
+    /// - text: The document.
+    ///
+    /// #### Returns:
+    /// - The value, or the line of the first syntax error.
+    ///
+    pub fn parse(text: &str) -> Result<Value, ParseError> {
+        Document::parse(text).map(|document| document.value)
+    }
+
+    /// Returns the value of an object's key.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(entries) => entries
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of a boolean.
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Returns the value if it's a whole number that fits in a `u64`.
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Value::Number(value)
+                if value.fract() == 0.0 && *value >= 0.0 && *value < u64::MAX as f64 =>
+            {
+                Some(*value as u64)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the contents of a string.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Returns the elements of an array.
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    /// Returns the keys and values of an object, in order.
+    pub fn as_object(&self) -> Option<&[(String, Value)]> {
+        match self {
+            Value::Object(entries) => Some(entries),
+            _ => None,
+        }
+    }
+}
+
+/// A parsed document that remembers the line each value starts on, so the
+/// formats built on JSON can report where a value they reject is.
+pub struct Document {
+    pub value: Value,
+    /// The line of every value, in the order they start in the text.
+    lines: Vec<usize>,
+}
+
+impl Document {
+    /// Parses a JSON document.
+    ///
+    /// #### Parameters:
+    /// - text: The document.
+    ///
+    /// #### Returns:
+    /// - The document, or the line of the first syntax error.
+    ///
+    pub fn parse(text: &str) -> Result<Self, ParseError> {
+        let mut parser = Parser {
+            text: text.as_bytes(),
+            position: 0,
+            depth: 0,
+            line: 1,
+            counted: 0,
+            lines: Vec::new(),
+        };
+        let value = parser.value()?;
+        parser.whitespace();
+        if parser.position != parser.text.len() {
+            return Err(parser.error("unexpected text after the value"));
+        }
+        Ok(Self {
+            value,
+            lines: parser.lines,
+        })
+    }
+
+    /// Returns the line a value starts on.
+    ///
+    /// #### Parameters:
+    /// - target: A value in this document, such as an object missing a key
+    ///   or an entry of the wrong type.
+    ///
+    /// #### Returns:
+    /// - The line, or 1 if the value isn't part of the document.
+    ///
+    pub fn line(&self, target: &Value) -> usize {
+        /// Counts the values before `target` in document order.
+        fn find(value: &Value, target: &Value, index: &mut usize) -> bool {
+            if std::ptr::eq(value, target) {
+                return true;
+            }
+            *index += 1;
+            match value {
+                Value::Array(values) => values.iter().any(|value| find(value, target, index)),
+                Value::Object(entries) => {
+                    entries.iter().any(|(_, value)| find(value, target, index))
+                }
+                _ => false,
+            }
+        }
+        let mut index = 0;
+        match find(&self.value, target, &mut index) {
+            true => self.lines[index],
+            false => 1,
+        }
+    }
+}
+
+/// Writes the value as compact JSON.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => write!(f, "null"),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Number(value) if value.is_finite() => write!(f, "{}", value),
+            // JSON has no infinities or NaN.
+            Value::Number(_) => write!(f, "null"),
+            Value::String(value) => write_string(f, value),
+            Value::Array(values) => {
+                write!(f, "[")?;
+                for (idx, value) in values.iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            }
+            Value::Object(entries) => {
+                write!(f, "{{")?;
+                for (idx, (key, value)) in entries.iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+/// Writes a string with the characters JSON needs escaped.
+fn write_string(f: &mut fmt::Formatter<'_>, value: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in value.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+/// A recursive descent parser over the document's bytes.
+struct Parser<'a> {
+    text: &'a [u8],
+    position: usize,
+    /// Objects and arrays open at the current position.
+    depth: usize,
+    /// The line of `counted`, newlines are counted up to the position as
+    /// values are reached.
+    line: usize,
+    counted: usize,
+    /// The line each value started on, in order.
+    lines: Vec<usize>,
+}
+
+impl Parser<'_> {
+    /// Builds an error on the line of the current position.
+    fn error(&self, reason: &'static str) -> ParseError {
+        let line = self.text[..self.position]
+            .iter()
+            .filter(|byte| **byte == b'\n')
+            .count()
+            + 1;
+        ParseError { line, reason }
+    }
+
+    fn whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek() {
+            self.position += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.get(self.position).copied()
+    }
+
+    /// Skips whitespace and consumes `byte` if it's next.
+    fn eat(&mut self, byte: u8) -> bool {
+        self.whitespace();
+        if self.peek() == Some(byte) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, byte: u8, reason: &'static str) -> Result<(), ParseError> {
+        match self.eat(byte) {
+            true => Ok(()),
+            false => Err(self.error(reason)),
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, ParseError> {
+        self.whitespace();
+        self.line += self.text[self.counted..self.position]
+            .iter()
+            .filter(|byte| **byte == b'\n')
+            .count();
+        self.counted = self.position;
+        self.lines.push(self.line);
+        match self.peek() {
+            Some(open @ (b'{' | b'[')) => {
+                // Each level is a recursive call, so deep nesting could
+                // overflow the stack.
+                if self.depth == MAX_DEPTH {
+                    return Err(self.error("objects and arrays nested too deep"));
+                }
+                self.depth += 1;
+                let value = match open {
+                    b'{' => self.object(),
+                    _ => self.array(),
+                }?;
+                self.depth -= 1;
+                Ok(value)
+            }
+            Some(b'"') => Ok(Value::String(self.string()?)),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(b't') => self.literal("true", Value::Bool(true)),
+            Some(b'f') => self.literal("false", Value::Bool(false)),
+            Some(b'n') => self.literal("null", Value::Null),
+            Some(_) => Err(self.error("expected a value")),
+            None => Err(self.error("unexpected end of the document")),
+        }
+    }
+
+    fn literal(&mut self, word: &str, value: Value) -> Result<Value, ParseError> {
+        if self.text[self.position..].starts_with(word.as_bytes()) {
+            self.position += word.len();
+            Ok(value)
+        } else {
+            Err(self.error("expected a value"))
+        }
+    }
+
+    fn object(&mut self) -> Result<Value, ParseError> {
+        self.position += 1;
+        let mut entries = Vec::new();
+        if self.eat(b'}') {
+            return Ok(Value::Object(entries));
+        }
+        loop {
+            self.whitespace();
+            if self.peek() != Some(b'"') {
+                return Err(self.error("expected a key"));
+            }
+            let key = self.string()?;
+            self.expect(b':', "expected ':' after a key")?;
+            entries.push((key, self.value()?));
+            if self.eat(b'}') {
+                return Ok(Value::Object(entries));
+            }
+            self.expect(b',', "expected ',' or '}'")?;
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, ParseError> {
+        self.position += 1;
+        let mut values = Vec::new();
+        if self.eat(b']') {
+            return Ok(Value::Array(values));
+        }
+        loop {
+            values.push(self.value()?);
+            if self.eat(b']') {
+                return Ok(Value::Array(values));
+            }
+            self.expect(b',', "expected ',' or ']'")?;
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, ParseError> {
+        let start = self.position;
+        while let Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9') = self.peek() {
+            self.position += 1;
+        }
+        std::str::from_utf8(&self.text[start..self.position])
+            .ok()
+            .and_then(|number| number.parse().ok())
+            .map(Value::Number)
+            .ok_or_else(|| self.error("invalid number"))
+    }
+
+    fn string(&mut self) -> Result<String, ParseError> {
+        self.position += 1;
+        let mut bytes = Vec::new();
+        loop {
+            let Some(byte) = self.peek() else {
+                return Err(self.error("unterminated string"));
+            };
+            self.position += 1;
+            match byte {
+                b'"' => break,
+                b'\\' => {
+                    let Some(escape) = self.peek() else {
+                        return Err(self.error("unterminated string"));
+                    };
+                    self.position += 1;
+                    let c = match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => self.unicode_escape()?,
+                        _ => return Err(self.error("invalid escape")),
+                    };
+                    bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                byte => bytes.push(byte),
+            }
+        }
+        String::from_utf8(bytes).map_err(|_| self.error("invalid UTF-8 in a string"))
+    }
+
+    /// Reads the digits of a `\u` escape, joining surrogate pairs.
+    fn unicode_escape(&mut self) -> Result<char, ParseError> {
+        let high = self.hex4()?;
+        let code = if (0xD800..0xDC00).contains(&high) {
+            if !self.text[self.position..].starts_with(b"\\u") {
+                return Err(self.error("unpaired surrogate"));
+            }
+            self.position += 2;
+            let low = self.hex4()?;
+            if !(0xDC00..0xE000).contains(&low) {
+                return Err(self.error("unpaired surrogate"));
+            }
+            0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+        } else {
+            high
+        };
+        char::from_u32(code).ok_or_else(|| self.error("invalid escape"))
+    }
+
+    fn hex4(&mut self) -> Result<u32, ParseError> {
+        let digits = self
+            .text
+            .get(self.position..self.position + 4)
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+            .ok_or_else(|| self.error("invalid escape"))?;
+        self.position += 4;
+        Ok(digits)
+    }
+}
@@ -0,0 +1,316 @@
+use std::collections::BTreeMap;
+
+use toml::de::{DeTable, DeValue};
+
+use crate::error::{MemoryError, ParseError};
+use crate::hash::{parse_sha1, sha1};
+use crate::NUM_KEYS;
+
+/// The CHIP-8 keys in the positions of the COSMAC VIP's hex keypad.
+const KEYPAD: [[usize; 4]; 4] = [
+    [0x1, 0x2, 0x3, 0xC],
+    [0x4, 0x5, 0x6, 0xD],
+    [0x7, 0x8, 0x9, 0xE],
+    [0xA, 0x0, 0xB, 0xF],
+];
+
+/// The names of the built-in layouts, for `Keymap::preset`.
+pub const PRESETS: [&str; 2] = ["qwerty", "numpad"];
+
+/// Translates host keys into the 16 CHIP-8 keys. Host keys are named by
+/// the W3C `KeyboardEvent.code` values (`KeyQ`, `Digit1`, `Numpad7`,
+/// `ArrowUp`), which name the physical key whatever the keyboard layout and
+/// are the names winit's `KeyCode` uses. Several host keys can press the
+/// same CHIP-8 key.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Keymap {
+    /// The CHIP-8 key for each bound host key.
+    bindings: BTreeMap<String, usize>,
+}
+
+impl Keymap {
+    /// Constructor, with no keys bound.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The usual layout, the hex keypad on the left of the keyboard:
+    ///
+    /// ```text
+    /// 1 2 3 4      1 2 3 C
+    /// Q W E R  ->  4 5 6 D
+    /// A S D F      7 8 9 E
+    /// Z X C V      A 0 B F
+    /// ```
+    pub fn qwerty() -> Self {
+        let rows = [
+            ["Digit1", "Digit2", "Digit3", "Digit4"],
+            ["KeyQ", "KeyW", "KeyE", "KeyR"],
+            ["KeyA", "KeyS", "KeyD", "KeyF"],
+            ["KeyZ", "KeyX", "KeyC", "KeyV"],
+        ];
+        let mut keymap = Self::new();
+        for (row, keys) in rows.iter().zip(KEYPAD) {
+            for (host, key) in row.iter().zip(keys) {
+                keymap.bindings.insert(host.to_string(), key);
+            }
+        }
+        keymap
+    }
+
+    /// The digits on their numpad keys, with A to F on the operators
+    /// around them:
+    ///
+    /// ```text
+    /// /  *  -      A B C
+    /// +  Enter  .  D E F
+    /// ```
+    pub fn numpad() -> Self {
+        let mut keymap = Self::new();
+        for digit in 0..10 {
+            keymap.bindings.insert(format!("Numpad{}", digit), digit);
+        }
+        let operators = [
+            "NumpadDivide",
+            "NumpadMultiply",
+            "NumpadSubtract",
+            "NumpadAdd",
+            "NumpadEnter",
+            "NumpadDecimal",
+        ];
+        for (host, key) in operators.iter().zip(0xA..) {
+            keymap.bindings.insert(host.to_string(), key);
+        }
+        keymap
+    }
+
+    /// Returns a built-in layout by name, one of `PRESETS`.
+    ///
+    /// #### Parameters:
+    /// - name: The layout's name.
+    ///
+    pub fn preset(name: &str) -> Option<Self> {
+        match name {
+            "qwerty" => Some(Self::qwerty()),
+            "numpad" => Some(Self::numpad()),
+            _ => None,
+        }
+    }
+
+    /// Binds a host key to a CHIP-8 key, replacing its previous binding.
+    ///
+    /// #### Parameters:
+    /// - host: The host key's `KeyboardEvent.code`.
+    /// - key: The CHIP-8 key (0x0 to 0xF).
+    ///
+    /// #### Returns:
+    /// - `MemoryError::InvalidKey` if the key isn't a CHIP-8 key.
+    ///
+    pub fn bind(&mut self, host: &str, key: usize) -> Result<(), MemoryError> {
+        if key >= NUM_KEYS {
+            return Err(MemoryError::InvalidKey(key));
+        }
+        self.bindings.insert(host.to_string(), key);
+        Ok(())
+    }
+
+    /// Removes every host key bound to a CHIP-8 key.
+    ///
+    /// #### Parameters:
+    /// - key: The CHIP-8 key (0x0 to 0xF).
+    ///
+    pub fn unbind(&mut self, key: usize) {
+        self.bindings.retain(|_, bound| *bound != key);
+    }
+
+    /// Returns the CHIP-8 key a host key presses, if it's bound.
+    ///
+    /// #### Parameters:
+    /// - host: The host key's `KeyboardEvent.code`.
+    ///
+    pub fn key(&self, host: &str) -> Option<usize> {
+        self.bindings.get(host).copied()
+    }
+
+    /// Returns the host keys bound to a CHIP-8 key.
+    ///
+    /// #### Parameters:
+    /// - key: The CHIP-8 key (0x0 to 0xF).
+    ///
+    pub fn host_keys(&self, key: usize) -> Vec<&str> {
+        self.bindings
+            .iter()
+            .filter(|(_, bound)| **bound == key)
+            .map(|(host, _)| host.as_str())
+            .collect()
+    }
+
+    /// Works out which CHIP-8 keys are down from the host keys held, so a
+    /// CHIP-8 key stays down while any of its host keys is. Frontends should
+    /// pass the result to `Emulator::keypress` rather than forwarding each
+    /// host key event on its own.
+    ///
+    /// #### Parameters:
+    /// - held: The host keys currently held down.
+    ///
+    /// #### Returns:
+    /// - Whether each CHIP-8 key is down.
+    ///
+    pub fn keys<'a>(&self, held: impl IntoIterator<Item = &'a str>) -> [bool; NUM_KEYS] {
+        let mut keys = [false; NUM_KEYS];
+        for key in held.into_iter().filter_map(|host| self.key(host)) {
+            keys[key] = true;
+        }
+        keys
+    }
+}
+
+/// A default keymap and per-ROM keymaps keyed by the SHA-1 of the ROM
+/// image, read from a TOML file:
+///
+/// ```toml
+/// # The keymap for every ROM without its own, "qwerty" unless set.
+/// preset = "numpad"
+///
+/// # A ROM's keymap starts from the default one, or from the preset it
+/// # names, and each CHIP-8 key listed gets these host keys instead.
+/// [2fb2bd787da9e5c2e12cefbf37ffcab4a1182df0]
+/// preset = "qwerty"
+/// 5 = ["Space", "KeyW"]
+/// A = ["ArrowLeft"]
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeymapOverrides {
+    default: Keymap,
+    roms: BTreeMap<[u8; 20], Keymap>,
+}
+
+impl KeymapOverrides {
+    /// Constructor, with the qwerty layout for every ROM.
+    pub fn new() -> Self {
+        Self {
+            default: Keymap::qwerty(),
+            roms: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the keymap for a ROM.
+    ///
+    /// #### Parameters:
+    /// - rom: The raw ROM bytes.
+    ///
+    pub fn keymap(&self, rom: &[u8]) -> &Keymap {
+        self.roms.get(&sha1(rom)).unwrap_or(&self.default)
+    }
+
+    /// Parses a keymap file.
+    ///
+    /// #### Parameters:
+    /// - text: The file contents.
+    ///
+    /// #### Returns:
+    /// - The keymaps, or the line of the first error.
+    ///
+    pub fn parse(text: &str) -> Result<Self, ParseError> {
+        let document = DeTable::parse(text).map_err(|error| ParseError {
+            line: line_at(text, error.span().map_or(text.len(), |span| span.start)),
+            reason: "invalid TOML",
+        })?;
+        let mut overrides = Self::new();
+        apply(&mut overrides.default, document.get_ref(), true, text)?;
+
+        // The line each ROM's table starts on, to catch a hash written twice
+        // in different cases.
+        let mut lines = BTreeMap::new();
+        for (name, value) in document.get_ref() {
+            let DeValue::Table(table) = value.get_ref() else {
+                continue;
+            };
+            let line = line_at(text, name.span().start);
+            let hash = parse_sha1(name.get_ref()).ok_or(ParseError {
+                line,
+                reason: "invalid ROM hash",
+            })?;
+            if let Some(first) = lines.insert(hash, line) {
+                return Err(ParseError {
+                    line: line.max(first),
+                    reason: "ROM listed twice",
+                });
+            }
+            let mut keymap = overrides.default.clone();
+            apply(&mut keymap, table, false, text)?;
+            overrides.roms.insert(hash, keymap);
+        }
+        Ok(overrides)
+    }
+}
+
+impl Default for KeymapOverrides {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Applies a table's preset and then its key bindings to a keymap.
+///
+/// #### Parameters:
+/// - keymap: The keymap to change.
+/// - table: The table of settings.
+/// - sections: Whether the tables in it are ROM sections, left to the
+///   caller, rather than errors.
+/// - text: The file contents, for the lines of errors.
+///
+fn apply(
+    keymap: &mut Keymap,
+    table: &DeTable,
+    sections: bool,
+    text: &str,
+) -> Result<(), ParseError> {
+    if let Some((name, value)) = table.get_key_value("preset") {
+        let error = |reason| ParseError {
+            line: line_at(text, name.span().start),
+            reason,
+        };
+        let preset = value.get_ref().as_str().ok_or(error("expected a string"))?;
+        *keymap = Keymap::preset(preset).ok_or(error("unknown preset"))?;
+    }
+
+    for (name, value) in table {
+        let error = |reason| ParseError {
+            line: line_at(text, name.span().start),
+            reason,
+        };
+        let name = name.get_ref();
+        if name == "preset" || (sections && value.get_ref().is_table()) {
+            continue;
+        }
+        let key = match usize::from_str_radix(name, 16) {
+            Ok(key) if key < NUM_KEYS && name.len() == 1 => key,
+            _ => return Err(error("expected `preset` or a CHIP-8 key")),
+        };
+        let hosts = value
+            .get_ref()
+            .as_array()
+            .and_then(|hosts| {
+                hosts
+                    .iter()
+                    .map(|host| host.get_ref().as_str())
+                    .collect::<Option<Vec<_>>>()
+            })
+            .ok_or(error("expected a list of host keys"))?;
+        keymap.unbind(key);
+        for host in hosts {
+            keymap.bindings.insert(host.to_string(), key);
+        }
+    }
+    Ok(())
+}
+
+/// Returns the 1-based line of a byte offset in the text.
+fn line_at(text: &str, offset: usize) -> usize {
+    text.as_bytes()[..offset]
+        .iter()
+        .filter(|byte| **byte == b'\n')
+        .count()
+        + 1
+}
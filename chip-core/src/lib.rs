@@ -1,19 +1,89 @@
-use rand::random;
+//! A CHIP-8 emulator. The interpreter itself needs neither `std` nor a heap,
+//! so it runs on microcontrollers. The `std` feature adds the modules that
+//! allocate or use the operating system, such as the exporters, the test
+//! harness and the recompilers, and seeds new emulators from the operating
+//! system's entropy.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(feature = "std")]
+pub mod aot;
+#[cfg(feature = "std")]
+pub mod batch;
+#[cfg(feature = "std")]
+pub mod cheat;
+#[cfg(feature = "std")]
+pub mod coverage;
+#[cfg(feature = "std")]
+mod deflate;
+pub mod display;
+#[cfg(feature = "std")]
+pub mod env;
+pub mod error;
+#[cfg(feature = "std")]
+pub mod gif;
+#[cfg(feature = "std")]
+pub mod harness;
+pub mod hash;
+pub mod instruction;
+#[cfg(feature = "std")]
+mod json;
+#[cfg(feature = "std")]
+pub mod keymap;
+mod memory;
+#[cfg(feature = "std")]
+pub mod netplay;
+pub mod observer;
+#[cfg(feature = "std")]
+pub mod profiler;
+pub mod quirks;
+#[cfg(feature = "std")]
+pub mod recompiler;
+#[cfg(feature = "std")]
+pub mod render;
+mod rng;
+#[cfg(feature = "std")]
+pub mod romdb;
+#[cfg(feature = "std")]
+pub mod rpc;
+pub mod scheduler;
+#[cfg(feature = "std")]
+pub mod screenshot;
+#[cfg(feature = "scripting")]
+pub mod script;
+pub mod state;
+
+use error::{ExecutionError, MemoryError};
+use instruction::Instruction;
+use observer::Observer;
+use quirks::Quirks;
+use rng::Rng;
+#[cfg(feature = "std")]
+use romdb::{RomDatabase, RomInfo};
+#[cfg(feature = "std")]
+use std::sync::Arc;
 
 /// Random-access memory (RAM) size.
-const RAM_SIZE: usize = 4096;
+pub const RAM_SIZE: usize = 4096;
 /// The RAM offset for ROM the available address space.
-const START_ADDRESS: u16 = 0x200;
+pub const START_ADDRESS: u16 = 0x200;
 /// Number of general purpose registers.
-const NUM_REGS: usize = 16;
+pub const NUM_REGS: usize = 16;
 /// Size of the stack.
-const STACK_SIZE: usize = 16;
+pub const STACK_SIZE: usize = 16;
 /// Number of supported keyboard inputs.
-const NUM_KEYS: usize = 16;
+pub const NUM_KEYS: usize = 16;
 /// Display width.
-const SCREEN_WIDTH: usize = 64;
+pub const SCREEN_WIDTH: usize = 64;
 /// Display height.
-const SCREEN_HEIGHT: usize = 32;
+pub const SCREEN_HEIGHT: usize = 32;
+/// Default number of CPU cycles run per 60 Hz frame (600 instructions per
+/// second).
+pub const DEFAULT_TICKS_PER_FRAME: usize = 10;
+
+/// Addresses are 12 bits wide, anything past the end of RAM wraps around to
+/// the start.
+const ADDRESS_MASK: u16 = (RAM_SIZE - 1) as u16;
 
 /// Amount of memory taken up by pre-loaded fonts (16
 /// supported characters that require 5 bytes each).
@@ -142,11 +212,70 @@ pub struct Emulator {
     delay_timer: u8,
     /// The 8-bit sound timer.
     sound_timer: u8,
+    /// Number of CPU cycles run by each call to `run_frame`.
+    ticks_per_frame: usize,
+    /// The interpreter behaviours to emulate.
+    quirks: Quirks,
+    /// Set by DXYN under the display wait quirk, the CPU stalls until the
+    /// next timer tick (the start of the next frame).
+    waiting_for_frame: bool,
+    /// The random number generator for CXNN.
+    rng: Rng,
+    /// The error that stopped the ROM, if any. Nothing runs until a reset.
+    fault: Option<ExecutionError>,
+    /// The database `load_rom` identifies ROMs in.
+    #[cfg(feature = "std")]
+    rom_database: Arc<RomDatabase>,
+    /// What the database knows about the loaded ROM.
+    #[cfg(feature = "std")]
+    rom_info: Option<RomInfo>,
+}
+
+/// Mutable borrows of one machine's state, which is everything an
+/// instruction can read or change. The instructions run on this rather than
+/// on `Emulator` so the batch emulator can run the same code on state stored
+/// in its own arrays. The observer is told about events as they happen.
+struct Cpu<'a, O: Observer = ()> {
+    program_counter: &'a mut u16,
+    ram: &'a mut [u8; RAM_SIZE],
+    screen: &'a mut [bool; SCREEN_WIDTH * SCREEN_HEIGHT],
+    registers: &'a mut [u8; NUM_REGS],
+    i_register: &'a mut u16,
+    stack_pointer: &'a mut u16,
+    stack: &'a mut [u16; STACK_SIZE],
+    keys: &'a [bool; NUM_KEYS],
+    delay_timer: &'a mut u8,
+    sound_timer: &'a mut u8,
+    quirks: Quirks,
+    waiting_for_frame: &'a mut bool,
+    rng: &'a mut Rng,
+    fault: &'a mut Option<ExecutionError>,
+    observer: O,
 }
 
 impl Emulator {
-    /// Constructor.
+    /// Constructor. With the `std` feature the random number generator is
+    /// seeded from the operating system, otherwise it starts from a fixed
+    /// seed and `seed_rng` should be given one from the hardware.
     pub fn new() -> Self {
+        #[cfg(feature = "std")]
+        let rng = Rng::from_entropy();
+        #[cfg(not(feature = "std"))]
+        let rng = Rng::new(0);
+        Self::with_rng(rng)
+    }
+
+    /// Creates an emulator with a seeded random number generator, so runs
+    /// are reproducible without an entropy source.
+    ///
+    /// #### Parameters:
+    /// - seed: Any value, each seed gives a different sequence.
+    ///
+    pub fn with_seed(seed: u64) -> Self {
+        Self::with_rng(Rng::new(seed))
+    }
+
+    fn with_rng(rng: Rng) -> Self {
         let mut new_emulator = Self {
             program_counter: START_ADDRESS,
             ram: [0; RAM_SIZE],
@@ -158,38 +287,272 @@ impl Emulator {
             keys: [false; NUM_KEYS],
             delay_timer: 0,
             sound_timer: 0,
+            ticks_per_frame: DEFAULT_TICKS_PER_FRAME,
+            quirks: Quirks::default(),
+            waiting_for_frame: false,
+            rng,
+            fault: None,
+            #[cfg(feature = "std")]
+            rom_database: RomDatabase::embedded(),
+            #[cfg(feature = "std")]
+            rom_info: None,
         };
 
         new_emulator.load_fonts();
         new_emulator
     }
 
-    /// Reset the emulator state.
+    /// Reset the emulator state. The tick rate, quirks and ROM database are
+    /// settings rather than machine state so they survive the reset.
     pub fn reset(&mut self) {
+        let ticks_per_frame = self.ticks_per_frame;
+        let quirks = self.quirks;
+        #[cfg(feature = "std")]
+        let (rom_database, rom_info) = (self.rom_database.clone(), self.rom_info.take());
+        // Without an entropy source every new emulator gets the same seed,
+        // so carry on with the current sequence instead of repeating it.
+        #[cfg(not(feature = "std"))]
+        let rng = self.rng;
         *self = Emulator::new();
+        self.ticks_per_frame = ticks_per_frame;
+        self.quirks = quirks;
+        #[cfg(feature = "std")]
+        {
+            self.rom_database = rom_database;
+            self.rom_info = rom_info;
+        }
+        #[cfg(not(feature = "std"))]
+        {
+            self.rng = rng;
+        }
+    }
+
+    /// Load a ROM image into RAM at the start address. With the `std`
+    /// feature the ROM is looked up in the ROM database, and if it's there
+    /// the quirks and tick rate are set to the ones it needs. Settings the
+    /// user chose go after this call, or turn the lookup off with an empty
+    /// database (see `set_rom_database`).
+    ///
+    /// #### Parameters:
+    /// - data: The raw ROM bytes.
+    ///
+    /// #### Returns:
+    /// - An error if the ROM doesn't fit in the memory after the start
+    ///   address, in which case nothing is loaded.
+    ///
+    pub fn load_rom(&mut self, data: &[u8]) -> Result<(), MemoryError> {
+        self.write_ram(START_ADDRESS as usize, data)?;
+        #[cfg(feature = "std")]
+        {
+            self.rom_info = self.rom_database.lookup(data).cloned();
+            if let Some(info) = &self.rom_info {
+                self.quirks = info.quirks;
+                if let Some(tick_rate) = info.tick_rate {
+                    self.ticks_per_frame = tick_rate;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns what the ROM database knows about the loaded ROM, such as
+    /// its title, keys and colours, or `None` if it wasn't found.
+    #[cfg(feature = "std")]
+    pub fn rom_info(&self) -> Option<&RomInfo> {
+        self.rom_info.as_ref()
+    }
+
+    /// Set the database ROMs are identified in when loaded, instead of the
+    /// embedded one. An empty database turns the identification off.
+    ///
+    /// #### Parameters:
+    /// - database: The database, such as the embedded one extended with a
+    ///   user's entries.
+    ///
+    #[cfg(feature = "std")]
+    pub fn set_rom_database(&mut self, database: Arc<RomDatabase>) {
+        self.rom_database = database;
+    }
+
+    /// Record a key press or release.
+    ///
+    /// #### Parameters:
+    /// - idx: The CHIP-8 key (0x0 to 0xF).
+    /// - pressed: Whether the key is currently held down.
+    ///
+    /// #### Returns:
+    /// - An error if there is no such key.
+    ///
+    pub fn keypress(&mut self, idx: usize, pressed: bool) -> Result<(), MemoryError> {
+        let key = self.keys.get_mut(idx).ok_or(MemoryError::InvalidKey(idx))?;
+        *key = pressed;
+        Ok(())
+    }
+
+    /// Sets every key at once.
+    ///
+    /// #### Parameters:
+    /// - keys: Bit N is set when key N is held.
+    ///
+    pub fn set_keys(&mut self, keys: u16) {
+        for (idx, key) in self.keys.iter_mut().enumerate() {
+            *key = keys & (1 << idx) != 0;
+        }
+    }
+
+    /// Returns the display as a row major array of pixels.
+    pub fn get_display(&self) -> &[bool] {
+        &self.screen
+    }
+
+    /// Returns the number of CPU cycles run per frame.
+    pub fn ticks_per_frame(&self) -> usize {
+        self.ticks_per_frame
+    }
+
+    /// Set the number of CPU cycles run per frame, which controls the
+    /// emulation speed (instructions per second is 60 times this value).
+    ///
+    /// #### Parameters:
+    /// - ticks: The number of cycles per frame.
+    ///
+    pub fn set_ticks_per_frame(&mut self, ticks: usize) {
+        self.ticks_per_frame = ticks;
+    }
+
+    /// Returns the interpreter behaviours being emulated.
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    /// Set the interpreter behaviours to emulate.
+    ///
+    /// #### Parameters:
+    /// - quirks: The behaviours, usually one of the `Quirks` presets.
+    ///
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    /// Seed the random number generator used by CXNN, making the rest of
+    /// the run reproducible. A new emulator is seeded from the operating
+    /// system when the `std` feature is on.
+    ///
+    /// #### Parameters:
+    /// - seed: Any value, each seed gives a different sequence.
+    ///
+    pub fn seed_rng(&mut self, seed: u64) {
+        self.rng = Rng::new(seed);
+    }
+
+    /// Returns the error that stopped the ROM, if it has stopped.
+    pub fn fault(&self) -> Option<ExecutionError> {
+        self.fault
+    }
+
+    /// Emulates one 60 Hz frame: runs the configured number of CPU cycles
+    /// and then ticks the timers once.
+    pub fn run_frame(&mut self) {
+        for _ in 0..self.ticks_per_frame {
+            self.tick();
+        }
+        self.timer_tick();
     }
 
     /// Defines one CPU loop iteration:
     /// 1. Starts with the `Fetch` step, fetches the value from the ROM
-    /// data (which is loaded into RAM) at the memory address stored in
-    /// the program counter.
+    ///    data (which is loaded into RAM) at the memory address stored in
+    ///    the program counter.
     /// 2. Decode the instruction.
     /// 3. Execute the instruction.
     /// 4. Move the program counter to the next instruction.
     pub fn tick(&mut self) {
-        let opcode = self.fetch();
-        self.execute(opcode);
+        self.cpu().tick();
     }
 
     /// The two special purpose timers, the delay and sound timers,
     /// tick once per frame rather than once per CPU cycle. As a
     /// result, these neeed a separate ticker function.
     pub fn timer_tick(&mut self) {
-        if self.delay_timer > 0 {
-            self.delay_timer -= 1;
+        self.cpu().timer_tick();
+    }
+
+    /// Borrows the machine state for running instructions.
+    fn cpu(&mut self) -> Cpu<'_> {
+        self.cpu_observed(())
+    }
+
+    /// Borrows the machine state for running instructions, reporting
+    /// events to an observer.
+    fn cpu_observed<O: Observer>(&mut self, observer: O) -> Cpu<'_, O> {
+        Cpu {
+            program_counter: &mut self.program_counter,
+            ram: &mut self.ram,
+            screen: &mut self.screen,
+            registers: &mut self.registers,
+            i_register: &mut self.i_register,
+            stack_pointer: &mut self.stack_pointer,
+            stack: &mut self.stack,
+            keys: &self.keys,
+            delay_timer: &mut self.delay_timer,
+            sound_timer: &mut self.sound_timer,
+            quirks: self.quirks,
+            waiting_for_frame: &mut self.waiting_for_frame,
+            rng: &mut self.rng,
+            fault: &mut self.fault,
+            observer,
         }
-        if self.sound_timer > 0 {
-            self.sound_timer -= 1;
+    }
+
+    /// Moves the program counter past the next instruction.
+    #[cfg(feature = "std")]
+    fn skip(&mut self) {
+        self.cpu().skip();
+    }
+
+    /// Performs a decoded instruction. The program counter has already
+    /// been moved past it.
+    ///
+    /// #### Parameters:
+    /// - instruction: The instruction fetched from the program counter.
+    ///
+    #[cfg(feature = "std")]
+    fn execute(&mut self, instruction: Instruction) {
+        self.cpu().execute(instruction);
+    }
+
+    /// Load the pre-configured fonts into RAM.
+    fn load_fonts(&mut self) {
+        self.ram[..FONTSET_SIZE].copy_from_slice(&FONTSET);
+    }
+}
+
+impl<O: Observer> Cpu<'_, O> {
+    /// Runs one CPU cycle, see `Emulator::tick`.
+    fn tick(&mut self) {
+        // Under the display wait quirk nothing runs until the next frame
+        // once a sprite has been drawn, and nothing runs at all after a
+        // fault.
+        if *self.waiting_for_frame || self.fault.is_some() {
+            return;
+        }
+        let address = *self.program_counter;
+        let opcode = self.fetch();
+        self.observer.on_fetch(address, opcode);
+        self.execute(Instruction::decode(opcode));
+    }
+
+    /// Ticks the delay and sound timers, see `Emulator::timer_tick`.
+    fn timer_tick(&mut self) {
+        *self.waiting_for_frame = false;
+        if *self.delay_timer > 0 {
+            *self.delay_timer -= 1;
+        }
+        if *self.sound_timer > 0 {
+            *self.sound_timer -= 1;
+            if *self.sound_timer == 0 {
+                self.observer.on_sound(false);
+            }
         }
     }
 
@@ -202,109 +565,102 @@ impl Emulator {
         // them in big endian fashion.
         //
         // In Rust, array indices are of type usize so have to cast
-        // the program counter from u16 to usize.
-        let higher_byte = self.ram[self.program_counter as usize] as u16;
-        let lower_byte = self.ram[{ self.program_counter + 1 } as usize] as u16;
+        // the program counter from u16 to usize. An instruction at the
+        // last address wraps around to the first.
+        let higher_byte = self.ram[*self.program_counter as usize] as u16;
+        let lower_byte = self.ram[((*self.program_counter + 1) & ADDRESS_MASK) as usize] as u16;
         let opcode = (higher_byte << 8) | lower_byte;
-        self.program_counter += 2;
+        self.skip();
         opcode
     }
 
-    /// Decodes and performs the opcode instruction.
+    /// Performs a decoded instruction. The program counter has already
+    /// been moved past it.
     ///
     /// #### Parameters:
-    /// - opcode: The opcode fetched from the program counter.
+    /// - instruction: The instruction fetched from the program counter.
     ///
-    fn execute(&mut self, opcode: u16) {
-        // We need to separate out each hex digit in the 2 byte opcode.
-        // We'll do this by bitwise AND'ing to retrieve the relevant
-        // bits and then right shifting them by the offset amount.
-        let hex_1 = ((opcode & 0xF000) >> 12) as usize;
-        let hex_2 = ((opcode & 0x0F00) >> 8) as usize;
-        let hex_3 = ((opcode & 0x00F0) >> 4) as usize;
-        let hex_4 = (opcode & 0x000F) as usize;
-
-        // Match statement for the opcode decoding and execution.
-        match (hex_1, hex_2, hex_3, hex_4) {
+    fn execute(&mut self, instruction: Instruction) {
+        match instruction {
             // NOP; do nothing opcode.
-            (0, 0, 0, 0) => (),
+            Instruction::Nop => (),
             // CLS; clear screen opcode.
-            (0, 0, 0xE, 0) => {
+            Instruction::Cls => {
                 // Clear the screen buffer.
-                self.screen = [false; SCREEN_WIDTH * SCREEN_HEIGHT]
+                *self.screen = [false; SCREEN_WIDTH * SCREEN_HEIGHT];
+                self.observer.on_clear();
             }
             // RET; return from subroutine.
-            (0, 0, 0xE, 0xE) => {
+            Instruction::Ret => {
                 // Pop the address to return to from the stack.
-                let return_address = self.pop();
-                // Set the program counter to the return address.
-                self.program_counter = return_address;
+                match self.pop() {
+                    // Set the program counter to the return address.
+                    Some(return_address) => {
+                        let address = self.program_counter.wrapping_sub(2) & ADDRESS_MASK;
+                        *self.program_counter = return_address;
+                        self.observer.on_return(address, return_address);
+                    }
+                    None => self.stop(|address| ExecutionError::StackUnderflow { address }),
+                }
             }
             // JUMP; jump to memory location at NNN.
-            (1, _, _, _) => {
-                // Grab the memory address to jump to.
-                let nnn = opcode & 0xFFF;
+            Instruction::Jump(nnn) => {
                 // Set the program counter to the jump address.
-                self.program_counter = nnn;
+                *self.program_counter = nnn;
             }
             // CALL; jump to subroutine at the memory location NNN.
-            (2, _, _, _) => {
-                // Grab the memory address of the subroutine being called.
-                let nnn = opcode & 0xFFF;
+            Instruction::Call(nnn) => {
                 // Push the current program counter onto the stack so it
                 // can be popped later when returning from the subroutine.
-                self.push(self.program_counter);
-                // Set the program counter to the subroutine address.
-                self.program_counter = nnn;
+                if self.push(*self.program_counter) {
+                    let address = self.program_counter.wrapping_sub(2) & ADDRESS_MASK;
+                    // Set the program counter to the subroutine address.
+                    *self.program_counter = nnn;
+                    self.observer.on_call(address, nnn);
+                } else {
+                    self.stop(|address| ExecutionError::StackOverflow { address });
+                }
             }
             // SKIP_EQ; 3XNN, skip one instruction (2 bytes) if some condition
             // is true. X is the register to retrieve a value from and NN is the
             // raw value to do the VX == NN comparison.
-            (3, _, _, _) => {
-                // Grab the raw value for the comparison.
-                let nn = (opcode & 0xFF) as u8;
-                let x = hex_2;
+            Instruction::SkipEq { x, nn } => {
+                let x = x as usize;
                 // Conditional operation.
                 if self.registers[x] == nn {
-                    self.program_counter += 2;
+                    self.skip();
                 }
             }
             // SKIP_NEQ; 4XNN, skip one instruction (2 bytes) if some condition
             // is true. X is the register to retrieve a value from and NN is the
             // raw value to do the VX != NN comparison.
-            (4, _, _, _) => {
-                // Grab the raw value for the comparison.
-                let nn = (opcode & 0xFF) as u8;
-                let x = hex_2;
+            Instruction::SkipNeq { x, nn } => {
+                let x = x as usize;
                 // Conditional operation.
                 if self.registers[x] != nn {
-                    self.program_counter += 2;
+                    self.skip();
                 }
             }
             // SKIPEQ_V; 5XY0, skip one instruction (2 bytes) if some condition
             // is true. X is the first register to retrieve a value from and Y
             // is the second register to retrieve a value from. The least
             // significant value is not used (opcode requires it be set to 0).
-            (5, _, _, 0) => {
-                let x = hex_2;
-                let y = hex_3;
+            Instruction::SkipEqV { x, y } => {
+                let x = x as usize;
+                let y = y as usize;
                 // Conditional operation.
                 if self.registers[x] == self.registers[y] {
-                    self.program_counter += 2;
+                    self.skip();
                 }
             }
             // SET; 6XNN, set register VX to the value NN.
-            (6, _, _, _) => {
-                let x = hex_2;
-                // Grab the value to set the register.
-                let nn = (opcode & 0xFF) as u8;
+            Instruction::Set { x, nn } => {
+                let x = x as usize;
                 self.registers[x] = nn;
             }
             // ADD; 7XNN, add the value NN to the value in register VX.
-            (7, _, _, _) => {
-                let x = hex_2;
-                // Grab the value to add.
-                let nn = (opcode & 0xFF) as u8;
+            Instruction::Add { x, nn } => {
+                let x = x as usize;
                 // Note we can't use the regular addition operator here
                 // because Rust (in debug mode) will panic in the event
                 // of an overflow. Wrapping add wraps around the maximum
@@ -312,41 +668,44 @@ impl Emulator {
                 self.registers[x] = self.registers[x].wrapping_add(nn);
             }
             // SET_V; 8XY0, sets the value in register VX to the value in VY.
-            (8, _, _, 0) => {
-                let x = hex_2;
-                let y = hex_3;
+            Instruction::SetV { x, y } => {
+                let x = x as usize;
+                let y = y as usize;
                 // Set VX.
                 self.registers[x] = self.registers[y];
             }
             // OR; 8XY1, sets the value in register VX to the result of
             // a bitwise OR with the value in register VY.
-            (8, _, _, 1) => {
-                let x = hex_2;
-                let y = hex_3;
+            Instruction::Or { x, y } => {
+                let x = x as usize;
+                let y = y as usize;
                 // Set the value of VX from the bitwise or.
                 self.registers[x] |= self.registers[y];
+                self.reset_vf();
             }
             // AND; 8XY2, sets the value in register VX to the result of
             // a bitwise AND with the value in register VY.
-            (8, _, _, 2) => {
-                let x = hex_2;
-                let y = hex_3;
+            Instruction::And { x, y } => {
+                let x = x as usize;
+                let y = y as usize;
                 // Set the value of VX from the bitwise and.
                 self.registers[x] &= self.registers[y];
+                self.reset_vf();
             }
             // XOR; 8XY3, sets the value in register VX to the result of
             // a bitwise XOR with the value in register VY.
-            (8, _, _, 3) => {
-                let x = hex_2;
-                let y = hex_3;
+            Instruction::Xor { x, y } => {
+                let x = x as usize;
+                let y = y as usize;
                 // Set the value of VX from the bitwise XOR.
                 self.registers[x] ^= self.registers[y];
+                self.reset_vf();
             }
             // ADD_V; 8XY4, adds the value in register VY to the value
             // in register VX and stores it in register VX.
-            (8, _, _, 4) => {
-                let x = hex_2;
-                let y = hex_3;
+            Instruction::AddV { x, y } => {
+                let x = x as usize;
+                let y = y as usize;
                 // Add the values together and get the value (which will be a wrapping
                 // add if an overflow occurs) and a boolean flag indicating if an
                 // overflow occurred.
@@ -360,9 +719,9 @@ impl Emulator {
             }
             // SUB_V; 8XY5, subtracts the value in the register VY from the
             // value in register VX and stores it in register VX.
-            (8, _, _, 5) => {
-                let x = hex_2;
-                let y = hex_3;
+            Instruction::SubV { x, y } => {
+                let x = x as usize;
+                let y = y as usize;
                 // Perform the subtraction and get the value (which will be a wrapping
                 // subtract if an underflow occurs) and a boolean flag indicating if
                 // an underflow occurred.
@@ -375,20 +734,27 @@ impl Emulator {
             }
             // SING_RSHIFT; 8XY6, performs a single right shift on the value in register VX and
             // store the overflow bit in the flag register.
-            (8, _, _, 6) => {
-                let x = hex_2;
+            Instruction::ShiftRight { x, y } => {
+                let x = x as usize;
+                let y = y as usize;
+                // Without the shifting quirk the value in VY is shifted instead.
+                let value = if self.quirks.shifting {
+                    self.registers[x]
+                } else {
+                    self.registers[y]
+                };
                 // Capture the dropped bit.
-                let lsb = self.registers[x] & 1;
+                let lsb = value & 1;
                 // Shift the value.
-                self.registers[x] >>= 1;
+                self.registers[x] = value >> 1;
                 // Set the dropped bit.
                 self.registers[0xF] = lsb;
             }
             // SUB_X; 8XY7, subtracts the value in the register VX from the value
             // in register VY and stores it in register VX.
-            (8, _, _, 7) => {
-                let x = hex_2;
-                let y = hex_3;
+            Instruction::SubX { x, y } => {
+                let x = x as usize;
+                let y = y as usize;
                 // Perform the subtraction and get the value (which will be a wrapping
                 // subtract if an underflow occurs) and a boolean flag indicating if
                 // an underflow occurred.
@@ -401,44 +767,52 @@ impl Emulator {
             }
             // SING_LSHIFT; 8XYE, performs a single left shift on the value in register VX
             // and stores the overflowed value in the VF flag register.
-            (8, _, _, 0xE) => {
-                let x = hex_2;
+            Instruction::ShiftLeft { x, y } => {
+                let x = x as usize;
+                let y = y as usize;
+                // Without the shifting quirk the value in VY is shifted instead.
+                let value = if self.quirks.shifting {
+                    self.registers[x]
+                } else {
+                    self.registers[y]
+                };
                 // Grab the overflow bit.
-                let msb = (self.registers[x] >> 7) & 1;
+                let msb = (value >> 7) & 1;
                 // Single left shift the register value.
-                self.registers[x] <<= 1;
+                self.registers[x] = value << 1;
                 // Set the flag register to the overflow bit.
                 self.registers[0xF] = msb;
             }
             // SKIPNEQ_V; 9XY0, skips the next instruction if the values retrieved from registers X
             // and Y are not equal.
-            (9, _, _, 0) => {
-                let x = hex_2;
-                let y = hex_3;
+            Instruction::SkipNeqV { x, y } => {
+                let x = x as usize;
+                let y = y as usize;
                 // Conditional check.
                 if self.registers[x] != self.registers[y] {
-                    self.program_counter += 2;
+                    self.skip();
                 }
             }
             // SET_I; ANNN, sets the index register to the value NNN (points to an address in RAM).
-            (0xA, _, _, _) => {
-                // Get the 12 bit address.
-                let nnn = opcode & 0xFFF;
-                self.i_register = nnn;
+            Instruction::SetI(nnn) => {
+                *self.i_register = nnn;
             }
             // JUMP_V0NNN; BNNN, moves the program counter to the sum of the value stored in
             // register 0 and the value NNN.
-            (0xB, _, _, _) => {
-                let nnn = opcode & 0xFFF;
-                self.program_counter = (self.registers[0] as u16) + nnn;
+            //
+            // With the jumping quirk the opcode is read as BXNN and the value stored in
+            // register X is used instead of register 0.
+            Instruction::JumpV0 { x, nnn } => {
+                let offset_register = if self.quirks.jumping { x as usize } else { 0 };
+                *self.program_counter =
+                    ((self.registers[offset_register] as u16) + nnn) & ADDRESS_MASK;
             }
             // RAND; CXNN, the chip8 random number generator. Calculates a random number and then
             // bitwise ANDs it with the lower 8 bits of the opcode (NN) and store the value in
             // register X.
-            (0xC, _, _, _) => {
-                let x = hex_2;
-                let nn = (opcode & 0xFF) as u8;
-                let rng: u8 = random();
+            Instruction::Rand { x, nn } => {
+                let x = x as usize;
+                let rng = self.rng.next_u8();
                 self.registers[x] = rng & nn;
             }
             // DRAW; DXYN, draws a sprite on screen at a specific X, Y point. Grabs the X and Y
@@ -454,29 +828,38 @@ impl Emulator {
             // drawing process. A collision happens when a sprite pixel tries to flip an
             // already-set screen pixel from on to off. VF is set to 1 if any screen pixel is
             // flipped from set (on) to unset (off) during the draw operation.
-            (0xD, _, _, _) => {
+            //
+            // The starting position always wraps around the screen. With the clipping quirk the
+            // parts of the sprite past the edges are cut off, otherwise they wrap as well.
+            Instruction::Draw { x, y, n } => {
                 // Get the X, Y coordinates
-                let xc = self.registers[hex_2] as u16;
-                let yc = self.registers[hex_3] as u16;
+                let xc = (self.registers[x as usize] as usize % SCREEN_WIDTH) as u16;
+                let yc = (self.registers[y as usize] as usize % SCREEN_HEIGHT) as u16;
                 // Grab the sprite height.
-                let height = hex_4;
+                let height = n as usize;
 
                 // collision flag
                 let mut collision = false;
 
                 for row in 0..height {
                     // Get the memory address for the row.
-                    let address = self.i_register + (row as u16);
+                    let address = self.i_register.wrapping_add(row as u16) & ADDRESS_MASK;
                     // Get the pixel flags.
                     let pixels = self.ram[address as usize];
+                    self.observer.on_ram_read(address, pixels);
 
                     for column in 0..8 {
                         // Check if the sprite pixel should be set to on (1).
                         if (pixels & (0x80 >> column)) != 0 {
-                            // Wrap the sprite if it overflows over the edge or bottom of the
-                            // screen.
-                            let x = (xc + column) as usize % SCREEN_WIDTH;
-                            let y = (yc + row as u16) as usize % SCREEN_HEIGHT;
+                            let x = (xc + column) as usize;
+                            let y = (yc + row as u16) as usize;
+                            // Clip the sprite if it overflows over the edge or bottom of the
+                            // screen, or wrap it around to the other side.
+                            if self.quirks.clipping && (x >= SCREEN_WIDTH || y >= SCREEN_HEIGHT) {
+                                continue;
+                            }
+                            let x = x % SCREEN_WIDTH;
+                            let y = y % SCREEN_HEIGHT;
 
                             // Get the pixel index from the row major stored screen array.
                             let idx = x + (SCREEN_WIDTH * y);
@@ -495,17 +878,143 @@ impl Emulator {
                 } else {
                     self.registers[0xF] = 0;
                 }
+                self.observer
+                    .on_draw(xc as u8, yc as u8, height as u8, collision);
+
+                if self.quirks.display_wait {
+                    *self.waiting_for_frame = true;
+                }
+            }
+            // SKIP_KEY; EX9E, skips the next instruction if the key stored in register VX is
+            // pressed.
+            Instruction::SkipKey { x } => {
+                let x = x as usize;
+                // Only the lowest nibble is a valid key.
+                let key = (self.registers[x] & 0xF) as usize;
+                if self.keys[key] {
+                    self.skip();
+                }
+            }
+            // SKIP_NKEY; EXA1, skips the next instruction if the key stored in register VX is
+            // not pressed.
+            Instruction::SkipNkey { x } => {
+                let x = x as usize;
+                let key = (self.registers[x] & 0xF) as usize;
+                if !self.keys[key] {
+                    self.skip();
+                }
+            }
+            // GET_DT; FX07, stores the value of the delay timer in register VX.
+            Instruction::GetDt { x } => {
+                let x = x as usize;
+                self.registers[x] = *self.delay_timer;
+            }
+            // WAIT_KEY; FX0A, blocks until a key is pressed and stores the key in register VX.
+            Instruction::WaitKey { x } => {
+                let x = x as usize;
+                match self.keys.iter().position(|pressed| *pressed) {
+                    Some(key) => self.registers[x] = key as u8,
+                    // No key is pressed, so move the program counter back onto this
+                    // instruction to run it again on the next tick. Timers keep running
+                    // while we wait.
+                    None => {
+                        *self.program_counter = self.program_counter.wrapping_sub(2) & ADDRESS_MASK
+                    }
+                }
+            }
+            // SET_DT; FX15, sets the delay timer to the value in register VX.
+            Instruction::SetDt { x } => {
+                let x = x as usize;
+                *self.delay_timer = self.registers[x];
+            }
+            // SET_ST; FX18, sets the sound timer to the value in register VX.
+            Instruction::SetSt { x } => {
+                let x = x as usize;
+                let playing = *self.sound_timer > 0;
+                *self.sound_timer = self.registers[x];
+                if playing != (*self.sound_timer > 0) {
+                    self.observer.on_sound(!playing);
+                }
+            }
+            // ADD_I; FX1E, adds the value in register VX to the index register.
+            Instruction::AddI { x } => {
+                let x = x as usize;
+                *self.i_register = self.i_register.wrapping_add(self.registers[x] as u16);
+            }
+            // FONT; FX29, sets the index register to the address of the font character for
+            // the hex digit in register VX.
+            Instruction::Font { x } => {
+                let x = x as usize;
+                // Each font character is 5 bytes and the font set starts at address 0.
+                let digit = (self.registers[x] & 0xF) as u16;
+                *self.i_register = digit * 5;
+            }
+            // BCD; FX33, stores the binary-coded decimal representation of the value in
+            // register VX at the addresses I (hundreds), I + 1 (tens) and I + 2 (ones).
+            Instruction::Bcd { x } => {
+                let x = x as usize;
+                let vx = self.registers[x];
+                let digits = [vx / 100, (vx / 10) % 10, vx % 10];
+                for (offset, digit) in digits.into_iter().enumerate() {
+                    let address = self.i_register.wrapping_add(offset as u16) & ADDRESS_MASK;
+                    self.ram[address as usize] = digit;
+                    self.observer.on_ram_write(address, digit);
+                }
+            }
+            // STORE; FX55, stores registers V0 through VX (inclusive) in RAM starting at the
+            // address in the index register.
+            Instruction::Store { x } => {
+                let x = x as usize;
+                for idx in 0..=x {
+                    let address = self.i_register.wrapping_add(idx as u16) & ADDRESS_MASK;
+                    self.ram[address as usize] = self.registers[idx];
+                    self.observer.on_ram_write(address, self.registers[idx]);
+                }
+                if self.quirks.memory {
+                    *self.i_register = self.i_register.wrapping_add(x as u16 + 1);
+                }
+            }
+            // LOAD; FX65, loads registers V0 through VX (inclusive) from RAM starting at the
+            // address in the index register.
+            Instruction::Load { x } => {
+                let x = x as usize;
+                for idx in 0..=x {
+                    let address = self.i_register.wrapping_add(idx as u16) & ADDRESS_MASK;
+                    self.registers[idx] = self.ram[address as usize];
+                    self.observer.on_ram_read(address, self.registers[idx]);
+                }
+                if self.quirks.memory {
+                    *self.i_register = self.i_register.wrapping_add(x as u16 + 1);
+                }
+            }
+            // Opcodes that don't decode to an instruction stop the ROM.
+            Instruction::Unknown(opcode) => {
+                self.stop(|address| ExecutionError::UnknownOpcode { address, opcode })
             }
-            // TODO 
-            // Rust match statements must be exhaustive, so we need this match
-            // to handle unsupported opcodes.
-            (_, _, _, _) => unimplemented!("Opcode not supported: {}", opcode),
         }
     }
 
-    /// Load the pre-configured fonts into RAM.
-    fn load_fonts(&mut self) {
-        self.ram[..FONTSET_SIZE].copy_from_slice(&FONTSET);
+    /// Moves the program counter past the next instruction.
+    fn skip(&mut self) {
+        *self.program_counter = (*self.program_counter + 2) & ADDRESS_MASK;
+    }
+
+    /// Stops the ROM on the instruction that just ran.
+    ///
+    /// #### Parameters:
+    /// - error: Builds the error from the address of the instruction.
+    ///
+    fn stop(&mut self, error: impl FnOnce(u16) -> ExecutionError) {
+        *self.program_counter = self.program_counter.wrapping_sub(2) & ADDRESS_MASK;
+        *self.fault = Some(error(*self.program_counter));
+    }
+
+    /// Clears the flag register after a bitwise operation when the VF reset
+    /// quirk is enabled.
+    fn reset_vf(&mut self) {
+        if self.quirks.vf_reset {
+            self.registers[0xF] = 0;
+        }
     }
 
     /// Push an address onto the stack.
@@ -513,26 +1022,39 @@ impl Emulator {
     /// #### Parameters:
     /// - val: The address to push onto the stack.
     ///
-    fn push(&mut self, val: u16) {
+    /// #### Returns:
+    /// - Whether there was room for the address.
+    ///
+    fn push(&mut self, val: u16) -> bool {
+        if *self.stack_pointer as usize >= STACK_SIZE {
+            return false;
+        }
         // At the current top of the stack, add the value.
-        self.stack[self.stack_pointer as usize] = val;
+        self.stack[*self.stack_pointer as usize] = val;
         // Increment the stack pointer.
-        self.stack_pointer += 1;
+        *self.stack_pointer += 1;
+        true
     }
 
     /// Pop an address off of the stack.
     ///
-    /// #### Panics
-    ///
-    /// Panics if pop is called on an empty stack.
-    ///
     /// #### Returns:
-    /// - The address at the top of the stack.
+    /// - The address at the top of the stack, or `None` if the stack is
+    ///   empty.
     ///
-    fn pop(&mut self) -> u16 {
+    fn pop(&mut self) -> Option<u16> {
+        if *self.stack_pointer == 0 {
+            return None;
+        }
         // Decrement the stack pointer.
-        self.stack_pointer -= 1;
+        *self.stack_pointer -= 1;
         // Get the last address pushed into the stack.
-        self.stack[self.stack_pointer as usize]
+        Some(self.stack[*self.stack_pointer as usize])
+    }
+}
+
+impl Default for Emulator {
+    fn default() -> Self {
+        Self::new()
     }
 }
@@ -0,0 +1,155 @@
+use crate::error::MemoryError;
+use crate::{Emulator, NUM_REGS, RAM_SIZE, STACK_SIZE};
+
+/// Checks that `len` bytes starting at `address` fit in RAM.
+fn check_range(address: usize, len: usize) -> Result<(), MemoryError> {
+    match address.checked_add(len) {
+        Some(end) if end <= RAM_SIZE => Ok(()),
+        _ => Err(MemoryError::AddressOutOfRange { address, len }),
+    }
+}
+
+/// Checked access to the machine state, for debuggers, trainers and test
+/// harnesses. Unlike the opcodes, which trust the ROM, every call validates
+/// its arguments and returns an error rather than panicking.
+impl Emulator {
+    /// Read a range of RAM.
+    ///
+    /// #### Parameters:
+    /// - address: The first address to read.
+    /// - len: The number of bytes to read.
+    ///
+    /// #### Returns:
+    /// - The bytes, or an error if the range doesn't fit in RAM.
+    ///
+    pub fn read_ram(&self, address: usize, len: usize) -> Result<&[u8], MemoryError> {
+        check_range(address, len)?;
+        Ok(&self.ram[address..address + len])
+    }
+
+    /// Write a range of RAM.
+    ///
+    /// #### Parameters:
+    /// - address: The first address to write.
+    /// - data: The bytes to write.
+    ///
+    /// #### Returns:
+    /// - An error if the range doesn't fit in RAM, in which case nothing
+    ///   is written.
+    ///
+    pub fn write_ram(&mut self, address: usize, data: &[u8]) -> Result<(), MemoryError> {
+        check_range(address, data.len())?;
+        self.ram[address..address + data.len()].copy_from_slice(data);
+        Ok(())
+    }
+
+    /// Returns the sixteen V registers.
+    pub fn registers(&self) -> &[u8; NUM_REGS] {
+        &self.registers
+    }
+
+    /// Set a V register.
+    ///
+    /// #### Parameters:
+    /// - idx: The register index (0x0 to 0xF).
+    /// - value: The new register value.
+    ///
+    /// #### Returns:
+    /// - An error if there is no register with that index.
+    ///
+    pub fn set_register(&mut self, idx: usize, value: u8) -> Result<(), MemoryError> {
+        let register = self
+            .registers
+            .get_mut(idx)
+            .ok_or(MemoryError::InvalidRegister(idx))?;
+        *register = value;
+        Ok(())
+    }
+
+    /// Returns the index register.
+    pub fn i_register(&self) -> u16 {
+        self.i_register
+    }
+
+    /// Set the index register.
+    ///
+    /// #### Parameters:
+    /// - address: The new value, which must point into RAM.
+    ///
+    /// #### Returns:
+    /// - An error if the address is outside of RAM.
+    ///
+    pub fn set_i_register(&mut self, address: u16) -> Result<(), MemoryError> {
+        check_range(address as usize, 1)?;
+        self.i_register = address;
+        Ok(())
+    }
+
+    /// Returns the program counter.
+    pub fn program_counter(&self) -> u16 {
+        self.program_counter
+    }
+
+    /// Set the program counter. An instruction at the last address wraps
+    /// around to the first byte of RAM, as it does when the ROM runs.
+    ///
+    /// #### Parameters:
+    /// - address: The address of the next instruction to run.
+    ///
+    /// #### Returns:
+    /// - An error if the address is outside of RAM.
+    ///
+    pub fn set_program_counter(&mut self, address: u16) -> Result<(), MemoryError> {
+        check_range(address as usize, 1)?;
+        self.program_counter = address;
+        Ok(())
+    }
+
+    /// Returns the delay timer.
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    /// Set the delay timer.
+    pub fn set_delay_timer(&mut self, value: u8) {
+        self.delay_timer = value;
+    }
+
+    /// Returns the sound timer.
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    /// Set the sound timer.
+    pub fn set_sound_timer(&mut self, value: u8) {
+        self.sound_timer = value;
+    }
+
+    /// Returns the return addresses currently on the stack, oldest first.
+    pub fn stack(&self) -> &[u16] {
+        &self.stack[..self.stack_pointer as usize]
+    }
+
+    /// Replace the stack contents. The stack pointer is moved to the top of
+    /// the new contents and unused entries are cleared.
+    ///
+    /// #### Parameters:
+    /// - addresses: The return addresses, oldest first.
+    ///
+    /// #### Returns:
+    /// - An error if there are more addresses than stack entries or an
+    ///   address is outside of RAM. The stack is unchanged on error.
+    ///
+    pub fn set_stack(&mut self, addresses: &[u16]) -> Result<(), MemoryError> {
+        if addresses.len() > STACK_SIZE {
+            return Err(MemoryError::StackOverflow(addresses.len()));
+        }
+        for address in addresses {
+            check_range(*address as usize, 1)?;
+        }
+        self.stack = [0; STACK_SIZE];
+        self.stack[..addresses.len()].copy_from_slice(addresses);
+        self.stack_pointer = addresses.len() as u16;
+        Ok(())
+    }
+}
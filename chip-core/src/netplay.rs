@@ -0,0 +1,393 @@
+use std::collections::VecDeque;
+use std::io::{self, BufReader, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::time::Duration;
+
+use crate::error::NetplayError;
+use crate::hash::crc32;
+use crate::quirks::Quirks;
+use crate::Emulator;
+
+/// Every connection starts with these bytes.
+const MAGIC: [u8; 4] = *b"C8NP";
+/// Bumped whenever the protocol changes.
+const VERSION: u8 = 1;
+
+/// The most frames of input delay a session accepts, a second at 60 frames
+/// a second.
+pub const MAX_INPUT_DELAY: u32 = 60;
+
+/// Message tags after the handshake.
+const INPUT: u8 = 1;
+const HASH: u8 = 2;
+
+/// Which end of the connection a player is.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Role {
+    Host = 0,
+    Guest = 1,
+}
+
+/// How one player takes part in a session.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NetplayConfig {
+    /// The keys this player controls, as a bit mask (bit N is key N). The
+    /// two players' keys can't overlap.
+    pub keys: u16,
+    /// Frames between a key press and the frame it's applied on, which
+    /// hides the round trip to the other player, at most
+    /// `MAX_INPUT_DELAY`. The host's value is used.
+    pub input_delay: u32,
+    /// Frames between comparisons of the machine states, 0 to never
+    /// compare. The host's value is used.
+    pub hash_interval: u32,
+    /// How long to wait for the other player before giving up, `None` to
+    /// wait forever.
+    pub timeout: Option<Duration>,
+}
+
+impl NetplayConfig {
+    /// Constructor, with 2 frames of input delay and the states compared
+    /// every frame.
+    ///
+    /// #### Parameters:
+    /// - keys: The keys this player controls, bit N is key N.
+    ///
+    pub fn new(keys: u16) -> Self {
+        Self {
+            keys,
+            input_delay: 2,
+            hash_interval: 1,
+            timeout: Some(Duration::from_secs(10)),
+        }
+    }
+}
+
+/// A two player game over TCP, run in deterministic lockstep: both players
+/// run the same ROM from the same seed, and each frame runs only once both
+/// players' keys for it are known, so the two emulators go through exactly
+/// the same states.
+///
+/// The protocol is a handshake followed by a stream of messages, with
+/// every number big endian:
+///
+/// - Hello, sent by both: `C8NP`, the version (1), the role (0 for the
+///   host, 1 for the guest), the player's key mask (u16), input delay
+///   (u32), hash interval (u32), seed (u64), tick rate (u32) and quirks
+///   (one bit per field, in declaration order). The guest takes the input
+///   delay, hash interval and seed from the host.
+/// - Start, sent by both once seeded: the CRC-32 of the save state (u32),
+///   so different ROMs are caught before the first frame.
+/// - Input, 1 then the frame (u32) and the player's keys held on it (u16),
+///   sent `input_delay` frames ahead of the frame.
+/// - Hash, 2 then the frame (u32) and the CRC-32 of the save state at its
+///   start (u32), sent every `hash_interval` frames before the frame's
+///   input message.
+///
+/// A session ends when either player closes the connection, which
+/// `close` does without losing anything still on the way.
+pub struct Netplay {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    keys: u16,
+    remote_keys: u16,
+    input_delay: u32,
+    hash_interval: u32,
+    seed: u64,
+    /// The next frame to run.
+    frame: u32,
+    /// Each player's keys for the frames from `frame` on.
+    local_input: VecDeque<u16>,
+    remote_input: VecDeque<u16>,
+    /// The frame the other player's next input message is for.
+    remote_frame: u32,
+    /// State hashes not yet compared, by frame.
+    local_hashes: VecDeque<(u32, u32)>,
+    remote_hashes: VecDeque<(u32, u32)>,
+}
+
+impl Netplay {
+    /// Starts a session as the host, the player who picks the settings.
+    /// Both emulators need the same ROM, tick rate and quirks.
+    ///
+    /// #### Parameters:
+    /// - stream: The connection to the guest.
+    /// - emulator: This player's emulator, reseeded and with every key
+    ///   released.
+    /// - config: How this player takes part.
+    /// - seed: The random number generator seed for both emulators.
+    ///
+    /// #### Returns:
+    /// - The session, or an error if the handshake failed.
+    ///
+    pub fn host(
+        stream: TcpStream,
+        emulator: &mut Emulator,
+        config: NetplayConfig,
+        seed: u64,
+    ) -> Result<Self, NetplayError> {
+        Self::connect(stream, emulator, config, Role::Host, seed)
+    }
+
+    /// Joins a session started by the host.
+    ///
+    /// #### Parameters:
+    /// - stream: The connection to the host.
+    /// - emulator: This player's emulator, reseeded and with every key
+    ///   released.
+    /// - config: How this player takes part, the input delay and hash
+    ///   interval are ignored.
+    ///
+    /// #### Returns:
+    /// - The session, or an error if the handshake failed.
+    ///
+    pub fn join(
+        stream: TcpStream,
+        emulator: &mut Emulator,
+        config: NetplayConfig,
+    ) -> Result<Self, NetplayError> {
+        Self::connect(stream, emulator, config, Role::Guest, 0)
+    }
+
+    fn connect(
+        stream: TcpStream,
+        emulator: &mut Emulator,
+        config: NetplayConfig,
+        role: Role,
+        seed: u64,
+    ) -> Result<Self, NetplayError> {
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(config.timeout)?;
+        let mut netplay = Self {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+            keys: config.keys,
+            remote_keys: 0,
+            input_delay: config.input_delay,
+            hash_interval: config.hash_interval,
+            seed,
+            frame: 0,
+            local_input: VecDeque::new(),
+            remote_input: VecDeque::new(),
+            remote_frame: 0,
+            local_hashes: VecDeque::new(),
+            remote_hashes: VecDeque::new(),
+        };
+
+        let mut hello = Vec::new();
+        hello.extend(MAGIC);
+        hello.push(VERSION);
+        hello.push(role as u8);
+        hello.extend(config.keys.to_be_bytes());
+        hello.extend(config.input_delay.to_be_bytes());
+        hello.extend(config.hash_interval.to_be_bytes());
+        hello.extend(seed.to_be_bytes());
+        hello.extend((emulator.ticks_per_frame() as u32).to_be_bytes());
+        hello.push(quirk_bits(emulator.quirks()));
+        netplay.writer.write_all(&hello)?;
+
+        if netplay.read::<4>()? != MAGIC {
+            return Err(NetplayError::Protocol("not a netplay connection"));
+        }
+        if netplay.read_u8()? != VERSION {
+            return Err(NetplayError::Protocol("unsupported protocol version"));
+        }
+        let remote_role = netplay.read_u8()?;
+        if remote_role == role as u8 || remote_role > Role::Guest as u8 {
+            return Err(NetplayError::Protocol("expected one host and one guest"));
+        }
+        netplay.remote_keys = netplay.read_u16()?;
+        let input_delay = netplay.read_u32()?;
+        let hash_interval = netplay.read_u32()?;
+        let remote_seed = u64::from_be_bytes(netplay.read()?);
+        let ticks_per_frame = netplay.read_u32()?;
+        let quirks = netplay.read_u8()?;
+        if role == Role::Guest {
+            netplay.input_delay = input_delay;
+            netplay.hash_interval = hash_interval;
+            netplay.seed = remote_seed;
+        }
+        if input_delay > MAX_INPUT_DELAY || netplay.input_delay > MAX_INPUT_DELAY {
+            return Err(NetplayError::Protocol("input delay too long"));
+        }
+        if netplay.keys & netplay.remote_keys != 0 {
+            return Err(NetplayError::Mismatch("both players control the same keys"));
+        }
+        if ticks_per_frame != emulator.ticks_per_frame() as u32 {
+            return Err(NetplayError::Mismatch("the tick rates differ"));
+        }
+        if quirks != quirk_bits(emulator.quirks()) {
+            return Err(NetplayError::Mismatch("the quirks differ"));
+        }
+
+        emulator.seed_rng(netplay.seed);
+        emulator.set_keys(0);
+        let hash = crc32(&emulator.save_state());
+        netplay.writer.write_all(&hash.to_be_bytes())?;
+        if netplay.read_u32()? != hash {
+            return Err(NetplayError::Mismatch("the ROMs or machine states differ"));
+        }
+
+        // Nobody pressed anything before the session started.
+        netplay.local_input = (0..netplay.input_delay).map(|_| 0).collect();
+        netplay.remote_input = netplay.local_input.clone();
+        netplay.remote_frame = netplay.input_delay;
+        Ok(netplay)
+    }
+
+    /// Returns the next frame to run, the number run so far.
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+
+    /// Returns the keys this player controls.
+    pub fn keys(&self) -> u16 {
+        self.keys
+    }
+
+    /// Returns the keys the other player controls.
+    pub fn remote_keys(&self) -> u16 {
+        self.remote_keys
+    }
+
+    /// Returns the frames between a key press and the frame it's applied
+    /// on.
+    pub fn input_delay(&self) -> u32 {
+        self.input_delay
+    }
+
+    /// Returns the seed both emulators were given.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Sends this player's keys and runs the next frame once the other
+    /// player's keys for it have arrived, blocking until they do.
+    ///
+    /// #### Parameters:
+    /// - emulator: This player's emulator.
+    /// - held: The keys this player holds now, bit N is key N. Keys the
+    ///   player doesn't control are ignored. They're applied
+    ///   `input_delay` frames later.
+    ///
+    /// #### Returns:
+    /// - An error if the connection failed or the states no longer match,
+    ///   after which the session can't continue.
+    ///
+    pub fn run_frame(&mut self, emulator: &mut Emulator, held: u16) -> Result<(), NetplayError> {
+        let mut message = Vec::new();
+        if self.hash_interval != 0 && self.frame.is_multiple_of(self.hash_interval) {
+            let hash = crc32(&emulator.save_state());
+            message.push(HASH);
+            message.extend(self.frame.to_be_bytes());
+            message.extend(hash.to_be_bytes());
+            self.local_hashes.push_back((self.frame, hash));
+        }
+        let held = held & self.keys;
+        message.push(INPUT);
+        message.extend((self.frame + self.input_delay).to_be_bytes());
+        message.extend(held.to_be_bytes());
+        self.local_input.push_back(held);
+        self.writer.write_all(&message)?;
+        self.compare_hashes()?;
+
+        while self.remote_input.is_empty() {
+            self.receive()?;
+        }
+        let keys =
+            self.local_input.pop_front().unwrap_or(0) | self.remote_input.pop_front().unwrap_or(0);
+        emulator.set_keys(keys);
+        emulator.run_frame();
+        self.frame += 1;
+        Ok(())
+    }
+
+    /// Ends the session, waiting for the other player to end it too. The
+    /// players are up to `input_delay` frames apart, so one that simply
+    /// drops its session can cut off the other's last frames, or the hash
+    /// that shows a desync.
+    ///
+    /// #### Returns:
+    /// - An error if the connection failed before the other player ended
+    ///   the session.
+    ///
+    pub fn close(mut self) -> Result<(), NetplayError> {
+        self.writer.shutdown(Shutdown::Write)?;
+        io::copy(&mut self.reader, &mut io::sink())?;
+        Ok(())
+    }
+
+    /// Reads one message from the other player.
+    fn receive(&mut self) -> Result<(), NetplayError> {
+        match self.read_u8()? {
+            INPUT => {
+                let frame = self.read_u32()?;
+                let keys = self.read_u16()?;
+                if frame != self.remote_frame {
+                    return Err(NetplayError::Protocol("input for the wrong frame"));
+                }
+                if keys & !self.remote_keys != 0 {
+                    return Err(NetplayError::Protocol("input for the other player's keys"));
+                }
+                self.remote_input.push_back(keys);
+                self.remote_frame += 1;
+            }
+            HASH => {
+                let frame = self.read_u32()?;
+                let hash = self.read_u32()?;
+                self.remote_hashes.push_back((frame, hash));
+                self.compare_hashes()?;
+            }
+            _ => return Err(NetplayError::Protocol("unknown message")),
+        }
+        Ok(())
+    }
+
+    /// Compares the state hashes both players have sent.
+    fn compare_hashes(&mut self) -> Result<(), NetplayError> {
+        while !self.local_hashes.is_empty() && !self.remote_hashes.is_empty() {
+            let (frame, local) = self.local_hashes.pop_front().unwrap();
+            let (remote_frame, remote) = self.remote_hashes.pop_front().unwrap();
+            if frame != remote_frame {
+                return Err(NetplayError::Protocol("hash for the wrong frame"));
+            }
+            if local != remote {
+                return Err(NetplayError::Desync { frame });
+            }
+        }
+        Ok(())
+    }
+
+    fn read<const N: usize>(&mut self) -> Result<[u8; N], NetplayError> {
+        let mut bytes = [0; N];
+        self.reader.read_exact(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    fn read_u8(&mut self) -> Result<u8, NetplayError> {
+        Ok(self.read::<1>()?[0])
+    }
+
+    fn read_u16(&mut self) -> Result<u16, NetplayError> {
+        Ok(u16::from_be_bytes(self.read()?))
+    }
+
+    fn read_u32(&mut self) -> Result<u32, NetplayError> {
+        Ok(u32::from_be_bytes(self.read()?))
+    }
+}
+
+/// Packs the quirks into a byte, one bit per field.
+fn quirk_bits(quirks: Quirks) -> u8 {
+    [
+        quirks.vf_reset,
+        quirks.memory,
+        quirks.display_wait,
+        quirks.clipping,
+        quirks.shifting,
+        quirks.jumping,
+    ]
+    .iter()
+    .enumerate()
+    .fold(0, |bits, (idx, quirk)| bits | (*quirk as u8) << idx)
+}
@@ -0,0 +1,252 @@
+use crate::Emulator;
+
+/// Receives events from inside the interpreter, for tracers, profilers,
+/// debuggers and achievement systems. Every method does nothing by
+/// default, so an observer only implements the events it needs.
+///
+/// Observers are passed to the `_observed` variants of the run methods and
+/// are static dispatch, so the plain methods (which use `()`, the observer
+/// that ignores everything) cost nothing extra.
+pub trait Observer {
+    /// An instruction was fetched, before it runs.
+    ///
+    /// #### Parameters:
+    /// - address: Where the instruction is.
+    /// - opcode: The raw instruction.
+    ///
+    fn on_fetch(&mut self, address: u16, opcode: u16) {
+        let _ = (address, opcode);
+    }
+
+    /// DXYN drew a sprite.
+    ///
+    /// #### Parameters:
+    /// - x: The column of the top left corner, after wrapping.
+    /// - y: The row of the top left corner, after wrapping.
+    /// - height: The number of rows drawn.
+    /// - collision: Whether any pixel was turned off, the new value of VF.
+    ///
+    fn on_draw(&mut self, x: u8, y: u8, height: u8, collision: bool) {
+        let _ = (x, y, height, collision);
+    }
+
+    /// 00E0 cleared the screen.
+    fn on_clear(&mut self) {}
+
+    /// The buzzer turned on or off. It's on while the sound timer is
+    /// non-zero, so it starts when FX18 sets the timer and stops when
+    /// `timer_tick` counts it down to zero or FX18 sets it to zero.
+    ///
+    /// #### Parameters:
+    /// - playing: Whether the buzzer is now on.
+    ///
+    fn on_sound(&mut self, playing: bool) {
+        let _ = playing;
+    }
+
+    /// An instruction read a byte of RAM as data, a sprite row for DXYN or
+    /// a register value for FX65.
+    ///
+    /// #### Parameters:
+    /// - address: The address read.
+    /// - value: The value read.
+    ///
+    fn on_ram_read(&mut self, address: u16, value: u8) {
+        let _ = (address, value);
+    }
+
+    /// An instruction wrote a byte of RAM (FX33 or FX55).
+    ///
+    /// #### Parameters:
+    /// - address: The address written.
+    /// - value: The new value.
+    ///
+    fn on_ram_write(&mut self, address: u16, value: u8) {
+        let _ = (address, value);
+    }
+
+    /// 2NNN called a subroutine.
+    ///
+    /// #### Parameters:
+    /// - address: Where the call instruction is.
+    /// - target: The start of the subroutine.
+    ///
+    fn on_call(&mut self, address: u16, target: u16) {
+        let _ = (address, target);
+    }
+
+    /// 00EE returned from a subroutine.
+    ///
+    /// #### Parameters:
+    /// - address: Where the return instruction is.
+    /// - target: The address returned to.
+    ///
+    fn on_return(&mut self, address: u16, target: u16) {
+        let _ = (address, target);
+    }
+}
+
+/// Ignores every event.
+impl Observer for () {}
+
+impl<T: Observer + ?Sized> Observer for &mut T {
+    fn on_fetch(&mut self, address: u16, opcode: u16) {
+        (**self).on_fetch(address, opcode);
+    }
+
+    fn on_draw(&mut self, x: u8, y: u8, height: u8, collision: bool) {
+        (**self).on_draw(x, y, height, collision);
+    }
+
+    fn on_clear(&mut self) {
+        (**self).on_clear();
+    }
+
+    fn on_sound(&mut self, playing: bool) {
+        (**self).on_sound(playing);
+    }
+
+    fn on_ram_read(&mut self, address: u16, value: u8) {
+        (**self).on_ram_read(address, value);
+    }
+
+    fn on_ram_write(&mut self, address: u16, value: u8) {
+        (**self).on_ram_write(address, value);
+    }
+
+    fn on_call(&mut self, address: u16, target: u16) {
+        (**self).on_call(address, target);
+    }
+
+    fn on_return(&mut self, address: u16, target: u16) {
+        (**self).on_return(address, target);
+    }
+}
+
+/// Reports to the observer if there is one, so optional tools can share a
+/// run.
+impl<T: Observer> Observer for Option<T> {
+    fn on_fetch(&mut self, address: u16, opcode: u16) {
+        if let Some(observer) = self {
+            observer.on_fetch(address, opcode);
+        }
+    }
+
+    fn on_draw(&mut self, x: u8, y: u8, height: u8, collision: bool) {
+        if let Some(observer) = self {
+            observer.on_draw(x, y, height, collision);
+        }
+    }
+
+    fn on_clear(&mut self) {
+        if let Some(observer) = self {
+            observer.on_clear();
+        }
+    }
+
+    fn on_sound(&mut self, playing: bool) {
+        if let Some(observer) = self {
+            observer.on_sound(playing);
+        }
+    }
+
+    fn on_ram_read(&mut self, address: u16, value: u8) {
+        if let Some(observer) = self {
+            observer.on_ram_read(address, value);
+        }
+    }
+
+    fn on_ram_write(&mut self, address: u16, value: u8) {
+        if let Some(observer) = self {
+            observer.on_ram_write(address, value);
+        }
+    }
+
+    fn on_call(&mut self, address: u16, target: u16) {
+        if let Some(observer) = self {
+            observer.on_call(address, target);
+        }
+    }
+
+    fn on_return(&mut self, address: u16, target: u16) {
+        if let Some(observer) = self {
+            observer.on_return(address, target);
+        }
+    }
+}
+
+/// Reports every event to both observers, first to second.
+impl<A: Observer, B: Observer> Observer for (A, B) {
+    fn on_fetch(&mut self, address: u16, opcode: u16) {
+        self.0.on_fetch(address, opcode);
+        self.1.on_fetch(address, opcode);
+    }
+
+    fn on_draw(&mut self, x: u8, y: u8, height: u8, collision: bool) {
+        self.0.on_draw(x, y, height, collision);
+        self.1.on_draw(x, y, height, collision);
+    }
+
+    fn on_clear(&mut self) {
+        self.0.on_clear();
+        self.1.on_clear();
+    }
+
+    fn on_sound(&mut self, playing: bool) {
+        self.0.on_sound(playing);
+        self.1.on_sound(playing);
+    }
+
+    fn on_ram_read(&mut self, address: u16, value: u8) {
+        self.0.on_ram_read(address, value);
+        self.1.on_ram_read(address, value);
+    }
+
+    fn on_ram_write(&mut self, address: u16, value: u8) {
+        self.0.on_ram_write(address, value);
+        self.1.on_ram_write(address, value);
+    }
+
+    fn on_call(&mut self, address: u16, target: u16) {
+        self.0.on_call(address, target);
+        self.1.on_call(address, target);
+    }
+
+    fn on_return(&mut self, address: u16, target: u16) {
+        self.0.on_return(address, target);
+        self.1.on_return(address, target);
+    }
+}
+
+/// Running with an observer.
+impl Emulator {
+    /// Runs one CPU cycle like `tick`, reporting what happens.
+    ///
+    /// #### Parameters:
+    /// - observer: Receives the events.
+    ///
+    pub fn tick_observed(&mut self, observer: &mut (impl Observer + ?Sized)) {
+        self.cpu_observed(observer).tick();
+    }
+
+    /// Ticks the timers like `timer_tick`, reporting what happens.
+    ///
+    /// #### Parameters:
+    /// - observer: Receives the events.
+    ///
+    pub fn timer_tick_observed(&mut self, observer: &mut (impl Observer + ?Sized)) {
+        self.cpu_observed(observer).timer_tick();
+    }
+
+    /// Emulates one frame like `run_frame`, reporting what happens.
+    ///
+    /// #### Parameters:
+    /// - observer: Receives the events.
+    ///
+    pub fn run_frame_observed(&mut self, observer: &mut (impl Observer + ?Sized)) {
+        for _ in 0..self.ticks_per_frame {
+            self.tick_observed(observer);
+        }
+        self.timer_tick_observed(observer);
+    }
+}
@@ -0,0 +1,302 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use crate::observer::Observer;
+use crate::RAM_SIZE;
+
+/// Loops of at most this many instructions that read the delay timer are
+/// counted as waiting on it, like `loop: VX = DT; skip if VX == 0; jump
+/// loop`.
+const DELAY_WAIT_INSTRUCTIONS: usize = 4;
+
+/// Where the cycles of one subroutine went.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SubroutineProfile {
+    /// The address 2NNN called, or `None` for code outside any subroutine.
+    pub entry: Option<u16>,
+    /// The number of times it was called.
+    pub calls: u64,
+    /// Cycles spent in the subroutine itself.
+    pub self_cycles: u64,
+    /// Cycles spent in the subroutine and everything it called.
+    pub total_cycles: u64,
+}
+
+/// A backward jump that was taken, closing a loop.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HotLoop {
+    /// The address jumped back to, the top of the loop.
+    pub start: u16,
+    /// The address of the last instruction before the jump back.
+    pub end: u16,
+    /// The number of times the loop went round.
+    pub iterations: u64,
+    /// Cycles spent on the instructions from `start` to `end`.
+    pub cycles: u64,
+    /// Whether the loop is a short spin reading the delay timer.
+    pub delay_wait: bool,
+}
+
+/// Counts where the CPU cycles go, as an observer passed to
+/// `Emulator::run_frame_observed`. Cycles are counted per instruction
+/// address, attributed to the subroutine 2NNN entered and to the loops
+/// closed by backward jumps.
+pub struct Profiler {
+    /// Executions of the instruction at each address.
+    counts: Vec<u64>,
+    /// The opcode last fetched at each address.
+    opcodes: Vec<u16>,
+    /// The entry addresses of the subroutines being run, innermost last.
+    stack: Vec<u16>,
+    /// Cycles per call stack, for the flame graph.
+    stacks: BTreeMap<Vec<u16>, u64>,
+    /// Calls per subroutine entry address.
+    calls: BTreeMap<u16, u64>,
+    /// Iterations per loop, keyed by its start and end.
+    loops: BTreeMap<(u16, u16), u64>,
+    /// The address fetched last, unless a call or return came after it.
+    previous: Option<u16>,
+}
+
+impl Profiler {
+    /// Constructor.
+    pub fn new() -> Self {
+        Self {
+            counts: vec![0; RAM_SIZE],
+            opcodes: vec![0; RAM_SIZE],
+            stack: Vec::new(),
+            stacks: BTreeMap::new(),
+            calls: BTreeMap::new(),
+            loops: BTreeMap::new(),
+            previous: None,
+        }
+    }
+
+    /// Returns the number of cycles profiled.
+    pub fn total_cycles(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Returns how many times the instruction at an address ran.
+    ///
+    /// #### Parameters:
+    /// - address: The instruction address.
+    ///
+    pub fn count(&self, address: u16) -> u64 {
+        self.counts[address as usize % RAM_SIZE]
+    }
+
+    /// Returns the subroutines that ran, most total cycles first. Code
+    /// outside any subroutine comes first as `None`.
+    pub fn subroutines(&self) -> Vec<SubroutineProfile> {
+        // Self and total cycles per subroutine.
+        let mut cycles: BTreeMap<Option<u16>, (u64, u64)> = BTreeMap::new();
+        for (stack, count) in &self.stacks {
+            // Recursive subroutines count once towards their own total.
+            let frames: BTreeSet<Option<u16>> = std::iter::once(None)
+                .chain(stack.iter().copied().map(Some))
+                .collect();
+            for frame in frames {
+                cycles.entry(frame).or_default().1 += count;
+            }
+            cycles.entry(stack.last().copied()).or_default().0 += count;
+        }
+        let mut subroutines: Vec<SubroutineProfile> = cycles
+            .into_iter()
+            .map(|(entry, (self_cycles, total_cycles))| SubroutineProfile {
+                entry,
+                calls: entry.map_or(0, |entry| self.calls.get(&entry).copied().unwrap_or(0)),
+                self_cycles,
+                total_cycles,
+            })
+            .collect();
+        subroutines.sort_by_key(|profile| (std::cmp::Reverse(profile.total_cycles), profile.entry));
+        subroutines
+    }
+
+    /// Returns the loops that ran, most cycles first.
+    pub fn hot_loops(&self) -> Vec<HotLoop> {
+        let mut loops: Vec<HotLoop> = self
+            .loops
+            .iter()
+            .map(|(&(start, end), &iterations)| HotLoop {
+                start,
+                end,
+                iterations,
+                cycles: self.counts[start as usize..=end as usize].iter().sum(),
+                delay_wait: self.is_delay_wait(start, end),
+            })
+            .collect();
+        loops.sort_by_key(|hot_loop| (std::cmp::Reverse(hot_loop.cycles), hot_loop.start));
+        loops
+    }
+
+    /// Returns the cycles spent spinning on the delay timer.
+    pub fn delay_wait_cycles(&self) -> u64 {
+        let addresses: BTreeSet<usize> = self
+            .loops
+            .keys()
+            .filter(|(start, end)| self.is_delay_wait(*start, *end))
+            .flat_map(|(start, end)| *start as usize..=*end as usize)
+            .collect();
+        addresses
+            .into_iter()
+            .map(|address| self.counts[address])
+            .sum()
+    }
+
+    /// Formats the profile as a text report: the hottest instructions,
+    /// subroutines and loops, each sorted by cycles.
+    pub fn report(&self) -> String {
+        let total = self.total_cycles();
+        let share = |cycles: u64| match total {
+            0 => 0.0,
+            _ => cycles as f64 * 100.0 / total as f64,
+        };
+        let mut out = String::new();
+        let _ = writeln!(out, "Total cycles: {}", total);
+
+        let mut addresses: Vec<usize> = (0..RAM_SIZE).filter(|a| self.counts[*a] > 0).collect();
+        addresses.sort_by_key(|address| (std::cmp::Reverse(self.counts[*address]), *address));
+        let _ = writeln!(out, "\nInstructions:");
+        let _ = writeln!(
+            out,
+            "  {:<8} {:<6} {:>12} {:>7}",
+            "Address", "Opcode", "Cycles", "Share"
+        );
+        for address in addresses {
+            let cycles = self.counts[address];
+            let _ = writeln!(
+                out,
+                "  {:#05X}    {:04X}   {:>12} {:>6.1}%",
+                address,
+                self.opcodes[address],
+                cycles,
+                share(cycles)
+            );
+        }
+
+        let _ = writeln!(out, "\nSubroutines:");
+        let _ = writeln!(
+            out,
+            "  {:<8} {:>10} {:>12} {:>12} {:>7}",
+            "Entry", "Calls", "Self", "Total", "Share"
+        );
+        for subroutine in self.subroutines() {
+            let _ = writeln!(
+                out,
+                "  {:<8} {:>10} {:>12} {:>12} {:>6.1}%",
+                frame_name(subroutine.entry),
+                match subroutine.entry {
+                    Some(_) => subroutine.calls.to_string(),
+                    None => "-".to_string(),
+                },
+                subroutine.self_cycles,
+                subroutine.total_cycles,
+                share(subroutine.total_cycles)
+            );
+        }
+
+        let _ = writeln!(out, "\nHot loops:");
+        let _ = writeln!(
+            out,
+            "  {:<13} {:>10} {:>12} {:>7}",
+            "Range", "Iterations", "Cycles", "Share"
+        );
+        for hot_loop in self.hot_loops() {
+            let _ = writeln!(
+                out,
+                "  {:#05X}-{:#05X} {:>10} {:>12} {:>6.1}%{}",
+                hot_loop.start,
+                hot_loop.end,
+                hot_loop.iterations,
+                hot_loop.cycles,
+                share(hot_loop.cycles),
+                if hot_loop.delay_wait {
+                    "  delay timer wait"
+                } else {
+                    ""
+                }
+            );
+        }
+
+        let waiting = self.delay_wait_cycles();
+        let _ = writeln!(
+            out,
+            "\nWaiting on the delay timer: {} cycles ({:.1}%)",
+            waiting,
+            share(waiting)
+        );
+        out
+    }
+
+    /// Formats the cycles per call stack in the collapsed format read by
+    /// flame graph tools (`main;0x20A;0x23C 1234` per line), with code
+    /// outside any subroutine as `main`.
+    pub fn collapsed_stacks(&self) -> String {
+        let mut out = String::new();
+        for (stack, cycles) in &self.stacks {
+            let _ = write!(out, "{}", frame_name(None));
+            for entry in stack {
+                let _ = write!(out, ";{}", frame_name(Some(*entry)));
+            }
+            let _ = writeln!(out, " {}", cycles);
+        }
+        out
+    }
+
+    /// Returns whether a loop is a short spin that reads the delay timer.
+    fn is_delay_wait(&self, start: u16, end: u16) -> bool {
+        let instructions = (end - start) as usize / 2 + 1;
+        instructions <= DELAY_WAIT_INSTRUCTIONS
+            && (start..=end)
+                .step_by(2)
+                .any(|address| self.opcodes[address as usize] & 0xF0FF == 0xF007)
+    }
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Observer for Profiler {
+    fn on_fetch(&mut self, address: u16, opcode: u16) {
+        self.counts[address as usize] += 1;
+        self.opcodes[address as usize] = opcode;
+        match self.stacks.get_mut(self.stack.as_slice()) {
+            Some(cycles) => *cycles += 1,
+            None => {
+                self.stacks.insert(self.stack.clone(), 1);
+            }
+        }
+        if let Some(previous) = self.previous {
+            if address <= previous {
+                *self.loops.entry((address, previous)).or_insert(0) += 1;
+            }
+        }
+        self.previous = Some(address);
+    }
+
+    fn on_call(&mut self, _address: u16, target: u16) {
+        self.stack.push(target);
+        *self.calls.entry(target).or_insert(0) += 1;
+        self.previous = None;
+    }
+
+    fn on_return(&mut self, _address: u16, _target: u16) {
+        // Profiling may have started inside a subroutine, in which case
+        // there's nothing to pop.
+        self.stack.pop();
+        self.previous = None;
+    }
+}
+
+/// Names a subroutine by its entry address.
+fn frame_name(entry: Option<u16>) -> String {
+    match entry {
+        Some(entry) => format!("{:#05X}", entry),
+        None => "main".to_string(),
+    }
+}
@@ -0,0 +1,77 @@
+/// Behaviour differences between CHIP-8 interpreters. Games were written
+/// against whichever interpreter their author had, so the right settings
+/// depend on the ROM.
+///
+/// The default matches the behaviour this emulator has always had: every
+/// quirk is off except `shifting`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    /// 8XY1, 8XY2 and 8XY3 reset VF to 0 (COSMAC VIP).
+    pub vf_reset: bool,
+    /// FX55 and FX65 leave I pointing after the last register stored or
+    /// loaded, i.e. I is increased by X + 1 (COSMAC VIP, XO-CHIP).
+    pub memory: bool,
+    /// DXYN waits for the start of the next frame, limiting drawing to one
+    /// sprite per frame (COSMAC VIP).
+    pub display_wait: bool,
+    /// Sprites are cut off at the edges of the screen instead of wrapping
+    /// around to the other side. The start position always wraps.
+    pub clipping: bool,
+    /// 8XY6 and 8XYE shift VX in place and ignore VY (CHIP-48, SCHIP). When
+    /// off, VY is shifted and the result stored in VX.
+    pub shifting: bool,
+    /// BNNN is read as BXNN and jumps to XNN plus VX instead of NNN plus V0
+    /// (CHIP-48, SCHIP).
+    pub jumping: bool,
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self {
+            vf_reset: false,
+            memory: false,
+            display_wait: false,
+            clipping: false,
+            shifting: true,
+            jumping: false,
+        }
+    }
+}
+
+impl Quirks {
+    /// The original COSMAC VIP interpreter.
+    pub const fn chip8() -> Self {
+        Self {
+            vf_reset: true,
+            memory: true,
+            display_wait: true,
+            clipping: true,
+            shifting: false,
+            jumping: false,
+        }
+    }
+
+    /// SUPER-CHIP 1.1 (modern behaviour, without the legacy display wait).
+    pub const fn schip() -> Self {
+        Self {
+            vf_reset: false,
+            memory: false,
+            display_wait: false,
+            clipping: true,
+            shifting: true,
+            jumping: true,
+        }
+    }
+
+    /// XO-CHIP.
+    pub const fn xochip() -> Self {
+        Self {
+            vf_reset: false,
+            memory: true,
+            display_wait: false,
+            clipping: false,
+            shifting: false,
+            jumping: false,
+        }
+    }
+}
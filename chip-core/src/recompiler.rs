@@ -0,0 +1,176 @@
+use crate::instruction::Instruction;
+use crate::{Emulator, ADDRESS_MASK, RAM_SIZE};
+
+/// The most instructions decoded into one block. Long runs of straight
+/// line code are split so a block never takes too long to compile.
+const MAX_BLOCK_LEN: usize = 64;
+
+/// A run of straight line code, decoded once and replayed from the cache
+/// until the RAM it came from is written to.
+struct Block {
+    /// The address of the first instruction.
+    start: u16,
+    /// The number of bytes of RAM the block was decoded from.
+    len: u16,
+    /// The decoded instructions. Only the last one can end the block.
+    instructions: Vec<Instruction>,
+}
+
+impl Block {
+    /// Decodes the block starting at an address. Blocks end at the first
+    /// instruction that branches, stops the CPU or writes to RAM, and never
+    /// wrap around the end of RAM.
+    fn compile(ram: &[u8; RAM_SIZE], start: u16) -> Self {
+        let mut instructions = Vec::new();
+        let mut address = start;
+        loop {
+            let opcode = u16::from_be_bytes([
+                ram[address as usize],
+                ram[((address + 1) & ADDRESS_MASK) as usize],
+            ]);
+            let instruction = Instruction::decode(opcode);
+            instructions.push(instruction);
+            address += 2;
+            if instruction.ends_block()
+                || instructions.len() == MAX_BLOCK_LEN
+                || address as usize >= RAM_SIZE - 1
+            {
+                break;
+            }
+        }
+        Self {
+            start,
+            len: address - start,
+            instructions,
+        }
+    }
+
+    /// The addresses the block was decoded from.
+    fn addresses(&self) -> impl Iterator<Item = usize> + '_ {
+        // The last instruction can wrap around to address 0.
+        (0..self.len).map(|offset| ((self.start + offset) & ADDRESS_MASK) as usize)
+    }
+}
+
+/// An alternative to `Emulator::tick` for long headless runs. Straight line
+/// code is decoded into blocks the first time it runs and replayed from a
+/// cache after that, skipping the fetch and decode steps.
+///
+/// The result is exactly the same as running the interpreter, one tick at a
+/// time. Blocks are invalidated when FX33 or FX55 write over them, but RAM
+/// written from outside the emulator (`write_ram`, `load_rom`, cheats) isn't
+/// tracked, so call `invalidate` or `invalidate_all` after doing that.
+pub struct Recompiler {
+    /// Cached blocks by start address.
+    blocks: Vec<Option<Block>>,
+    /// The start addresses of the cached blocks decoded from each address,
+    /// so a write only has to look at the blocks it lands in.
+    covering: Vec<Vec<u16>>,
+}
+
+impl Recompiler {
+    /// Creates a recompiler with an empty cache.
+    pub fn new() -> Self {
+        Self {
+            blocks: (0..RAM_SIZE).map(|_| None).collect(),
+            covering: vec![Vec::new(); RAM_SIZE],
+        }
+    }
+
+    /// Returns the number of cached blocks.
+    pub fn block_count(&self) -> usize {
+        self.blocks.iter().flatten().count()
+    }
+
+    /// Drops every cached block, e.g. after loading a new ROM.
+    pub fn invalidate_all(&mut self) {
+        self.blocks.iter_mut().for_each(|block| *block = None);
+        self.covering.iter_mut().for_each(Vec::clear);
+    }
+
+    /// Drops the cached blocks decoded from a range of RAM.
+    ///
+    /// #### Parameters:
+    /// - address: The first address written.
+    /// - len: The number of bytes written, wrapping around at the end of
+    ///   RAM.
+    ///
+    pub fn invalidate(&mut self, address: usize, len: usize) {
+        for offset in 0..len.min(RAM_SIZE) {
+            let written = (address + offset) % RAM_SIZE;
+            while let Some(start) = self.covering[written].last() {
+                self.remove(*start);
+            }
+        }
+    }
+
+    /// Drops a cached block and its entries in the address index.
+    fn remove(&mut self, start: u16) {
+        if let Some(block) = self.blocks[start as usize].take() {
+            for address in block.addresses() {
+                self.covering[address].retain(|covering| *covering != start);
+            }
+        }
+    }
+
+    /// Runs CPU cycles, the same as calling `Emulator::tick` that many
+    /// times.
+    ///
+    /// #### Parameters:
+    /// - emulator: The emulator to run.
+    /// - ticks: The number of cycles.
+    ///
+    pub fn run(&mut self, emulator: &mut Emulator, ticks: usize) {
+        let mut remaining = ticks;
+        while remaining > 0 {
+            // The interpreter does nothing on these ticks, so neither do we.
+            if emulator.waiting_for_frame || emulator.fault.is_some() {
+                return;
+            }
+
+            let start = emulator.program_counter;
+            let covering = &mut self.covering;
+            let block = self.blocks[start as usize].get_or_insert_with(|| {
+                let block = Block::compile(&emulator.ram, start);
+                for address in block.addresses() {
+                    covering[address].push(start);
+                }
+                block
+            });
+
+            let mut written = None;
+            for instruction in &block.instructions {
+                if remaining == 0 {
+                    return;
+                }
+                remaining -= 1;
+                // Only the last instruction of a block can write to RAM, so
+                // the rest of the block is never stale.
+                if instruction.ram_write_len() > 0 {
+                    written = Some((emulator.i_register, instruction.ram_write_len()));
+                }
+                emulator.skip();
+                emulator.execute(*instruction);
+            }
+            if let Some((address, len)) = written {
+                self.invalidate(address as usize, len);
+            }
+        }
+    }
+
+    /// Emulates one 60 Hz frame, the same as `Emulator::run_frame`.
+    ///
+    /// #### Parameters:
+    /// - emulator: The emulator to run.
+    ///
+    pub fn run_frame(&mut self, emulator: &mut Emulator) {
+        self.run(emulator, emulator.ticks_per_frame);
+        emulator.timer_tick();
+    }
+}
+
+impl Default for Recompiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,300 @@
+use crate::display::{Framebuffer, Palette, Rgb};
+
+/// Bytes per pixel in the RGBA8 output.
+const BYTES_PER_PIXEL: usize = 4;
+
+/// How the rendered display is scaled up to the output size.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Upscaler {
+    /// Each display pixel becomes a `scale` by `scale` block.
+    Nearest(usize),
+    /// The Scale2x (EPX) pixel art scaler, doubles the size while rounding
+    /// off diagonal edges.
+    Scale2x,
+    /// Nearest neighbour scaling with the last row of every display pixel
+    /// darkened to mimic the gaps between CRT scanlines.
+    Scanlines {
+        /// The width and height of each display pixel in output pixels.
+        scale: usize,
+        /// Brightness of the scanline rows as a percentage of the pixel.
+        brightness: u8,
+    },
+}
+
+impl Default for Upscaler {
+    fn default() -> Self {
+        Upscaler::Nearest(1)
+    }
+}
+
+/// Converts a display into an RGBA8 image with optional post-processing.
+///
+/// CHIP-8 games erase and redraw sprites by XOR every frame, so moving
+/// objects are often only visible on every other frame. The renderer can
+/// hide this in two ways:
+/// - Phosphor persistence keeps a pixel glowing for a number of frames after
+///   it is turned off, fading linearly to the background colour.
+/// - Frame blending averages each frame with the previous one.
+///
+/// The processed image is then upscaled. Everything runs on the CPU so the
+/// output can be handed to any frontend as a plain texture.
+pub struct Renderer {
+    /// The colours used for each pixel value.
+    palette: Palette,
+    /// Number of frames a pixel keeps glowing after being turned off.
+    phosphor_frames: u8,
+    /// Whether each frame is averaged with the previous one.
+    anti_flicker: bool,
+    /// How the image is scaled to the output size.
+    upscaler: Upscaler,
+    /// Display size of the previous frame, history is dropped on a change.
+    size: (usize, usize),
+    /// Colour each pixel had when it was last lit.
+    lit_colors: Vec<Rgb>,
+    /// Frames of glow left for each pixel.
+    glow: Vec<u8>,
+    /// The previous frame before blending, used by the anti-flicker filter.
+    previous: Vec<Rgb>,
+    /// The RGBA8 output buffer.
+    output: Vec<u8>,
+    /// Output width in pixels.
+    output_width: usize,
+    /// Output height in pixels.
+    output_height: usize,
+}
+
+impl Renderer {
+    /// Constructor, creates a renderer with no post-processing and no
+    /// scaling.
+    ///
+    /// #### Parameters:
+    /// - palette: The colours to use for each pixel value.
+    ///
+    pub fn new(palette: Palette) -> Self {
+        Self {
+            palette,
+            phosphor_frames: 0,
+            anti_flicker: false,
+            upscaler: Upscaler::default(),
+            size: (0, 0),
+            lit_colors: Vec::new(),
+            glow: Vec::new(),
+            previous: Vec::new(),
+            output: Vec::new(),
+            output_width: 0,
+            output_height: 0,
+        }
+    }
+
+    /// Set the colours used for each pixel value.
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+    }
+
+    /// Set how many frames a pixel keeps glowing after it is turned off
+    /// (0 disables phosphor persistence). Pixels already fading are cut
+    /// short to the new length.
+    pub fn set_phosphor_frames(&mut self, frames: u8) {
+        self.phosphor_frames = frames;
+        for glow in &mut self.glow {
+            *glow = (*glow).min(frames);
+        }
+    }
+
+    /// Enable or disable blending each frame with the previous one.
+    pub fn set_anti_flicker(&mut self, enabled: bool) {
+        self.anti_flicker = enabled;
+        self.previous.clear();
+    }
+
+    /// Set how the image is scaled to the output size.
+    pub fn set_upscaler(&mut self, upscaler: Upscaler) {
+        self.upscaler = upscaler;
+    }
+
+    /// Forget the frame history used by phosphor persistence and frame
+    /// blending, should be called when a new ROM is loaded.
+    pub fn reset(&mut self) {
+        self.size = (0, 0);
+        self.lit_colors.clear();
+        self.glow.clear();
+        self.previous.clear();
+    }
+
+    /// Output width in pixels of the last rendered frame.
+    pub fn width(&self) -> usize {
+        self.output_width
+    }
+
+    /// Output height in pixels of the last rendered frame.
+    pub fn height(&self) -> usize {
+        self.output_height
+    }
+
+    /// Renders the next frame. Should be called once per emulated frame so
+    /// the persistence effects fade at the right speed.
+    ///
+    /// #### Parameters:
+    /// - fb: The display to render.
+    ///
+    /// #### Returns:
+    /// - The frame as row major RGBA8 pixels, `width() * height() * 4` bytes.
+    ///
+    pub fn render<F: Framebuffer + ?Sized>(&mut self, fb: &F) -> &[u8] {
+        let (width, height) = (fb.width(), fb.height());
+        if self.size != (width, height) {
+            self.reset();
+            self.size = (width, height);
+        }
+
+        let mut frame: Vec<Rgb> = (0..width * height)
+            .map(|idx| self.palette.color(fb.pixel(idx % width, idx / width)))
+            .collect();
+        if self.phosphor_frames > 0 {
+            self.apply_phosphor(fb, &mut frame);
+        }
+        if self.anti_flicker {
+            self.apply_blend(&mut frame);
+        }
+
+        match self.upscaler {
+            Upscaler::Nearest(scale) => self.upscale_nearest(&frame, scale, 100),
+            Upscaler::Scanlines { scale, brightness } => {
+                self.upscale_nearest(&frame, scale, brightness.min(100))
+            }
+            Upscaler::Scale2x => self.upscale_scale2x(&frame),
+        }
+        &self.output
+    }
+
+    /// Keeps recently turned off pixels glowing, fading linearly from the
+    /// colour they were lit with to the background colour.
+    fn apply_phosphor<F: Framebuffer + ?Sized>(&mut self, fb: &F, frame: &mut [Rgb]) {
+        let width = fb.width();
+        if self.glow.len() != frame.len() {
+            self.glow = vec![0; frame.len()];
+            self.lit_colors = vec![self.palette.color(0); frame.len()];
+        }
+
+        let background = self.palette.color(0);
+        let steps = self.phosphor_frames as u32 + 1;
+        for (idx, color) in frame.iter_mut().enumerate() {
+            if fb.pixel(idx % width, idx / width) != 0 {
+                self.lit_colors[idx] = *color;
+                self.glow[idx] = self.phosphor_frames;
+            } else if self.glow[idx] > 0 {
+                *color = mix(
+                    background,
+                    self.lit_colors[idx],
+                    self.glow[idx] as u32,
+                    steps,
+                );
+                self.glow[idx] -= 1;
+            }
+        }
+    }
+
+    /// Averages the frame with the previous one.
+    fn apply_blend(&mut self, frame: &mut [Rgb]) {
+        let current = frame.to_vec();
+        if self.previous.len() == frame.len() {
+            for (color, previous) in frame.iter_mut().zip(&self.previous) {
+                *color = mix(*color, *previous, 1, 2);
+            }
+        }
+        self.previous = current;
+    }
+
+    /// Resizes the output buffer for a new output size.
+    fn resize_output(&mut self, width: usize, height: usize) {
+        self.output_width = width;
+        self.output_height = height;
+        self.output.resize(width * height * BYTES_PER_PIXEL, 0);
+    }
+
+    /// Writes an output pixel.
+    fn put(&mut self, x: usize, y: usize, color: Rgb) {
+        let offset = (x + self.output_width * y) * BYTES_PER_PIXEL;
+        self.output[offset..offset + BYTES_PER_PIXEL]
+            .copy_from_slice(&[color.r, color.g, color.b, 0xFF]);
+    }
+
+    /// Nearest neighbour scaling, darkening the last row of every display
+    /// pixel to `brightness` percent (100 leaves the image untouched).
+    fn upscale_nearest(&mut self, frame: &[Rgb], scale: usize, brightness: u8) {
+        let scale = scale.max(1);
+        let (width, height) = self.size;
+        self.resize_output(width * scale, height * scale);
+
+        let black = Rgb::new(0, 0, 0);
+        for y in 0..height * scale {
+            let scanline = scale > 1 && brightness < 100 && y % scale == scale - 1;
+            for x in 0..width * scale {
+                let mut color = frame[x / scale + width * (y / scale)];
+                if scanline {
+                    color = mix(black, color, brightness as u32, 100);
+                }
+                self.put(x, y, color);
+            }
+        }
+    }
+
+    /// Scale2x: each pixel `P` becomes a 2x2 block. A corner takes the colour
+    /// of its two neighbouring edge pixels when they match each other but not
+    /// the pixels across from them, which smooths diagonal lines.
+    fn upscale_scale2x(&mut self, frame: &[Rgb]) {
+        let (width, height) = self.size;
+        self.resize_output(width * 2, height * 2);
+
+        let at = |x: isize, y: isize| {
+            let x = x.clamp(0, width as isize - 1) as usize;
+            let y = y.clamp(0, height as isize - 1) as usize;
+            frame[x + width * y]
+        };
+        for y in 0..height as isize {
+            for x in 0..width as isize {
+                let p = at(x, y);
+                let a = at(x, y - 1);
+                let b = at(x + 1, y);
+                let c = at(x - 1, y);
+                let d = at(x, y + 1);
+
+                let (mut e0, mut e1, mut e2, mut e3) = (p, p, p, p);
+                if a != d && c != b {
+                    if c == a {
+                        e0 = a;
+                    }
+                    if a == b {
+                        e1 = b;
+                    }
+                    if c == d {
+                        e2 = c;
+                    }
+                    if d == b {
+                        e3 = d;
+                    }
+                }
+
+                let (ox, oy) = (x as usize * 2, y as usize * 2);
+                self.put(ox, oy, e0);
+                self.put(ox + 1, oy, e1);
+                self.put(ox, oy + 1, e2);
+                self.put(ox + 1, oy + 1, e3);
+            }
+        }
+    }
+}
+
+/// Linearly interpolates between two colours, `numerator / denominator` of
+/// the way from `from` to `to`.
+fn mix(from: Rgb, to: Rgb, numerator: u32, denominator: u32) -> Rgb {
+    let channel = |from: u8, to: u8| {
+        let (from, to) = (from as u32, to as u32);
+        ((from * (denominator - numerator) + to * numerator) / denominator) as u8
+    };
+    Rgb::new(
+        channel(from.r, to.r),
+        channel(from.g, to.g),
+        channel(from.b, to.b),
+    )
+}
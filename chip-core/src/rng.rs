@@ -0,0 +1,52 @@
+/// The random number generator behind CXNN (SplitMix64). The state is
+/// part of the machine so runs can be reproduced from a seed, which golden
+/// image tests, replays and netplay all depend on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    /// Constructor.
+    ///
+    /// #### Parameters:
+    /// - seed: Any value, each seed gives a different sequence.
+    ///
+    pub const fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// Returns the internal state, which `new` turns back into a generator
+    /// that continues the same sequence.
+    pub const fn state(&self) -> u64 {
+        self.state
+    }
+
+    /// Creates a generator with a seed from the operating system.
+    #[cfg(feature = "std")]
+    pub fn from_entropy() -> Self {
+        Self::new(rand::random())
+    }
+
+    /// Returns the next random 64-bit value.
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Returns the next random byte.
+    pub fn next_u8(&mut self) -> u8 {
+        // The high bits are the best mixed.
+        (self.next_u64() >> 56) as u8
+    }
+
+    /// Returns a random number in [0, 1).
+    #[cfg(feature = "std")]
+    pub fn next_f32(&mut self) -> f32 {
+        // 24 bits is all the precision an f32 has.
+        (self.next_u64() >> 40) as f32 / (1 << 24) as f32
+    }
+}
@@ -0,0 +1,300 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, OnceLock};
+
+use crate::display::{Palette, Rgb};
+use crate::error::ParseError;
+use crate::hash::{parse_sha1, sha1};
+use crate::json::{Document, Value};
+use crate::quirks::Quirks;
+use crate::NUM_KEYS;
+
+/// The database built into the crate, in the community database's
+/// `programs.json` format. It only lists the ROMs in this repository, the
+/// community database itself isn't bundled.
+const EMBEDDED: &str = include_str!("../data/programs.json");
+
+/// The interpreter family a ROM was written for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Platform {
+    Chip8,
+    Schip,
+    XoChip,
+}
+
+impl Platform {
+    /// Returns the platform for a community database platform id, or `None`
+    /// for platforms this emulator doesn't run.
+    ///
+    /// #### Parameters:
+    /// - id: The platform id, such as `originalChip8` or `superchip`.
+    ///
+    pub fn from_id(id: &str) -> Option<Self> {
+        match id {
+            "originalChip8" | "hybridVIP" => Some(Platform::Chip8),
+            "chip48" | "superchip1" | "superchip" => Some(Platform::Schip),
+            "xochip" => Some(Platform::XoChip),
+            _ => None,
+        }
+    }
+
+    /// Returns the quirks of the platform's interpreter.
+    pub fn quirks(&self) -> Quirks {
+        match self {
+            Platform::Chip8 => Quirks::chip8(),
+            Platform::Schip => Quirks::schip(),
+            Platform::XoChip => Quirks::xochip(),
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Platform::Chip8 => write!(f, "CHIP-8"),
+            Platform::Schip => write!(f, "SCHIP"),
+            Platform::XoChip => write!(f, "XO-CHIP"),
+        }
+    }
+}
+
+/// What the database knows about a ROM and how it should be run.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RomInfo {
+    /// The program's title.
+    pub title: String,
+    /// The platform it was written for.
+    pub platform: Platform,
+    /// The platform's quirks with any the ROM needs changed.
+    pub quirks: Quirks,
+    /// CPU cycles per frame, if the ROM needs a particular speed.
+    pub tick_rate: Option<usize>,
+    /// CHIP-8 keys by what they do in the game, such as `("up", 5)`.
+    pub keys: Vec<(String, u8)>,
+    /// The colours the ROM was designed with.
+    pub palette: Option<Palette>,
+}
+
+/// ROM information keyed by the SHA-1 of the ROM image, read from JSON in
+/// the format of the community chip-8-database's `programs.json`:
+///
+/// ```json
+/// [{"title": "Pong", "roms": {"<sha1>": {
+///     "platforms": ["originalChip8"], "tickrate": 15,
+///     "quirkyPlatforms": {"originalChip8": {"vblank": false}},
+///     "keys": {"up": 1, "down": 4},
+///     "colors": {"pixels": ["#000000", "#ffffff"]}}}}]
+/// ```
+///
+/// A ROM runs on the first of its platforms this emulator supports, ROMs
+/// with none are left out.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RomDatabase {
+    roms: BTreeMap<[u8; 20], RomInfo>,
+}
+
+impl RomDatabase {
+    /// Constructor, for an empty database.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the database built into the crate, which new emulators use.
+    /// It only knows the die roll demo, extend it with the community
+    /// database's `programs.json` to identify other games.
+    pub fn embedded() -> Arc<RomDatabase> {
+        static EMBEDDED_DATABASE: OnceLock<Arc<RomDatabase>> = OnceLock::new();
+        EMBEDDED_DATABASE
+            .get_or_init(|| {
+                Arc::new(RomDatabase::parse(EMBEDDED).expect("the embedded ROM database is valid"))
+            })
+            .clone()
+    }
+
+    /// Parses a database.
+    ///
+    /// #### Parameters:
+    /// - text: The JSON, a list of programs.
+    ///
+    /// #### Returns:
+    /// - The database, or the first error in it.
+    ///
+    pub fn parse(text: &str) -> Result<Self, ParseError> {
+        let document = Document::parse(text)?;
+        let invalid = |value, reason| ParseError {
+            line: document.line(value),
+            reason,
+        };
+        let json = &document.value;
+        let programs = json
+            .as_array()
+            .ok_or_else(|| invalid(json, "expected a list of programs"))?;
+        let mut database = RomDatabase::new();
+        for program in programs {
+            let title = program
+                .get("title")
+                .and_then(Value::as_str)
+                .ok_or_else(|| invalid(program, "a program has no title"))?;
+            let roms = program
+                .get("roms")
+                .and_then(Value::as_object)
+                .ok_or_else(|| invalid(program, "a program has no roms"))?;
+            for (hash, rom) in roms {
+                let hash = parse_sha1(hash).ok_or_else(|| invalid(rom, "invalid SHA-1 hash"))?;
+                if let Some(info) = parse_rom(&document, title, rom)? {
+                    database.roms.insert(hash, info);
+                }
+            }
+        }
+        Ok(database)
+    }
+
+    /// Adds the ROMs of another database, replacing any already listed.
+    ///
+    /// #### Parameters:
+    /// - other: The database to add, such as a user's overrides.
+    ///
+    pub fn extend(&mut self, other: RomDatabase) {
+        self.roms.extend(other.roms);
+    }
+
+    /// Identifies a ROM image.
+    ///
+    /// #### Parameters:
+    /// - rom: The raw ROM bytes.
+    ///
+    /// #### Returns:
+    /// - The ROM's information, or `None` if it isn't in the database.
+    ///
+    pub fn lookup(&self, rom: &[u8]) -> Option<&RomInfo> {
+        self.get(&sha1(rom))
+    }
+
+    /// Returns the information for a ROM by its SHA-1.
+    ///
+    /// #### Parameters:
+    /// - hash: The SHA-1 of the ROM image.
+    ///
+    pub fn get(&self, hash: &[u8; 20]) -> Option<&RomInfo> {
+        self.roms.get(hash)
+    }
+
+    /// Returns the number of ROMs in the database.
+    pub fn len(&self) -> usize {
+        self.roms.len()
+    }
+
+    /// Returns whether the database has no ROMs.
+    pub fn is_empty(&self) -> bool {
+        self.roms.is_empty()
+    }
+}
+
+/// Parses one ROM's entry, or returns `None` if none of its platforms are
+/// supported. Errors are reported on the line of the value at fault.
+fn parse_rom(document: &Document, title: &str, rom: &Value) -> Result<Option<RomInfo>, ParseError> {
+    let invalid = |value, reason| ParseError {
+        line: document.line(value),
+        reason,
+    };
+    let platforms = rom
+        .get("platforms")
+        .and_then(Value::as_array)
+        .ok_or_else(|| invalid(rom, "a ROM has no platforms"))?;
+    let Some((id, platform)) = platforms.iter().find_map(|id| {
+        let id = id.as_str()?;
+        Some((id, Platform::from_id(id)?))
+    }) else {
+        return Ok(None);
+    };
+
+    let mut quirks = platform.quirks();
+    let overrides = rom
+        .get("quirkyPlatforms")
+        .and_then(|platforms| platforms.get(id));
+    if let Some(overrides) = overrides {
+        let entries = overrides
+            .as_object()
+            .ok_or_else(|| invalid(overrides, "quirks must be an object"))?;
+        for (name, value) in entries {
+            let value = value
+                .as_bool()
+                .ok_or_else(|| invalid(value, "a quirk must be true or false"))?;
+            match name.as_str() {
+                "logic" => quirks.vf_reset = value,
+                // Interpreters that add X to I instead of X + 1 are close
+                // enough to leaving it unchanged for the ROMs that rely on
+                // either.
+                "memoryLeaveIUnchanged" | "memoryIncrementByX" => quirks.memory = !value,
+                "vblank" => quirks.display_wait = value,
+                "wrap" => quirks.clipping = !value,
+                "shift" => quirks.shifting = value,
+                "jump" => quirks.jumping = value,
+                _ => (),
+            }
+        }
+    }
+
+    let tick_rate = match rom.get("tickrate") {
+        Some(tick_rate) => Some(
+            tick_rate
+                .as_u64()
+                .ok_or_else(|| invalid(tick_rate, "invalid tick rate"))? as usize,
+        ),
+        None => None,
+    };
+
+    let mut keys = Vec::new();
+    if let Some(bindings) = rom.get("keys") {
+        let entries = bindings
+            .as_object()
+            .ok_or_else(|| invalid(bindings, "keys must be an object"))?;
+        for (name, key) in entries {
+            match key.as_u64() {
+                Some(index) if index < NUM_KEYS as u64 => keys.push((name.clone(), index as u8)),
+                _ => return Err(invalid(key, "invalid CHIP-8 key")),
+            }
+        }
+    }
+
+    let palette = match rom.get("colors").and_then(|colors| colors.get("pixels")) {
+        Some(pixels) => {
+            let colors = pixels
+                .as_array()
+                .ok_or_else(|| invalid(pixels, "pixel colours must be a list"))?
+                .iter()
+                .map(|color| {
+                    color
+                        .as_str()
+                        .and_then(parse_color)
+                        .ok_or_else(|| invalid(color, "invalid colour"))
+                })
+                .collect::<Result<Vec<Rgb>, _>>()?;
+            Some(Palette::from_colors(&colors))
+        }
+        None => None,
+    };
+
+    Ok(Some(RomInfo {
+        title: title.to_string(),
+        platform,
+        quirks,
+        tick_rate,
+        keys,
+        palette,
+    }))
+}
+
+/// Parses a colour written as `#rrggbb`.
+fn parse_color(text: &str) -> Option<Rgb> {
+    let digits = text.strip_prefix('#')?;
+    if digits.len() != 6 || !digits.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return None;
+    }
+    let value = u32::from_str_radix(digits, 16).ok()?;
+    Some(Rgb::new(
+        (value >> 16) as u8,
+        (value >> 8) as u8,
+        value as u8,
+    ))
+}
@@ -0,0 +1,368 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+
+use crate::json::Value;
+use crate::screenshot::to_png;
+use crate::{Emulator, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+/// The request wasn't JSON.
+const PARSE_ERROR: i64 = -32700;
+/// The request was JSON but not a JSON-RPC request.
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// The emulator refused the request, such as a ROM too big for RAM.
+const EMULATOR_ERROR: i64 = -32000;
+
+/// The longest request line read, far more than a ROM or save state needs.
+const MAX_REQUEST_LENGTH: usize = 1 << 20;
+/// The most frames one `run_frames` call runs, a minute of play.
+const MAX_FRAMES: u64 = 3600;
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// The error member of a response.
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+/// Serves an emulator to other programs with JSON-RPC 2.0, one request or
+/// response per line. Clients load ROMs, press keys, run frames and read
+/// back the screen, memory and save states; the methods are documented in
+/// the chip-cli README.
+pub struct Server {
+    emulator: Emulator,
+}
+
+impl Server {
+    /// Constructor.
+    ///
+    /// #### Parameters:
+    /// - emulator: The emulator to serve, with a ROM loaded or not.
+    ///
+    pub fn new(emulator: Emulator) -> Self {
+        Self { emulator }
+    }
+
+    /// Returns the emulator being served.
+    pub fn emulator(&self) -> &Emulator {
+        &self.emulator
+    }
+
+    /// Answers requests from one client until it disconnects.
+    ///
+    /// #### Parameters:
+    /// - stream: The connection, such as a `TcpStream` or `UnixStream`.
+    ///
+    /// #### Returns:
+    /// - An error if reading or writing the connection failed.
+    ///
+    pub fn serve<S: Read + Write>(&mut self, stream: S) -> io::Result<()> {
+        let mut reader = BufReader::new(stream);
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            let limit = MAX_REQUEST_LENGTH as u64 + 1;
+            if (&mut reader).take(limit).read_until(b'\n', &mut line)? == 0 {
+                return Ok(());
+            }
+            if line.len() > MAX_REQUEST_LENGTH {
+                // The rest of the line can't be skipped without reading it
+                // all, so the client is dropped.
+                let error = RpcError::new(INVALID_REQUEST, "request too long");
+                return send(reader.get_mut(), response(Value::Null, Err(error)));
+            }
+            let response = match std::str::from_utf8(&line) {
+                Ok(request) if request.trim().is_empty() => continue,
+                Ok(request) => self.handle(request),
+                Err(_) => Some(response(
+                    Value::Null,
+                    Err(RpcError::new(PARSE_ERROR, "parse error")),
+                )),
+            };
+            if let Some(response) = response {
+                send(reader.get_mut(), response)?;
+            }
+        }
+    }
+
+    /// Runs one request.
+    ///
+    /// #### Parameters:
+    /// - request: The JSON-RPC request.
+    ///
+    /// #### Returns:
+    /// - The response, or `None` for a notification (a valid request
+    ///   without an id).
+    ///
+    pub fn handle(&mut self, request: &str) -> Option<String> {
+        // Nesting too deep to parse is a parse error like any other.
+        let request = match Value::parse(request) {
+            Ok(request) => request,
+            Err(_) => {
+                return Some(response(
+                    Value::Null,
+                    Err(RpcError::new(PARSE_ERROR, "parse error")),
+                ))
+            }
+        };
+        // Anything that isn't a valid request gets an error even without an
+        // id, so only well-formed notifications go unanswered.
+        let method = match check(&request) {
+            Ok(method) => method,
+            Err(error) => {
+                let id = match request.get("id") {
+                    Some(id @ (Value::Number(_) | Value::String(_))) => id.clone(),
+                    _ => Value::Null,
+                };
+                return Some(response(id, Err(error)));
+            }
+        };
+        let id = request.get("id").cloned();
+        let result = self.call(method, &request);
+        // Notifications get no response, even when they fail.
+        id.map(|id| response(id, result))
+    }
+
+    /// Runs a request's method.
+    fn call(&mut self, method: &str, request: &Value) -> Result<Value, RpcError> {
+        let no_params = Value::Object(Vec::new());
+        let params = match request.get("params") {
+            None => &no_params,
+            Some(params @ Value::Object(_)) => params,
+            Some(_) => return Err(RpcError::new(INVALID_PARAMS, "params must be an object")),
+        };
+
+        let emulator = &mut self.emulator;
+        match method {
+            "load_rom" => {
+                let rom = bytes_param(params, "rom")?;
+                emulator.load_rom(&rom).map_err(emulator_error)?;
+                Ok(Value::Null)
+            }
+            "reset" => {
+                emulator.reset();
+                Ok(Value::Null)
+            }
+            "press_key" | "release_key" => {
+                let key = number_param(params, "key")?;
+                emulator
+                    .keypress(key as usize, method == "press_key")
+                    .map_err(|_| RpcError::new(INVALID_PARAMS, "key must be 0 to 15"))?;
+                Ok(Value::Null)
+            }
+            "run_frames" => {
+                let frames = number_param(params, "frames")?;
+                if frames > MAX_FRAMES {
+                    return Err(RpcError::new(
+                        INVALID_PARAMS,
+                        format!("frames must be at most {}", MAX_FRAMES),
+                    ));
+                }
+                for _ in 0..frames {
+                    emulator.run_frame();
+                }
+                let fault = match emulator.fault() {
+                    Some(fault) => Value::String(fault.to_string()),
+                    None => Value::Null,
+                };
+                Ok(Value::Object(vec![("fault".to_string(), fault)]))
+            }
+            "get_screen" => {
+                let format = match params.get("format") {
+                    None => "bitmap",
+                    Some(format) => format
+                        .as_str()
+                        .ok_or(RpcError::new(INVALID_PARAMS, "format must be a string"))?,
+                };
+                let (width, height, data) = match format {
+                    "bitmap" => {
+                        let data = emulator
+                            .get_display()
+                            .chunks(8)
+                            .map(|pixels| {
+                                pixels
+                                    .iter()
+                                    .fold(0u8, |byte, pixel| byte << 1 | *pixel as u8)
+                            })
+                            .collect();
+                        (SCREEN_WIDTH, SCREEN_HEIGHT, data)
+                    }
+                    "png" => {
+                        let scale = match params.get("scale") {
+                            None => 1,
+                            Some(_) => number_param(params, "scale")?.clamp(1, 64) as usize,
+                        };
+                        let palette = emulator
+                            .rom_info()
+                            .and_then(|info| info.palette)
+                            .unwrap_or_default();
+                        let png = to_png(&*emulator, scale, &palette);
+                        (SCREEN_WIDTH * scale, SCREEN_HEIGHT * scale, png)
+                    }
+                    _ => {
+                        return Err(RpcError::new(
+                            INVALID_PARAMS,
+                            "format must be bitmap or png",
+                        ))
+                    }
+                };
+                Ok(Value::Object(vec![
+                    ("width".to_string(), Value::Number(width as f64)),
+                    ("height".to_string(), Value::Number(height as f64)),
+                    ("data".to_string(), Value::String(base64_encode(&data))),
+                ]))
+            }
+            "read_memory" => {
+                let address = number_param(params, "address")? as usize;
+                let length = number_param(params, "length")? as usize;
+                let data = emulator.read_ram(address, length).map_err(emulator_error)?;
+                Ok(Value::Object(vec![(
+                    "data".to_string(),
+                    Value::String(base64_encode(data)),
+                )]))
+            }
+            "write_memory" => {
+                let address = number_param(params, "address")? as usize;
+                let data = bytes_param(params, "data")?;
+                emulator.write_ram(address, &data).map_err(emulator_error)?;
+                Ok(Value::Null)
+            }
+            "save_state" => Ok(Value::Object(vec![(
+                "state".to_string(),
+                Value::String(base64_encode(&emulator.save_state())),
+            )])),
+            "load_state" => {
+                let state = bytes_param(params, "state")?;
+                emulator.load_state(&state).map_err(emulator_error)?;
+                Ok(Value::Null)
+            }
+            _ => Err(RpcError::new(METHOD_NOT_FOUND, "method not found")),
+        }
+    }
+}
+
+/// Checks that a request is a JSON-RPC 2.0 request object.
+///
+/// #### Returns:
+/// - The method's name, or an invalid request error.
+///
+fn check(request: &Value) -> Result<&str, RpcError> {
+    let invalid = || RpcError::new(INVALID_REQUEST, "invalid request");
+    if let Value::Array(_) = request {
+        return Err(RpcError::new(INVALID_REQUEST, "batches aren't supported"));
+    }
+    if !matches!(request, Value::Object(_))
+        || request.get("jsonrpc").and_then(Value::as_str) != Some("2.0")
+    {
+        return Err(invalid());
+    }
+    if let Some(Value::Bool(_) | Value::Array(_) | Value::Object(_)) = request.get("id") {
+        return Err(invalid());
+    }
+    request
+        .get("method")
+        .and_then(Value::as_str)
+        .ok_or_else(invalid)
+}
+
+/// Writes a response line.
+fn send(stream: &mut impl Write, mut response: String) -> io::Result<()> {
+    // One write per response, so Nagle's algorithm doesn't hold back the
+    // end of the line.
+    response.push('\n');
+    stream.write_all(response.as_bytes())?;
+    stream.flush()
+}
+
+/// Builds a response line.
+fn response(id: Value, result: Result<Value, RpcError>) -> String {
+    let outcome = match result {
+        Ok(result) => ("result".to_string(), result),
+        Err(error) => (
+            "error".to_string(),
+            Value::Object(vec![
+                ("code".to_string(), Value::Number(error.code as f64)),
+                ("message".to_string(), Value::String(error.message)),
+            ]),
+        ),
+    };
+    Value::Object(vec![
+        ("jsonrpc".to_string(), Value::String("2.0".to_string())),
+        ("id".to_string(), id),
+        outcome,
+    ])
+    .to_string()
+}
+
+fn emulator_error(error: impl std::fmt::Display) -> RpcError {
+    RpcError::new(EMULATOR_ERROR, error.to_string())
+}
+
+/// Reads a parameter that must be a whole number.
+fn number_param(params: &Value, name: &'static str) -> Result<u64, RpcError> {
+    params
+        .get(name)
+        .and_then(Value::as_u64)
+        .ok_or_else(|| RpcError::new(INVALID_PARAMS, format!("{} must be a whole number", name)))
+}
+
+/// Reads a parameter that must be base64 encoded bytes.
+fn bytes_param(params: &Value, name: &'static str) -> Result<Vec<u8>, RpcError> {
+    params
+        .get(name)
+        .and_then(Value::as_str)
+        .and_then(base64_decode)
+        .ok_or_else(|| RpcError::new(INVALID_PARAMS, format!("{} must be a base64 string", name)))
+}
+
+/// Encodes bytes as padded standard base64.
+fn base64_encode(data: &[u8]) -> String {
+    let mut text = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (idx, byte)| {
+            bits | (*byte as u32) << (16 - 8 * idx)
+        });
+        for idx in 0..4 {
+            if idx <= chunk.len() {
+                text.push(BASE64[(bits >> (18 - 6 * idx) & 0x3F) as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+/// Decodes padded standard base64.
+fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let text = text.as_bytes();
+    if !text.len().is_multiple_of(4) {
+        return None;
+    }
+    let mut data = Vec::with_capacity(text.len() / 4 * 3);
+    for (chunk_idx, chunk) in text.chunks(4).enumerate() {
+        let last = chunk_idx == text.len() / 4 - 1;
+        let padding = chunk.iter().rev().take_while(|c| **c == b'=').count();
+        if padding > 2 || (padding > 0 && !last) {
+            return None;
+        }
+        let mut bits = 0u32;
+        for c in &chunk[..4 - padding] {
+            let value = BASE64.iter().position(|b| b == c)?;
+            bits = bits << 6 | value as u32;
+        }
+        bits <<= 6 * padding;
+        data.extend(&bits.to_be_bytes()[1..4 - padding]);
+    }
+    Some(data)
+}
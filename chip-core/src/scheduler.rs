@@ -0,0 +1,316 @@
+use core::cell::Cell;
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::time::Instant;
+
+use crate::observer::Observer;
+use crate::Emulator;
+
+/// The rate of the delay and sound timers.
+pub const TIMER_HZ: u64 = 60;
+
+/// How much host time an update catches up on by default. Longer stalls,
+/// such as the host sleeping or sitting in a debugger, are skipped rather
+/// than run as a burst.
+pub const DEFAULT_MAX_CATCH_UP: Duration = Duration::from_millis(250);
+
+/// The fastest speed multiplier, far more cycles than any host runs in
+/// real time.
+pub const MAX_SPEED: f64 = 1000.0;
+
+const NANOS_PER_SECOND: u128 = 1_000_000_000;
+
+/// A monotonic source of host time for the scheduler.
+pub trait Clock {
+    /// Returns the time since some fixed point, which never goes backwards.
+    fn now(&self) -> Duration;
+}
+
+impl<C: Clock + ?Sized> Clock for &C {
+    fn now(&self) -> Duration {
+        (**self).now()
+    }
+}
+
+/// The operating system's monotonic clock.
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug)]
+pub struct RealClock {
+    start: Instant,
+}
+
+#[cfg(feature = "std")]
+impl RealClock {
+    /// Constructor, the clock starts at zero.
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for RealClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl Clock for RealClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+/// A clock that only moves when told to, for tests. The scheduler can hold
+/// a reference to it while the test advances it.
+#[derive(Debug, Default)]
+pub struct FakeClock {
+    now: Cell<Duration>,
+}
+
+impl FakeClock {
+    /// Constructor, the clock starts at zero.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Moves the clock forward.
+    ///
+    /// #### Parameters:
+    /// - duration: How much time passes.
+    ///
+    pub fn advance(&self, duration: Duration) {
+        self.now.set(self.now.get() + duration);
+    }
+}
+
+impl Clock for FakeClock {
+    fn now(&self) -> Duration {
+        self.now.get()
+    }
+}
+
+/// What one scheduler update ran.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Steps {
+    /// CPU cycles run.
+    pub cycles: u64,
+    /// 60 Hz timer ticks run, the frames that ended.
+    pub timer_ticks: u64,
+}
+
+/// Runs the emulator in step with a clock. Each update turns the host time
+/// that passed into emulated time, scaled by the speed, and runs the CPU
+/// cycles and timer ticks that end in it, in order. The cycles and ticks are
+/// counted from fixed points, so updates at any rate neither drift nor lose
+/// fractions of a cycle.
+///
+/// At 60 times the emulator's tick rate, the state at the end of each frame
+/// is the same as after `Emulator::run_frame`.
+pub struct Scheduler<C: Clock> {
+    clock: C,
+    /// The clock reading at the last update.
+    last: Duration,
+    /// Emulated time since the start.
+    emulated: Duration,
+    /// Instructions per second, or `None` to follow the emulator's tick
+    /// rate.
+    instructions_per_second: Option<u64>,
+    /// Emulated time per host time.
+    speed: f64,
+    paused: bool,
+    max_catch_up: Duration,
+    /// The rate the cycles are being counted at, and the emulated time the
+    /// count started from.
+    rate: u64,
+    origin: Duration,
+    /// Cycles run since `origin`.
+    cycles: u64,
+    /// Timer ticks run since the start.
+    timer_ticks: u64,
+}
+
+impl<C: Clock> Scheduler<C> {
+    /// Constructor, starting from the clock's current time at normal speed.
+    ///
+    /// #### Parameters:
+    /// - clock: The host time source.
+    ///
+    pub fn new(clock: C) -> Self {
+        let last = clock.now();
+        Self {
+            clock,
+            last,
+            emulated: Duration::ZERO,
+            instructions_per_second: None,
+            speed: 1.0,
+            paused: false,
+            max_catch_up: DEFAULT_MAX_CATCH_UP,
+            rate: 0,
+            origin: Duration::ZERO,
+            cycles: 0,
+            timer_ticks: 0,
+        }
+    }
+
+    /// Returns the clock.
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    /// Returns the instructions per second set, or `None` if it follows
+    /// the emulator's tick rate.
+    pub fn instructions_per_second(&self) -> Option<u64> {
+        self.instructions_per_second
+    }
+
+    /// Set the CPU speed.
+    ///
+    /// #### Parameters:
+    /// - instructions_per_second: Cycles per second of emulated time, or
+    ///   `None` for 60 times the emulator's tick rate.
+    ///
+    pub fn set_instructions_per_second(&mut self, instructions_per_second: Option<u64>) {
+        self.instructions_per_second = instructions_per_second;
+    }
+
+    /// Returns the speed multiplier.
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    /// Set how fast emulated time runs compared to host time, which speeds
+    /// up or slows down the CPU and the timers together.
+    ///
+    /// #### Parameters:
+    /// - speed: 1.0 for real time, above for fast forward (2.0 runs twice
+    ///   as fast) and below for slow motion. Negative values and NaN count
+    ///   as 0, values above `MAX_SPEED` as `MAX_SPEED`.
+    ///
+    pub fn set_speed(&mut self, speed: f64) {
+        self.speed = if speed.is_nan() {
+            0.0
+        } else {
+            speed.clamp(0.0, MAX_SPEED)
+        };
+    }
+
+    /// Returns whether emulated time is stopped.
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Stop or restart emulated time. Host time that passes while paused is
+    /// never caught up on.
+    ///
+    /// #### Parameters:
+    /// - paused: Whether to stop.
+    ///
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    /// Set the most host time one update catches up on.
+    ///
+    /// #### Parameters:
+    /// - max_catch_up: The limit, `DEFAULT_MAX_CATCH_UP` to start with.
+    ///
+    pub fn set_max_catch_up(&mut self, max_catch_up: Duration) {
+        self.max_catch_up = max_catch_up;
+    }
+
+    /// Runs the cycles and timer ticks due since the last update.
+    ///
+    /// #### Parameters:
+    /// - emulator: The emulator to run.
+    ///
+    /// #### Returns:
+    /// - What was run.
+    ///
+    pub fn update(&mut self, emulator: &mut Emulator) -> Steps {
+        self.update_observed(emulator, &mut ())
+    }
+
+    /// Same as `update`, reporting the instructions' events to an
+    /// observer.
+    ///
+    /// #### Parameters:
+    /// - emulator: The emulator to run.
+    /// - observer: Told about each event as it happens.
+    ///
+    /// #### Returns:
+    /// - What was run.
+    ///
+    pub fn update_observed(
+        &mut self,
+        emulator: &mut Emulator,
+        observer: &mut (impl Observer + ?Sized),
+    ) -> Steps {
+        let rate = self
+            .instructions_per_second
+            .unwrap_or(emulator.ticks_per_frame() as u64 * TIMER_HZ);
+        if rate != self.rate {
+            // Count from the end of the last cycle at the old rate, so a
+            // change only affects the cycles after it.
+            self.origin = match self.rate {
+                0 => self.emulated,
+                rate => at(self.cycles, rate, self.origin),
+            };
+            self.rate = rate;
+            self.cycles = 0;
+        }
+
+        let now = self.clock.now();
+        let elapsed = now.saturating_sub(self.last).min(self.max_catch_up);
+        self.last = now;
+        if !self.paused {
+            // Saturates rather than panicking if the catch up limit was
+            // raised far enough to overflow.
+            let scaled = Duration::try_from_secs_f64(elapsed.as_secs_f64() * self.speed)
+                .unwrap_or(Duration::MAX);
+            self.emulated = self.emulated.saturating_add(scaled);
+        }
+
+        let mut steps = Steps::default();
+        loop {
+            let next_timer = at(self.timer_ticks + 1, TIMER_HZ, Duration::ZERO);
+            let next_cycle = self.next_cycle();
+            // The frame's last cycle ends with it, and runs before the
+            // frame's timer tick.
+            if next_cycle <= self.emulated && next_cycle <= next_timer {
+                emulator.tick_observed(observer);
+                self.cycles += 1;
+                steps.cycles += 1;
+            } else if next_timer <= self.emulated {
+                emulator.timer_tick_observed(observer);
+                self.timer_ticks += 1;
+                steps.timer_ticks += 1;
+            } else {
+                return steps;
+            }
+        }
+    }
+
+    /// Returns the emulated time the next cycle ends, never if the rate is
+    /// 0.
+    fn next_cycle(&self) -> Duration {
+        match self.rate {
+            0 => Duration::MAX,
+            rate => at(self.cycles + 1, rate, self.origin),
+        }
+    }
+}
+
+/// Returns the emulated time a number of events on a fixed rate take.
+///
+/// #### Parameters:
+/// - count: The number of events.
+/// - rate: Events per second.
+/// - origin: The time the first event started.
+///
+fn at(count: u64, rate: u64, origin: Duration) -> Duration {
+    let nanos = count as u128 * NANOS_PER_SECOND / rate as u128;
+    origin.saturating_add(Duration::from_nanos(nanos.min(u64::MAX as u128) as u64))
+}
@@ -0,0 +1,166 @@
+use std::fmt::Write;
+
+use crate::deflate::zlib_compress;
+use crate::display::{Framebuffer, Palette, Rgb, PALETTE_SIZE};
+use crate::hash::crc32_update;
+
+/// The eight byte signature every PNG file starts with.
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+/// Luma below which a palette colour is written as black in a PBM image.
+const PBM_THRESHOLD: u8 = 128;
+
+/// Encodes the display as an indexed colour PNG image.
+///
+/// #### Parameters:
+/// - fb: The display to export.
+/// - scale: The width and height of each display pixel in image pixels
+///   (0 is treated as 1).
+/// - palette: The colours to use for each pixel value.
+///
+/// #### Returns:
+/// - The PNG file contents.
+///
+pub fn to_png<F: Framebuffer + ?Sized>(fb: &F, scale: usize, palette: &Palette) -> Vec<u8> {
+    let scale = scale.max(1);
+    let width = fb.width() * scale;
+    let height = fb.height() * scale;
+
+    // Each scanline is a filter type byte (0, no filtering) followed by one
+    // palette index per pixel.
+    let stride = width + 1;
+    let mut raw = Vec::with_capacity(stride * height);
+    for y in 0..height {
+        raw.push(0);
+        for x in 0..width {
+            raw.push((fb.pixel(x / scale, y / scale) % PALETTE_SIZE) as u8);
+        }
+    }
+
+    let mut header = Vec::with_capacity(13);
+    header.extend((width as u32).to_be_bytes());
+    header.extend((height as u32).to_be_bytes());
+    // Bit depth 8, colour type 3 (indexed), default compression, filter and
+    // no interlacing.
+    header.extend([8, 3, 0, 0, 0]);
+
+    let plte: Vec<u8> = palette
+        .colors()
+        .iter()
+        .flat_map(|color| [color.r, color.g, color.b])
+        .collect();
+
+    let mut png = PNG_SIGNATURE.to_vec();
+    write_chunk(&mut png, b"IHDR", &header);
+    write_chunk(&mut png, b"PLTE", &plte);
+    write_chunk(&mut png, b"IDAT", &zlib_compress(&raw, stride));
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+/// Appends a PNG chunk (length, type, data and CRC).
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend((data.len() as u32).to_be_bytes());
+    png.extend(kind);
+    png.extend(data);
+    let crc = crc32_update(crc32_update(0, kind), data);
+    png.extend(crc.to_be_bytes());
+}
+
+/// Encodes the display as a plain (ASCII) PBM image. PBM is strictly black
+/// and white so each pixel is written as black when its palette colour is
+/// dark and white otherwise.
+///
+/// #### Parameters:
+/// - fb: The display to export.
+/// - scale: The width and height of each display pixel in image pixels
+///   (0 is treated as 1).
+/// - palette: The colours to use for each pixel value.
+///
+/// #### Returns:
+/// - The PBM file contents.
+///
+pub fn to_pbm<F: Framebuffer + ?Sized>(fb: &F, scale: usize, palette: &Palette) -> Vec<u8> {
+    let scale = scale.max(1);
+    let width = fb.width() * scale;
+    let height = fb.height() * scale;
+
+    let mut pbm = format!("P1\n{} {}\n", width, height).into_bytes();
+    for y in 0..height {
+        for x in 0..width {
+            let color = palette.color(fb.pixel(x / scale, y / scale));
+            pbm.push(if color.luma() < PBM_THRESHOLD {
+                b'1'
+            } else {
+                b'0'
+            });
+        }
+        pbm.push(b'\n');
+    }
+    pbm
+}
+
+/// Encodes the display as an SVG image. Horizontal runs of the same colour
+/// are merged into a single rectangle to keep the file small.
+///
+/// #### Parameters:
+/// - fb: The display to export.
+/// - scale: The width and height of each display pixel in image units
+///   (0 is treated as 1).
+/// - palette: The colours to use for each pixel value.
+///
+/// #### Returns:
+/// - The SVG document.
+///
+pub fn to_svg<F: Framebuffer + ?Sized>(fb: &F, scale: usize, palette: &Palette) -> String {
+    let scale = scale.max(1);
+    let width = fb.width() * scale;
+    let height = fb.height() * scale;
+
+    let mut svg = String::new();
+    // Writing to a String can't fail so the results are ignored.
+    let _ = writeln!(
+        svg,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" \
+         viewBox=\"0 0 {w} {h}\" shape-rendering=\"crispEdges\">",
+        w = width,
+        h = height
+    );
+    let _ = writeln!(
+        svg,
+        "<rect width=\"{}\" height=\"{}\" fill=\"{}\"/>",
+        width,
+        height,
+        hex(palette.color(0))
+    );
+
+    for y in 0..fb.height() {
+        let mut x = 0;
+        while x < fb.width() {
+            let value = fb.pixel(x, y);
+            let start = x;
+            while x < fb.width() && fb.pixel(x, y) == value {
+                x += 1;
+            }
+            // The background rectangle already covers index 0.
+            if value % PALETTE_SIZE != 0 {
+                let _ = writeln!(
+                    svg,
+                    "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>",
+                    start * scale,
+                    y * scale,
+                    (x - start) * scale,
+                    scale,
+                    hex(palette.color(value))
+                );
+            }
+        }
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Formats a colour as a CSS hex colour.
+fn hex(color: Rgb) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b)
+}
@@ -0,0 +1,308 @@
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use rhai::{Blob, Dynamic, Engine, EvalAltResult, FnPtr, NativeCallContext, INT};
+
+use crate::error::ScriptError;
+use crate::observer::Observer;
+use crate::screenshot::to_png;
+use crate::{Emulator, NUM_REGS, RAM_SIZE};
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+/// Image pixels per display pixel for `screenshot(path)`, the same as the
+/// headless runner's default.
+const DEFAULT_SCALE: INT = 8;
+
+/// The callbacks a script has registered.
+#[derive(Default)]
+struct Hooks {
+    /// Called before the instruction at an address runs.
+    pc: BTreeMap<u16, Vec<FnPtr>>,
+    /// Called after each sprite is drawn.
+    draw: Vec<FnPtr>,
+}
+
+/// Collects the sprites drawn by one instruction, so the callbacks can run
+/// once it's done and the emulator is free.
+#[derive(Default)]
+struct Draws(Vec<(u8, u8, u8, bool)>);
+
+impl Observer for Draws {
+    fn on_draw(&mut self, x: u8, y: u8, height: u8, collision: bool) {
+        self.0.push((x, y, height, collision));
+    }
+}
+
+/// What the functions given to the script share.
+struct Shared {
+    emulator: RefCell<Emulator>,
+    hooks: RefCell<Hooks>,
+    /// Frames the script has run.
+    frames: Cell<INT>,
+}
+
+/// Runs a [Rhai](https://rhai.rs) script against an emulator, for
+/// automation such as "press 5 until VA is 3, then take a screenshot":
+///
+/// ```text
+/// press(5);
+/// while reg(0xA) != 3 {
+///     frame();
+/// }
+/// screenshot("done.png");
+/// ```
+///
+/// Scripts get these functions on top of Rhai's own:
+///
+/// - `press(key)` and `release(key)` hold and let go of a CHIP-8 key.
+/// - `frame()` runs one 60 Hz frame, `frames(n)` runs several and `step()`
+///   runs one CPU cycle. `frame_count()` is the number of frames run.
+/// - `reg(x)`, `set_reg(x, value)`, `reg_i()`, `set_reg_i(address)`,
+///   `pc()`, `delay_timer()` and `sound_timer()` read and write the
+///   registers, and `peek(address)` and `poke(address, value)` the RAM.
+/// - `on_pc(address, callback)` calls `callback()` before each run of the
+///   instruction at the address, and `on_draw(callback)` calls
+///   `callback(x, y, height, collision)` after each sprite is drawn.
+///   Callbacks can use every other function, including running frames.
+/// - `save_state()` returns the machine state as a blob, which
+///   `load_state(state)` restores.
+/// - `screenshot(path)` and `screenshot(path, scale)` save the display as
+///   a PNG image.
+/// - `fault()` returns why the ROM stopped, or `()` while it runs.
+///
+/// #### Parameters:
+/// - emulator: The emulator to drive, usually with a ROM loaded.
+/// - source: The script.
+///
+/// #### Returns:
+/// - An error if the script failed to compile or raised one, in which case
+///   the emulator is left as the script left it.
+///
+pub fn run(emulator: &mut Emulator, source: &str) -> Result<(), ScriptError> {
+    let shared = Rc::new(Shared {
+        emulator: RefCell::new(std::mem::replace(emulator, Emulator::with_seed(0))),
+        hooks: RefCell::default(),
+        frames: Cell::new(0),
+    });
+    let result = engine(&shared).run(source);
+    *emulator = shared.emulator.replace(Emulator::with_seed(0));
+
+    result.map_err(|mut error| {
+        let position = error.take_position();
+        ScriptError {
+            line: position.line(),
+            message: error.to_string(),
+        }
+    })
+}
+
+/// Creates an engine with the emulator functions registered.
+fn engine(shared: &Rc<Shared>) -> Engine {
+    let mut engine = Engine::new();
+
+    let s = shared.clone();
+    engine.register_fn("press", move |key: INT| -> ScriptResult<()> {
+        s.emulator
+            .borrow_mut()
+            .keypress(key_index(key)?, true)
+            .map_err(|e| e.to_string().into())
+    });
+    let s = shared.clone();
+    engine.register_fn("release", move |key: INT| -> ScriptResult<()> {
+        s.emulator
+            .borrow_mut()
+            .keypress(key_index(key)?, false)
+            .map_err(|e| e.to_string().into())
+    });
+
+    let s = shared.clone();
+    engine.register_fn("step", move |context: NativeCallContext| step(&context, &s));
+    let s = shared.clone();
+    engine.register_fn("frame", move |context: NativeCallContext| {
+        frame(&context, &s)
+    });
+    let s = shared.clone();
+    engine.register_fn(
+        "frames",
+        move |context: NativeCallContext, count: INT| -> ScriptResult<()> {
+            for _ in 0..count {
+                frame(&context, &s)?;
+            }
+            Ok(())
+        },
+    );
+    let s = shared.clone();
+    engine.register_fn("frame_count", move || s.frames.get());
+
+    let s = shared.clone();
+    engine.register_fn("reg", move |x: INT| -> ScriptResult<INT> {
+        Ok(s.emulator.borrow().registers()[check_register(x)?] as INT)
+    });
+    let s = shared.clone();
+    engine.register_fn("set_reg", move |x: INT, value: INT| -> ScriptResult<()> {
+        let value = check_byte(value)?;
+        s.emulator
+            .borrow_mut()
+            .set_register(check_register(x)?, value)
+            .map_err(|e| e.to_string().into())
+    });
+    let s = shared.clone();
+    engine.register_fn("reg_i", move || s.emulator.borrow().i_register() as INT);
+    let s = shared.clone();
+    engine.register_fn("set_reg_i", move |address: INT| -> ScriptResult<()> {
+        let address = check_address(address)?;
+        s.emulator
+            .borrow_mut()
+            .set_i_register(address)
+            .map_err(|e| e.to_string().into())
+    });
+    let s = shared.clone();
+    engine.register_fn("pc", move || s.emulator.borrow().program_counter() as INT);
+    let s = shared.clone();
+    engine.register_fn("delay_timer", move || {
+        s.emulator.borrow().delay_timer() as INT
+    });
+    let s = shared.clone();
+    engine.register_fn("sound_timer", move || {
+        s.emulator.borrow().sound_timer() as INT
+    });
+
+    let s = shared.clone();
+    engine.register_fn("peek", move |address: INT| -> ScriptResult<INT> {
+        let address = check_address(address)?;
+        Ok(s.emulator.borrow().ram[address as usize] as INT)
+    });
+    let s = shared.clone();
+    engine.register_fn(
+        "poke",
+        move |address: INT, value: INT| -> ScriptResult<()> {
+            let (address, value) = (check_address(address)?, check_byte(value)?);
+            s.emulator.borrow_mut().ram[address as usize] = value;
+            Ok(())
+        },
+    );
+
+    let s = shared.clone();
+    engine.register_fn(
+        "on_pc",
+        move |address: INT, callback: FnPtr| -> ScriptResult<()> {
+            let address = check_address(address)?;
+            s.hooks
+                .borrow_mut()
+                .pc
+                .entry(address)
+                .or_default()
+                .push(callback);
+            Ok(())
+        },
+    );
+    let s = shared.clone();
+    engine.register_fn("on_draw", move |callback: FnPtr| {
+        s.hooks.borrow_mut().draw.push(callback);
+    });
+
+    let s = shared.clone();
+    engine.register_fn("save_state", move || -> Blob {
+        s.emulator.borrow().save_state().to_vec()
+    });
+    let s = shared.clone();
+    engine.register_fn("load_state", move |state: Blob| -> ScriptResult<()> {
+        s.emulator
+            .borrow_mut()
+            .load_state(&state)
+            .map_err(|e| e.to_string().into())
+    });
+
+    let s = shared.clone();
+    engine.register_fn("screenshot", move |path: &str| {
+        screenshot(&s, path, DEFAULT_SCALE)
+    });
+    let s = shared.clone();
+    engine.register_fn("screenshot", move |path: &str, scale: INT| {
+        screenshot(&s, path, scale)
+    });
+    let s = shared.clone();
+    engine.register_fn("fault", move || match s.emulator.borrow().fault() {
+        Some(fault) => Dynamic::from(fault.to_string()),
+        None => Dynamic::UNIT,
+    });
+
+    engine
+}
+
+/// Runs one CPU cycle with the callbacks.
+fn step(context: &NativeCallContext, shared: &Shared) -> ScriptResult<()> {
+    // The instruction only runs if the CPU isn't stopped.
+    let address = {
+        let emulator = shared.emulator.borrow();
+        let running = !emulator.waiting_for_frame && emulator.fault.is_none();
+        running.then_some(emulator.program_counter)
+    };
+    if let Some(address) = address {
+        let callbacks = shared.hooks.borrow().pc.get(&address).cloned();
+        for callback in callbacks.unwrap_or_default() {
+            let _ = callback.call_within_context::<Dynamic>(context, ())?;
+        }
+    }
+
+    let mut draws = Draws::default();
+    shared.emulator.borrow_mut().tick_observed(&mut draws);
+    if !draws.0.is_empty() {
+        let callbacks = shared.hooks.borrow().draw.clone();
+        for (x, y, height, collision) in draws.0 {
+            for callback in &callbacks {
+                let args = (x as INT, y as INT, height as INT, collision);
+                let _ = callback.call_within_context::<Dynamic>(context, args)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Runs one frame with the callbacks, the same as `Emulator::run_frame`.
+fn frame(context: &NativeCallContext, shared: &Shared) -> ScriptResult<()> {
+    let ticks = shared.emulator.borrow().ticks_per_frame();
+    for _ in 0..ticks {
+        step(context, shared)?;
+    }
+    shared.emulator.borrow_mut().timer_tick();
+    shared.frames.set(shared.frames.get() + 1);
+    Ok(())
+}
+
+/// Saves the display as a PNG image, in the ROM's colours if it has any.
+fn screenshot(shared: &Shared, path: &str, scale: INT) -> ScriptResult<()> {
+    let png = {
+        let emulator = shared.emulator.borrow();
+        let palette = emulator
+            .rom_info()
+            .and_then(|info| info.palette)
+            .unwrap_or_default();
+        to_png(&*emulator, scale.clamp(1, 64) as usize, &palette)
+    };
+    std::fs::write(path, png).map_err(|e| format!("{}: {}", path, e).into())
+}
+
+fn key_index(key: INT) -> ScriptResult<usize> {
+    usize::try_from(key).map_err(|_| format!("no key {}", key).into())
+}
+
+fn check_register(x: INT) -> ScriptResult<usize> {
+    match usize::try_from(x) {
+        Ok(x) if x < NUM_REGS => Ok(x),
+        _ => Err(format!("no V register {}", x).into()),
+    }
+}
+
+fn check_address(address: INT) -> ScriptResult<u16> {
+    match u16::try_from(address) {
+        Ok(address) if (address as usize) < RAM_SIZE => Ok(address),
+        _ => Err(format!("address {} is outside of RAM", address).into()),
+    }
+}
+
+fn check_byte(value: INT) -> ScriptResult<u8> {
+    u8::try_from(value).map_err(|_| format!("{} doesn't fit in a byte", value).into())
+}
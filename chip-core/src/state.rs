@@ -0,0 +1,204 @@
+use crate::error::{ExecutionError, StateError};
+use crate::rng::Rng;
+use crate::{Emulator, NUM_KEYS, NUM_REGS, RAM_SIZE, SCREEN_HEIGHT, SCREEN_WIDTH, STACK_SIZE};
+
+/// Every save state starts with these bytes.
+const MAGIC: [u8; 4] = *b"C8SS";
+/// Bumped whenever the layout changes.
+const VERSION: u8 = 1;
+/// Bytes in the packed display, one bit per pixel.
+const SCREEN_BYTES: usize = SCREEN_WIDTH * SCREEN_HEIGHT / 8;
+
+/// The size of every save state in bytes.
+pub const STATE_SIZE: usize = MAGIC.len()
+    + 1 // version
+    + 2 // program counter
+    + RAM_SIZE
+    + SCREEN_BYTES
+    + NUM_REGS
+    + 2 // I
+    + 1 // stack pointer
+    + STACK_SIZE * 2
+    + 2 // keys
+    + 1 // delay timer
+    + 1 // sound timer
+    + 1 // waiting for frame
+    + 8 // random number generator
+    + 5; // fault
+
+/// Appends fields to a save state.
+struct Writer<'a> {
+    data: &'a mut [u8; STATE_SIZE],
+    len: usize,
+}
+
+impl Writer<'_> {
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.data[self.len..self.len + bytes.len()].copy_from_slice(bytes);
+        self.len += bytes.len();
+    }
+}
+
+/// Reads fields back out of a save state.
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> &'a [u8] {
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        bytes
+    }
+
+    fn u8(&mut self) -> u8 {
+        self.bytes(1)[0]
+    }
+
+    fn u16(&mut self) -> u16 {
+        u16::from_be_bytes([self.u8(), self.u8()])
+    }
+
+    fn u64(&mut self) -> u64 {
+        (0..8).fold(0, |value, _| value << 8 | self.u8() as u64)
+    }
+
+    /// Reads an address that must point into RAM.
+    fn address(&mut self, field: &'static str) -> Result<u16, StateError> {
+        let address = self.u16();
+        if address as usize >= RAM_SIZE {
+            return Err(StateError::InvalidField(field));
+        }
+        Ok(address)
+    }
+
+    fn bool(&mut self, field: &'static str) -> Result<bool, StateError> {
+        match self.u8() {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::InvalidField(field)),
+        }
+    }
+}
+
+/// Save states: a fixed size snapshot of the whole machine, including the
+/// random number generator so a loaded state plays out exactly the same.
+/// The tick rate and quirks are settings rather than machine state, so
+/// they aren't saved.
+impl Emulator {
+    /// Captures the machine state.
+    ///
+    /// #### Returns:
+    /// - The state, which `load_state` can restore later.
+    ///
+    pub fn save_state(&self) -> [u8; STATE_SIZE] {
+        let mut data = [0; STATE_SIZE];
+        let mut writer = Writer {
+            data: &mut data,
+            len: 0,
+        };
+        writer.bytes(&MAGIC);
+        writer.bytes(&[VERSION]);
+        writer.bytes(&self.program_counter.to_be_bytes());
+        writer.bytes(&self.ram);
+        for pixels in self.screen.chunks(8) {
+            let byte = pixels
+                .iter()
+                .fold(0u8, |byte, pixel| byte << 1 | *pixel as u8);
+            writer.bytes(&[byte]);
+        }
+        writer.bytes(&self.registers);
+        writer.bytes(&self.i_register.to_be_bytes());
+        writer.bytes(&[self.stack_pointer as u8]);
+        for address in self.stack {
+            writer.bytes(&address.to_be_bytes());
+        }
+        let keys = (0..NUM_KEYS).fold(0u16, |keys, key| keys | (self.keys[key] as u16) << key);
+        writer.bytes(&keys.to_be_bytes());
+        writer.bytes(&[self.delay_timer, self.sound_timer]);
+        writer.bytes(&[self.waiting_for_frame as u8]);
+        writer.bytes(&self.rng.state().to_be_bytes());
+        let (kind, address, opcode) = match self.fault {
+            None => (0, 0, 0),
+            Some(ExecutionError::UnknownOpcode { address, opcode }) => (1, address, opcode),
+            Some(ExecutionError::StackOverflow { address }) => (2, address, 0),
+            Some(ExecutionError::StackUnderflow { address }) => (3, address, 0),
+        };
+        writer.bytes(&[kind]);
+        writer.bytes(&address.to_be_bytes());
+        writer.bytes(&opcode.to_be_bytes());
+        data
+    }
+
+    /// Restores a state captured by `save_state`.
+    ///
+    /// #### Parameters:
+    /// - data: The saved state.
+    ///
+    /// #### Returns:
+    /// - An error if the data isn't a valid save state, in which case the
+    ///   emulator is unchanged.
+    ///
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        if data.len() != STATE_SIZE {
+            return Err(StateError::WrongSize(data.len()));
+        }
+        let mut reader = Reader { data };
+        if reader.bytes(MAGIC.len()) != MAGIC {
+            return Err(StateError::NotAState);
+        }
+        let version = reader.u8();
+        if version != VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+
+        // Everything is read into a copy first so nothing changes on error.
+        let mut state = Emulator {
+            program_counter: reader.address("program counter")?,
+            ..Emulator::new()
+        };
+        state.ram.copy_from_slice(reader.bytes(RAM_SIZE));
+        for (pixels, byte) in state.screen.chunks_mut(8).zip(reader.bytes(SCREEN_BYTES)) {
+            for (bit, pixel) in pixels.iter_mut().enumerate() {
+                *pixel = byte & (0x80 >> bit) != 0;
+            }
+        }
+        state.registers.copy_from_slice(reader.bytes(NUM_REGS));
+        state.i_register = reader.u16();
+        state.stack_pointer = reader.u8() as u16;
+        if state.stack_pointer as usize > STACK_SIZE {
+            return Err(StateError::InvalidField("stack pointer"));
+        }
+        for address in state.stack.iter_mut() {
+            *address = reader.address("stack")?;
+        }
+        let keys = reader.u16();
+        for (key, pressed) in state.keys.iter_mut().enumerate() {
+            *pressed = keys & (1 << key) != 0;
+        }
+        state.delay_timer = reader.u8();
+        state.sound_timer = reader.u8();
+        state.waiting_for_frame = reader.bool("waiting for frame")?;
+        state.rng = Rng::new(reader.u64());
+        let kind = reader.u8();
+        let address = reader.address("fault")?;
+        let opcode = reader.u16();
+        state.fault = match kind {
+            0 => None,
+            1 => Some(ExecutionError::UnknownOpcode { address, opcode }),
+            2 => Some(ExecutionError::StackOverflow { address }),
+            3 => Some(ExecutionError::StackUnderflow { address }),
+            _ => return Err(StateError::InvalidField("fault")),
+        };
+
+        state.ticks_per_frame = self.ticks_per_frame;
+        state.quirks = self.quirks;
+        #[cfg(feature = "std")]
+        {
+            state.rom_database = self.rom_database.clone();
+            state.rom_info = self.rom_info.take();
+        }
+        *self = state;
+        Ok(())
+    }
+}
@@ -0,0 +1,140 @@
+//! Checks a batch gives the same results as running the same number of
+//! emulators one by one.
+
+mod common;
+
+use chip_core::batch::Batch;
+use chip_core::quirks::Quirks;
+use chip_core::{Emulator, NUM_KEYS, RAM_SIZE, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+use common::test_roms;
+
+/// Machines per batch, each with its own seed and keys.
+const LEN: usize = 8;
+
+/// Creates a batch and matching emulators with the ROM loaded.
+fn setup(rom: &[u8], quirks: Quirks) -> (Batch, Vec<Emulator>) {
+    let mut batch = Batch::new(LEN);
+    batch.set_quirks(quirks);
+    batch.load_rom(rom).unwrap();
+    let emulators = (0..LEN)
+        .map(|instance| {
+            let seed = instance as u64 * 7919;
+            batch.seed_rng(instance, seed);
+            let keys = (instance as u16).wrapping_mul(0x1111);
+            batch.set_keys(instance, keys);
+
+            let mut emulator = Emulator::new();
+            emulator.set_quirks(quirks);
+            emulator.seed_rng(seed);
+            emulator.load_rom(rom).unwrap();
+            emulator.set_keys(keys);
+            emulator
+        })
+        .collect();
+    (batch, emulators)
+}
+
+/// Checks every machine in the batch matches its emulator.
+fn check(batch: &Batch, emulators: &[Emulator], frame: usize) {
+    let screen_size = SCREEN_WIDTH * SCREEN_HEIGHT;
+    for (instance, emulator) in emulators.iter().enumerate() {
+        let copy = batch.emulator(instance);
+        assert_eq!(
+            copy.read_ram(0, RAM_SIZE).unwrap(),
+            emulator.read_ram(0, RAM_SIZE).unwrap(),
+            "frame {} machine {}",
+            frame,
+            instance
+        );
+        assert_eq!(copy.registers(), emulator.registers());
+        assert_eq!(copy.i_register(), emulator.i_register());
+        assert_eq!(copy.program_counter(), emulator.program_counter());
+        assert_eq!(copy.stack(), emulator.stack());
+        assert_eq!(copy.delay_timer(), emulator.delay_timer());
+        assert_eq!(copy.sound_timer(), emulator.sound_timer());
+        assert_eq!(copy.fault(), emulator.fault());
+        assert_eq!(batch.screen(instance), emulator.get_display());
+        assert_eq!(
+            &batch.screens()[instance * screen_size..(instance + 1) * screen_size],
+            emulator.get_display()
+        );
+    }
+}
+
+#[test]
+fn batch_matches_emulators() {
+    for rom in test_roms() {
+        for quirks in [Quirks::default(), Quirks::chip8(), Quirks::schip()] {
+            let (mut batch, mut emulators) = setup(&rom, quirks);
+            for frame in 0..60 {
+                batch.run_frame();
+                emulators.iter_mut().for_each(Emulator::run_frame);
+                check(&batch, &emulators, frame);
+            }
+        }
+    }
+}
+
+#[test]
+fn ticks_and_timers_match_emulators() {
+    let rom = &test_roms()[0];
+    let (mut batch, mut emulators) = setup(rom, Quirks::default());
+    for cycle in 0..300 {
+        batch.tick();
+        emulators.iter_mut().for_each(Emulator::tick);
+        if cycle % 7 == 6 {
+            batch.timer_tick();
+            emulators.iter_mut().for_each(Emulator::timer_tick);
+        }
+        check(&batch, &emulators, cycle);
+    }
+}
+
+#[test]
+fn machines_reset_on_their_own() {
+    let rom = &test_roms()[0];
+    let (mut batch, mut emulators) = setup(rom, Quirks::default());
+    for _ in 0..10 {
+        batch.run_frame();
+        emulators.iter_mut().for_each(Emulator::run_frame);
+    }
+
+    batch.reset(3);
+    batch.seed_rng(3, 3);
+    batch.write_ram(3, 0x200, rom).unwrap();
+    emulators[3].reset();
+    emulators[3].seed_rng(3);
+    emulators[3].load_rom(rom).unwrap();
+    check(&batch, &emulators, 10);
+    assert_eq!(batch.keys()[3 * NUM_KEYS..4 * NUM_KEYS], [false; NUM_KEYS]);
+
+    for frame in 11..30 {
+        batch.run_frame();
+        emulators.iter_mut().for_each(Emulator::run_frame);
+        check(&batch, &emulators, frame);
+    }
+}
+
+#[test]
+fn keys_are_one_array() {
+    let mut batch = Batch::new(2);
+    batch.keys_mut()[NUM_KEYS + 5] = true;
+    assert!(!batch.keys()[5]);
+    assert!(batch.keys()[NUM_KEYS + 5]);
+    assert_eq!(batch.screens().len(), 2 * SCREEN_WIDTH * SCREEN_HEIGHT);
+    assert!(batch.load_rom(&[0; RAM_SIZE]).is_err());
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn parallel_frames_match_emulators() {
+    for rom in test_roms() {
+        let (mut batch, mut emulators) = setup(&rom, Quirks::xochip());
+        for frame in 0..60 {
+            batch.par_run_frame();
+            emulators.iter_mut().for_each(Emulator::run_frame);
+            check(&batch, &emulators, frame);
+        }
+    }
+}
@@ -0,0 +1,158 @@
+//! Cheats: RAM searches narrow down to the address that behaves as asked,
+//! freeze codes hold their values, and cheat files round trip.
+
+use chip_core::cheat::{Cheat, CheatDatabase, CheatTarget, RamSearch, SearchFilter};
+use chip_core::error::{MemoryError, ParseError};
+use chip_core::hash::sha1;
+use chip_core::Emulator;
+
+/// The SHA-1 of the empty ROM.
+const EMPTY: &str = "da39a3ee5e6b4b0d3255bfef95601890afd80709";
+
+const CHEATS: &str = "
+# Lives and ammo
+[DA39A3EE5E6B4B0D3255BFEF95601890AFD80709]
+on 02F0=09 Infinite lives
+off ve=00   No enemy fire
+
+[0000000000000000000000000000000000000001]
+on 0FFF=FF
+";
+
+#[test]
+fn searches_narrow_down_to_one_address() {
+    let mut emulator = Emulator::with_seed(0);
+    emulator.write_ram(0x300, &[3, 7]).unwrap();
+    let mut search = RamSearch::new(&emulator);
+    assert_eq!(search.candidates().len(), 4096);
+
+    // Lose a life, and something else changes too.
+    emulator.write_ram(0x300, &[2, 8]).unwrap();
+    assert_eq!(search.filter(&emulator, SearchFilter::Changed), 2);
+    assert_eq!(search.filter(&emulator, SearchFilter::Unchanged), 2);
+    emulator.write_ram(0x300, &[1, 9]).unwrap();
+    assert_eq!(search.filter(&emulator, SearchFilter::Decreased), 1);
+    assert_eq!(search.candidates(), [0x300]);
+    assert_eq!(search.snapshot_value(0x300), Some(1));
+    assert_eq!(search.snapshot_value(0x1000), None);
+
+    // The other filters, from a fresh start.
+    let mut search = RamSearch::new(&emulator);
+    emulator.write_ram(0x300, &[5, 9]).unwrap();
+    assert_eq!(search.filter(&emulator, SearchFilter::Increased), 1);
+    assert_eq!(search.candidates(), [0x300]);
+    let mut search = RamSearch::new(&emulator);
+    assert_eq!(search.filter(&emulator, SearchFilter::Equal(9)), 1);
+    assert_eq!(search.candidates(), [0x301]);
+}
+
+#[test]
+fn enabled_cheats_are_applied() {
+    let rom = [0x12, 0x00];
+    let hash = sha1(&rom);
+    let mut emulator = Emulator::with_seed(0);
+    emulator.load_rom(&rom).unwrap();
+
+    let cheat = |target, value, enabled| Cheat {
+        target,
+        value,
+        enabled,
+        description: String::new(),
+    };
+    let mut database = CheatDatabase::new();
+    database.cheats_mut(&hash).extend([
+        cheat(CheatTarget::Ram(0x2F0), 9, true),
+        cheat(CheatTarget::Register(0xE), 0x42, true),
+        cheat(CheatTarget::Ram(0x2F1), 1, false),
+    ]);
+    assert!(database.cheats(&sha1(b"other")).is_empty());
+
+    // Held each frame however the ROM changes them.
+    for _ in 0..2 {
+        emulator.write_ram(0x2F0, &[0]).unwrap();
+        database.apply(&hash, &mut emulator).unwrap();
+        emulator.run_frame();
+        assert_eq!(emulator.read_ram(0x2F0, 2), Ok(&[9, 0][..]));
+        assert_eq!(emulator.registers()[0xE], 0x42);
+    }
+
+    // A bad cheat stops there, the ones before it still apply.
+    database
+        .cheats_mut(&hash)
+        .insert(1, cheat(CheatTarget::Register(16), 1, true));
+    emulator.write_ram(0x2F0, &[0]).unwrap();
+    emulator.set_register(0xE, 0).unwrap();
+    assert_eq!(
+        database.apply(&hash, &mut emulator),
+        Err(MemoryError::InvalidRegister(16))
+    );
+    assert_eq!(emulator.read_ram(0x2F0, 1), Ok(&[9][..]));
+    assert_eq!(emulator.registers()[0xE], 0);
+}
+
+#[test]
+fn cheat_files_round_trip() {
+    let database = CheatDatabase::parse(CHEATS).unwrap();
+    let empty = sha1(&[]);
+    assert_eq!(
+        database.cheats(&empty),
+        [
+            Cheat {
+                target: CheatTarget::Ram(0x2F0),
+                value: 9,
+                enabled: true,
+                description: "Infinite lives".to_string(),
+            },
+            Cheat {
+                target: CheatTarget::Register(0xE),
+                value: 0,
+                enabled: false,
+                description: "No enemy fire".to_string(),
+            },
+        ]
+    );
+
+    let text = database.to_text();
+    assert_eq!(
+        text,
+        format!(
+            "[0000000000000000000000000000000000000001]\n\
+             on 0FFF=FF\n\
+             [{}]\n\
+             on 02F0=09 Infinite lives\n\
+             off VE=00 No enemy fire\n",
+            EMPTY
+        )
+    );
+    assert_eq!(CheatDatabase::parse(&text), Ok(database));
+}
+
+#[test]
+fn malformed_lines_are_reported() {
+    let section = format!("[{}]\n", EMPTY);
+    for (text, line, reason) in [
+        ("[1A2B3C4D]".to_string(), 1, "invalid ROM hash"),
+        (
+            "on 0200=01".to_string(),
+            1,
+            "cheat before the first ROM section",
+        ),
+        (format!("{}on", section), 2, "missing cheat"),
+        (
+            format!("{}maybe 0200=01", section),
+            2,
+            "expected `on` or `off`",
+        ),
+        (format!("{}on 0200", section), 2, "expected `target=value`"),
+        (format!("{}\n\non VG=01", section), 4, "invalid register"),
+        (format!("{}on 1000=01", section), 2, "invalid RAM address"),
+        (format!("{}on 0200=100", section), 2, "invalid value"),
+    ] {
+        assert_eq!(
+            CheatDatabase::parse(&text),
+            Err(ParseError { line, reason }),
+            "{}",
+            text
+        );
+    }
+}
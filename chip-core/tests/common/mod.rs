@@ -0,0 +1,20 @@
+//! Helpers shared by the integration tests.
+
+use std::path::Path;
+
+use chip_core::harness::parse_listing;
+
+/// Loads the conformance test ROMs, the listings in `tests/roms`, sorted by
+/// file name.
+pub fn test_roms() -> Vec<Vec<u8>> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/roms");
+    let mut paths: Vec<_> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    paths.sort();
+    paths
+        .iter()
+        .map(|path| parse_listing(&std::fs::read_to_string(path).unwrap()).unwrap())
+        .collect()
+}
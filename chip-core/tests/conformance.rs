@@ -0,0 +1,160 @@
+//! Golden image tests: each test ROM in `tests/roms` runs headlessly and its
+//! final screen is compared against a PBM image in `tests/golden`. Broken
+//! images are reported with the line of the problem.
+//!
+//! After an intended behaviour change, run the tests with `CHIP_BLESS=1` to
+//! rewrite the golden images from the current output, then check the new
+//! images by eye before committing them.
+
+use std::path::PathBuf;
+
+use chip_core::error::ParseError;
+use chip_core::harness::{parse_listing, Golden, HeadlessRun, KeyEvent};
+use chip_core::quirks::Quirks;
+use chip_core::Emulator;
+
+/// Returns the path of a file in the `tests` directory.
+fn test_path(dir: &str, file: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join(dir)
+        .join(file)
+}
+
+/// Runs a test ROM and compares the final screen against a golden image.
+fn check(rom: &str, golden: &str, run: HeadlessRun) -> Emulator {
+    let listing = std::fs::read_to_string(test_path("roms", &format!("{}.hex", rom))).unwrap();
+    let image = parse_listing(&listing).unwrap();
+    let emulator = run.run(&image).unwrap();
+
+    let golden_path = test_path("golden", &format!("{}.pbm", golden));
+    if std::env::var_os("CHIP_BLESS").is_some() {
+        std::fs::write(&golden_path, Golden::capture(&emulator).to_pbm()).unwrap();
+        return emulator;
+    }
+
+    let expected = Golden::parse_pbm(&std::fs::read_to_string(&golden_path).unwrap()).unwrap();
+    if let Err(diff) = expected.compare(&emulator) {
+        panic!("{} doesn't match {}\n{}", rom, golden, diff);
+    }
+    emulator
+}
+
+#[test]
+fn alu_logic() {
+    check("alu_logic", "alu_logic", HeadlessRun::default());
+}
+
+#[test]
+fn alu_arith() {
+    check("alu_arith", "alu_arith", HeadlessRun::default());
+}
+
+#[test]
+fn flow() {
+    check("flow", "flow", HeadlessRun::default());
+}
+
+#[test]
+fn memory() {
+    check("memory", "memory", HeadlessRun::default());
+}
+
+#[test]
+fn timers() {
+    let emulator = check("timers", "timers", HeadlessRun::default());
+    // The sound timer was set to FF and has been counting down since.
+    assert!(emulator.sound_timer() > 0 && emulator.sound_timer() < 0xFF);
+}
+
+#[test]
+fn keys() {
+    let press = |frame, key, pressed| KeyEvent {
+        frame,
+        key,
+        pressed,
+    };
+    let run = HeadlessRun {
+        input: vec![
+            press(5, 0x7, true),
+            press(10, 0x7, false),
+            press(20, 0xA, true),
+        ],
+        ..HeadlessRun::default()
+    };
+    check("keys", "keys", run);
+}
+
+#[test]
+fn draw() {
+    check("draw", "draw", HeadlessRun::default());
+}
+
+#[test]
+fn random() {
+    let run = HeadlessRun {
+        seed: 0xC8,
+        ..HeadlessRun::default()
+    };
+    check("random", "random", run);
+}
+
+#[test]
+fn quirks_default() {
+    check("quirks", "quirks_default", HeadlessRun::default());
+}
+
+#[test]
+fn quirks_chip8() {
+    let run = HeadlessRun {
+        quirks: Quirks::chip8(),
+        ..HeadlessRun::default()
+    };
+    check("quirks", "quirks_chip8", run);
+}
+
+#[test]
+fn quirks_schip() {
+    let run = HeadlessRun {
+        quirks: Quirks::schip(),
+        ..HeadlessRun::default()
+    };
+    check("quirks", "quirks_schip", run);
+}
+
+#[test]
+fn quirks_xochip() {
+    let run = HeadlessRun {
+        quirks: Quirks::xochip(),
+        ..HeadlessRun::default()
+    };
+    check("quirks", "quirks_xochip", run);
+}
+
+#[test]
+fn broken_images_report_their_line() {
+    for (text, line, reason) in [
+        ("", 1, "not a plain PBM image"),
+        ("# comment\nP4\n", 2, "not a plain PBM image"),
+        ("P1\n# size\n2 x\n", 3, "invalid image size"),
+        ("P1 2\n", 1, "invalid image size"),
+        ("P1\n2 2\n10\n0a\n", 4, "invalid pixel"),
+        (
+            "P1\n2 2\n10\n01\n1\n",
+            5,
+            "pixel count doesn't match the image size",
+        ),
+        (
+            "P1\n2 2\n10\n0\n\n",
+            5,
+            "pixel count doesn't match the image size",
+        ),
+    ] {
+        assert_eq!(
+            Golden::parse_pbm(text),
+            Err(ParseError { line, reason }),
+            "{:?}",
+            text
+        );
+    }
+}
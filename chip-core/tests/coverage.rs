@@ -0,0 +1,168 @@
+//! Coverage: each byte is tagged with how it was used, coverage saved as
+//! JSON loads back and merges across runs, and broken JSON is reported with
+//! its line.
+
+use chip_core::coverage::{Access, Coverage};
+use chip_core::harness::parse_listing;
+use chip_core::quirks::Quirks;
+use chip_core::Emulator;
+
+/// Draws the sprite at 210, stores its BCD at 300 and loads it back, then
+/// loops. The code at 20E is never reached.
+const ROM: &str = "
+200: A210   ; I = 210
+202: D012   ; draw the 2 rows at 210
+204: A300   ; I = 300
+206: F033   ; BCD of V0 at 300
+208: F265   ; load V0-V2 from 300
+20A: 120A   ; loop forever
+20C: 0000
+20E: 00E0   ; never run
+210: F0 90  ; sprite
+";
+
+fn run(rom: &str) -> (Emulator, Coverage) {
+    let mut emulator = Emulator::with_seed(0);
+    emulator.set_quirks(Quirks {
+        display_wait: false,
+        ..Quirks::chip8()
+    });
+    emulator.load_rom(&parse_listing(rom).unwrap()).unwrap();
+    let mut coverage = Coverage::new();
+    emulator.run_frame_observed(&mut coverage);
+    (emulator, coverage)
+}
+
+#[test]
+fn accesses_are_tagged() {
+    let (_, coverage) = run(ROM);
+    let executed = Access {
+        executed: true,
+        ..Access::default()
+    };
+    for address in 0x200..0x20C {
+        assert_eq!(coverage.access(address), executed, "{:#X}", address);
+    }
+    assert!(coverage.access(0x20E).untouched());
+
+    let read = Access {
+        read: true,
+        ..Access::default()
+    };
+    assert_eq!(coverage.access(0x210), read);
+    assert_eq!(coverage.access(0x211), read);
+    let read_and_written = Access {
+        read: true,
+        written: true,
+        ..Access::default()
+    };
+    for address in 0x300..0x303 {
+        assert_eq!(coverage.access(address), read_and_written);
+    }
+    assert!(coverage.access(0x303).untouched());
+}
+
+#[test]
+fn json_round_trips_and_merges() {
+    let (_, coverage) = run(ROM);
+    let json = coverage.to_json();
+    assert_eq!(
+        json,
+        "{\"ram_size\":4096,\"executed\":[[512,524]],\"read\":[[528,530],[768,771]],\
+         \"written\":[[768,771]]}\n"
+    );
+    assert_eq!(Coverage::from_json(&json).unwrap(), coverage);
+
+    // A second run that only reaches the rest of the ROM adds to the first.
+    let (_, other) = run("200: 120E 0000 0000 0000 0000 0000 0000 120E");
+    let mut merged = Coverage::from_json(&json).unwrap();
+    merged.merge(&other);
+    assert!(merged.access(0x202).executed);
+    assert!(merged.access(0x20E).executed);
+    assert!(merged.access(0x300).written);
+    assert!(!merged.access(0x20C).executed);
+}
+
+#[test]
+fn invalid_json_is_rejected() {
+    for json in [
+        "",
+        "{\"ram_size\":4096,",
+        "{\"ram_size\":8192,\"executed\":[],\"read\":[],\"written\":[]}",
+        "{\"ram_size\":4096,\"executed\":[],\"read\":[]}",
+        "{\"ram_size\":4096,\"executed\":[[2,1]],\"read\":[],\"written\":[]}",
+        "{\"ram_size\":4096,\"executed\":[[0,4097]],\"read\":[],\"written\":[]}",
+    ] {
+        assert!(Coverage::from_json(json).is_err(), "{}", json);
+    }
+    let error = Coverage::from_json("{\n\"ram_size\" 4096}").unwrap_err();
+    assert_eq!(error.line, 2);
+    let error = Coverage::from_json(&"{\"a\":".repeat(100_000)).unwrap_err();
+    assert_eq!(error.reason, "objects and arrays nested too deep");
+}
+
+#[test]
+fn errors_report_their_line() {
+    let mut json =
+        "{\n  \"ram_size\": 4096,\n  \"executed\": [\n    [0, 2],\n    [2, 1]\n  ],\n".to_string();
+    let error =
+        Coverage::from_json(&format!("{}  \"read\": [], \"written\": []\n}}", json)).unwrap_err();
+    assert_eq!((error.line, error.reason), (5, "invalid address range"));
+
+    json = json.replace("[2, 1]", "[2, 3]");
+    let error = Coverage::from_json(&format!("{}  \"read\": []\n}}", json)).unwrap_err();
+    assert_eq!(
+        (error.line, error.reason),
+        (1, "missing a list of address ranges")
+    );
+    let error = Coverage::from_json(&format!("{}  \"read\": [],\n  \"written\": {{}}\n}}", json))
+        .unwrap_err();
+    assert_eq!(
+        (error.line, error.reason),
+        (8, "missing a list of address ranges")
+    );
+    let error = Coverage::from_json("\n{\"ram_size\":\n8192}").unwrap_err();
+    assert_eq!(
+        (error.line, error.reason),
+        (3, "ram_size doesn't match the emulator's RAM")
+    );
+}
+
+#[test]
+fn deep_nesting_is_rejected() {
+    // Far deeper than the stack could take one call per level.
+    let deep = "[".repeat(200_000);
+    let error = Coverage::from_json(&deep).unwrap_err();
+    assert_eq!(error.reason, "objects and arrays nested too deep");
+
+    // 128 levels is fine as JSON, the coverage just has the wrong shape.
+    let nested = format!("{}{}", "[".repeat(128), "]".repeat(128));
+    let error = Coverage::from_json(&nested).unwrap_err();
+    assert_ne!(error.reason, "objects and arrays nested too deep");
+    let nested = format!("{}{}", "[".repeat(129), "]".repeat(129));
+    let error = Coverage::from_json(&nested).unwrap_err();
+    assert_eq!(error.reason, "objects and arrays nested too deep");
+}
+
+#[test]
+fn hex_dump_marks_accesses() {
+    let (emulator, coverage) = run(ROM);
+    let dump = coverage.to_hex_dump(&emulator);
+    let lines: Vec<&str> = dump.lines().collect();
+    assert_eq!(
+        lines[0],
+        "; 12 bytes executed, 5 read, 3 written, 4079 untouched"
+    );
+    assert!(
+        lines.contains(&"200:  A2 10 D0 12 A3 00 F0 33 F2 65 12 0A 00 00 00 E0  xxxxxxxxxxxx....")
+    );
+    assert!(
+        lines.contains(&"210:  F0 90 00 00 00 00 00 00 00 00 00 00 00 00 00 00  rr..............")
+    );
+    assert!(
+        lines.contains(&"300:  00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00  ***.............")
+    );
+    // Untouched zero rows collapse, so the dump stays short.
+    assert!(lines.contains(&"*"));
+    assert!(lines.len() < 40, "{}", dump);
+}
@@ -0,0 +1,144 @@
+//! Differential tests of the recompiler: the same ROM runs on the
+//! interpreter and the recompiler and the whole machine state is compared
+//! after every step.
+
+use chip_core::harness::{parse_listing, State};
+use chip_core::quirks::Quirks;
+use chip_core::recompiler::Recompiler;
+use chip_core::Emulator;
+use proptest::prelude::*;
+
+/// Creates two identical emulators with the ROM loaded.
+fn pair(rom: &[u8], quirks: Quirks) -> (Emulator, Emulator) {
+    let new = || {
+        let mut emulator = Emulator::new();
+        emulator.set_quirks(quirks);
+        emulator.seed_rng(0x5EED);
+        emulator.load_rom(rom).unwrap();
+        emulator
+    };
+    (new(), new())
+}
+
+/// Runs a ROM on both engines, `ticks` cycles per step and a timer tick
+/// after each step, checking the states match after every step.
+fn compare(rom: &[u8], quirks: Quirks, ticks: &[usize], keys: u16) -> Result<(), TestCaseError> {
+    let (mut interpreter, mut recompiled) = pair(rom, quirks);
+    interpreter.set_keys(keys);
+    recompiled.set_keys(keys);
+    let mut recompiler = Recompiler::new();
+
+    for (step, ticks) in ticks.iter().enumerate() {
+        for _ in 0..*ticks {
+            interpreter.tick();
+        }
+        interpreter.timer_tick();
+        recompiler.run(&mut recompiled, *ticks);
+        recompiled.timer_tick();
+
+        prop_assert_eq!(
+            State::capture(&recompiled),
+            State::capture(&interpreter),
+            "step {} with {:?}",
+            step,
+            quirks
+        );
+    }
+    Ok(())
+}
+
+fn presets() -> [Quirks; 4] {
+    [
+        Quirks::default(),
+        Quirks::chip8(),
+        Quirks::schip(),
+        Quirks::xochip(),
+    ]
+}
+
+#[test]
+fn test_roms() {
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/roms");
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let rom = parse_listing(&std::fs::read_to_string(entry.unwrap().path()).unwrap()).unwrap();
+        for quirks in presets() {
+            // Uneven steps so blocks are cut off part way through.
+            let ticks: Vec<usize> = (0..120).map(|step| step % 13 + 1).collect();
+            compare(&rom, quirks, &ticks, 0).unwrap();
+            compare(&rom, quirks, &ticks, 0x0480).unwrap();
+        }
+    }
+}
+
+#[test]
+fn self_modifying_code() {
+    let rom = parse_listing(
+        "
+        200: 6A00   ; VA = 00
+        202: 7A01   ; loop: VA += 1
+        204: 6B11   ; target: VB = 11, rewritten to VB = 22
+        206: 3A02   ; skip if VA == 2
+        208: 120C   ; jump to rewrite
+        20A: 120A   ; halt
+        20C: 606B   ; rewrite: V0 = 6B
+        20E: 6122   ; V1 = 22
+        210: A204   ; I = target
+        212: F155   ; store V0..V1
+        214: 1202   ; jump to loop
+        ",
+    )
+    .unwrap();
+
+    let (mut emulator, _) = pair(&rom, Quirks::default());
+    let mut recompiler = Recompiler::new();
+    recompiler.run(&mut emulator, 100);
+    assert_eq!(emulator.registers()[0xB], 0x22);
+    compare(&rom, Quirks::default(), &[1; 50], 0).unwrap();
+    compare(&rom, Quirks::default(), &[100], 0).unwrap();
+}
+
+/// An opcode that mostly decodes to a real instruction, with addresses in
+/// the first page of the ROM so jumps stay in the program and FX33 and
+/// FX55 write over it.
+fn opcode() -> impl Strategy<Value = u16> {
+    let address = 0x200u16..0x300;
+    (0u16..16, 0u16..16, 0u16..16, any::<u8>(), address).prop_map(|(kind, x, y, nn, nnn)| {
+        let nn = nn as u16;
+        match kind {
+            0x0 => [0x00E0, 0x00EE, 0x0000][x as usize % 3],
+            0x5 | 0x9 => kind << 12 | x << 8 | y << 4,
+            0x8 => 0x8000 | x << 8 | y << 4 | [0, 1, 2, 3, 4, 5, 6, 7, 0xE][nn as usize % 9],
+            0xE => 0xE000 | x << 8 | [0x9E, 0xA1][nn as usize % 2],
+            0xF => {
+                let low = [0x07, 0x0A, 0x15, 0x18, 0x1E, 0x29, 0x33, 0x55, 0x65];
+                0xF000 | x << 8 | low[nn as usize % low.len()]
+            }
+            0x1 | 0x2 | 0xA | 0xB => kind << 12 | nnn,
+            _ => kind << 12 | x << 8 | nn,
+        }
+    })
+}
+
+fn quirks() -> impl Strategy<Value = Quirks> {
+    any::<[bool; 6]>().prop_map(|flags| Quirks {
+        vf_reset: flags[0],
+        memory: flags[1],
+        display_wait: flags[2],
+        clipping: flags[3],
+        shifting: flags[4],
+        jumping: flags[5],
+    })
+}
+
+proptest! {
+    #[test]
+    fn random_roms(
+        program in prop::collection::vec(opcode(), 1..128),
+        quirks in quirks(),
+        ticks in prop::collection::vec(1usize..40, 1..60),
+        keys in any::<u16>(),
+    ) {
+        let rom: Vec<u8> = program.iter().flat_map(|op| op.to_be_bytes()).collect();
+        compare(&rom, quirks, &ticks, keys)?;
+    }
+}
@@ -0,0 +1,263 @@
+//! Tests of the environment wrapper on a tiny game: key 5 scores a point,
+//! key 6 loses a life and the game is over when the lives run out.
+
+use chip_core::env::{Comparison, Condition, Env, EnvConfig, Reward, Source};
+use chip_core::error::{EnvError, MemoryError};
+use chip_core::harness::parse_listing;
+use chip_core::quirks::Quirks;
+
+const GAME: &str = "
+    200: 6E03   ; VE = 3 lives
+    202: 6105   ; V1 = 5 points
+    204: 6005   ; loop: V0 = 5
+    206: E0A1   ; skip if key 5 isn't pressed
+    208: 7101   ; V1 += 1
+    20A: 6006   ; V0 = 6
+    20C: E0A1   ; skip if key 6 isn't pressed
+    20E: 7EFF   ; VE -= 1
+    210: A300   ; I = 300
+    212: F133   ; score digits at 300
+    214: 6201   ; V2 = 1
+    216: F215   ; DT = 1
+    218: F207   ; wait: V2 = DT
+    21A: 3200   ; skip if V2 == 0
+    21C: 1218   ; jump to wait
+    21E: 1204   ; jump to loop
+";
+
+const CONFIG: &str = "
+    # The score is 3 digits at 0300, lives are in VE.
+    ticks_per_frame = 20
+    quirks = schip
+    action = none
+    action = 5
+    action = 6
+    action = 5 6
+    reward = bcd 0300 3
+    reward = VE * 10
+    done = VE == 0
+";
+
+fn game() -> Vec<u8> {
+    parse_listing(GAME).unwrap()
+}
+
+#[test]
+fn parses_config() {
+    let config = EnvConfig::parse(CONFIG).unwrap();
+    assert_eq!(config.ticks_per_frame, 20);
+    assert_eq!(config.quirks, Quirks::schip());
+    assert_eq!(config.frame_skip, 1);
+    assert_eq!(config.actions, vec![0, 0x20, 0x40, 0x60]);
+    assert_eq!(
+        config.rewards,
+        vec![
+            Reward {
+                source: Source::Bcd {
+                    address: 0x300,
+                    digits: 3
+                },
+                weight: 1.0
+            },
+            Reward {
+                source: Source::Register(0xE),
+                weight: 10.0
+            },
+        ]
+    );
+    assert_eq!(
+        config.done,
+        vec![Condition {
+            source: Source::Register(0xE),
+            comparison: Comparison::Equal,
+            value: 0
+        }]
+    );
+
+    // Without actions every key gets one.
+    assert_eq!(EnvConfig::parse("").unwrap().actions.len(), 17);
+
+    for (text, line) in [
+        ("speed = 3", 1),
+        ("\naction = G", 2),
+        ("reward = bcd 0FFF 3", 1),
+        ("done = VE =< 3", 1),
+        ("done = VE", 1),
+        ("sticky = 2", 1),
+        ("reward = V0 * NaN", 1),
+        ("reward = V0\nreward = V1 * inf", 2),
+        ("frame_skip = 0", 1),
+        ("# comment\n\nquirks = cosmac", 3),
+    ] {
+        assert_eq!(EnvConfig::parse(text).unwrap_err().line, line, "{}", text);
+    }
+}
+
+#[test]
+fn rewards_follow_score() {
+    let mut env = Env::new(&game(), EnvConfig::parse(CONFIG).unwrap(), 1).unwrap();
+    let mut total = 0.0;
+    for _ in 0..20 {
+        let (_, reward, done) = env.step(1);
+        assert!(!done);
+        total += reward;
+    }
+    // The score counts from 0 at the start of the episode and the lives
+    // from 0 as well, so the first frame also rewards 3 lives.
+    let points = env.emulator().registers()[1];
+    assert!(points > 5);
+    assert_eq!(total, points as f32 + 30.0);
+}
+
+#[test]
+fn ends_when_lives_run_out() {
+    let mut env = Env::new(&game(), EnvConfig::parse(CONFIG).unwrap(), 1).unwrap();
+    env.step(0);
+    let mut steps = 0;
+    loop {
+        let (_, reward, done) = env.step(2);
+        steps += 1;
+        if done {
+            break;
+        }
+        assert!(reward <= 0.0);
+        assert!(steps < 100);
+    }
+    assert_eq!(env.emulator().registers()[0xE], 0);
+
+    // A new episode starts from the beginning of the ROM.
+    let observation = env.reset();
+    assert!(observation.screen.iter().all(|pixel| !pixel));
+    assert_eq!(env.emulator().program_counter(), 0x200);
+    let (_, _, done) = env.step(0);
+    assert!(!done);
+}
+
+#[test]
+fn frame_skip_and_max_frames() {
+    let config = EnvConfig {
+        frame_skip: 4,
+        max_frames: Some(10),
+        ..EnvConfig::parse(CONFIG).unwrap()
+    };
+    let mut env = Env::new(&game(), config, 1).unwrap();
+    assert!(!env.step(0).2);
+    assert!(!env.step(0).2);
+    assert!(env.step(0).2);
+}
+
+#[test]
+fn sticky_actions_are_seeded() {
+    let config = EnvConfig {
+        sticky: 0.5,
+        ..EnvConfig::parse(CONFIG).unwrap()
+    };
+    let play = |seed| {
+        let mut env = Env::new(&game(), config.clone(), seed).unwrap();
+        (0..50)
+            .map(|step| env.step(step % 2).1)
+            .collect::<Vec<f32>>()
+    };
+    assert_eq!(play(7), play(7));
+    assert_ne!(play(7), play(8));
+
+    // Fully sticky actions never let go of the first action (no keys).
+    let config = EnvConfig {
+        sticky: 1.0,
+        ..EnvConfig::parse(CONFIG).unwrap()
+    };
+    let mut env = Env::new(&game(), config, 1).unwrap();
+    env.step(0);
+    for _ in 0..20 {
+        assert_eq!(env.step(1).1, 0.0);
+    }
+}
+
+#[test]
+fn non_digit_bcd_bytes_count_as_zero() {
+    // The font at 0000 starts with 9 bytes above 9.
+    let config = EnvConfig::parse("reward = bcd 0000 9\ndone = bcd 0000 9 == 0").unwrap();
+    let mut env = Env::new(&game(), config, 1).unwrap();
+    let (_, reward, done) = env.step(0);
+    assert_eq!(reward, 0.0);
+    assert!(done);
+}
+
+#[test]
+fn invalid_configs_are_rejected() {
+    let reward = |source| EnvConfig {
+        rewards: vec![Reward {
+            source,
+            weight: 1.0,
+        }],
+        ..EnvConfig::default()
+    };
+    let done = |source| EnvConfig {
+        done: vec![Condition {
+            source,
+            comparison: Comparison::Equal,
+            value: 0,
+        }],
+        ..EnvConfig::default()
+    };
+    let weighted = |weight| EnvConfig {
+        rewards: vec![Reward {
+            source: Source::Register(0),
+            weight,
+        }],
+        ..EnvConfig::default()
+    };
+    for (config, reason) in [
+        (reward(Source::Ram(0x1000)), "invalid reward source"),
+        (weighted(f32::NAN), "reward weights must be finite"),
+        (weighted(f32::NEG_INFINITY), "reward weights must be finite"),
+        (reward(Source::Register(16)), "invalid reward source"),
+        (
+            reward(Source::Bcd {
+                address: 0xFFE,
+                digits: 3,
+            }),
+            "invalid reward source",
+        ),
+        (
+            done(Source::Bcd {
+                address: 0x300,
+                digits: 10,
+            }),
+            "invalid done source",
+        ),
+        (
+            EnvConfig {
+                frame_skip: 0,
+                ..EnvConfig::default()
+            },
+            "frame skip must be at least 1",
+        ),
+        (
+            EnvConfig {
+                sticky: f32::NAN,
+                ..EnvConfig::default()
+            },
+            "sticky must be between 0 and 1",
+        ),
+        (
+            EnvConfig {
+                actions: Vec::new(),
+                ..EnvConfig::default()
+            },
+            "there must be at least one action",
+        ),
+    ] {
+        assert_eq!(
+            Env::new(&game(), config, 1).err(),
+            Some(EnvError::InvalidConfig(reason))
+        );
+    }
+    assert_eq!(
+        Env::new(&[0; 4096], EnvConfig::default(), 1).err(),
+        Some(EnvError::Rom(MemoryError::AddressOutOfRange {
+            address: 0x200,
+            len: 4096
+        }))
+    );
+}
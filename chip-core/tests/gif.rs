@@ -0,0 +1,283 @@
+//! GIF recording: identical frames merge, delays add up to the 60 Hz
+//! timeline, frames too short to show are dropped, the LZW data decodes back
+//! to the pixels, screens held longer than a delay can say are repeated and
+//! recordings too large for a GIF are refused.
+
+use chip_core::display::{Framebuffer, Palette};
+use chip_core::error::GifError;
+use chip_core::gif::GifRecorder;
+
+/// A display with any size and pixels.
+#[derive(Clone)]
+struct Screen {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+impl Screen {
+    fn filled(width: usize, height: usize, value: u8) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![value; width * height],
+        }
+    }
+}
+
+impl Framebuffer for Screen {
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn height(&self) -> usize {
+        self.height
+    }
+
+    fn pixel(&self, x: usize, y: usize) -> usize {
+        self.pixels[x + self.width * y] as usize
+    }
+}
+
+/// One image of a decoded GIF.
+struct Image {
+    /// Delay in hundredths of a second.
+    delay: u16,
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+/// Decompresses GIF LZW data.
+fn lzw_decode(data: &[u8], min_code_size: u32) -> Vec<u8> {
+    let clear = 1u16 << min_code_size;
+    let end = clear + 1;
+    let initial = || -> Vec<Vec<u8>> { (0..=end).map(|code| vec![code as u8]).collect() };
+    let mut table = initial();
+    let mut size = min_code_size + 1;
+    let mut pos = 0;
+    let mut previous: Option<u16> = None;
+    let mut pixels = Vec::new();
+    loop {
+        let code = (0..size).fold(0u16, |code, bit| {
+            let value = data[(pos + bit as usize) / 8] >> ((pos + bit as usize) % 8) & 1;
+            code | (value as u16) << bit
+        });
+        pos += size as usize;
+        if code == clear {
+            table = initial();
+            size = min_code_size + 1;
+            previous = None;
+            continue;
+        }
+        if code == end {
+            return pixels;
+        }
+        let entry = match (table.get(code as usize), previous) {
+            (Some(entry), _) => entry.clone(),
+            // The code being defined by this very step.
+            (None, Some(previous)) => {
+                let mut entry = table[previous as usize].clone();
+                entry.push(entry[0]);
+                entry
+            }
+            (None, None) => panic!("undefined code {}", code),
+        };
+        pixels.extend(&entry);
+        if let Some(previous) = previous {
+            if table.len() < 4096 {
+                let mut new = table[previous as usize].clone();
+                new.push(entry[0]);
+                table.push(new);
+                if table.len() == 1 << size && size < 12 {
+                    size += 1;
+                }
+            }
+        }
+        previous = Some(code);
+    }
+}
+
+/// Reads data sub-blocks up to the terminator.
+fn sub_blocks(gif: &[u8], pos: &mut usize) -> Vec<u8> {
+    let mut data = Vec::new();
+    loop {
+        let len = gif[*pos] as usize;
+        *pos += 1;
+        if len == 0 {
+            return data;
+        }
+        data.extend(&gif[*pos..*pos + len]);
+        *pos += len;
+    }
+}
+
+/// Decodes a GIF written by `GifRecorder`, returning the palette and the
+/// images.
+fn decode(gif: &[u8]) -> (Vec<u8>, Vec<Image>) {
+    assert_eq!(&gif[..6], b"GIF89a");
+    let word = |pos: usize| u16::from_le_bytes([gif[pos], gif[pos + 1]]);
+    assert_eq!(gif[10], 0xF3, "16 colour global table");
+    let palette = gif[13..13 + 48].to_vec();
+
+    let mut pos = 13 + 48;
+    let mut images = Vec::new();
+    let mut delay = None;
+    loop {
+        match gif[pos] {
+            0x21 => {
+                let label = gif[pos + 1];
+                pos += 2;
+                let data = sub_blocks(gif, &mut pos);
+                match label {
+                    0xF9 => delay = Some(u16::from_le_bytes([data[1], data[2]])),
+                    0xFF => assert_eq!(&data[..11], b"NETSCAPE2.0"),
+                    _ => panic!("unexpected extension {:#X}", label),
+                }
+            }
+            0x2C => {
+                let (width, height) = (word(pos + 5) as usize, word(pos + 7) as usize);
+                assert_eq!((word(pos + 1), word(pos + 3)), (0, 0));
+                assert_eq!(gif[pos + 9], 0, "local colour table");
+                let min_code_size = gif[pos + 10] as u32;
+                pos += 11;
+                let pixels = lzw_decode(&sub_blocks(gif, &mut pos), min_code_size);
+                assert_eq!(pixels.len(), width * height);
+                images.push(Image {
+                    delay: delay.take().expect("graphic control extension"),
+                    width,
+                    height,
+                    pixels,
+                });
+            }
+            0x3B => return (palette, images),
+            block => panic!("unexpected block {:#X}", block),
+        }
+    }
+}
+
+/// Records `frames` frames of the screen `frame(n)` returns.
+fn record(scale: usize, frames: u32, frame: impl Fn(u32) -> Screen) -> Vec<u8> {
+    let mut recorder = GifRecorder::new(scale, Palette::default());
+    assert!(recorder.stop().is_none());
+    recorder.start();
+    for idx in 0..frames {
+        recorder.capture(&frame(idx)).unwrap();
+    }
+    assert_eq!(recorder.frame_count(), frames);
+    recorder.stop().unwrap()
+}
+
+#[test]
+fn identical_frames_merge() {
+    let gif = record(2, 90, |idx| Screen::filled(8, 4, (idx >= 30) as u8));
+    let (palette, images) = decode(&gif);
+    assert_eq!(&palette[..6], [0, 0, 0, 0xFF, 0xFF, 0xFF]);
+
+    // Half a second of background and a second of foreground.
+    let delays: Vec<u16> = images.iter().map(|image| image.delay).collect();
+    assert_eq!(delays, [50, 100]);
+    for (image, value) in images.iter().zip([0, 1]) {
+        assert_eq!((image.width, image.height), (16, 8));
+        assert!(image.pixels.iter().all(|pixel| *pixel == value));
+    }
+}
+
+#[test]
+fn delays_follow_the_60_hz_timeline() {
+    // A change every 7 frames doesn't land on whole hundredths of a second,
+    // the rounding mustn't build up.
+    let frames = 600;
+    let gif = record(1, frames, |idx| Screen::filled(4, 4, (idx / 7 % 2) as u8));
+    let (_, images) = decode(&gif);
+    assert_eq!(images.len(), 86);
+    let total: u32 = images.iter().map(|image| image.delay as u32).sum();
+    assert_eq!(total, frames * 100 / 60);
+    // The last frame is cut short by the end of the recording.
+    assert!(images[..85]
+        .iter()
+        .all(|image| (11..=12).contains(&image.delay)));
+}
+
+#[test]
+fn frames_too_short_to_show_are_dropped() {
+    // Flickering every frame gives 1.67 hundredths per frame, below what
+    // viewers show, so frames are dropped but the length is kept.
+    let frames = 60;
+    let gif = record(1, frames, |idx| Screen::filled(4, 4, (idx % 2) as u8));
+    let (_, images) = decode(&gif);
+    assert!(images.len() < frames as usize, "{}", images.len());
+    assert!(images.len() >= 30);
+    assert!(images.iter().all(|image| image.delay >= 2));
+    let total: u32 = images.iter().map(|image| image.delay as u32).sum();
+    assert_eq!(total, 100);
+}
+
+#[test]
+fn long_delays_repeat_the_image() {
+    // A second of one screen, then the rest of 20 minutes of another: far
+    // past the 65535 hundredths a delay holds.
+    let frames = 20 * 60 * 60;
+    let gif = record(1, frames, |idx| Screen::filled(4, 4, (idx >= 60) as u8));
+    let (_, images) = decode(&gif);
+    let delays: Vec<u16> = images.iter().map(|image| image.delay).collect();
+    assert_eq!(delays, [100, 65535, 54365]);
+    assert!(images[1..]
+        .iter()
+        .all(|image| image.pixels == images[2].pixels));
+    assert_ne!(images[0].pixels, images[1].pixels);
+}
+
+#[test]
+fn lzw_data_decodes_to_the_pixels() {
+    // Noise in every colour, enough to fill the code table several times.
+    let mut state = 0x1234_5678u32;
+    let mut noise = Screen::filled(160, 120, 0);
+    for pixel in &mut noise.pixels {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        *pixel = (state % 16) as u8;
+    }
+    let scale = 3;
+    let frames = [noise.clone(), Screen::filled(160, 120, 7)];
+    let gif = record(scale, 2, |idx| frames[idx as usize].clone());
+
+    let (_, images) = decode(&gif);
+    assert_eq!(images.len(), 2);
+    for (image, frame) in images.iter().zip(&frames) {
+        assert_eq!((image.width, image.height), (480, 360));
+        for y in 0..image.height {
+            for x in 0..image.width {
+                let expected = frame.pixel(x / scale, y / scale) as u8;
+                assert_eq!(
+                    image.pixels[x + image.width * y],
+                    expected,
+                    "({}, {})",
+                    x,
+                    y
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn oversized_recordings_are_refused() {
+    let mut recorder = GifRecorder::new(1024, Palette::default());
+    recorder.start();
+    assert_eq!(
+        recorder.capture(&Screen::filled(64, 32, 1)),
+        Err(GifError::TooLarge {
+            width: 65536,
+            height: 32768
+        })
+    );
+    assert_eq!(recorder.frame_count(), 0);
+
+    // The largest size that fits is fine.
+    let mut recorder = GifRecorder::new(1023, Palette::default());
+    recorder.start();
+    recorder.capture(&Screen::filled(64, 1, 1)).unwrap();
+    assert_eq!(recorder.frame_count(), 1);
+}
@@ -0,0 +1,142 @@
+//! Keymaps: the presets cover the keypad, several host keys can press one
+//! CHIP-8 key and per-ROM layouts load from TOML.
+
+use chip_core::error::MemoryError;
+use chip_core::keymap::{Keymap, KeymapOverrides, PRESETS};
+use chip_core::NUM_KEYS;
+
+/// The SHA-1 of the ROM `00E0`.
+const CLEAR: &str = "159ba69f4c40be3042fc54c7fbb2025f7e49f8e0";
+
+#[test]
+fn presets_cover_the_keypad() {
+    for name in PRESETS {
+        let keymap = Keymap::preset(name).unwrap();
+        for key in 0..NUM_KEYS {
+            assert_eq!(keymap.host_keys(key).len(), 1, "{} {:X}", name, key);
+        }
+    }
+    assert!(Keymap::preset("dvorak").is_none());
+
+    let qwerty = Keymap::qwerty();
+    assert_eq!(qwerty.key("Digit1"), Some(0x1));
+    assert_eq!(qwerty.key("Digit4"), Some(0xC));
+    assert_eq!(qwerty.key("KeyW"), Some(0x5));
+    assert_eq!(qwerty.key("KeyX"), Some(0x0));
+    assert_eq!(qwerty.key("KeyV"), Some(0xF));
+    assert_eq!(qwerty.key("Numpad5"), None);
+
+    let numpad = Keymap::numpad();
+    assert_eq!(numpad.key("Numpad0"), Some(0x0));
+    assert_eq!(numpad.key("Numpad9"), Some(0x9));
+    assert_eq!(numpad.key("NumpadDivide"), Some(0xA));
+    assert_eq!(numpad.key("NumpadDecimal"), Some(0xF));
+}
+
+#[test]
+fn keys_stay_down_while_any_binding_is_held() {
+    let mut keymap = Keymap::qwerty();
+    keymap.bind("ArrowUp", 0x5).unwrap();
+    assert_eq!(keymap.host_keys(0x5), ["ArrowUp", "KeyW"]);
+
+    let keys = keymap.keys(["ArrowUp", "KeyW", "Space"]);
+    let down: Vec<usize> = (0..NUM_KEYS).filter(|key| keys[*key]).collect();
+    assert_eq!(down, [0x5]);
+    // Letting go of one of them leaves the key down.
+    assert!(keymap.keys(["KeyW"])[0x5]);
+    assert!(!keymap.keys([])[0x5]);
+
+    keymap.unbind(0x5);
+    assert!(keymap.host_keys(0x5).is_empty());
+    assert_eq!(keymap.key("KeyW"), None);
+}
+
+#[test]
+fn binding_past_the_keypad_fails() {
+    let mut keymap = Keymap::new();
+    assert_eq!(
+        keymap.bind("Space", NUM_KEYS),
+        Err(MemoryError::InvalidKey(NUM_KEYS))
+    );
+    assert_eq!(keymap, Keymap::new());
+}
+
+#[test]
+fn overrides_load_from_toml() {
+    let text = format!(
+        r#"
+# Everything on the numpad, with space as an extra 5.
+preset = "numpad"
+5 = ["Numpad5", "Space"]
+
+["{CLEAR}"]
+preset = "qwerty"  # this ROM plays better on the letters
+2 = ["ArrowUp", "KeyW"]
+a = []
+"#
+    );
+    let overrides = KeymapOverrides::parse(&text).unwrap();
+
+    let default = overrides.keymap(&[0x12, 0x00]);
+    assert_eq!(default.host_keys(0x5), ["Numpad5", "Space"]);
+    assert_eq!(default.key("Numpad7"), Some(0x7));
+
+    let clear = overrides.keymap(&[0x00, 0xE0]);
+    assert_eq!(clear.host_keys(0x2), ["ArrowUp", "KeyW"]);
+    // KeyW moved from 5 to 2, and A has no keys left.
+    assert!(clear.host_keys(0x5).is_empty());
+    assert!(clear.host_keys(0xA).is_empty());
+    assert_eq!(clear.key("KeyE"), Some(0x6));
+    assert_eq!(clear.key("Space"), None);
+
+    // Without a file every ROM gets the qwerty layout.
+    assert_eq!(
+        KeymapOverrides::new().keymap(&[0x00, 0xE0]),
+        &Keymap::qwerty()
+    );
+}
+
+#[test]
+fn overrides_accept_any_toml() {
+    let text = format!(
+        r#"
+'preset' = 'numpad'
+5 = [
+    "Numpad5",
+    'Space',  # literal strings
+    "Key\u0041",
+]
+
+"{CLEAR}" = {{ preset = "qwerty", 2 = ["Arrow\x55p"] }}
+"#
+    );
+    let overrides = KeymapOverrides::parse(&text).unwrap();
+
+    let default = overrides.keymap(&[0x12, 0x00]);
+    assert_eq!(default.host_keys(0x5), ["KeyA", "Numpad5", "Space"]);
+
+    let clear = overrides.keymap(&[0x00, 0xE0]);
+    assert_eq!(clear.host_keys(0x2), ["ArrowUp"]);
+    assert_eq!(clear.key("KeyA"), Some(0x7));
+}
+
+#[test]
+fn invalid_overrides_are_rejected() {
+    for (text, line) in [
+        ("preset = \"dvorak\"", 1),
+        ("preset = qwerty", 1),
+        ("\n10 = [\"KeyQ\"]", 2),
+        ("G = [\"KeyQ\"]", 1),
+        ("5 = \"KeyQ\"", 1),
+        ("5 = [KeyQ]", 1),
+        ("5", 1),
+        ("\n\n5 = [\n  \"KeyQ\",\n  7,\n]", 3),
+        ("[1234]", 1),
+        (&format!("[{CLEAR}]\n[{CLEAR}]"), 2),
+        (&format!("[{CLEAR}]\n\n[\"{}\"]", CLEAR.to_uppercase()), 3),
+        (&format!("[{CLEAR}]\n[{CLEAR}.5]"), 2),
+    ] {
+        let error = KeymapOverrides::parse(text).unwrap_err();
+        assert_eq!(error.line, line, "{}", text);
+    }
+}
@@ -0,0 +1,119 @@
+//! Checked access: reads and writes of RAM, registers, the program counter,
+//! the stack and the keys are validated and leave the machine alone on
+//! error.
+
+use chip_core::error::MemoryError;
+use chip_core::{Emulator, RAM_SIZE, STACK_SIZE, START_ADDRESS};
+
+#[test]
+fn ram_ranges_must_fit() {
+    let mut emulator = Emulator::with_seed(0);
+    emulator.write_ram(0xFFE, &[1, 2]).unwrap();
+    assert_eq!(emulator.read_ram(0xFFE, 2), Ok(&[1, 2][..]));
+    assert_eq!(emulator.read_ram(RAM_SIZE, 0), Ok(&[][..]));
+
+    for (address, len) in [(0xFFF, 2), (RAM_SIZE, 1), (usize::MAX, 2)] {
+        let error = MemoryError::AddressOutOfRange { address, len };
+        assert_eq!(emulator.read_ram(address, len), Err(error));
+        assert_eq!(emulator.write_ram(address, &vec![0xAA; len]), Err(error));
+    }
+    // Nothing was written by the failed calls.
+    assert_eq!(emulator.read_ram(0xFFE, 2), Ok(&[1, 2][..]));
+    assert_eq!(
+        MemoryError::AddressOutOfRange {
+            address: 0xFFF,
+            len: 2
+        }
+        .to_string(),
+        "address range 0xFFF (+2 bytes) is outside of RAM"
+    );
+}
+
+#[test]
+fn registers_must_exist() {
+    let mut emulator = Emulator::with_seed(0);
+    emulator.set_register(0xF, 7).unwrap();
+    assert_eq!(emulator.registers()[0xF], 7);
+    assert_eq!(
+        emulator.set_register(16, 1),
+        Err(MemoryError::InvalidRegister(16))
+    );
+
+    emulator.set_i_register(0xFFF).unwrap();
+    assert_eq!(emulator.i_register(), 0xFFF);
+    assert_eq!(
+        emulator.set_i_register(0x1000),
+        Err(MemoryError::AddressOutOfRange {
+            address: 0x1000,
+            len: 1
+        })
+    );
+    assert_eq!(emulator.i_register(), 0xFFF);
+
+    emulator.set_delay_timer(30);
+    emulator.set_sound_timer(40);
+    emulator.timer_tick();
+    assert_eq!((emulator.delay_timer(), emulator.sound_timer()), (29, 39));
+}
+
+#[test]
+fn program_counter_wraps_like_the_interpreter() {
+    let mut emulator = Emulator::with_seed(0);
+    // The last byte of RAM and the first byte of the font make 12F0, a
+    // jump to 2F0.
+    emulator.write_ram(0xFFF, &[0x12]).unwrap();
+    emulator.set_program_counter(0xFFF).unwrap();
+    emulator.tick();
+    assert_eq!(emulator.program_counter(), 0x2F0);
+    assert_eq!(emulator.fault(), None);
+
+    assert_eq!(
+        emulator.set_program_counter(0x1000),
+        Err(MemoryError::AddressOutOfRange {
+            address: 0x1000,
+            len: 1
+        })
+    );
+    assert_eq!(emulator.program_counter(), 0x2F0);
+}
+
+#[test]
+fn stack_contents_are_checked() {
+    let mut emulator = Emulator::with_seed(0);
+    assert_eq!(emulator.stack(), &[]);
+    emulator.set_stack(&[START_ADDRESS, 0xFFF]).unwrap();
+    assert_eq!(emulator.stack(), &[START_ADDRESS, 0xFFF]);
+
+    let full = [START_ADDRESS; STACK_SIZE];
+    emulator.set_stack(&full).unwrap();
+    assert_eq!(emulator.stack(), &full);
+
+    let too_many = [START_ADDRESS; STACK_SIZE + 1];
+    assert_eq!(
+        emulator.set_stack(&too_many),
+        Err(MemoryError::StackOverflow(STACK_SIZE + 1))
+    );
+    assert_eq!(
+        emulator.set_stack(&[START_ADDRESS, 0x1000]),
+        Err(MemoryError::AddressOutOfRange {
+            address: 0x1000,
+            len: 1
+        })
+    );
+    // The stack is unchanged by the failed calls.
+    assert_eq!(emulator.stack(), &full);
+}
+
+#[test]
+fn keys_must_exist() {
+    let mut pressed = Emulator::with_seed(0);
+    pressed.keypress(0x3, true).unwrap();
+    pressed.keypress(0xF, true).unwrap();
+    let before = pressed.save_state();
+    assert_eq!(pressed.keypress(16, true), Err(MemoryError::InvalidKey(16)));
+    assert_eq!(pressed.save_state(), before);
+
+    let mut masked = Emulator::with_seed(0);
+    masked.set_keys(1 << 0x3 | 1 << 0xF);
+    assert_eq!(masked.save_state(), before);
+}
@@ -0,0 +1,261 @@
+//! Netplay: two sessions on localhost stay in lockstep with each other and
+//! with a local replay of their inputs, and desyncs, mismatched setups and
+//! overlong input delays are caught.
+
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+use chip_core::error::NetplayError;
+use chip_core::harness::parse_listing;
+use chip_core::netplay::{Netplay, NetplayConfig, MAX_INPUT_DELAY};
+use chip_core::Emulator;
+
+/// Counts frames with key 1 held in V3 and with key 2 held in V4, and
+/// sums random numbers into V6.
+const ROM: &str = "
+200: 6101   ; V1 = 1
+202: 6202   ; V2 = 2
+204: E19E   ; skip if key V1 down
+206: 120A   ; jump 20A
+208: 7301   ; V3 += 1
+20A: E29E   ; skip if key V2 down
+20C: 1210   ; jump 210
+20E: 7401   ; V4 += 1
+210: C5FF   ; V5 = random
+212: 8654   ; V6 += V5
+214: 1204   ; jump 204
+";
+
+const SEED: u64 = 1234;
+const FRAMES: u32 = 120;
+const HOST_KEYS: u16 = 1 << 1;
+const GUEST_KEYS: u16 = 1 << 2;
+
+fn emulator(listing: &str) -> Emulator {
+    // Different seeds, the host's is used for both.
+    let mut emulator = Emulator::new();
+    emulator.load_rom(&parse_listing(listing).unwrap()).unwrap();
+    emulator
+}
+
+/// The keys each player holds on a frame, every key of theirs on some
+/// frames and none on others.
+fn host_held(frame: u32) -> u16 {
+    if frame.is_multiple_of(3) {
+        0xFFFF
+    } else {
+        0
+    }
+}
+
+fn guest_held(frame: u32) -> u16 {
+    if frame % 5 < 2 {
+        0xFFFF
+    } else {
+        0
+    }
+}
+
+/// Connects a host and a guest on localhost and runs each on its own
+/// thread.
+fn play<H, G, R>(host: H, guest: G) -> (R, R)
+where
+    H: FnOnce(TcpStream) -> R + Send + 'static,
+    G: FnOnce(TcpStream) -> R + Send + 'static,
+    R: Send + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let host = thread::spawn(move || host(listener.accept().unwrap().0));
+    let guest = thread::spawn(move || guest(TcpStream::connect(address).unwrap()));
+    (host.join().unwrap(), guest.join().unwrap())
+}
+
+/// Plays the game for `FRAMES` frames, recording the state after each.
+fn run(netplay: &mut Netplay, emulator: &mut Emulator, held: fn(u32) -> u16) -> Vec<Vec<u8>> {
+    (0..FRAMES)
+        .map(|frame| {
+            netplay.run_frame(emulator, held(frame)).unwrap();
+            emulator.save_state().to_vec()
+        })
+        .collect()
+}
+
+#[test]
+fn players_stay_in_lockstep() {
+    let mut config = NetplayConfig::new(HOST_KEYS);
+    config.input_delay = 3;
+    let (host, guest) = play(
+        move |stream| {
+            let mut emulator = emulator(ROM);
+            let mut netplay = Netplay::host(stream, &mut emulator, config, SEED).unwrap();
+            let states = run(&mut netplay, &mut emulator, host_held);
+            netplay.close().unwrap();
+            states
+        },
+        |stream| {
+            let mut emulator = emulator(ROM);
+            let config = NetplayConfig::new(GUEST_KEYS);
+            let mut netplay = Netplay::join(stream, &mut emulator, config).unwrap();
+            assert_eq!(netplay.input_delay(), 3);
+            assert_eq!(netplay.seed(), SEED);
+            assert_eq!(netplay.remote_keys(), HOST_KEYS);
+            let states = run(&mut netplay, &mut emulator, guest_held);
+            netplay.close().unwrap();
+            states
+        },
+    );
+    assert_eq!(host, guest);
+
+    // The same as one emulator given both players' keys, delayed.
+    let mut local = Emulator::with_seed(SEED);
+    local.load_rom(&parse_listing(ROM).unwrap()).unwrap();
+    for (frame, state) in (0..FRAMES).zip(&host) {
+        let keys = match frame.checked_sub(3) {
+            Some(pressed) => host_held(pressed) & HOST_KEYS | guest_held(pressed) & GUEST_KEYS,
+            None => 0,
+        };
+        local.set_keys(keys);
+        local.run_frame();
+        assert_eq!(&local.save_state().to_vec(), state, "{}", frame);
+    }
+    // Both players' keys reached the game.
+    let registers = local.registers();
+    assert!(registers[3] > 0 && registers[4] > 0);
+}
+
+#[test]
+fn desyncs_are_detected() {
+    let (host, guest) = play(
+        |stream| {
+            let mut emulator = emulator(ROM);
+            let config = NetplayConfig::new(HOST_KEYS);
+            let mut netplay = Netplay::host(stream, &mut emulator, config, SEED).unwrap();
+            let error = (0..FRAMES)
+                .map(|_| netplay.run_frame(&mut emulator, 0))
+                .find_map(Result::err);
+            netplay.close().unwrap();
+            error
+        },
+        |stream| {
+            let mut emulator = emulator(ROM);
+            let config = NetplayConfig::new(GUEST_KEYS);
+            let mut netplay = Netplay::join(stream, &mut emulator, config).unwrap();
+            let error = (0..FRAMES)
+                .map(|frame| {
+                    // Something outside the session changes one machine.
+                    if frame == 10 {
+                        emulator.set_register(0xE, 1).unwrap();
+                    }
+                    netplay.run_frame(&mut emulator, 0)
+                })
+                .find_map(Result::err);
+            netplay.close().unwrap();
+            error
+        },
+    );
+    for error in [host, guest] {
+        assert!(
+            matches!(error, Some(NetplayError::Desync { frame: 10 })),
+            "{:?}",
+            error
+        );
+    }
+}
+
+#[test]
+fn mismatched_setups_are_rejected() {
+    let other_rom = ROM.replace("V6 += V5", "V6 -= V5").replace("8654", "8655");
+    let (host, guest) = play(
+        |stream| {
+            let config = NetplayConfig::new(HOST_KEYS);
+            Netplay::host(stream, &mut emulator(ROM), config, SEED).err()
+        },
+        move |stream| {
+            let config = NetplayConfig::new(GUEST_KEYS);
+            Netplay::join(stream, &mut emulator(&other_rom), config).err()
+        },
+    );
+    for error in [host, guest] {
+        assert!(
+            matches!(error, Some(NetplayError::Mismatch(_))),
+            "{:?}",
+            error
+        );
+    }
+
+    let (host, guest) = play(
+        |stream| {
+            let config = NetplayConfig::new(HOST_KEYS | GUEST_KEYS);
+            Netplay::host(stream, &mut emulator(ROM), config, SEED).err()
+        },
+        |stream| {
+            let config = NetplayConfig::new(GUEST_KEYS);
+            Netplay::join(stream, &mut emulator(ROM), config).err()
+        },
+    );
+    for error in [host, guest] {
+        assert!(
+            matches!(error, Some(NetplayError::Mismatch(_))),
+            "{:?}",
+            error
+        );
+    }
+
+    // Two hosts can't play each other.
+    let (first, second) = play(
+        |stream| {
+            let config = NetplayConfig::new(HOST_KEYS);
+            Netplay::host(stream, &mut emulator(ROM), config, SEED).err()
+        },
+        |stream| {
+            let config = NetplayConfig::new(GUEST_KEYS);
+            Netplay::host(stream, &mut emulator(ROM), config, SEED).err()
+        },
+    );
+    for error in [first, second] {
+        assert!(
+            matches!(error, Some(NetplayError::Protocol(_))),
+            "{:?}",
+            error
+        );
+    }
+}
+
+#[test]
+fn long_input_delays_are_rejected() {
+    let (host, guest) = play(
+        |stream| {
+            let mut config = NetplayConfig::new(HOST_KEYS);
+            config.input_delay = u32::MAX;
+            Netplay::host(stream, &mut emulator(ROM), config, SEED).err()
+        },
+        |stream| {
+            let config = NetplayConfig::new(GUEST_KEYS);
+            Netplay::join(stream, &mut emulator(ROM), config).err()
+        },
+    );
+    for error in [host, guest] {
+        assert!(
+            matches!(error, Some(NetplayError::Protocol(_))),
+            "{:?}",
+            error
+        );
+    }
+
+    let (host, guest) = play(
+        |stream| {
+            let mut config = NetplayConfig::new(HOST_KEYS);
+            config.input_delay = MAX_INPUT_DELAY;
+            Netplay::host(stream, &mut emulator(ROM), config, SEED).map(|_| ())
+        },
+        |stream| {
+            let config = NetplayConfig::new(GUEST_KEYS);
+            Netplay::join(stream, &mut emulator(ROM), config).map(|netplay| {
+                assert_eq!(netplay.input_delay(), MAX_INPUT_DELAY);
+            })
+        },
+    );
+    host.unwrap();
+    guest.unwrap();
+}
@@ -0,0 +1,139 @@
+//! Observers: every event fires once, in order, with the values the
+//! instruction saw, and observing doesn't change how the ROM runs.
+
+use chip_core::harness::parse_listing;
+use chip_core::observer::Observer;
+use chip_core::quirks::Quirks;
+use chip_core::Emulator;
+
+const ROM: &str = "
+200: 00E0   ; clear the screen
+202: 220A   ; call 20A
+204: 6003   ; V0 = 3
+206: F018   ; sound timer = V0
+208: 1208   ; loop forever
+
+20A: A300   ; I = 300
+20C: 627B   ; V2 = 123
+20E: F233   ; BCD of V2 at 300
+210: 6000   ; V0 = 0
+212: F029   ; I = sprite for V0
+214: 613E   ; V1 = 62
+216: D115   ; draw at (62, 30), the y wraps
+218: D115   ; draw again, erasing it
+21A: 00EE   ; return
+";
+
+#[derive(Debug, PartialEq)]
+enum Event {
+    Draw(u8, u8, u8, bool),
+    Clear,
+    Sound(bool),
+    RamWrite(u16, u8),
+    Call(u16, u16),
+    Return(u16, u16),
+}
+
+#[derive(Default)]
+struct Recorder {
+    fetches: Vec<(u16, u16)>,
+    events: Vec<Event>,
+}
+
+impl Observer for Recorder {
+    fn on_fetch(&mut self, address: u16, opcode: u16) {
+        self.fetches.push((address, opcode));
+    }
+
+    fn on_draw(&mut self, x: u8, y: u8, height: u8, collision: bool) {
+        self.events.push(Event::Draw(x, y, height, collision));
+    }
+
+    fn on_clear(&mut self) {
+        self.events.push(Event::Clear);
+    }
+
+    fn on_sound(&mut self, playing: bool) {
+        self.events.push(Event::Sound(playing));
+    }
+
+    fn on_ram_write(&mut self, address: u16, value: u8) {
+        self.events.push(Event::RamWrite(address, value));
+    }
+
+    fn on_call(&mut self, address: u16, target: u16) {
+        self.events.push(Event::Call(address, target));
+    }
+
+    fn on_return(&mut self, address: u16, target: u16) {
+        self.events.push(Event::Return(address, target));
+    }
+}
+
+fn emulator() -> Emulator {
+    let mut emulator = Emulator::with_seed(1);
+    // Without the display wait, so both draws run in one frame.
+    emulator.set_quirks(Quirks {
+        display_wait: false,
+        ..Quirks::chip8()
+    });
+    emulator.load_rom(&parse_listing(ROM).unwrap()).unwrap();
+    emulator
+}
+
+#[test]
+fn events_fire_in_order() {
+    let mut emulator = emulator();
+    let mut recorder = Recorder::default();
+    for _ in 0..14 {
+        emulator.tick_observed(&mut recorder);
+    }
+    let addresses: Vec<u16> = recorder
+        .fetches
+        .iter()
+        .map(|(address, _)| *address)
+        .collect();
+    assert_eq!(
+        addresses,
+        [
+            0x200, 0x202, 0x20A, 0x20C, 0x20E, 0x210, 0x212, 0x214, 0x216, 0x218, 0x21A, 0x204,
+            0x206, 0x208
+        ]
+    );
+    assert_eq!(recorder.fetches[0].1, 0x00E0);
+
+    // The sound stops on the third timer tick.
+    for _ in 0..3 {
+        emulator.timer_tick_observed(&mut recorder);
+    }
+    assert_eq!(
+        recorder.events,
+        [
+            Event::Clear,
+            Event::Call(0x202, 0x20A),
+            Event::RamWrite(0x300, 1),
+            Event::RamWrite(0x301, 2),
+            Event::RamWrite(0x302, 3),
+            Event::Draw(62, 30, 5, false),
+            Event::Draw(62, 30, 5, true),
+            Event::Return(0x21A, 0x204),
+            Event::Sound(true),
+            Event::Sound(false),
+        ]
+    );
+}
+
+#[test]
+fn observing_doesnt_change_the_run() {
+    let mut observed = emulator();
+    let mut plain = emulator();
+    // Trait objects work too, for tools that pick an observer at runtime.
+    let mut recorder = Recorder::default();
+    let observer: &mut dyn Observer = &mut recorder;
+    for _ in 0..5 {
+        observed.run_frame_observed(observer);
+        plain.run_frame();
+    }
+    assert_eq!(observed.save_state(), plain.save_state());
+    assert_eq!(recorder.fetches.len(), 5 * observed.ticks_per_frame());
+}
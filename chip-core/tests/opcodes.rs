@@ -0,0 +1,269 @@
+//! Property tests of the ALU and flow control opcodes. Each case runs one
+//! instruction from a random machine state under every combination of
+//! quirks and compares the result with a deliberately simple model of the
+//! CHIP-8 written separately from `execute`.
+
+use chip_core::quirks::Quirks;
+use chip_core::{Emulator, NUM_REGS, RAM_SIZE, STACK_SIZE};
+use proptest::prelude::*;
+
+/// The parts of the machine the ALU and flow control opcodes touch.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Model {
+    v: [u8; NUM_REGS],
+    i: u16,
+    pc: u16,
+    stack: Vec<u16>,
+    fault: bool,
+}
+
+impl Model {
+    /// Runs one instruction. Flags are computed from the operands before
+    /// anything is written and VF is written last, so when X is F the flag
+    /// wins over the result.
+    fn step(&mut self, op: u16, quirks: Quirks) {
+        let x = (op >> 8 & 0xF) as usize;
+        let y = (op >> 4 & 0xF) as usize;
+        let nn = (op & 0xFF) as u8;
+        let nnn = op & 0xFFF;
+        let (vx, vy) = (self.v[x], self.v[y]);
+
+        let at = self.pc;
+        self.pc = (self.pc + 2) % RAM_SIZE as u16;
+        let skip = |model: &mut Model, condition: bool| {
+            if condition {
+                model.pc = (model.pc + 2) % RAM_SIZE as u16;
+            }
+        };
+
+        match op >> 12 {
+            0x0 => match self.stack.pop() {
+                Some(address) => self.pc = address,
+                None => {
+                    self.pc = at;
+                    self.fault = true;
+                }
+            },
+            0x1 => self.pc = nnn,
+            0x2 if self.stack.len() == STACK_SIZE => {
+                self.pc = at;
+                self.fault = true;
+            }
+            0x2 => {
+                self.stack.push(self.pc);
+                self.pc = nnn;
+            }
+            0x3 => skip(self, vx == nn),
+            0x4 => skip(self, vx != nn),
+            0x5 => skip(self, vx == vy),
+            0x6 => self.v[x] = nn,
+            0x7 => self.v[x] = ((vx as u16 + nn as u16) % 256) as u8,
+            0x8 => {
+                let reset = if quirks.vf_reset { Some(0) } else { None };
+                let shifted = if quirks.shifting { vx } else { vy };
+                let (result, flag) = match op & 0xF {
+                    0x0 => (vy, None),
+                    0x1 => (vx | vy, reset),
+                    0x2 => (vx & vy, reset),
+                    0x3 => (vx ^ vy, reset),
+                    0x4 => {
+                        let sum = vx as u16 + vy as u16;
+                        ((sum % 256) as u8, Some((sum > 255) as u8))
+                    }
+                    0x5 => {
+                        let difference = vx as i16 - vy as i16;
+                        (
+                            difference.rem_euclid(256) as u8,
+                            Some((difference >= 0) as u8),
+                        )
+                    }
+                    0x6 => (shifted / 2, Some(shifted % 2)),
+                    0x7 => {
+                        let difference = vy as i16 - vx as i16;
+                        (
+                            difference.rem_euclid(256) as u8,
+                            Some((difference >= 0) as u8),
+                        )
+                    }
+                    0xE => (((shifted as u16 * 2) % 256) as u8, Some(shifted / 128)),
+                    _ => unreachable!("not generated"),
+                };
+                self.v[x] = result;
+                if let Some(flag) = flag {
+                    self.v[0xF] = flag;
+                }
+            }
+            0x9 => skip(self, vx != vy),
+            0xA => self.i = nnn,
+            0xB => {
+                let offset = if quirks.jumping { self.v[x] } else { self.v[0] };
+                self.pc = (nnn + offset as u16) % RAM_SIZE as u16;
+            }
+            0xF => self.i = ((self.i as u32 + vx as u32) % 0x10000) as u16,
+            _ => unreachable!("not generated"),
+        }
+    }
+
+    /// Creates an emulator in the same state, with `op` at the program
+    /// counter.
+    fn emulator(&self, op: u16, quirks: Quirks) -> Emulator {
+        let mut emulator = Emulator::new();
+        emulator.set_quirks(quirks);
+        for (idx, value) in self.v.iter().enumerate() {
+            emulator.set_register(idx, *value).unwrap();
+        }
+        emulator.set_i_register(self.i).unwrap();
+        emulator.set_program_counter(self.pc).unwrap();
+        emulator.set_stack(&self.stack).unwrap();
+        emulator
+            .write_ram(self.pc as usize, &op.to_be_bytes())
+            .unwrap();
+        emulator
+    }
+
+    /// Reads the same state back from an emulator.
+    fn from_emulator(emulator: &Emulator) -> Self {
+        Self {
+            v: *emulator.registers(),
+            i: emulator.i_register(),
+            pc: emulator.program_counter(),
+            stack: emulator.stack().to_vec(),
+            fault: emulator.fault().is_some(),
+        }
+    }
+}
+
+/// Every combination of quirk settings.
+fn all_quirks() -> impl Iterator<Item = Quirks> {
+    (0..64u8).map(|bits| Quirks {
+        vf_reset: bits & 0x01 != 0,
+        memory: bits & 0x02 != 0,
+        display_wait: bits & 0x04 != 0,
+        clipping: bits & 0x08 != 0,
+        shifting: bits & 0x10 != 0,
+        jumping: bits & 0x20 != 0,
+    })
+}
+
+/// Runs an instruction on the emulator and the model under every quirk
+/// setting and checks they end up in the same state.
+fn check(start: &Model, op: u16) -> Result<(), TestCaseError> {
+    for quirks in all_quirks() {
+        let mut emulator = start.emulator(op, quirks);
+        emulator.tick();
+
+        let mut expected = start.clone();
+        expected.step(op, quirks);
+        prop_assert_eq!(
+            Model::from_emulator(&emulator),
+            expected,
+            "opcode {:04X} with {:?}",
+            op,
+            quirks
+        );
+    }
+    Ok(())
+}
+
+/// A byte, with the values where carries, borrows and shifted out bits
+/// change drawn far more often than chance.
+fn byte() -> impl Strategy<Value = u8> {
+    prop_oneof![
+        any::<u8>(),
+        prop::sample::select(vec![0x00, 0x01, 0x7F, 0x80, 0x81, 0xFE, 0xFF]),
+    ]
+}
+
+/// A random machine state. Addresses leave room to fetch an instruction.
+fn model() -> impl Strategy<Value = Model> {
+    (
+        prop::array::uniform16(byte()),
+        0..RAM_SIZE as u16,
+        (0..RAM_SIZE as u16 / 2).prop_map(|word| word * 2),
+        prop::collection::vec(
+            (0..RAM_SIZE as u16 / 2).prop_map(|word| word * 2),
+            0..=STACK_SIZE,
+        ),
+    )
+        .prop_map(|(v, i, pc, stack)| Model {
+            v,
+            i,
+            pc,
+            stack,
+            fault: false,
+        })
+}
+
+/// An ALU opcode with the given X: 6XNN, 7XNN, 8XYN, ANNN or FX1E.
+fn alu_op(x: impl Strategy<Value = u16>) -> impl Strategy<Value = u16> {
+    let n = prop::sample::select(vec![0x0, 0x1, 0x2, 0x3, 0x4, 0x5, 0x6, 0x7, 0xE]);
+    (x, 0..16u16, n, byte(), 0..0x1000u16).prop_flat_map(|(x, y, n, nn, nnn)| {
+        prop::sample::select(vec![
+            0x6000 | x << 8 | nn as u16,
+            0x7000 | x << 8 | nn as u16,
+            0x8000 | x << 8 | y << 4 | n,
+            0xA000 | nnn,
+            0xF01E | x << 8,
+        ])
+    })
+}
+
+/// A flow control opcode: 00EE, 1NNN, 2NNN, 3XNN, 4XNN, 5XY0, 9XY0 or BNNN.
+fn flow_op() -> impl Strategy<Value = u16> {
+    (0..16u16, 0..16u16, byte(), 0..0x1000u16).prop_flat_map(|(x, y, nn, nnn)| {
+        prop::sample::select(vec![
+            0x00EE,
+            0x1000 | nnn,
+            0x2000 | nnn,
+            0x3000 | x << 8 | nn as u16,
+            0x4000 | x << 8 | nn as u16,
+            0x5000 | x << 8 | y << 4,
+            0x9000 | x << 8 | y << 4,
+            0xB000 | nnn,
+        ])
+    })
+}
+
+proptest! {
+    #[test]
+    fn alu_matches_model(start in model(), op in alu_op(0..16u16)) {
+        check(&start, op)?;
+    }
+
+    #[test]
+    fn alu_with_vf_as_destination(start in model(), op in alu_op(Just(0xF))) {
+        check(&start, op)?;
+    }
+
+    #[test]
+    fn carry_and_borrow_boundaries(
+        mut start in model(),
+        x in 0..16usize,
+        y in 0..16usize,
+        vx in any::<u8>(),
+        offset in -1i16..=1,
+        n in prop::sample::select(vec![0x4, 0x5, 0x7]),
+    ) {
+        // VY is picked so the sum is 255, 256 or 257 for 8XY4, and the
+        // difference -1, 0 or 1 for the subtractions.
+        let vy = if n == 0x4 { 256 - vx as i16 + offset } else { vx as i16 + offset };
+        start.v[x] = vx;
+        start.v[y] = vy.rem_euclid(256) as u8;
+        check(&start, 0x8000 | (x as u16) << 8 | (y as u16) << 4 | n)?;
+    }
+
+    #[test]
+    fn flow_matches_model(start in model(), op in flow_op()) {
+        check(&start, op)?;
+    }
+
+    #[test]
+    fn skips_with_equal_operands(mut start in model(), x in 0..16usize, y in 0..16usize) {
+        // Random registers are rarely equal, so force the taken branches.
+        start.v[y] = start.v[x];
+        let nn = start.v[x] as u16;
+        for op in [0x3000 | nn, 0x4000 | nn, 0x5000 | (y as u16) << 4, 0x9000 | (y as u16) << 4] {
+            check(&start, op | (x as u16) << 8)?;
+        }
+    }
+}
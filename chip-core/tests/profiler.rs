@@ -0,0 +1,91 @@
+//! Profiler: cycles are attributed to the right addresses, subroutines and
+//! loops, and delay timer spins are recognised.
+
+use chip_core::harness::parse_listing;
+use chip_core::profiler::{HotLoop, Profiler};
+use chip_core::Emulator;
+
+/// Calls a subroutine, then spins on the delay timer for two frames, over
+/// and over.
+const ROM: &str = "
+200: 6A00   ; VA = 0
+202: 2210   ; call 210
+204: 6B02   ; VB = 2
+206: FB15   ; DT = VB
+208: FC07   ; VC = DT
+20A: 3C00   ; skip if VC == 0
+20C: 1208   ; jump 208, waiting for the timer
+20E: 1202   ; jump 202
+210: 7A01   ; VA += 1
+212: 00EE   ; return
+";
+
+fn profile(frames: usize) -> Profiler {
+    let mut emulator = Emulator::with_seed(0);
+    emulator.load_rom(&parse_listing(ROM).unwrap()).unwrap();
+    let mut profiler = Profiler::new();
+    for _ in 0..frames {
+        emulator.run_frame_observed(&mut profiler);
+    }
+    profiler
+}
+
+#[test]
+fn cycles_are_attributed() {
+    let profiler = profile(30);
+    let total = profiler.total_cycles();
+    assert_eq!(total, 30 * 10);
+    assert_eq!(profiler.count(0x200), 1);
+
+    let calls = profiler.count(0x202);
+    assert!(calls > 1);
+    let subroutines = profiler.subroutines();
+    assert_eq!(subroutines[0].entry, None);
+    assert_eq!(subroutines[0].total_cycles, total);
+    assert_eq!(subroutines[1].entry, Some(0x210));
+    assert_eq!(subroutines[1].calls, calls);
+    assert_eq!(subroutines[1].self_cycles, 2 * calls);
+    assert_eq!(
+        subroutines[0].self_cycles + subroutines[1].self_cycles,
+        total
+    );
+
+    // The outer loop contains the spin on the delay timer, so it's hotter.
+    let wait: u64 = [0x208, 0x20A, 0x20C]
+        .iter()
+        .map(|address| profiler.count(*address))
+        .sum();
+    let loops = profiler.hot_loops();
+    assert_eq!((loops[0].start, loops[0].end), (0x202, 0x20E));
+    assert!(!loops[0].delay_wait);
+    assert_eq!(
+        loops[1],
+        HotLoop {
+            start: 0x208,
+            end: 0x20C,
+            iterations: profiler.count(0x20C),
+            cycles: wait,
+            delay_wait: true,
+        }
+    );
+    assert_eq!(profiler.delay_wait_cycles(), wait);
+}
+
+#[test]
+fn outputs_list_the_profile() {
+    let profiler = profile(30);
+    let report = profiler.report();
+    assert!(report.starts_with("Total cycles: 300\n"), "{}", report);
+    assert!(report.contains("delay timer wait"), "{}", report);
+
+    let calls = profiler.count(0x202);
+    let stacks = profiler.collapsed_stacks();
+    let lines: Vec<&str> = stacks.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            format!("main {}", 300 - 2 * calls),
+            format!("main;0x210 {}", 2 * calls)
+        ]
+    );
+}
@@ -0,0 +1,75 @@
+//! Rendering: phosphor persistence fades turned off pixels to the
+//! background, including after the fade is shortened part way through.
+
+use chip_core::display::{Framebuffer, Palette, Rgb};
+use chip_core::render::{Renderer, Upscaler};
+
+/// A 2x1 display with the left pixel lit or not.
+struct Screen(bool);
+
+impl Framebuffer for Screen {
+    fn width(&self) -> usize {
+        2
+    }
+
+    fn height(&self) -> usize {
+        1
+    }
+
+    fn pixel(&self, x: usize, _y: usize) -> usize {
+        (x == 0 && self.0) as usize
+    }
+}
+
+/// Renders a frame and returns the red channel of the left pixel.
+fn render(renderer: &mut Renderer, lit: bool) -> u8 {
+    renderer.render(&Screen(lit))[0]
+}
+
+fn renderer() -> Renderer {
+    let palette = Palette::monochrome(Rgb::new(0, 0, 0), Rgb::new(240, 240, 240));
+    Renderer::new(palette)
+}
+
+#[test]
+fn phosphor_fades_to_the_background() {
+    let mut renderer = renderer();
+    renderer.set_phosphor_frames(3);
+    assert_eq!(render(&mut renderer, true), 240);
+    let fade: Vec<u8> = (0..5).map(|_| render(&mut renderer, false)).collect();
+    assert_eq!(fade, [180, 120, 60, 0, 0]);
+
+    // The unlit pixel never glows.
+    assert_eq!(renderer.render(&Screen(true))[4..8], [0, 0, 0, 0xFF]);
+}
+
+#[test]
+fn shortening_the_phosphor_cuts_the_fade() {
+    let mut renderer = renderer();
+    renderer.set_phosphor_frames(10);
+    render(&mut renderer, true);
+    render(&mut renderer, false);
+
+    // Nine frames of glow are left, but only two are allowed now.
+    renderer.set_phosphor_frames(2);
+    let fade: Vec<u8> = (0..3).map(|_| render(&mut renderer, false)).collect();
+    assert_eq!(fade, [160, 80, 0]);
+
+    // Turning it off altogether stops the glow at once.
+    renderer.set_phosphor_frames(10);
+    render(&mut renderer, true);
+    renderer.set_phosphor_frames(0);
+    assert_eq!(render(&mut renderer, false), 0);
+    renderer.set_phosphor_frames(10);
+    assert_eq!(render(&mut renderer, false), 0);
+}
+
+#[test]
+fn nearest_scaling_repeats_pixels() {
+    let mut renderer = renderer();
+    renderer.set_upscaler(Upscaler::Nearest(2));
+    let output = renderer.render(&Screen(true)).to_vec();
+    assert_eq!((renderer.width(), renderer.height()), (4, 2));
+    let red: Vec<u8> = output.chunks(4).map(|pixel| pixel[0]).collect();
+    assert_eq!(red, [240, 240, 0, 0, 240, 240, 0, 0]);
+}
@@ -0,0 +1,112 @@
+//! Regression tests for ROMs that used to panic the emulator. The fuzz
+//! targets in `fuzz` search for new ones, these keep the known cases fixed
+//! on the stable toolchain.
+
+use chip_core::error::ExecutionError;
+use chip_core::{Emulator, RAM_SIZE, STACK_SIZE};
+
+/// Creates an emulator with the program at the start address.
+fn emulator(program: &[u16]) -> Emulator {
+    let rom: Vec<u8> = program.iter().flat_map(|op| op.to_be_bytes()).collect();
+    let mut emulator = Emulator::new();
+    emulator.load_rom(&rom).unwrap();
+    emulator
+}
+
+#[test]
+fn stack_overflow() {
+    // 2200: call itself forever.
+    let mut emulator = emulator(&[0x2200]);
+    for _ in 0..=STACK_SIZE {
+        emulator.tick();
+    }
+    assert_eq!(emulator.stack().len(), STACK_SIZE);
+    assert_eq!(
+        emulator.fault(),
+        Some(ExecutionError::StackOverflow { address: 0x200 })
+    );
+    assert_eq!(emulator.program_counter(), 0x200);
+}
+
+#[test]
+fn stack_underflow() {
+    let mut emulator = emulator(&[0x00EE]);
+    emulator.tick();
+    assert_eq!(
+        emulator.fault(),
+        Some(ExecutionError::StackUnderflow { address: 0x200 })
+    );
+}
+
+#[test]
+fn unknown_opcode() {
+    let mut emulator = emulator(&[0x6001, 0x5121]);
+    emulator.run_frame();
+    assert_eq!(
+        emulator.fault(),
+        Some(ExecutionError::UnknownOpcode {
+            address: 0x202,
+            opcode: 0x5121
+        })
+    );
+    // Nothing runs after the fault.
+    assert_eq!(emulator.program_counter(), 0x202);
+    assert_eq!(emulator.registers()[0], 1);
+}
+
+#[test]
+fn fetch_wraps_at_end_of_ram() {
+    let mut emulator = emulator(&[0x1FFF]);
+    // 6AFF split across the last and first addresses.
+    emulator.write_ram(RAM_SIZE - 1, &[0x6A]).unwrap();
+    emulator.write_ram(0, &[0xFF]).unwrap();
+    emulator.tick();
+    emulator.tick();
+    assert_eq!(emulator.registers()[0xA], 0xFF);
+    assert_eq!(emulator.program_counter(), 1);
+    assert_eq!(emulator.fault(), None);
+}
+
+#[test]
+fn memory_access_wraps_at_end_of_ram() {
+    let mut emulator = emulator(&[
+        0x60FF, // V0 = FF
+        0xAFFE, // I = FFE
+        0xD00F, // draw 15 rows from FFE
+        0xF033, // BCD of V0 at FFE
+        0xFF55, // store V0..VF at FFE
+        0xFF65, // load V0..VF from FFE
+        0xBFFF, // jump to FFF + V0
+    ]);
+    for _ in 0..7 {
+        emulator.tick();
+    }
+    assert_eq!(emulator.read_ram(RAM_SIZE - 2, 2).unwrap(), [0xFF, 0x00]);
+    assert_eq!(emulator.read_ram(0, 1).unwrap(), [0x00]);
+    assert_eq!(emulator.program_counter(), 0x0FE);
+    assert_eq!(emulator.fault(), None);
+}
+
+#[test]
+fn random_images_keep_state_valid() {
+    // A fixed xorshift sequence so failures reproduce.
+    let mut state = 0x2545_F491_4F6C_DD1Du64;
+    let mut next = || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+
+    for _ in 0..200 {
+        let rom: Vec<u8> = (0..256).map(|_| next() as u8).collect();
+        let mut emulator = Emulator::new();
+        emulator.seed_rng(0);
+        emulator.load_rom(&rom).unwrap();
+        for _ in 0..100 {
+            emulator.run_frame();
+            assert!((emulator.program_counter() as usize) < RAM_SIZE);
+            assert!(emulator.stack().len() <= STACK_SIZE);
+        }
+    }
+}
@@ -0,0 +1,244 @@
+//! ROM database: ROMs are identified by SHA-1 and loading one configures
+//! the emulator for it, settings chosen afterwards win, and broken
+//! databases are reported with the line of the problem.
+
+use std::sync::Arc;
+
+use chip_core::batch::Batch;
+use chip_core::display::{Palette, Rgb};
+use chip_core::env::{Env, EnvConfig};
+use chip_core::hash::sha1;
+use chip_core::quirks::Quirks;
+use chip_core::romdb::{Platform, RomDatabase};
+use chip_core::{Emulator, DEFAULT_TICKS_PER_FRAME};
+
+/// The die roll demo from `chip-core/embedded`, which is in the embedded
+/// database.
+const DIE_ROLL: [u8; 24] = [
+    0x00, 0xE0, 0xC0, 0x0F, 0xF0, 0x29, 0x61, 0x1E, 0x62, 0x0D, 0xD1, 0x25, 0x63, 0x1E, 0xF3, 0x15,
+    0xF4, 0x07, 0x34, 0x00, 0x12, 0x10, 0x12, 0x00,
+];
+
+/// Two ROMs: `00E0` on SCHIP with some quirks changed, and one that only
+/// runs on a platform this emulator doesn't support.
+const DATABASE: &str = r##"[
+  {
+    "title": "Clear",
+    "authors": ["Nobody"],
+    "roms": {
+      "159ba69f4c40be3042fc54c7fbb2025f7e49f8e0": {
+        "file": "clear.ch8",
+        "platforms": ["megachip8", "superchip", "originalChip8"],
+        "tickrate": 30,
+        "quirkyPlatforms": {"superchip": {"shift": false, "wrap": true}},
+        "keys": {"up": 5, "a": 10},
+        "colors": {"pixels": ["#102030", "#ffcc00"], "buzzer": "#990000"}
+      }
+    }
+  },
+  {
+    "title": "Loop",
+    "roms": {
+      "5b8c2d0fbc6e1e5a8e9b9e1f3f5bcd1c1a5a1f4e": {"platforms": ["megachip8"]}
+    }
+  }
+]"##;
+
+fn hex(digest: [u8; 20]) -> String {
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[test]
+fn sha1_matches_the_test_vectors() {
+    assert_eq!(hex(sha1(b"")), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+    assert_eq!(
+        hex(sha1(b"abc")),
+        "a9993e364706816aba3e25717850c26c9cd0d89d"
+    );
+    // 56 bytes, so the padding needs a second block.
+    assert_eq!(
+        hex(sha1(
+            b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"
+        )),
+        "84983e441c3bd26ebaae4aa1f95129e5e54670f1"
+    );
+    assert_eq!(
+        hex(sha1(&[b'a'; 1000])),
+        "291e9a6c66994949b57ba5e650361e98fc36b1ba"
+    );
+}
+
+#[test]
+fn embedded_roms_configure_the_emulator() {
+    let mut emulator = Emulator::with_seed(0);
+    emulator.load_rom(&DIE_ROLL).unwrap();
+    let info = emulator.rom_info().unwrap();
+    assert_eq!(info.title, "Die Roll");
+    assert_eq!(info.platform, Platform::Chip8);
+    assert_eq!(emulator.quirks(), Quirks::chip8());
+    assert_eq!(emulator.ticks_per_frame(), 10);
+    assert_eq!(info.palette.unwrap().color(1), Rgb::new(0x33, 0xFF, 0x66));
+
+    // The ROM is still loaded after a reset, so its information stays.
+    emulator.reset();
+    assert_eq!(emulator.rom_info().unwrap().title, "Die Roll");
+
+    // Unknown ROMs keep the current settings.
+    let mut emulator = Emulator::with_seed(0);
+    emulator.set_quirks(Quirks::schip());
+    emulator.load_rom(&[0x12, 0x00]).unwrap();
+    assert!(emulator.rom_info().is_none());
+    assert_eq!(emulator.quirks(), Quirks::schip());
+    assert_eq!(emulator.ticks_per_frame(), DEFAULT_TICKS_PER_FRAME);
+}
+
+#[test]
+fn user_databases_override_the_embedded_one() {
+    let user = RomDatabase::parse(DATABASE).unwrap();
+    // The megachip only ROM is left out.
+    assert_eq!(user.len(), 1);
+    let mut database = (*RomDatabase::embedded()).clone();
+    let embedded = database.len();
+    database.extend(user);
+    assert_eq!(database.len(), embedded + 1);
+
+    let mut emulator = Emulator::with_seed(0);
+    emulator.set_rom_database(Arc::new(database));
+    emulator.load_rom(&[0x00, 0xE0]).unwrap();
+    let info = emulator.rom_info().unwrap();
+    assert_eq!(info.title, "Clear");
+    assert_eq!(info.platform, Platform::Schip);
+    assert_eq!(info.platform.to_string(), "SCHIP");
+    assert_eq!(info.keys, [("up".to_string(), 5), ("a".to_string(), 10)]);
+    assert_eq!(
+        info.palette,
+        Some(Palette::monochrome(
+            Rgb::new(0x10, 0x20, 0x30),
+            Rgb::new(0xFF, 0xCC, 0x00)
+        ))
+    );
+    assert_eq!(
+        emulator.quirks(),
+        Quirks {
+            shifting: false,
+            clipping: false,
+            ..Quirks::schip()
+        }
+    );
+    assert_eq!(emulator.ticks_per_frame(), 30);
+
+    // An empty database turns the identification off.
+    let mut emulator = Emulator::with_seed(0);
+    emulator.set_rom_database(Arc::new(RomDatabase::new()));
+    emulator.load_rom(&DIE_ROLL).unwrap();
+    assert!(emulator.rom_info().is_none());
+}
+
+#[test]
+fn chosen_settings_win() {
+    // Settings chosen after loading replace the database's.
+    let mut emulator = Emulator::with_seed(0);
+    emulator.load_rom(&DIE_ROLL).unwrap();
+    emulator.set_quirks(Quirks::schip());
+    emulator.set_ticks_per_frame(DEFAULT_TICKS_PER_FRAME);
+    assert_eq!(emulator.rom_info().unwrap().title, "Die Roll");
+    assert_eq!(emulator.quirks(), Quirks::schip());
+
+    // Loading another ROM forgets the last one's entry.
+    emulator.load_rom(&[0x12, 0x00]).unwrap();
+    assert!(emulator.rom_info().is_none());
+    assert_eq!(emulator.quirks(), Quirks::schip());
+
+    // An environment plays with its config's settings, episode after
+    // episode.
+    let config = EnvConfig {
+        ticks_per_frame: 7,
+        quirks: Quirks::xochip(),
+        ..EnvConfig::default()
+    };
+    let mut env = Env::new(&DIE_ROLL, config, 1).unwrap();
+    env.reset();
+    assert_eq!(env.emulator().quirks(), Quirks::xochip());
+    assert_eq!(env.emulator().ticks_per_frame(), 7);
+
+    // A batch runs like emulators with the ROM loaded the same way.
+    let mut batch = Batch::new(2);
+    batch.set_quirks(Quirks::schip());
+    batch.load_rom(&DIE_ROLL).unwrap();
+    let mut emulator = Emulator::with_seed(0);
+    emulator.set_quirks(Quirks::schip());
+    emulator.load_rom(&DIE_ROLL).unwrap();
+    assert_eq!(emulator.quirks(), Quirks::chip8());
+    assert_eq!(batch.emulator(0).quirks(), emulator.quirks());
+    assert_eq!(
+        batch.emulator(0).ticks_per_frame(),
+        emulator.ticks_per_frame()
+    );
+}
+
+#[test]
+fn loading_a_state_keeps_the_database() {
+    let mut database = RomDatabase::new();
+    database.extend(RomDatabase::parse(DATABASE).unwrap());
+    let database = Arc::new(database);
+
+    let mut emulator = Emulator::with_seed(0);
+    emulator.set_rom_database(database.clone());
+    emulator.load_rom(&[0x00, 0xE0]).unwrap();
+    let saved = emulator.save_state();
+    emulator.load_state(&saved).unwrap();
+    assert_eq!(emulator.rom_info().unwrap().title, "Clear");
+
+    // So is the database, the embedded one doesn't know the ROM.
+    emulator.load_rom(&[0x00, 0xE0]).unwrap();
+    assert_eq!(emulator.rom_info().unwrap().title, "Clear");
+}
+
+#[test]
+fn invalid_databases_are_rejected() {
+    let rom = |entry: &str| {
+        format!(
+            "[{{\"title\": \"T\", \"roms\": {{\"{}\": {}}}}}]",
+            "159ba69f4c40be3042fc54c7fbb2025f7e49f8e0", entry
+        )
+    };
+    for text in [
+        "{}".to_string(),
+        "[{\"roms\": {}}]".to_string(),
+        "[{\"title\": \"T\", \"roms\": {\"+7a0\": {\"platforms\": []}}}]".to_string(),
+        rom("{}"),
+        rom("{\"platforms\": [\"superchip\"], \"tickrate\": -1}"),
+        rom("{\"platforms\": [\"superchip\"], \"keys\": {\"up\": 16}}"),
+        rom("{\"platforms\": [\"superchip\"], \"colors\": {\"pixels\": [\"#12345\"]}}"),
+        rom(
+            "{\"platforms\": [\"superchip\"], \"quirkyPlatforms\": {\"superchip\": {\"jump\": 1}}}",
+        ),
+    ] {
+        assert!(RomDatabase::parse(&text).is_err(), "{}", text);
+    }
+
+    // Errors point at the value at fault, or the object missing a key.
+    let mut lines: Vec<&str> = DATABASE.lines().collect();
+    for (line, replacement, reason) in [
+        (9, r#"        "tickrate": "fast","#, "invalid tick rate"),
+        (
+            11,
+            r#"        "keys": {"up": 5, "a": 16},"#,
+            "invalid CHIP-8 key",
+        ),
+        (
+            12,
+            r##"        "colors": {"pixels": ["#102030", "yellow"], "buzzer": "#990000"}"##,
+            "invalid colour",
+        ),
+    ] {
+        let original = lines[line - 1];
+        lines[line - 1] = replacement;
+        let error = RomDatabase::parse(&lines.join("\n")).unwrap_err();
+        assert_eq!((error.line, error.reason), (line, reason));
+        lines[line - 1] = original;
+    }
+    let untitled = DATABASE.replace("\"title\": \"Clear\"", "\"name\": \"Clear\"");
+    let error = RomDatabase::parse(&untitled).unwrap_err();
+    assert_eq!((error.line, error.reason), (2, "a program has no title"));
+}
@@ -0,0 +1,344 @@
+//! JSON-RPC server: a client on a socket can drive the emulator through
+//! every method, and bad requests, including batches, requests without ids
+//! and ones too big or too deep to handle, get JSON-RPC errors.
+
+use std::io::{self, BufRead, BufReader, Cursor, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+use chip_core::harness::parse_listing;
+use chip_core::rpc::Server;
+use chip_core::Emulator;
+
+/// Draws the font's 0 at (0, 0) while key 5 is held, then stops.
+const ROM: &str = "
+200: 6005   ; V0 = 5
+202: E09E   ; skip if key V0 down
+204: 1202   ; jump 202
+206: 6000   ; V0 = 0
+208: F029   ; I = font(V0)
+20A: D005   ; draw 5 rows at (V0, V0)
+20C: 120C   ; jump 20C
+";
+
+/// Encodes bytes as padded standard base64, the way the server sends them.
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut text = String::new();
+    for chunk in data.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (idx, byte)| {
+            bits | (*byte as u32) << (16 - 8 * idx)
+        });
+        for idx in 0..4 {
+            text.push(match idx <= chunk.len() {
+                true => ALPHABET[(bits >> (18 - 6 * idx) & 0x3F) as usize] as char,
+                false => '=',
+            });
+        }
+    }
+    text
+}
+
+/// A minimal client: sends each request on its own line and reads the
+/// response line.
+struct Client {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    id: u32,
+}
+
+impl Client {
+    fn connect(emulator: Emulator) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let mut server = Server::new(emulator);
+            let (stream, _) = listener.accept().unwrap();
+            server.serve(stream).unwrap();
+        });
+        let writer = TcpStream::connect(address).unwrap();
+        Self {
+            reader: BufReader::new(writer.try_clone().unwrap()),
+            writer,
+            id: 0,
+        }
+    }
+
+    /// Sends a raw line and returns the response line.
+    fn send(&mut self, request: &str) -> String {
+        self.writer
+            .write_all(format!("{}\n", request).as_bytes())
+            .unwrap();
+        let mut response = String::new();
+        self.reader.read_line(&mut response).unwrap();
+        response.trim_end().to_string()
+    }
+
+    /// Calls a method and returns the response after the id, the
+    /// `"result":...` or `"error":...` member.
+    fn call(&mut self, method: &str, params: &str) -> String {
+        self.id += 1;
+        let response = self.send(&format!(
+            r#"{{"jsonrpc":"2.0","id":{},"method":"{}","params":{}}}"#,
+            self.id, method, params
+        ));
+        let prefix = format!(r#"{{"jsonrpc":"2.0","id":{},"#, self.id);
+        response
+            .strip_prefix(&prefix)
+            .and_then(|rest| rest.strip_suffix('}'))
+            .unwrap_or_else(|| panic!("{}", response))
+            .to_string()
+    }
+}
+
+#[test]
+fn client_drives_the_emulator() {
+    let rom = parse_listing(ROM).unwrap();
+    let mut client = Client::connect(Emulator::with_seed(0));
+    let mut local = Emulator::with_seed(0);
+    local.load_rom(&rom).unwrap();
+
+    let params = format!(r#"{{"rom":"{}"}}"#, base64(&rom));
+    assert_eq!(client.call("load_rom", &params), r#""result":null"#);
+    let blank = base64(&[0; 256]);
+    assert_eq!(
+        client.call("get_screen", "{}"),
+        format!(r#""result":{{"width":64,"height":32,"data":"{}"}}"#, blank)
+    );
+
+    // The ROM waits for the key, so nothing is drawn until it's pressed.
+    assert_eq!(
+        client.call("run_frames", r#"{"frames":5}"#),
+        r#""result":{"fault":null}"#
+    );
+    let state = client.call("save_state", "{}");
+    assert_eq!(client.call("press_key", r#"{"key":5}"#), r#""result":null"#);
+    client.call("run_frames", r#"{"frames":1}"#);
+    client.call("release_key", r#"{"key":5}"#);
+    local.keypress(5, true).unwrap();
+    for _ in 0..6 {
+        local.run_frame();
+    }
+    local.keypress(5, false).unwrap();
+    let screen: Vec<u8> = local
+        .get_display()
+        .chunks(8)
+        .map(|pixels| {
+            pixels
+                .iter()
+                .fold(0, |byte, pixel| byte << 1 | *pixel as u8)
+        })
+        .collect();
+    assert_ne!(base64(&screen), blank);
+    assert_eq!(
+        client.call("get_screen", r#"{"format":"bitmap"}"#),
+        format!(
+            r#""result":{{"width":64,"height":32,"data":"{}"}}"#,
+            base64(&screen)
+        )
+    );
+    assert_eq!(
+        client.call("save_state", "{}"),
+        format!(r#""result":{{"state":"{}"}}"#, base64(&local.save_state()))
+    );
+
+    let png = client.call("get_screen", r#"{"format":"png","scale":2}"#);
+    // The PNG signature, base64 encoded.
+    assert!(
+        png.starts_with(r#""result":{"width":128,"height":64,"data":"iVBORw0KGgo"#),
+        "{}",
+        png
+    );
+
+    // Memory reads and writes.
+    assert_eq!(
+        client.call("read_memory", r#"{"address":512,"length":4}"#),
+        format!(r#""result":{{"data":"{}"}}"#, base64(&rom[..4]))
+    );
+    let params = format!(r#"{{"address":1024,"data":"{}"}}"#, base64(b"QA"));
+    assert_eq!(client.call("write_memory", &params), r#""result":null"#);
+    assert_eq!(
+        client.call("read_memory", r#"{"address":1024,"length":2}"#),
+        format!(r#""result":{{"data":"{}"}}"#, base64(b"QA"))
+    );
+
+    // Loading the state from before the key press blanks the screen again.
+    let state = state
+        .strip_prefix(r#""result":{"state":"#)
+        .and_then(|state| state.strip_suffix('}'))
+        .unwrap();
+    let params = format!(r#"{{"state":{}}}"#, state);
+    assert_eq!(client.call("load_state", &params), r#""result":null"#);
+    assert_eq!(
+        client.call("get_screen", "{}"),
+        format!(r#""result":{{"width":64,"height":32,"data":"{}"}}"#, blank)
+    );
+
+    assert_eq!(client.call("reset", "{}"), r#""result":null"#);
+    assert_eq!(
+        client.call("read_memory", r#"{"address":1024,"length":2}"#),
+        format!(r#""result":{{"data":"{}"}}"#, base64(&[0, 0]))
+    );
+}
+
+#[test]
+fn bad_requests_get_errors() {
+    let mut client = Client::connect(Emulator::with_seed(0));
+
+    assert_eq!(
+        client.send("{not json"),
+        r#"{"jsonrpc":"2.0","id":null,"error":{"code":-32700,"message":"parse error"}}"#
+    );
+    assert_eq!(
+        client.send(r#"{"id":1,"method":"reset"}"#),
+        r#"{"jsonrpc":"2.0","id":1,"error":{"code":-32600,"message":"invalid request"}}"#
+    );
+    // Notifications run without a response, so the next line answers the
+    // request after it.
+    assert_eq!(
+        client.send(concat!(
+            r#"{"jsonrpc":"2.0","method":"press_key","params":{"key":1}}"#,
+            "\n",
+            r#"{"jsonrpc":"2.0","id":"last","method":"reset"}"#
+        )),
+        r#"{"jsonrpc":"2.0","id":"last","result":null}"#
+    );
+
+    for (method, params, code) in [
+        ("fly", "{}", -32601),
+        ("press_key", r#"{"key":16}"#, -32602),
+        ("press_key", r#"{"key":"A"}"#, -32602),
+        ("run_frames", "{}", -32602),
+        ("run_frames", r#"{"frames":1000000000}"#, -32602),
+        ("load_rom", r#"{"rom":"not base64!"}"#, -32602),
+        ("get_screen", r#"{"format":"jpeg"}"#, -32602),
+        ("read_memory", r#"{"address":4095,"length":2}"#, -32000),
+        ("load_state", r#"{"state":"QUJD"}"#, -32000),
+    ] {
+        let response = client.call(method, params);
+        assert!(
+            response.starts_with(&format!(r#""error":{{"code":{},"#, code)),
+            "{} {}",
+            method,
+            response
+        );
+    }
+}
+
+#[test]
+fn malformed_requests_without_ids_get_errors() {
+    let mut server = Server::new(Emulator::with_seed(0));
+    let invalid =
+        r#"{"jsonrpc":"2.0","id":null,"error":{"code":-32600,"message":"invalid request"}}"#;
+    for request in [
+        "7",
+        r#""reset""#,
+        "null",
+        r#"{"method":"reset"}"#,
+        r#"{"jsonrpc":"1.0","method":"reset"}"#,
+        r#"{"jsonrpc":"2.0"}"#,
+        r#"{"jsonrpc":"2.0","id":[1],"method":"reset"}"#,
+    ] {
+        assert_eq!(
+            server.handle(request).as_deref(),
+            Some(invalid),
+            "{}",
+            request
+        );
+    }
+    assert_eq!(
+        server
+            .handle(r#"[{"jsonrpc":"2.0","id":1,"method":"reset"}]"#)
+            .unwrap(),
+        r#"{"jsonrpc":"2.0","id":null,"error":{"code":-32600,"message":"batches aren't supported"}}"#
+    );
+    assert_eq!(
+        server.handle("[]").unwrap(),
+        r#"{"jsonrpc":"2.0","id":null,"error":{"code":-32600,"message":"batches aren't supported"}}"#
+    );
+
+    // A well-formed notification still goes unanswered, even when its
+    // method fails.
+    assert_eq!(server.handle(r#"{"jsonrpc":"2.0","method":"reset"}"#), None);
+    assert_eq!(server.handle(r#"{"jsonrpc":"2.0","method":"fly"}"#), None);
+}
+
+/// A connection with everything the client sends already written.
+struct Pipe {
+    input: Cursor<Vec<u8>>,
+    output: Vec<u8>,
+}
+
+impl Read for Pipe {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.input.read(buf)
+    }
+}
+
+impl Write for Pipe {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn oversized_requests_get_errors() {
+    let mut server = Server::new(Emulator::with_seed(0));
+    assert_eq!(
+        server.handle(&"[".repeat(200_000)).unwrap(),
+        r#"{"jsonrpc":"2.0","id":null,"error":{"code":-32700,"message":"parse error"}}"#
+    );
+
+    // Nothing after a line that's too long is answered.
+    let mut input = "x".repeat(2 << 20);
+    input.push_str("\n{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"reset\"}\n");
+    let mut pipe = Pipe {
+        input: Cursor::new(input.into_bytes()),
+        output: Vec::new(),
+    };
+    server.serve(&mut pipe).unwrap();
+    assert_eq!(
+        String::from_utf8(pipe.output).unwrap(),
+        concat!(
+            r#"{"jsonrpc":"2.0","id":null,"error":{"code":-32600,"message":"request too long"}}"#,
+            "\n"
+        )
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn serves_unix_sockets() {
+    use std::os::unix::net::{UnixListener, UnixStream};
+
+    let path = std::env::temp_dir().join(format!("chip-rpc-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path).unwrap();
+    thread::spawn(move || {
+        let mut server = Server::new(Emulator::with_seed(0));
+        server.serve(listener.accept().unwrap().0).unwrap();
+    });
+
+    let mut stream = UnixStream::connect(&path).unwrap();
+    writeln!(
+        stream,
+        r#"{{"jsonrpc":"2.0","id":7,"method":"read_memory","params":{{"address":0,"length":5}}}}"#
+    )
+    .unwrap();
+    let mut response = String::new();
+    BufReader::new(&stream).read_line(&mut response).unwrap();
+    // The font's 0 glyph.
+    let glyph = base64(&[0xF0, 0x90, 0x90, 0x90, 0xF0]);
+    assert_eq!(
+        response.trim_end(),
+        format!(
+            r#"{{"jsonrpc":"2.0","id":7,"result":{{"data":"{}"}}}}"#,
+            glyph
+        )
+    );
+    let _ = std::fs::remove_file(&path);
+}
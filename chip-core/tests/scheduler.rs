@@ -0,0 +1,204 @@
+//! Scheduler: host time becomes the right number of cycles and timer ticks
+//! at any speed, whatever the update rate, and stalls are caught up on.
+
+use std::time::Duration;
+
+use chip_core::harness::parse_listing;
+use chip_core::quirks::Quirks;
+use chip_core::scheduler::{Clock, FakeClock, Scheduler, Steps, DEFAULT_MAX_CATCH_UP, MAX_SPEED};
+use chip_core::Emulator;
+
+/// Counts up in V0 forever, playing a tone, and draws each time round
+/// under the display wait quirk, so the state depends on where the frames
+/// end.
+const ROM: &str = "
+200: 6005   ; V0 = 5
+202: F018   ; sound timer = V0
+204: F015   ; delay timer = V0
+206: 7001   ; V0 += 1
+208: D001   ; draw a row of the font
+20A: 1206   ; jump 206
+";
+
+fn emulator() -> Emulator {
+    let mut emulator = Emulator::with_seed(0);
+    emulator.set_quirks(Quirks::chip8());
+    emulator.load_rom(&parse_listing(ROM).unwrap()).unwrap();
+    emulator
+}
+
+/// The host time a number of frames take, rounded up to whole nanoseconds.
+fn frames(count: u64) -> Duration {
+    Duration::from_nanos((count * 1_000_000_000).div_ceil(60))
+}
+
+/// Moves the clock forward to a time.
+fn advance_to(clock: &FakeClock, time: Duration) {
+    clock.advance(time - clock.now());
+}
+
+fn add(total: &mut Steps, steps: Steps) {
+    total.cycles += steps.cycles;
+    total.timer_ticks += steps.timer_ticks;
+}
+
+#[test]
+fn real_time_matches_run_frame() {
+    let clock = FakeClock::new();
+    let mut scheduler = Scheduler::new(&clock);
+    let mut scheduled = emulator();
+    let mut framed = emulator();
+
+    // Two updates a frame, the first partway through it.
+    let mut total = Steps::default();
+    for frame in 1..=120 {
+        advance_to(&clock, frames(frame - 1) + frames(1) / 3);
+        add(&mut total, scheduler.update(&mut scheduled));
+        advance_to(&clock, frames(frame));
+        add(&mut total, scheduler.update(&mut scheduled));
+
+        framed.run_frame();
+        assert_eq!(scheduled.save_state(), framed.save_state(), "{}", frame);
+    }
+    assert_eq!(
+        total,
+        Steps {
+            cycles: 1200,
+            timer_ticks: 120
+        }
+    );
+}
+
+#[test]
+fn speed_scales_emulated_time() {
+    let clock = FakeClock::new();
+    let mut scheduler = Scheduler::new(&clock);
+    let mut emulator = emulator();
+
+    scheduler.set_speed(2.0);
+    clock.advance(Duration::from_millis(100));
+    assert_eq!(
+        scheduler.update(&mut emulator),
+        Steps {
+            cycles: 120,
+            timer_ticks: 12
+        }
+    );
+
+    scheduler.set_speed(0.25);
+    clock.advance(Duration::from_millis(200));
+    assert_eq!(
+        scheduler.update(&mut emulator),
+        Steps {
+            cycles: 30,
+            timer_ticks: 3
+        }
+    );
+
+    // Paused time is skipped, not caught up on after.
+    scheduler.set_speed(1.0);
+    scheduler.set_paused(true);
+    clock.advance(Duration::from_millis(200));
+    assert_eq!(scheduler.update(&mut emulator), Steps::default());
+    scheduler.set_paused(false);
+    clock.advance(Duration::from_millis(50));
+    assert_eq!(
+        scheduler.update(&mut emulator),
+        Steps {
+            cycles: 30,
+            timer_ticks: 3
+        }
+    );
+}
+
+#[test]
+fn instructions_per_second_can_change() {
+    let clock = FakeClock::new();
+    let mut scheduler = Scheduler::new(&clock);
+    let mut emulator = emulator();
+
+    // Follows the emulator's tick rate until set.
+    emulator.set_ticks_per_frame(20);
+    clock.advance(Duration::from_millis(100));
+    assert_eq!(scheduler.update(&mut emulator).cycles, 120);
+
+    scheduler.set_instructions_per_second(Some(1000));
+    clock.advance(Duration::from_millis(100));
+    assert_eq!(scheduler.update(&mut emulator).cycles, 100);
+
+    // Rates that don't divide a second evenly don't drift.
+    scheduler.set_instructions_per_second(Some(7));
+    let mut cycles = 0;
+    for _ in 0..300 {
+        clock.advance(Duration::from_millis(10));
+        cycles += scheduler.update(&mut emulator).cycles;
+    }
+    assert_eq!(cycles, 21);
+
+    scheduler.set_instructions_per_second(Some(0));
+    clock.advance(Duration::from_millis(100));
+    assert_eq!(
+        scheduler.update(&mut emulator),
+        Steps {
+            cycles: 0,
+            timer_ticks: 6
+        }
+    );
+}
+
+#[test]
+fn stalls_are_caught_up_on() {
+    let clock = FakeClock::new();
+    let mut scheduler = Scheduler::new(&clock);
+    let mut emulator = emulator();
+    let mut framed = self::emulator();
+
+    // A short stall is run in full, in order.
+    advance_to(&clock, frames(12));
+    assert_eq!(
+        scheduler.update(&mut emulator),
+        Steps {
+            cycles: 120,
+            timer_ticks: 12
+        }
+    );
+    for _ in 0..12 {
+        framed.run_frame();
+    }
+    assert_eq!(emulator.save_state(), framed.save_state());
+
+    // A long one only up to the limit.
+    clock.advance(Duration::from_secs(10));
+    let steps = scheduler.update(&mut emulator);
+    assert_eq!(
+        steps.timer_ticks,
+        60 * DEFAULT_MAX_CATCH_UP.as_millis() as u64 / 1000
+    );
+    scheduler.set_max_catch_up(Duration::from_secs(2));
+    clock.advance(Duration::from_secs(10));
+    assert_eq!(scheduler.update(&mut emulator).timer_ticks, 120);
+}
+
+#[test]
+fn speeds_are_clamped() {
+    let clock = FakeClock::new();
+    let mut scheduler = Scheduler::new(&clock);
+    let mut emulator = emulator();
+
+    for (speed, clamped) in [
+        (f64::INFINITY, MAX_SPEED),
+        (1e300, MAX_SPEED),
+        (f64::NAN, 0.0),
+        (f64::NEG_INFINITY, 0.0),
+    ] {
+        scheduler.set_speed(speed);
+        assert_eq!(scheduler.speed(), clamped, "{}", speed);
+    }
+
+    // 10 ms at the fastest speed is 10 seconds of emulated time.
+    scheduler.set_speed(f64::INFINITY);
+    clock.advance(Duration::from_millis(10));
+    let steps = scheduler.update(&mut emulator);
+    assert_eq!(steps.timer_ticks, 600);
+    assert_eq!(steps.cycles, 6000);
+}
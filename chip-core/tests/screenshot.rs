@@ -0,0 +1,287 @@
+//! Screenshots: the checksums match published test vectors, and PNG files
+//! have valid chunk CRCs and decompress to the same pixels as the PBM and
+//! SVG exports.
+
+use chip_core::display::{Framebuffer, Palette, Rgb};
+use chip_core::harness::parse_listing;
+use chip_core::hash::{adler32, crc32, crc32_update};
+use chip_core::screenshot::{to_pbm, to_png, to_svg};
+use chip_core::Emulator;
+
+/// Draws the font's 0 and 8 at (0, 0) and (60, 28), the 8 wrapping around
+/// the bottom edge.
+const ROM: &str = "
+200: 6000   ; V0 = 0
+202: F029   ; I = font(V0)
+204: D005   ; draw 5 rows at (V0, V0)
+206: 6808   ; V8 = 8
+208: F829   ; I = font(V8)
+20A: 613C   ; V1 = 60
+20C: 621C   ; V2 = 28
+20E: D125   ; draw 5 rows at (V1, V2)
+210: 1210   ; loop forever
+";
+
+/// A four colour display with runs, repeated rows and every pixel value,
+/// standing in for a bitplane display.
+struct Stripes;
+
+impl Framebuffer for Stripes {
+    fn width(&self) -> usize {
+        40
+    }
+
+    fn height(&self) -> usize {
+        12
+    }
+
+    fn pixel(&self, x: usize, y: usize) -> usize {
+        ((x / 3) ^ (y / 2)) % 4
+    }
+}
+
+fn emulator() -> Emulator {
+    let mut emulator = Emulator::with_seed(0);
+    emulator.load_rom(&parse_listing(ROM).unwrap()).unwrap();
+    emulator.run_frame();
+    emulator
+}
+
+/// Reads a DEFLATE stream least significant bit first.
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl BitReader<'_> {
+    fn bits(&mut self, count: usize) -> u32 {
+        (0..count).fold(0, |value, idx| {
+            let bit = self.data[self.pos / 8] >> (self.pos % 8) & 1;
+            self.pos += 1;
+            value | (bit as u32) << idx
+        })
+    }
+
+    /// Reads a Huffman code, which is stored most significant bit first.
+    fn code(&mut self, len: usize) -> u32 {
+        (0..len).fold(0, |code, _| code << 1 | self.bits(1))
+    }
+
+    /// Reads a literal/length symbol with the fixed Huffman table.
+    fn symbol(&mut self) -> u32 {
+        let code = self.code(7);
+        if code <= 0x17 {
+            return 256 + code;
+        }
+        let code = code << 1 | self.bits(1);
+        match code {
+            0x30..=0xBF => code - 0x30,
+            0xC0..=0xC7 => 280 + code - 0xC0,
+            _ => 144 + (code << 1 | self.bits(1)) - 0x190,
+        }
+    }
+}
+
+/// Decompresses a zlib stream of fixed Huffman blocks, checking the header
+/// and the Adler-32 trailer.
+fn inflate(stream: &[u8]) -> Vec<u8> {
+    const LENGTH_BASE: [usize; 29] = [
+        3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115,
+        131, 163, 195, 227, 258,
+    ];
+    const DISTANCE_BASE: [usize; 30] = [
+        1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+        2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+    ];
+
+    assert_eq!(stream[0] & 0x0F, 8, "not deflate");
+    assert_eq!(u16::from_be_bytes([stream[0], stream[1]]) % 31, 0);
+    let mut reader = BitReader {
+        data: &stream[2..stream.len() - 4],
+        pos: 0,
+    };
+    let mut data = Vec::new();
+    loop {
+        let last = reader.bits(1) == 1;
+        assert_eq!(reader.bits(2), 1, "not a fixed Huffman block");
+        loop {
+            let symbol = reader.symbol() as usize;
+            match symbol {
+                0..=255 => data.push(symbol as u8),
+                256 => break,
+                _ => {
+                    let code = symbol - 257;
+                    let extra = if (8..28).contains(&code) {
+                        (code - 4) / 4
+                    } else {
+                        0
+                    };
+                    let length = LENGTH_BASE[code] + reader.bits(extra) as usize;
+                    let code = reader.code(5) as usize;
+                    let extra = if code >= 4 { (code - 2) / 2 } else { 0 };
+                    let distance = DISTANCE_BASE[code] + reader.bits(extra) as usize;
+                    for _ in 0..length {
+                        data.push(data[data.len() - distance]);
+                    }
+                }
+            }
+        }
+        if last {
+            break;
+        }
+    }
+    let trailer = &stream[stream.len() - 4..];
+    assert_eq!(adler32(&data).to_be_bytes(), trailer);
+    data
+}
+
+/// Splits a PNG file into its chunks, checking the signature and each
+/// chunk's CRC.
+fn chunks(png: &[u8]) -> Vec<([u8; 4], &[u8])> {
+    assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+    let mut chunks = Vec::new();
+    let mut rest = &png[8..];
+    while !rest.is_empty() {
+        let len = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+        let kind: [u8; 4] = rest[4..8].try_into().unwrap();
+        let data = &rest[8..8 + len];
+        let crc = u32::from_be_bytes(rest[8 + len..12 + len].try_into().unwrap());
+        assert_eq!(
+            crc,
+            crc32(&rest[4..8 + len]),
+            "{}",
+            String::from_utf8_lossy(&kind)
+        );
+        chunks.push((kind, data));
+        rest = &rest[12 + len..];
+    }
+    chunks
+}
+
+/// Decodes a PNG written by `to_png` into its dimensions and one palette
+/// index per pixel.
+fn decode_png(png: &[u8]) -> (usize, usize, Vec<u8>) {
+    let chunks = chunks(png);
+    let kinds: Vec<&[u8; 4]> = chunks.iter().map(|(kind, _)| kind).collect();
+    assert_eq!(kinds, [b"IHDR", b"PLTE", b"IDAT", b"IEND"]);
+
+    let header = chunks[0].1;
+    let width = u32::from_be_bytes(header[..4].try_into().unwrap()) as usize;
+    let height = u32::from_be_bytes(header[4..8].try_into().unwrap()) as usize;
+    assert_eq!(&header[8..], [8, 3, 0, 0, 0]);
+
+    let raw = inflate(chunks[2].1);
+    assert_eq!(raw.len(), (width + 1) * height);
+    let mut pixels = Vec::with_capacity(width * height);
+    for row in raw.chunks(width + 1) {
+        assert_eq!(row[0], 0, "filtered scanline");
+        pixels.extend(&row[1..]);
+    }
+    (width, height, pixels)
+}
+
+#[test]
+fn checksums_match_test_vectors() {
+    assert_eq!(crc32(b""), 0);
+    assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    assert_eq!(
+        crc32(b"The quick brown fox jumps over the lazy dog"),
+        0x414F_A339
+    );
+    assert_eq!(crc32_update(crc32(b"12345"), b"6789"), 0xCBF4_3926);
+
+    assert_eq!(adler32(b""), 1);
+    assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+    assert_eq!(adler32(b"123456789"), 0x091E_01DE);
+    // Long enough for the sums to be reduced part way through.
+    let long = vec![0xFF; 100_000];
+    let (a, b) = long.iter().fold((1u32, 0u32), |(a, b), byte| {
+        let a = (a + *byte as u32) % 65521;
+        (a, (b + a) % 65521)
+    });
+    assert_eq!(adler32(&long), b << 16 | a);
+}
+
+#[test]
+fn png_matches_the_pbm_pixels() {
+    let emulator = emulator();
+    let palette = Palette::default();
+    for scale in [1, 3, 10] {
+        let (width, height, pixels) = decode_png(&to_png(&emulator, scale, &palette));
+        assert_eq!((width, height), (64 * scale, 32 * scale));
+
+        // PBM writes the dark background as 1 and the lit pixels as 0.
+        let pbm = to_pbm(&emulator, scale, &palette);
+        let header = format!("P1\n{} {}\n", width, height);
+        assert!(pbm.starts_with(header.as_bytes()));
+        let bits: Vec<u8> = pbm[header.len()..]
+            .iter()
+            .filter(|c| **c != b'\n')
+            .map(|c| c - b'0')
+            .collect();
+        let expected: Vec<u8> = pixels.iter().map(|pixel| 1 - pixel).collect();
+        assert_eq!(bits, expected, "scale {}", scale);
+    }
+
+    // Spot checks against the display: the 0's top row, and the 8 in the
+    // bottom right corner with its last row wrapped to the top.
+    let (_, _, pixels) = decode_png(&to_png(&emulator, 1, &palette));
+    let display: Vec<u8> = emulator.get_display().iter().map(|p| *p as u8).collect();
+    assert_eq!(pixels, display);
+    assert_eq!(&pixels[..5], [1, 1, 1, 1, 0]);
+    assert_eq!(pixels[64 * 28 + 60], 1);
+    assert_eq!(pixels[64 * 31 + 61], 0);
+    assert_eq!(pixels[60], 1);
+}
+
+#[test]
+fn png_keeps_every_palette_index() {
+    let colors = [
+        Rgb::new(0x10, 0x20, 0x30),
+        Rgb::new(0xFF, 0, 0),
+        Rgb::new(0, 0xFF, 0),
+        Rgb::new(0, 0, 0xFF),
+    ];
+    let palette = Palette::from_colors(&colors);
+    let png = to_png(&Stripes, 4, &palette);
+
+    let plte = chunks(&png)[1].1.to_vec();
+    assert_eq!(plte.len(), 16 * 3);
+    assert_eq!(&plte[..6], [0x10, 0x20, 0x30, 0xFF, 0, 0]);
+
+    let (width, height, pixels) = decode_png(&png);
+    assert_eq!((width, height), (160, 48));
+    for y in 0..height {
+        for x in 0..width {
+            assert_eq!(pixels[y * width + x] as usize, Stripes.pixel(x / 4, y / 4));
+        }
+    }
+}
+
+#[test]
+fn svg_draws_runs_of_lit_pixels() {
+    let emulator = emulator();
+    let palette = Palette::monochrome(Rgb::new(0, 0, 0), Rgb::new(0x33, 0xFF, 0x66));
+    let svg = to_svg(&emulator, 2, &palette);
+
+    assert!(
+        svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"128\" height=\"64\"")
+    );
+    assert!(svg.contains("<rect width=\"128\" height=\"64\" fill=\"#000000\"/>"));
+    // The 0's top row is one run of four pixels.
+    assert!(svg.contains("<rect x=\"0\" y=\"0\" width=\"8\" height=\"2\" fill=\"#33ff66\"/>"));
+    assert!(svg.trim_end().ends_with("</svg>"));
+
+    // One rectangle per run of lit pixels.
+    let runs: usize = emulator
+        .get_display()
+        .chunks(64)
+        .map(|row| {
+            row.iter()
+                .enumerate()
+                .filter(|(x, lit)| **lit && (*x == 0 || !row[x - 1]))
+                .count()
+        })
+        .sum();
+    assert_eq!(svg.matches("<rect ").count(), runs + 1);
+}
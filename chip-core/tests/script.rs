@@ -0,0 +1,153 @@
+//! Scripting: scripts drive the emulator through keys, frames, registers,
+//! RAM, callbacks and save states, and report errors with their line.
+
+use chip_core::harness::parse_listing;
+use chip_core::script;
+use chip_core::Emulator;
+
+/// Counts up in VA while key 5 is held, drawing the font's 0 from a
+/// subroutine every time round.
+const ROM: &str = "
+200: 6505   ; V5 = 5
+202: E5A1   ; skip if key V5 up
+204: 7A01   ; VA += 1
+206: 2210   ; call 210
+208: 1202   ; jump 202
+20A: 0000
+20C: 0000
+20E: 0000
+210: 6000   ; V0 = 0
+212: F029   ; I = font(V0)
+214: D005   ; draw 5 rows at (V0, V0)
+216: 00EE   ; return
+";
+
+fn emulator() -> Emulator {
+    let mut emulator = Emulator::with_seed(0);
+    emulator.load_rom(&parse_listing(ROM).unwrap()).unwrap();
+    emulator
+}
+
+#[test]
+fn scripts_press_keys_and_step() {
+    let path = std::env::temp_dir().join(format!("chip-script-{}.png", std::process::id()));
+    let source = format!(
+        r#"
+        frames(2);
+        press(5);
+        while reg(0xA) != 3 {{
+            step();
+        }}
+        release(5);
+        frame();
+        screenshot("{}");
+        "#,
+        path.display()
+    );
+    let mut emulator = emulator();
+    script::run(&mut emulator, &source).unwrap();
+
+    assert_eq!(emulator.registers()[0xA], 3);
+    let png = std::fs::read(&path).unwrap();
+    assert_eq!(&png[1..4], b"PNG");
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn registers_ram_and_frames_match_the_emulator() {
+    let mut emulator = emulator();
+    script::run(
+        &mut emulator,
+        r#"
+        frames(3);
+        if frame_count() != 3 { throw "frame count"; }
+        if pc() < 0x200 || pc() > 0x216 { throw "pc"; }
+        if reg_i() != 0 { throw "I points at the font's 0"; }
+        if peek(0) != 0xF0 { throw "font"; }
+        set_reg(1, 0x42);
+        set_reg_i(0x300);
+        poke(0x300, 0xAB);
+        "#,
+    )
+    .unwrap();
+
+    let mut framed = self::emulator();
+    for _ in 0..3 {
+        framed.run_frame();
+    }
+    framed.set_register(1, 0x42).unwrap();
+    framed.set_i_register(0x300).unwrap();
+    framed.write_ram(0x300, &[0xAB]).unwrap();
+    assert_eq!(emulator.save_state(), framed.save_state());
+}
+
+#[test]
+fn callbacks_run_on_pc_and_draws() {
+    let mut emulator = emulator();
+    script::run(
+        &mut emulator,
+        r#"
+        let calls = [];
+        on_pc(0x210, || calls.push(pc()));
+        let draws = [];
+        on_draw(|x, y, height, collision| draws.push([x, y, height, collision]));
+        frames(2);
+
+        // Twenty cycles reach the subroutine three times, drawing the 0 on,
+        // off and on again.
+        if calls != [0x210, 0x210, 0x210] { throw `calls ${calls}`; }
+        let expected = [[0, 0, 5, false], [0, 0, 5, true], [0, 0, 5, false]];
+        if draws != expected { throw `draws ${draws}`; }
+        "#,
+    )
+    .unwrap();
+}
+
+#[test]
+fn save_states_restore_the_machine() {
+    let mut emulator = emulator();
+    script::run(
+        &mut emulator,
+        r#"
+        press(5);
+        frames(2);
+        let state = save_state();
+        let va = reg(0xA);
+        frames(5);
+        if reg(0xA) == va { throw "VA didn't count"; }
+        load_state(state);
+        if reg(0xA) != va { throw "VA wasn't restored"; }
+        release(5);
+        "#,
+    )
+    .unwrap();
+
+    let mut framed = self::emulator();
+    framed.keypress(5, true).unwrap();
+    framed.run_frame();
+    framed.run_frame();
+    framed.keypress(5, false).unwrap();
+    assert_eq!(emulator.save_state(), framed.save_state());
+}
+
+#[test]
+fn errors_report_their_line() {
+    for (source, line, message) in [
+        ("frame();\npress(16);", Some(2), "no key with index 16"),
+        ("\n\nload_state(blob(3));", Some(3), "save state"),
+        ("poke(4096, 1);", Some(1), "outside of RAM"),
+        ("set_reg(0, 256);", Some(1), "doesn't fit in a byte"),
+        ("frame(\n", Some(2), ""),
+        ("throw \"gave up\";", Some(1), "gave up"),
+    ] {
+        let mut emulator = emulator();
+        let error = script::run(&mut emulator, source).unwrap_err();
+        assert_eq!(error.line, line, "{}", source);
+        assert!(error.message.contains(message), "{}", error);
+    }
+
+    // The emulator is left as the script left it.
+    let mut emulator = emulator();
+    script::run(&mut emulator, "set_reg(3, 7); throw 1;").unwrap_err();
+    assert_eq!(emulator.registers()[3], 7);
+}
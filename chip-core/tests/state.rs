@@ -0,0 +1,113 @@
+//! Save states: a restored state must play out exactly like the original,
+//! and bad data must be rejected without touching the emulator.
+
+use chip_core::error::StateError;
+use chip_core::harness::parse_listing;
+use chip_core::quirks::Quirks;
+use chip_core::state::STATE_SIZE;
+use chip_core::{Emulator, RAM_SIZE};
+
+fn emulator(listing: &str) -> Emulator {
+    let mut emulator = Emulator::new();
+    emulator.set_quirks(Quirks::chip8());
+    emulator.seed_rng(42);
+    emulator.load_rom(&parse_listing(listing).unwrap()).unwrap();
+    emulator
+}
+
+/// Everything visible from outside the emulator.
+fn fingerprint(emulator: &Emulator) -> Vec<u8> {
+    let mut data = emulator.read_ram(0, RAM_SIZE).unwrap().to_vec();
+    data.extend(emulator.registers());
+    data.extend(emulator.i_register().to_be_bytes());
+    data.extend(emulator.program_counter().to_be_bytes());
+    data.extend(
+        emulator
+            .stack()
+            .iter()
+            .flat_map(|address| address.to_be_bytes()),
+    );
+    data.extend([emulator.delay_timer(), emulator.sound_timer()]);
+    data.extend(emulator.get_display().iter().map(|pixel| *pixel as u8));
+    data.push(emulator.fault().is_some() as u8);
+    data
+}
+
+#[test]
+fn restored_state_plays_out_the_same() {
+    let listing = include_str!("roms/random.hex");
+    let mut original = emulator(listing);
+    original.keypress(3, true).unwrap();
+    for _ in 0..7 {
+        original.run_frame();
+    }
+    let saved = original.save_state();
+    assert_eq!(saved.len(), STATE_SIZE);
+
+    // A restored emulator keeps its own settings.
+    let mut restored = Emulator::new();
+    restored.set_quirks(Quirks::chip8());
+    restored.load_state(&saved).unwrap();
+    assert_eq!(restored.quirks(), Quirks::chip8());
+    assert_eq!(restored.save_state(), saved);
+
+    for frame in 0..60 {
+        original.run_frame();
+        restored.run_frame();
+        assert_eq!(
+            fingerprint(&restored),
+            fingerprint(&original),
+            "frame {}",
+            frame
+        );
+    }
+
+    // Loading rewinds.
+    original.load_state(&saved).unwrap();
+    assert_eq!(original.save_state(), saved);
+}
+
+#[test]
+fn faults_are_saved() {
+    let mut original = emulator("200: 00EE");
+    original.tick();
+    let mut restored = Emulator::new();
+    restored.load_state(&original.save_state()).unwrap();
+    assert_eq!(restored.fault(), original.fault());
+}
+
+#[test]
+fn bad_states_are_rejected() {
+    let mut emulator = emulator(include_str!("roms/draw.hex"));
+    emulator.run_frame();
+    let saved = emulator.save_state();
+    let before = fingerprint(&emulator);
+
+    let mut other = Emulator::new();
+    other.load_rom(&[0x12, 0x00]).unwrap();
+    other.tick();
+
+    let mut corrupt = |offset: usize, value: u8| {
+        let mut data = saved;
+        data[offset] = value;
+        other.load_state(&data).unwrap_err()
+    };
+    assert_eq!(corrupt(0, b'X'), StateError::NotAState);
+    assert_eq!(corrupt(4, 9), StateError::UnsupportedVersion(9));
+    // The program counter is just after the version.
+    assert_eq!(
+        corrupt(5, 0x10),
+        StateError::InvalidField("program counter")
+    );
+    // The fault kind is 5 bytes from the end.
+    assert_eq!(
+        corrupt(STATE_SIZE - 5, 4),
+        StateError::InvalidField("fault")
+    );
+    assert_eq!(
+        other.load_state(&saved[1..]).unwrap_err(),
+        StateError::WrongSize(STATE_SIZE - 1)
+    );
+    assert_eq!(other.program_counter(), 0x200);
+    assert_eq!(fingerprint(&emulator), before);
+}
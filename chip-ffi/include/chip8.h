@@ -0,0 +1,212 @@
+#ifndef CHIP8_H
+#define CHIP8_H
+
+/* Generated by cbindgen from chip-ffi, do not edit. Regenerate with `CHIP_BLESS=1 cargo test -p chip-ffi`. */
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+// The call succeeded.
+#define CHIP8_OK 0
+
+// A required pointer was null.
+#define CHIP8_NULL_POINTER -1
+
+// The ROM doesn't fit in RAM after the start address.
+#define CHIP8_ROM_TOO_LARGE -2
+
+// There is no key with that index.
+#define CHIP8_INVALID_KEY -3
+
+// The buffer is too small for a save state.
+#define CHIP8_BUFFER_TOO_SMALL -4
+
+// The data isn't a valid save state.
+#define CHIP8_INVALID_STATE -5
+
+// The ROM stopped with an error and nothing runs until a reset.
+#define CHIP8_STOPPED -6
+
+// Display width in pixels.
+#define CHIP8_SCREEN_WIDTH 64
+
+// Display height in pixels.
+#define CHIP8_SCREEN_HEIGHT 32
+
+// The size of a save state in bytes.
+#define CHIP8_STATE_SIZE 4428
+
+// An emulator, only ever handled through a pointer.
+typedef struct Chip8 Chip8;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Creates an emulator.
+//
+// #### Parameters:
+// - seed: Seeds the random numbers for CXNN, the same seed and inputs
+//   always play out the same way.
+//
+// #### Returns:
+// - A handle to free with `chip8_free`.
+//
+Chip8 *chip8_new(uint64_t seed);
+
+// Frees an emulator.
+//
+// #### Parameters:
+// - chip8: The handle from `chip8_new`, or null to do nothing.
+//
+// #### Safety
+// - The handle must not be used again afterwards.
+//
+void chip8_free(Chip8 *chip8);
+
+// Resets the machine, keeping the tick rate.
+//
+// #### Safety
+// - `chip8` must be null or a live handle from `chip8_new`.
+//
+int chip8_reset(Chip8 *chip8);
+
+// Loads a ROM image at the start address.
+//
+// #### Parameters:
+// - chip8: The emulator.
+// - data: The ROM bytes.
+// - len: The number of bytes.
+//
+// #### Returns:
+// - `CHIP8_ROM_TOO_LARGE` if it doesn't fit, in which case nothing is
+//   loaded.
+//
+// #### Safety
+// - `chip8` must be null or a live handle from `chip8_new`, and `data`
+//   must be null or point to `len` readable bytes.
+//
+int chip8_load_rom(Chip8 *chip8, const uint8_t *data, size_t len);
+
+// Runs one CPU cycle.
+//
+// #### Returns:
+// - `CHIP8_STOPPED` if the ROM has stopped with an error.
+//
+// #### Safety
+// - `chip8` must be null or a live handle from `chip8_new`.
+//
+int chip8_tick(Chip8 *chip8);
+
+// Ticks the delay and sound timers, once per 60 Hz frame.
+//
+// #### Safety
+// - `chip8` must be null or a live handle from `chip8_new`.
+//
+int chip8_timer_tick(Chip8 *chip8);
+
+// Emulates one 60 Hz frame.
+//
+// #### Returns:
+// - `CHIP8_STOPPED` if the ROM has stopped with an error.
+//
+// #### Safety
+// - `chip8` must be null or a live handle from `chip8_new`.
+//
+int chip8_run_frame(Chip8 *chip8);
+
+// Sets the number of CPU cycles run per frame.
+//
+// #### Safety
+// - `chip8` must be null or a live handle from `chip8_new`.
+//
+int chip8_set_ticks_per_frame(Chip8 *chip8, size_t ticks);
+
+// Presses or releases one key.
+//
+// #### Parameters:
+// - chip8: The emulator.
+// - key: The CHIP-8 key (0x0 to 0xF).
+// - pressed: Whether the key is held down.
+//
+// #### Safety
+// - `chip8` must be null or a live handle from `chip8_new`.
+//
+int chip8_set_key(Chip8 *chip8, uint8_t key, bool pressed);
+
+// Sets every key at once.
+//
+// #### Parameters:
+// - chip8: The emulator.
+// - keys: Bit N is set when key N is held.
+//
+// #### Safety
+// - `chip8` must be null or a live handle from `chip8_new`.
+//
+int chip8_set_keys(Chip8 *chip8, uint16_t keys);
+
+// Returns the display, one `bool` per pixel in row major order. The
+// pointer stays valid until the handle is freed and always shows the
+// current display.
+//
+// #### Parameters:
+// - chip8: The emulator.
+// - width: Set to the display width if not null.
+// - height: Set to the display height if not null.
+//
+// #### Returns:
+// - The pixels, or null if `chip8` is null.
+//
+// #### Safety
+// - `chip8` must be null or a live handle from `chip8_new`, `width` and
+//   `height` must be null or writable.
+//
+const bool *chip8_framebuffer(const Chip8 *chip8, size_t *width, size_t *height);
+
+// Returns whether the sound timer is running, i.e. the buzzer is on.
+//
+// #### Safety
+// - `chip8` must be null or a live handle from `chip8_new`.
+//
+bool chip8_sound_on(const Chip8 *chip8);
+
+// Saves the machine state.
+//
+// #### Parameters:
+// - chip8: The emulator.
+// - buffer: Receives `CHIP8_STATE_SIZE` bytes.
+// - len: The size of the buffer.
+//
+// #### Returns:
+// - `CHIP8_BUFFER_TOO_SMALL` if the state doesn't fit, in which case
+//   nothing is written.
+//
+// #### Safety
+// - `chip8` must be null or a live handle from `chip8_new`, and `buffer`
+//   must be null or point to `len` writable bytes.
+//
+int chip8_save_state(const Chip8 *chip8, uint8_t *buffer, size_t len);
+
+// Restores a state saved by `chip8_save_state`.
+//
+// #### Parameters:
+// - chip8: The emulator.
+// - data: The saved state.
+// - len: The number of bytes, `CHIP8_STATE_SIZE` for a valid state.
+//
+// #### Returns:
+// - `CHIP8_INVALID_STATE` if the data isn't a save state, in which case
+//   the emulator is unchanged.
+//
+// #### Safety
+// - `chip8` must be null or a live handle from `chip8_new`, and `data`
+//   must be null or point to `len` readable bytes.
+//
+int chip8_load_state(Chip8 *chip8, const uint8_t *data, size_t len);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* CHIP8_H */
@@ -0,0 +1,337 @@
+//! C API for embedding the emulator. Every function takes the opaque
+//! `Chip8` handle from `chip8_new` and returns one of the `CHIP8_*` status
+//! codes, so nothing needs to cross the boundary except plain integers and
+//! byte buffers.
+
+use std::ffi::c_int;
+
+use chip_core::state::STATE_SIZE;
+use chip_core::{Emulator, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+/// The call succeeded.
+pub const CHIP8_OK: c_int = 0;
+/// A required pointer was null.
+pub const CHIP8_NULL_POINTER: c_int = -1;
+/// The ROM doesn't fit in RAM after the start address.
+pub const CHIP8_ROM_TOO_LARGE: c_int = -2;
+/// There is no key with that index.
+pub const CHIP8_INVALID_KEY: c_int = -3;
+/// The buffer is too small for a save state.
+pub const CHIP8_BUFFER_TOO_SMALL: c_int = -4;
+/// The data isn't a valid save state.
+pub const CHIP8_INVALID_STATE: c_int = -5;
+/// The ROM stopped with an error and nothing runs until a reset.
+pub const CHIP8_STOPPED: c_int = -6;
+
+// The sizes are written out so cbindgen can put them in the header, the
+// assertions keep them in step with chip-core.
+
+/// Display width in pixels.
+pub const CHIP8_SCREEN_WIDTH: usize = 64;
+/// Display height in pixels.
+pub const CHIP8_SCREEN_HEIGHT: usize = 32;
+/// The size of a save state in bytes.
+pub const CHIP8_STATE_SIZE: usize = 4428;
+
+const _: () = assert!(CHIP8_SCREEN_WIDTH == SCREEN_WIDTH);
+const _: () = assert!(CHIP8_SCREEN_HEIGHT == SCREEN_HEIGHT);
+const _: () = assert!(CHIP8_STATE_SIZE == STATE_SIZE);
+
+/// An emulator, only ever handled through a pointer.
+pub struct Chip8 {
+    emulator: Emulator,
+}
+
+/// Turns the ROM's state after running into a status code.
+fn status(emulator: &Emulator) -> c_int {
+    match emulator.fault() {
+        Some(_) => CHIP8_STOPPED,
+        None => CHIP8_OK,
+    }
+}
+
+/// Creates an emulator.
+///
+/// #### Parameters:
+/// - seed: Seeds the random numbers for CXNN, the same seed and inputs
+///   always play out the same way.
+///
+/// #### Returns:
+/// - A handle to free with `chip8_free`.
+///
+#[no_mangle]
+pub extern "C" fn chip8_new(seed: u64) -> *mut Chip8 {
+    let emulator = Emulator::with_seed(seed);
+    Box::into_raw(Box::new(Chip8 { emulator }))
+}
+
+/// Frees an emulator.
+///
+/// #### Parameters:
+/// - chip8: The handle from `chip8_new`, or null to do nothing.
+///
+/// #### Safety
+/// - The handle must not be used again afterwards.
+///
+#[no_mangle]
+pub unsafe extern "C" fn chip8_free(chip8: *mut Chip8) {
+    if !chip8.is_null() {
+        drop(Box::from_raw(chip8));
+    }
+}
+
+/// Resets the machine, keeping the tick rate.
+///
+/// #### Safety
+/// - `chip8` must be null or a live handle from `chip8_new`.
+///
+#[no_mangle]
+pub unsafe extern "C" fn chip8_reset(chip8: *mut Chip8) -> c_int {
+    let Some(chip8) = chip8.as_mut() else {
+        return CHIP8_NULL_POINTER;
+    };
+    chip8.emulator.reset();
+    CHIP8_OK
+}
+
+/// Loads a ROM image at the start address.
+///
+/// #### Parameters:
+/// - chip8: The emulator.
+/// - data: The ROM bytes.
+/// - len: The number of bytes.
+///
+/// #### Returns:
+/// - `CHIP8_ROM_TOO_LARGE` if it doesn't fit, in which case nothing is
+///   loaded.
+///
+/// #### Safety
+/// - `chip8` must be null or a live handle from `chip8_new`, and `data`
+///   must be null or point to `len` readable bytes.
+///
+#[no_mangle]
+pub unsafe extern "C" fn chip8_load_rom(chip8: *mut Chip8, data: *const u8, len: usize) -> c_int {
+    let Some(chip8) = chip8.as_mut() else {
+        return CHIP8_NULL_POINTER;
+    };
+    if data.is_null() {
+        return CHIP8_NULL_POINTER;
+    }
+    let rom = std::slice::from_raw_parts(data, len);
+    match chip8.emulator.load_rom(rom) {
+        Ok(()) => CHIP8_OK,
+        Err(_) => CHIP8_ROM_TOO_LARGE,
+    }
+}
+
+/// Runs one CPU cycle.
+///
+/// #### Returns:
+/// - `CHIP8_STOPPED` if the ROM has stopped with an error.
+///
+/// #### Safety
+/// - `chip8` must be null or a live handle from `chip8_new`.
+///
+#[no_mangle]
+pub unsafe extern "C" fn chip8_tick(chip8: *mut Chip8) -> c_int {
+    let Some(chip8) = chip8.as_mut() else {
+        return CHIP8_NULL_POINTER;
+    };
+    chip8.emulator.tick();
+    status(&chip8.emulator)
+}
+
+/// Ticks the delay and sound timers, once per 60 Hz frame.
+///
+/// #### Safety
+/// - `chip8` must be null or a live handle from `chip8_new`.
+///
+#[no_mangle]
+pub unsafe extern "C" fn chip8_timer_tick(chip8: *mut Chip8) -> c_int {
+    let Some(chip8) = chip8.as_mut() else {
+        return CHIP8_NULL_POINTER;
+    };
+    chip8.emulator.timer_tick();
+    CHIP8_OK
+}
+
+/// Emulates one 60 Hz frame.
+///
+/// #### Returns:
+/// - `CHIP8_STOPPED` if the ROM has stopped with an error.
+///
+/// #### Safety
+/// - `chip8` must be null or a live handle from `chip8_new`.
+///
+#[no_mangle]
+pub unsafe extern "C" fn chip8_run_frame(chip8: *mut Chip8) -> c_int {
+    let Some(chip8) = chip8.as_mut() else {
+        return CHIP8_NULL_POINTER;
+    };
+    chip8.emulator.run_frame();
+    status(&chip8.emulator)
+}
+
+/// Sets the number of CPU cycles run per frame.
+///
+/// #### Safety
+/// - `chip8` must be null or a live handle from `chip8_new`.
+///
+#[no_mangle]
+pub unsafe extern "C" fn chip8_set_ticks_per_frame(chip8: *mut Chip8, ticks: usize) -> c_int {
+    let Some(chip8) = chip8.as_mut() else {
+        return CHIP8_NULL_POINTER;
+    };
+    chip8.emulator.set_ticks_per_frame(ticks);
+    CHIP8_OK
+}
+
+/// Presses or releases one key.
+///
+/// #### Parameters:
+/// - chip8: The emulator.
+/// - key: The CHIP-8 key (0x0 to 0xF).
+/// - pressed: Whether the key is held down.
+///
+/// #### Safety
+/// - `chip8` must be null or a live handle from `chip8_new`.
+///
+#[no_mangle]
+pub unsafe extern "C" fn chip8_set_key(chip8: *mut Chip8, key: u8, pressed: bool) -> c_int {
+    let Some(chip8) = chip8.as_mut() else {
+        return CHIP8_NULL_POINTER;
+    };
+    match chip8.emulator.keypress(key as usize, pressed) {
+        Ok(()) => CHIP8_OK,
+        Err(_) => CHIP8_INVALID_KEY,
+    }
+}
+
+/// Sets every key at once.
+///
+/// #### Parameters:
+/// - chip8: The emulator.
+/// - keys: Bit N is set when key N is held.
+///
+/// #### Safety
+/// - `chip8` must be null or a live handle from `chip8_new`.
+///
+#[no_mangle]
+pub unsafe extern "C" fn chip8_set_keys(chip8: *mut Chip8, keys: u16) -> c_int {
+    let Some(chip8) = chip8.as_mut() else {
+        return CHIP8_NULL_POINTER;
+    };
+    chip8.emulator.set_keys(keys);
+    CHIP8_OK
+}
+
+/// Returns the display, one `bool` per pixel in row major order. The
+/// pointer stays valid until the handle is freed and always shows the
+/// current display.
+///
+/// #### Parameters:
+/// - chip8: The emulator.
+/// - width: Set to the display width if not null.
+/// - height: Set to the display height if not null.
+///
+/// #### Returns:
+/// - The pixels, or null if `chip8` is null.
+///
+/// #### Safety
+/// - `chip8` must be null or a live handle from `chip8_new`, `width` and
+///   `height` must be null or writable.
+///
+#[no_mangle]
+pub unsafe extern "C" fn chip8_framebuffer(
+    chip8: *const Chip8,
+    width: *mut usize,
+    height: *mut usize,
+) -> *const bool {
+    let Some(chip8) = chip8.as_ref() else {
+        return std::ptr::null();
+    };
+    if let Some(width) = width.as_mut() {
+        *width = SCREEN_WIDTH;
+    }
+    if let Some(height) = height.as_mut() {
+        *height = SCREEN_HEIGHT;
+    }
+    chip8.emulator.get_display().as_ptr()
+}
+
+/// Returns whether the sound timer is running, i.e. the buzzer is on.
+///
+/// #### Safety
+/// - `chip8` must be null or a live handle from `chip8_new`.
+///
+#[no_mangle]
+pub unsafe extern "C" fn chip8_sound_on(chip8: *const Chip8) -> bool {
+    chip8
+        .as_ref()
+        .is_some_and(|chip8| chip8.emulator.sound_timer() > 0)
+}
+
+/// Saves the machine state.
+///
+/// #### Parameters:
+/// - chip8: The emulator.
+/// - buffer: Receives `CHIP8_STATE_SIZE` bytes.
+/// - len: The size of the buffer.
+///
+/// #### Returns:
+/// - `CHIP8_BUFFER_TOO_SMALL` if the state doesn't fit, in which case
+///   nothing is written.
+///
+/// #### Safety
+/// - `chip8` must be null or a live handle from `chip8_new`, and `buffer`
+///   must be null or point to `len` writable bytes.
+///
+#[no_mangle]
+pub unsafe extern "C" fn chip8_save_state(
+    chip8: *const Chip8,
+    buffer: *mut u8,
+    len: usize,
+) -> c_int {
+    let Some(chip8) = chip8.as_ref() else {
+        return CHIP8_NULL_POINTER;
+    };
+    if buffer.is_null() {
+        return CHIP8_NULL_POINTER;
+    }
+    if len < STATE_SIZE {
+        return CHIP8_BUFFER_TOO_SMALL;
+    }
+    let state = chip8.emulator.save_state();
+    std::ptr::copy_nonoverlapping(state.as_ptr(), buffer, STATE_SIZE);
+    CHIP8_OK
+}
+
+/// Restores a state saved by `chip8_save_state`.
+///
+/// #### Parameters:
+/// - chip8: The emulator.
+/// - data: The saved state.
+/// - len: The number of bytes, `CHIP8_STATE_SIZE` for a valid state.
+///
+/// #### Returns:
+/// - `CHIP8_INVALID_STATE` if the data isn't a save state, in which case
+///   the emulator is unchanged.
+///
+/// #### Safety
+/// - `chip8` must be null or a live handle from `chip8_new`, and `data`
+///   must be null or point to `len` readable bytes.
+///
+#[no_mangle]
+pub unsafe extern "C" fn chip8_load_state(chip8: *mut Chip8, data: *const u8, len: usize) -> c_int {
+    let Some(chip8) = chip8.as_mut() else {
+        return CHIP8_NULL_POINTER;
+    };
+    if data.is_null() {
+        return CHIP8_NULL_POINTER;
+    }
+    let state = std::slice::from_raw_parts(data, len);
+    match chip8.emulator.load_state(state) {
+        Ok(()) => CHIP8_OK,
+        Err(_) => CHIP8_INVALID_STATE,
+    }
+}
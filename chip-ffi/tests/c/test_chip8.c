@@ -0,0 +1,90 @@
+/* Exercises the C API through the generated header. Built and run by
+ * tests/c_api.rs, exits with a non-zero status on the first failure. */
+
+#include <stdio.h>
+#include <string.h>
+
+#include "chip8.h"
+
+#define CHECK(condition)                                                      \
+    do {                                                                      \
+        if (!(condition)) {                                                   \
+            fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__, __LINE__,  \
+                    #condition);                                              \
+            return 1;                                                         \
+        }                                                                     \
+    } while (0)
+
+/* 6005: V0 = 5, F029: I = font 5, D005: draw it at (5, 5), 1206: loop. */
+static const uint8_t DRAW_FIVE[] = {0x60, 0x05, 0xF0, 0x29, 0xD0, 0x05, 0x12, 0x06};
+
+/* F00A: wait for a key into V0, 6120: V1 = 20, F118: sound timer = V1,
+ * 120A: loop. */
+static const uint8_t BEEP_ON_KEY[] = {0xF0, 0x0A, 0x61, 0x20, 0xF1, 0x18, 0x12, 0x06};
+
+static size_t lit_pixels(const bool *pixels) {
+    size_t lit = 0;
+    for (size_t i = 0; i < CHIP8_SCREEN_WIDTH * CHIP8_SCREEN_HEIGHT; i++) {
+        lit += pixels[i];
+    }
+    return lit;
+}
+
+int main(void) {
+    Chip8 *chip8 = chip8_new(1);
+    CHECK(chip8 != NULL);
+
+    /* Errors come back as codes. */
+    static uint8_t too_large[4096];
+    CHECK(chip8_load_rom(chip8, too_large, sizeof too_large) == CHIP8_ROM_TOO_LARGE);
+    CHECK(chip8_load_rom(NULL, DRAW_FIVE, sizeof DRAW_FIVE) == CHIP8_NULL_POINTER);
+    CHECK(chip8_set_key(chip8, 16, true) == CHIP8_INVALID_KEY);
+
+    /* The framebuffer pointer shows the live display. */
+    CHECK(chip8_load_rom(chip8, DRAW_FIVE, sizeof DRAW_FIVE) == CHIP8_OK);
+    size_t width = 0, height = 0;
+    const bool *pixels = chip8_framebuffer(chip8, &width, &height);
+    CHECK(pixels != NULL);
+    CHECK(width == 64 && height == 32);
+    CHECK(lit_pixels(pixels) == 0);
+    CHECK(chip8_run_frame(chip8) == CHIP8_OK);
+    CHECK(lit_pixels(pixels) == 14);
+    CHECK(pixels[5 * width + 5]);
+
+    /* Keys and single steps. */
+    CHECK(chip8_reset(chip8) == CHIP8_OK);
+    CHECK(chip8_load_rom(chip8, BEEP_ON_KEY, sizeof BEEP_ON_KEY) == CHIP8_OK);
+    for (int i = 0; i < 100; i++) {
+        CHECK(chip8_tick(chip8) == CHIP8_OK);
+    }
+    CHECK(!chip8_sound_on(chip8));
+    CHECK(chip8_set_key(chip8, 0xA, true) == CHIP8_OK);
+    CHECK(chip8_run_frame(chip8) == CHIP8_OK);
+    CHECK(chip8_sound_on(chip8));
+    CHECK(chip8_set_keys(chip8, 0) == CHIP8_OK);
+
+    /* Save states rewind the machine. */
+    uint8_t state[CHIP8_STATE_SIZE];
+    CHECK(chip8_save_state(chip8, state, sizeof state - 1) == CHIP8_BUFFER_TOO_SMALL);
+    CHECK(chip8_save_state(chip8, state, sizeof state) == CHIP8_OK);
+    for (int i = 0; i < 40; i++) {
+        CHECK(chip8_timer_tick(chip8) == CHIP8_OK);
+    }
+    CHECK(!chip8_sound_on(chip8));
+    CHECK(chip8_load_state(chip8, state, sizeof state - 1) == CHIP8_INVALID_STATE);
+    CHECK(chip8_load_state(chip8, state, sizeof state) == CHIP8_OK);
+    CHECK(chip8_sound_on(chip8));
+
+    /* A ROM that stops reports it on every step. */
+    static const uint8_t RETURN[] = {0x00, 0xEE};
+    CHECK(chip8_reset(chip8) == CHIP8_OK);
+    CHECK(chip8_set_ticks_per_frame(chip8, 20) == CHIP8_OK);
+    CHECK(chip8_load_rom(chip8, RETURN, sizeof RETURN) == CHIP8_OK);
+    CHECK(chip8_run_frame(chip8) == CHIP8_STOPPED);
+    CHECK(chip8_tick(chip8) == CHIP8_STOPPED);
+
+    chip8_free(chip8);
+    chip8_free(NULL);
+    printf("ok\n");
+    return 0;
+}
@@ -0,0 +1,37 @@
+//! Builds `tests/c/test_chip8.c` against the generated header and the
+//! static library, then runs it.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The directory cargo builds the library into, next to this test.
+fn artifact_dir() -> PathBuf {
+    let exe = std::env::current_exe().unwrap();
+    exe.parent().unwrap().to_path_buf()
+}
+
+#[test]
+fn c_program_passes() {
+    let crate_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let lib_dir = artifact_dir();
+    let out = lib_dir.join("test_chip8");
+
+    let status = Command::new(std::env::var("CC").unwrap_or_else(|_| "cc".to_string()))
+        .args(["-std=c99", "-Wall", "-Wextra", "-Werror", "-o"])
+        .arg(&out)
+        .arg(crate_dir.join("tests/c/test_chip8.c"))
+        .arg("-I")
+        .arg(crate_dir.join("include"))
+        .arg(lib_dir.join("libchip_ffi.a"))
+        .args(["-lpthread", "-ldl", "-lm"])
+        .status()
+        .expect("a C compiler is needed to build the C test program");
+    assert!(status.success(), "the C test program didn't compile");
+
+    let output = Command::new(&out).output().unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
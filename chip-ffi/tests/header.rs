@@ -0,0 +1,26 @@
+//! Checks the committed header matches what cbindgen generates from the
+//! source. After changing the API, run `CHIP_BLESS=1 cargo test -p chip-ffi`
+//! to regenerate it.
+
+use std::path::Path;
+
+#[test]
+fn header_is_up_to_date() {
+    let crate_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let path = crate_dir.join("include/chip8.h");
+    let mut generated = Vec::new();
+    cbindgen::generate(crate_dir)
+        .expect("cbindgen failed")
+        .write(&mut generated);
+    let generated = String::from_utf8(generated).unwrap();
+
+    if std::env::var_os("CHIP_BLESS").is_some() {
+        std::fs::write(&path, &generated).unwrap();
+        return;
+    }
+    let committed = std::fs::read_to_string(&path).unwrap_or_default();
+    assert!(
+        committed == generated,
+        "include/chip8.h is out of date, run `CHIP_BLESS=1 cargo test -p chip-ffi`"
+    );
+}
@@ -0,0 +1,491 @@
+//! A libretro core, so RetroArch and other libretro frontends can run
+//! CHIP-8 ROMs. libretro drives the core through global functions, so the
+//! emulator and the frontend's callbacks live in statics.
+
+pub mod sys;
+
+use std::ffi::{c_char, c_uint, c_void, CStr};
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+
+use chip_core::display::{Framebuffer, Palette};
+use chip_core::keymap::{Keymap, KeymapOverrides};
+use chip_core::state::STATE_SIZE;
+use chip_core::{Emulator, NUM_KEYS, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+use sys::*;
+
+/// The rate the audio is generated at.
+pub const SAMPLE_RATE: usize = 44_100;
+/// Stereo audio frames sent each video frame.
+pub const AUDIO_FRAMES: usize = SAMPLE_RATE / 60;
+/// The pitch of the buzzer.
+const TONE_HZ: usize = 440;
+/// The amplitude of the buzzer's square wave.
+const VOLUME: i16 = 0x1000;
+
+/// The CHIP-8 key for each joypad button. The d-pad is on 2, 4, 6 and 8,
+/// which most games use for movement, and A is on 5, their usual action
+/// key.
+pub const JOYPAD_KEYS: [(c_uint, usize); NUM_KEYS] = [
+    (RETRO_DEVICE_ID_JOYPAD_UP, 0x2),
+    (RETRO_DEVICE_ID_JOYPAD_DOWN, 0x8),
+    (RETRO_DEVICE_ID_JOYPAD_LEFT, 0x4),
+    (RETRO_DEVICE_ID_JOYPAD_RIGHT, 0x6),
+    (RETRO_DEVICE_ID_JOYPAD_A, 0x5),
+    (RETRO_DEVICE_ID_JOYPAD_B, 0x0),
+    (RETRO_DEVICE_ID_JOYPAD_Y, 0x7),
+    (RETRO_DEVICE_ID_JOYPAD_X, 0x9),
+    (RETRO_DEVICE_ID_JOYPAD_L, 0x1),
+    (RETRO_DEVICE_ID_JOYPAD_R, 0x3),
+    (RETRO_DEVICE_ID_JOYPAD_SELECT, 0xC),
+    (RETRO_DEVICE_ID_JOYPAD_START, 0xD),
+    (RETRO_DEVICE_ID_JOYPAD_L2, 0xA),
+    (RETRO_DEVICE_ID_JOYPAD_R2, 0xB),
+    (RETRO_DEVICE_ID_JOYPAD_L3, 0xE),
+    (RETRO_DEVICE_ID_JOYPAD_R3, 0xF),
+];
+
+/// The keymap file read from the frontend's system directory, with the
+/// per-ROM layouts described by `chip_core::keymap::KeymapOverrides`.
+pub const KEYMAP_FILE: &str = "chip8_keymap.toml";
+
+/// The functions the frontend gives the core.
+struct Callbacks {
+    environment: Option<RetroEnvironment>,
+    video_refresh: Option<RetroVideoRefresh>,
+    audio_sample_batch: Option<RetroAudioSampleBatch>,
+    input_poll: Option<RetroInputPoll>,
+    input_state: Option<RetroInputState>,
+}
+
+/// A loaded game.
+struct Core {
+    emulator: Emulator,
+    /// Kept to reload after a reset, which clears RAM.
+    rom: Vec<u8>,
+    palette: Palette,
+    /// The libretro key code and CHIP-8 key of each key in the keymap.
+    keyboard: Vec<(c_uint, usize)>,
+    /// The display in XRGB8888.
+    video: [u32; SCREEN_WIDTH * SCREEN_HEIGHT],
+    /// Interleaved stereo samples for one frame.
+    audio: [i16; AUDIO_FRAMES * 2],
+    /// Samples generated so far, the position in the square wave.
+    audio_clock: usize,
+}
+
+impl Core {
+    /// Reads the held keys from the frontend's joypad and keyboard.
+    fn poll_input(&mut self, input_state: RetroInputState) {
+        let mut keys = 0u16;
+        for (button, key) in JOYPAD_KEYS {
+            // SAFETY: The frontend's callback, called as the API requires.
+            let held = unsafe { input_state(0, RETRO_DEVICE_JOYPAD, 0, button) } != 0;
+            keys |= (held as u16) << key;
+        }
+        for (code, key) in &self.keyboard {
+            // SAFETY: As above.
+            let held = unsafe { input_state(0, RETRO_DEVICE_KEYBOARD, 0, *code) } != 0;
+            keys |= (held as u16) << key;
+        }
+        self.emulator.set_keys(keys);
+    }
+
+    /// Converts the display to XRGB8888.
+    fn render_video(&mut self) {
+        for y in 0..SCREEN_HEIGHT {
+            for x in 0..SCREEN_WIDTH {
+                let color = self.palette.color(self.emulator.pixel(x, y));
+                self.video[x + SCREEN_WIDTH * y] =
+                    (color.r as u32) << 16 | (color.g as u32) << 8 | color.b as u32;
+            }
+        }
+    }
+
+    /// Fills a frame of audio, a square wave while the sound timer runs and
+    /// silence otherwise.
+    fn render_audio(&mut self) {
+        let sounding = self.emulator.sound_timer() > 0;
+        for frame in self.audio.chunks_mut(2) {
+            let high = (self.audio_clock * TONE_HZ * 2 / SAMPLE_RATE).is_multiple_of(2);
+            let sample = match (sounding, high) {
+                (false, _) => 0,
+                (true, true) => VOLUME,
+                (true, false) => -VOLUME,
+            };
+            frame.fill(sample);
+            self.audio_clock = (self.audio_clock + 1) % SAMPLE_RATE;
+        }
+    }
+}
+
+static CALLBACKS: Mutex<Callbacks> = Mutex::new(Callbacks {
+    environment: None,
+    video_refresh: None,
+    audio_sample_batch: None,
+    input_poll: None,
+    input_state: None,
+});
+
+static CORE: Mutex<Option<Core>> = Mutex::new(None);
+
+/// Locks a static, carrying on after a panic elsewhere since the data is
+/// still usable.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[no_mangle]
+pub extern "C" fn retro_api_version() -> c_uint {
+    RETRO_API_VERSION
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_environment(callback: RetroEnvironment) {
+    lock(&CALLBACKS).environment = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_video_refresh(callback: RetroVideoRefresh) {
+    lock(&CALLBACKS).video_refresh = Some(callback);
+}
+
+/// Unused, the audio is sent a frame at a time.
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample(_callback: RetroAudioSample) {}
+
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample_batch(callback: RetroAudioSampleBatch) {
+    lock(&CALLBACKS).audio_sample_batch = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_poll(callback: RetroInputPoll) {
+    lock(&CALLBACKS).input_poll = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_state(callback: RetroInputState) {
+    lock(&CALLBACKS).input_state = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_init() {}
+
+#[no_mangle]
+pub extern "C" fn retro_deinit() {
+    *lock(&CORE) = None;
+}
+
+/// Describes the core to the frontend.
+///
+/// #### Safety
+/// - `info` must be null or writable.
+///
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_info(info: *mut RetroSystemInfo) {
+    let Some(info) = info.as_mut() else {
+        return;
+    };
+    *info = RetroSystemInfo {
+        library_name: c"CHIP-8".as_ptr(),
+        library_version: concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr().cast(),
+        valid_extensions: c"ch8|c8".as_ptr(),
+        need_fullpath: false,
+        block_extract: false,
+    };
+}
+
+/// Describes the video and audio to the frontend.
+///
+/// #### Safety
+/// - `info` must be null or writable.
+///
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_av_info(info: *mut RetroSystemAvInfo) {
+    let Some(info) = info.as_mut() else {
+        return;
+    };
+    *info = RetroSystemAvInfo {
+        geometry: RetroGameGeometry {
+            base_width: SCREEN_WIDTH as c_uint,
+            base_height: SCREEN_HEIGHT as c_uint,
+            max_width: SCREEN_WIDTH as c_uint,
+            max_height: SCREEN_HEIGHT as c_uint,
+            aspect_ratio: SCREEN_WIDTH as f32 / SCREEN_HEIGHT as f32,
+        },
+        timing: RetroSystemTiming {
+            fps: 60.0,
+            sample_rate: SAMPLE_RATE as f64,
+        },
+    };
+}
+
+/// Every device is read the same way, so there's nothing to set up.
+#[no_mangle]
+pub extern "C" fn retro_set_controller_port_device(_port: c_uint, _device: c_uint) {}
+
+#[no_mangle]
+pub extern "C" fn retro_reset() {
+    if let Some(core) = lock(&CORE).as_mut() {
+        core.emulator.reset();
+        // The ROM fitted when the game was loaded.
+        let _ = core.emulator.load_rom(&core.rom);
+    }
+}
+
+/// Emulates one frame: reads the input, runs the emulator and sends the
+/// video and audio to the frontend.
+#[no_mangle]
+pub extern "C" fn retro_run() {
+    let (video_refresh, audio_sample_batch, input_poll, input_state) = {
+        let callbacks = lock(&CALLBACKS);
+        (
+            callbacks.video_refresh,
+            callbacks.audio_sample_batch,
+            callbacks.input_poll,
+            callbacks.input_state,
+        )
+    };
+    let mut core = lock(&CORE);
+    let Some(core) = core.as_mut() else {
+        return;
+    };
+
+    if let Some(input_poll) = input_poll {
+        // SAFETY: The frontend's callback, called as the API requires.
+        unsafe { input_poll() };
+    }
+    if let Some(input_state) = input_state {
+        core.poll_input(input_state);
+    }
+
+    core.emulator.run_frame();
+
+    core.render_video();
+    if let Some(video_refresh) = video_refresh {
+        // SAFETY: The buffer holds `SCREEN_HEIGHT` rows of `SCREEN_WIDTH`
+        // pixels, as described.
+        unsafe {
+            video_refresh(
+                core.video.as_ptr().cast(),
+                SCREEN_WIDTH as c_uint,
+                SCREEN_HEIGHT as c_uint,
+                SCREEN_WIDTH * size_of::<u32>(),
+            )
+        };
+    }
+
+    core.render_audio();
+    if let Some(audio_sample_batch) = audio_sample_batch {
+        let mut sent = 0;
+        while sent < AUDIO_FRAMES {
+            let rest = &core.audio[sent * 2..];
+            // SAFETY: `rest` holds `AUDIO_FRAMES - sent` stereo frames.
+            let taken = unsafe { audio_sample_batch(rest.as_ptr(), AUDIO_FRAMES - sent) };
+            if taken == 0 {
+                break;
+            }
+            sent += taken;
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_serialize_size() -> usize {
+    STATE_SIZE
+}
+
+/// Saves the machine state.
+///
+/// #### Safety
+/// - `data` must be null or point to `size` writable bytes.
+///
+#[no_mangle]
+pub unsafe extern "C" fn retro_serialize(data: *mut c_void, size: usize) -> bool {
+    let core = lock(&CORE);
+    let Some(core) = core.as_ref() else {
+        return false;
+    };
+    if data.is_null() || size < STATE_SIZE {
+        return false;
+    }
+    let state = core.emulator.save_state();
+    std::ptr::copy_nonoverlapping(state.as_ptr(), data.cast(), STATE_SIZE);
+    true
+}
+
+/// Restores a state saved by `retro_serialize`.
+///
+/// #### Safety
+/// - `data` must be null or point to `size` readable bytes.
+///
+#[no_mangle]
+pub unsafe extern "C" fn retro_unserialize(data: *const c_void, size: usize) -> bool {
+    let mut core = lock(&CORE);
+    let Some(core) = core.as_mut() else {
+        return false;
+    };
+    if data.is_null() {
+        return false;
+    }
+    let state = std::slice::from_raw_parts(data.cast(), size);
+    core.emulator.load_state(state).is_ok()
+}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_reset() {}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_set(_index: c_uint, _enabled: bool, _code: *const c_char) {}
+
+/// Loads a ROM from memory.
+///
+/// #### Returns:
+/// - Whether the game loaded. It fails if the frontend can't take
+///   XRGB8888 video or the ROM doesn't fit in RAM.
+///
+/// #### Safety
+/// - `game` must be null or point to a game info whose `data` holds `size`
+///   readable bytes.
+///
+#[no_mangle]
+pub unsafe extern "C" fn retro_load_game(game: *const RetroGameInfo) -> bool {
+    let Some(game) = game.as_ref() else {
+        return false;
+    };
+    if game.data.is_null() {
+        return false;
+    }
+    let environment = lock(&CALLBACKS).environment;
+    let Some(environment) = environment else {
+        return false;
+    };
+    let mut format = RETRO_PIXEL_FORMAT_XRGB8888;
+    if !environment(
+        RETRO_ENVIRONMENT_SET_PIXEL_FORMAT,
+        (&mut format as *mut c_uint).cast(),
+    ) {
+        return false;
+    }
+
+    let rom = std::slice::from_raw_parts(game.data.cast::<u8>(), game.size).to_vec();
+    let mut emulator = Emulator::new();
+    if emulator.load_rom(&rom).is_err() {
+        return false;
+    }
+    let keymap = load_keymap(environment, &rom);
+    *lock(&CORE) = Some(Core {
+        emulator,
+        rom,
+        palette: Palette::default(),
+        keyboard: (0..NUM_KEYS)
+            .flat_map(|key| {
+                let codes = keymap.host_keys(key).into_iter().filter_map(retro_key);
+                codes.map(move |code| (code, key))
+            })
+            .collect(),
+        video: [0; SCREEN_WIDTH * SCREEN_HEIGHT],
+        audio: [0; AUDIO_FRAMES * 2],
+        audio_clock: 0,
+    });
+    true
+}
+
+/// Returns the ROM's keymap from the keymap file in the frontend's system
+/// directory, or the qwerty layout if there's no file or it can't be read.
+///
+/// #### Safety
+/// - `environment` must be the frontend's environment callback.
+///
+unsafe fn load_keymap(environment: RetroEnvironment, rom: &[u8]) -> Keymap {
+    let mut directory: *const c_char = std::ptr::null();
+    if !environment(
+        RETRO_ENVIRONMENT_GET_SYSTEM_DIRECTORY,
+        (&mut directory as *mut *const c_char).cast(),
+    ) || directory.is_null()
+    {
+        return Keymap::qwerty();
+    }
+    let Ok(directory) = CStr::from_ptr(directory).to_str() else {
+        return Keymap::qwerty();
+    };
+    std::fs::read_to_string(Path::new(directory).join(KEYMAP_FILE))
+        .ok()
+        .and_then(|text| KeymapOverrides::parse(&text).ok())
+        .map_or_else(Keymap::qwerty, |overrides| overrides.keymap(rom).clone())
+}
+
+/// Returns the libretro key code for a host key's `KeyboardEvent.code`, if
+/// libretro has the key.
+fn retro_key(host: &str) -> Option<c_uint> {
+    let single = |name: &str| match name.as_bytes() {
+        [c] => Some(*c),
+        _ => None,
+    };
+    if let Some(letter) = host.strip_prefix("Key").and_then(single) {
+        return letter
+            .is_ascii_uppercase()
+            .then_some(letter.to_ascii_lowercase() as c_uint);
+    }
+    if let Some(digit) = host.strip_prefix("Digit").and_then(single) {
+        return digit.is_ascii_digit().then_some(digit as c_uint);
+    }
+    if let Some(digit) = host.strip_prefix("Numpad").and_then(single) {
+        return digit
+            .is_ascii_digit()
+            .then_some(RETROK_KP0 + (digit - b'0') as c_uint);
+    }
+    match host {
+        "Backspace" => Some(RETROK_BACKSPACE),
+        "Tab" => Some(RETROK_TAB),
+        "Enter" => Some(RETROK_RETURN),
+        "Escape" => Some(RETROK_ESCAPE),
+        "Space" => Some(RETROK_SPACE),
+        "NumpadDecimal" => Some(RETROK_KP_PERIOD),
+        "NumpadDivide" => Some(RETROK_KP_DIVIDE),
+        "NumpadMultiply" => Some(RETROK_KP_MULTIPLY),
+        "NumpadSubtract" => Some(RETROK_KP_MINUS),
+        "NumpadAdd" => Some(RETROK_KP_PLUS),
+        "NumpadEnter" => Some(RETROK_KP_ENTER),
+        "ArrowUp" => Some(RETROK_UP),
+        "ArrowDown" => Some(RETROK_DOWN),
+        "ArrowRight" => Some(RETROK_RIGHT),
+        "ArrowLeft" => Some(RETROK_LEFT),
+        _ => None,
+    }
+}
+
+/// Special game types aren't supported.
+#[no_mangle]
+pub extern "C" fn retro_load_game_special(
+    _game_type: c_uint,
+    _info: *const RetroGameInfo,
+    _num_info: usize,
+) -> bool {
+    false
+}
+
+#[no_mangle]
+pub extern "C" fn retro_unload_game() {
+    *lock(&CORE) = None;
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_region() -> c_uint {
+    RETRO_REGION_NTSC
+}
+
+/// RAM isn't exposed to the frontend.
+#[no_mangle]
+pub extern "C" fn retro_get_memory_data(_id: c_uint) -> *mut c_void {
+    std::ptr::null_mut()
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_size(_id: c_uint) -> usize {
+    0
+}
@@ -0,0 +1,243 @@
+//! A minimal libretro frontend: loads the built core as a shared library,
+//! the way RetroArch does, and runs a ROM through the C ABI.
+
+use std::ffi::{c_uint, c_void, CStr};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use chip8_libretro::sys::*;
+use chip_core::harness::parse_listing;
+use libloading::{Library, Symbol};
+
+/// Waits for key 5, then draws the 8 sprite in the top left corner and
+/// beeps.
+const ROM: &str = "
+200: F50A   ; V5 = key
+202: 6008   ; V0 = 8
+204: F029   ; I = sprite for V0
+206: 6100   ; V1 = 0
+208: D115   ; draw at (V1, V1)
+20A: 623C   ; V2 = 60
+20C: F218   ; sound timer = V2
+20E: 120E   ; loop forever
+";
+
+/// What the core has sent to the frontend, and the input to give it.
+struct Frontend {
+    pixel_format: Option<c_uint>,
+    video: Vec<u32>,
+    geometry: (c_uint, c_uint, usize),
+    audio: Vec<i16>,
+    joypad: u16,
+    keyboard: Vec<c_uint>,
+}
+
+static FRONTEND: Mutex<Frontend> = Mutex::new(Frontend {
+    pixel_format: None,
+    video: Vec::new(),
+    geometry: (0, 0, 0),
+    audio: Vec::new(),
+    joypad: 0,
+    keyboard: Vec::new(),
+});
+
+unsafe extern "C" fn environment(cmd: c_uint, data: *mut c_void) -> bool {
+    match cmd {
+        RETRO_ENVIRONMENT_SET_PIXEL_FORMAT => {
+            FRONTEND.lock().unwrap().pixel_format = Some(*data.cast::<c_uint>());
+            true
+        }
+        _ => false,
+    }
+}
+
+unsafe extern "C" fn video_refresh(
+    data: *const c_void,
+    width: c_uint,
+    height: c_uint,
+    pitch: usize,
+) {
+    let mut frontend = FRONTEND.lock().unwrap();
+    frontend.geometry = (width, height, pitch);
+    let pixels = std::slice::from_raw_parts(data.cast::<u32>(), pitch / 4 * height as usize);
+    frontend.video = pixels.to_vec();
+}
+
+unsafe extern "C" fn audio_sample(_left: i16, _right: i16) {
+    panic!("the core sends audio in batches");
+}
+
+unsafe extern "C" fn audio_sample_batch(data: *const i16, frames: usize) -> usize {
+    let samples = std::slice::from_raw_parts(data, frames * 2);
+    FRONTEND.lock().unwrap().audio.extend_from_slice(samples);
+    frames
+}
+
+unsafe extern "C" fn input_poll() {}
+
+unsafe extern "C" fn input_state(port: c_uint, device: c_uint, _index: c_uint, id: c_uint) -> i16 {
+    let frontend = FRONTEND.lock().unwrap();
+    let pressed = match (port, device) {
+        (0, RETRO_DEVICE_JOYPAD) => frontend.joypad & (1 << id) != 0,
+        (0, RETRO_DEVICE_KEYBOARD) => frontend.keyboard.contains(&id),
+        _ => false,
+    };
+    pressed as i16
+}
+
+/// The core, loaded from the shared library cargo built next to this test.
+struct Core {
+    library: Library,
+}
+
+impl Core {
+    fn load() -> Self {
+        let exe = std::env::current_exe().unwrap();
+        let path: PathBuf = exe
+            .parent()
+            .unwrap()
+            .join(libloading::library_filename("chip8_libretro"));
+        // SAFETY: The core runs no code when it's loaded.
+        let library = unsafe { Library::new(&path) }.expect("the core should be built");
+        Self { library }
+    }
+
+    fn get<T>(&self, name: &str) -> Symbol<'_, T> {
+        // SAFETY: Each call site gives the type from libretro.h.
+        unsafe { self.library.get(name.as_bytes()) }
+            .unwrap_or_else(|_| panic!("the core doesn't export {}", name))
+    }
+
+    fn call(&self, name: &str) {
+        // SAFETY: Takes nothing and returns nothing.
+        unsafe { self.get::<unsafe extern "C" fn()>(name)() }
+    }
+
+    fn run_frames(&self, count: usize) {
+        for _ in 0..count {
+            self.call("retro_run");
+        }
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        // SAFETY: Types from libretro.h, and the buffer is the size asked for.
+        unsafe {
+            let size = self.get::<unsafe extern "C" fn() -> usize>("retro_serialize_size")();
+            let mut state = vec![0; size];
+            let serialize =
+                self.get::<unsafe extern "C" fn(*mut c_void, usize) -> bool>("retro_serialize");
+            assert!(serialize(state.as_mut_ptr().cast(), size));
+            state
+        }
+    }
+
+    fn unserialize(&self, state: &[u8]) -> bool {
+        // SAFETY: Type from libretro.h, and the buffer is `state.len()` bytes.
+        unsafe {
+            self.get::<unsafe extern "C" fn(*const c_void, usize) -> bool>("retro_unserialize")(
+                state.as_ptr().cast(),
+                state.len(),
+            )
+        }
+    }
+}
+
+fn lit(video: &[u32]) -> usize {
+    video.iter().filter(|pixel| **pixel != 0).count()
+}
+
+#[test]
+fn core_runs_through_the_c_abi() {
+    let core = Core::load();
+
+    // SAFETY: The types are from libretro.h and the pointers are valid for
+    // each call.
+    unsafe {
+        assert_eq!(
+            core.get::<unsafe extern "C" fn() -> c_uint>("retro_api_version")(),
+            RETRO_API_VERSION
+        );
+        let mut info: RetroSystemInfo = std::mem::zeroed();
+        core.get::<unsafe extern "C" fn(*mut RetroSystemInfo)>("retro_get_system_info")(&mut info);
+        assert_eq!(CStr::from_ptr(info.library_name), c"CHIP-8");
+        assert!(!info.need_fullpath);
+
+        core.get::<unsafe extern "C" fn(RetroEnvironment)>("retro_set_environment")(environment);
+        core.get::<unsafe extern "C" fn(RetroVideoRefresh)>("retro_set_video_refresh")(
+            video_refresh,
+        );
+        core.get::<unsafe extern "C" fn(RetroAudioSample)>("retro_set_audio_sample")(audio_sample);
+        core.get::<unsafe extern "C" fn(RetroAudioSampleBatch)>("retro_set_audio_sample_batch")(
+            audio_sample_batch,
+        );
+        core.get::<unsafe extern "C" fn(RetroInputPoll)>("retro_set_input_poll")(input_poll);
+        core.get::<unsafe extern "C" fn(RetroInputState)>("retro_set_input_state")(input_state);
+        core.call("retro_init");
+
+        let rom = parse_listing(ROM).unwrap();
+        let game = RetroGameInfo {
+            path: std::ptr::null(),
+            data: rom.as_ptr().cast(),
+            size: rom.len(),
+            meta: std::ptr::null(),
+        };
+        assert!(core
+            .get::<unsafe extern "C" fn(*const RetroGameInfo) -> bool>(
+                "retro_load_game"
+            )(&game));
+
+        let mut av_info: RetroSystemAvInfo = std::mem::zeroed();
+        core.get::<unsafe extern "C" fn(*mut RetroSystemAvInfo)>("retro_get_system_av_info")(
+            &mut av_info,
+        );
+        assert_eq!(av_info.geometry.base_width, 64);
+        assert_eq!(av_info.geometry.base_height, 32);
+        assert_eq!(av_info.timing.fps, 60.0);
+    }
+    assert_eq!(
+        FRONTEND.lock().unwrap().pixel_format,
+        Some(RETRO_PIXEL_FORMAT_XRGB8888)
+    );
+
+    // Nothing happens until key 5 is pressed.
+    core.run_frames(3);
+    {
+        let mut frontend = FRONTEND.lock().unwrap();
+        assert_eq!(frontend.geometry, (64, 32, 64 * 4));
+        assert_eq!(lit(&frontend.video), 0);
+        assert_eq!(frontend.audio.len(), 3 * 735 * 2);
+        assert!(frontend.audio.iter().all(|sample| *sample == 0));
+        frontend.audio.clear();
+        // A is mapped to key 5.
+        frontend.joypad = 1 << RETRO_DEVICE_ID_JOYPAD_A;
+    }
+    core.run_frames(2);
+    {
+        let mut frontend = FRONTEND.lock().unwrap();
+        // The 8 sprite has 16 lit pixels, in white.
+        assert_eq!(lit(&frontend.video), 16);
+        assert_eq!(frontend.video[0], 0x00FF_FFFF);
+        assert!(frontend.audio.iter().any(|sample| *sample != 0));
+        frontend.joypad = 0;
+    }
+
+    // A restored state plays out the same.
+    let state = core.serialize();
+    core.run_frames(60);
+    let after = FRONTEND.lock().unwrap().video.clone();
+    assert!(core.unserialize(&state));
+    core.run_frames(60);
+    assert_eq!(FRONTEND.lock().unwrap().video, after);
+    assert!(!core.unserialize(&state[1..]));
+
+    // A reset reloads the ROM, and the keyboard works too: W is key 5.
+    core.call("retro_reset");
+    core.run_frames(1);
+    assert_eq!(lit(&FRONTEND.lock().unwrap().video), 0);
+    FRONTEND.lock().unwrap().keyboard = vec![b'w' as c_uint];
+    core.run_frames(2);
+    assert_eq!(lit(&FRONTEND.lock().unwrap().video), 16);
+
+    core.call("retro_unload_game");
+    core.call("retro_deinit");
+}
@@ -0,0 +1,261 @@
+use chip_core::env::EnvConfig;
+use chip_core::state::STATE_SIZE;
+use chip_core::{RAM_SIZE, SCREEN_HEIGHT, SCREEN_WIDTH};
+use numpy::ndarray::{ArrayView1, ArrayView2};
+use numpy::npyffi::flags::NPY_ARRAY_WRITEABLE;
+use numpy::{PyArray1, PyArray2, PyArrayMethods, PyUntypedArrayMethods};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+/// Turns a Rust error into a Python `ValueError` with the same message.
+fn value_error(error: impl std::fmt::Display) -> PyErr {
+    PyValueError::new_err(error.to_string())
+}
+
+/// Marks a view of the machine state as read-only, so Python can't write
+/// behind the emulator's back.
+fn read_only<'py, A: PyUntypedArrayMethods<'py>>(array: A) -> A {
+    // SAFETY: The array was just created and nothing else holds it yet.
+    unsafe { (*array.as_array_ptr()).flags &= !NPY_ARRAY_WRITEABLE };
+    array
+}
+
+/// A CHIP-8 emulator.
+///
+/// The `screen`, `ram` and `registers` arrays are views of the live machine
+/// state rather than copies, so they change as the emulator runs. Call
+/// `.copy()` on them to keep a snapshot, and don't read them from another
+/// thread while this one is stepping frames.
+#[pyclass(module = "chip8")]
+struct Emulator {
+    emulator: chip_core::Emulator,
+}
+
+#[pymethods]
+impl Emulator {
+    /// Creates an emulator, seeding CXNN's random numbers from `seed` if
+    /// given or from the operating system otherwise.
+    #[new]
+    #[pyo3(signature = (seed=None))]
+    fn new(seed: Option<u64>) -> Self {
+        let mut emulator = chip_core::Emulator::new();
+        if let Some(seed) = seed {
+            emulator.seed_rng(seed);
+        }
+        Self { emulator }
+    }
+
+    /// Resets the machine, keeping the tick rate.
+    fn reset(&mut self) {
+        self.emulator.reset();
+    }
+
+    /// Loads a ROM at 0x200, raising `ValueError` if it doesn't fit, and
+    /// sets the quirks and tick rate the ROM database has for it.
+    fn load_rom(&mut self, rom: &[u8]) -> PyResult<()> {
+        self.emulator.load_rom(rom).map_err(value_error)
+    }
+
+    /// Presses or releases one key (0 to 15).
+    fn keypress(&mut self, key: usize, pressed: bool) -> PyResult<()> {
+        self.emulator.keypress(key, pressed).map_err(value_error)
+    }
+
+    /// Sets every key at once from a bit mask, bit N is key N.
+    fn set_keys(&mut self, keys: u16) {
+        self.emulator.set_keys(keys);
+    }
+
+    /// Runs one CPU cycle.
+    fn tick(&mut self) {
+        self.emulator.tick();
+    }
+
+    /// Ticks the delay and sound timers, once per 60 Hz frame.
+    fn timer_tick(&mut self) {
+        self.emulator.timer_tick();
+    }
+
+    /// Runs one 60 Hz frame, without holding the GIL.
+    fn run_frame(&mut self, py: Python<'_>) {
+        let emulator = &mut self.emulator;
+        py.detach(|| emulator.run_frame());
+    }
+
+    /// Runs `count` frames, without holding the GIL.
+    fn run_frames(&mut self, py: Python<'_>, count: usize) {
+        let emulator = &mut self.emulator;
+        py.detach(|| {
+            for _ in 0..count {
+                emulator.run_frame();
+            }
+        });
+    }
+
+    /// The display as a read-only (32, 64) bool array view.
+    #[getter]
+    fn screen(slf: Bound<'_, Self>) -> Bound<'_, PyArray2<bool>> {
+        let this = slf.borrow();
+        let pixels = this.emulator.get_display();
+        let view = ArrayView2::from_shape((SCREEN_HEIGHT, SCREEN_WIDTH), pixels)
+            .expect("the display is SCREEN_HEIGHT rows of SCREEN_WIDTH pixels");
+        // SAFETY: The display is an array inside the emulator, which lives as
+        // long as the Python object set as the array's base and is never
+        // reallocated.
+        read_only(unsafe { PyArray2::borrow_from_array(&view, slf.clone().into_any()) })
+    }
+
+    /// The 4 KB of RAM as a read-only uint8 array view.
+    #[getter]
+    fn ram(slf: Bound<'_, Self>) -> PyResult<Bound<'_, PyArray1<u8>>> {
+        let this = slf.borrow();
+        let ram = this.emulator.read_ram(0, RAM_SIZE).map_err(value_error)?;
+        let view = ArrayView1::from(ram);
+        // SAFETY: As for `screen`.
+        Ok(read_only(unsafe {
+            PyArray1::borrow_from_array(&view, slf.clone().into_any())
+        }))
+    }
+
+    /// The V registers as a read-only uint8 array view.
+    #[getter]
+    fn registers(slf: Bound<'_, Self>) -> Bound<'_, PyArray1<u8>> {
+        let this = slf.borrow();
+        let view = ArrayView1::from(this.emulator.registers());
+        // SAFETY: As for `screen`.
+        read_only(unsafe { PyArray1::borrow_from_array(&view, slf.clone().into_any()) })
+    }
+
+    /// Writes bytes to RAM, raising `ValueError` if they don't fit.
+    fn write_ram(&mut self, address: usize, data: &[u8]) -> PyResult<()> {
+        self.emulator.write_ram(address, data).map_err(value_error)
+    }
+
+    /// Sets one V register.
+    fn set_register(&mut self, idx: usize, value: u8) -> PyResult<()> {
+        self.emulator.set_register(idx, value).map_err(value_error)
+    }
+
+    #[getter]
+    fn program_counter(&self) -> u16 {
+        self.emulator.program_counter()
+    }
+
+    #[getter]
+    fn i_register(&self) -> u16 {
+        self.emulator.i_register()
+    }
+
+    #[getter]
+    fn delay_timer(&self) -> u8 {
+        self.emulator.delay_timer()
+    }
+
+    #[getter]
+    fn sound_timer(&self) -> u8 {
+        self.emulator.sound_timer()
+    }
+
+    /// CPU cycles per frame.
+    #[getter]
+    fn ticks_per_frame(&self) -> usize {
+        self.emulator.ticks_per_frame()
+    }
+
+    #[setter]
+    fn set_ticks_per_frame(&mut self, ticks: usize) {
+        self.emulator.set_ticks_per_frame(ticks);
+    }
+
+    /// Why the ROM stopped, or None while it's running.
+    #[getter]
+    fn fault(&self) -> Option<String> {
+        self.emulator.fault().map(|fault| fault.to_string())
+    }
+
+    /// Captures the whole machine as `STATE_SIZE` bytes.
+    fn save_state<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.emulator.save_state())
+    }
+
+    /// Restores a state from `save_state`, raising `ValueError` if the
+    /// bytes aren't one.
+    fn load_state(&mut self, state: &[u8]) -> PyResult<()> {
+        self.emulator.load_state(state).map_err(value_error)
+    }
+}
+
+/// A game wrapped up for reinforcement learning, in the style of a Gym
+/// environment. `config` is the text of a game config file, see
+/// `chip_core::env::EnvConfig`.
+#[pyclass(module = "chip8")]
+struct Env {
+    env: chip_core::env::Env,
+}
+
+#[pymethods]
+impl Env {
+    #[new]
+    #[pyo3(signature = (rom, config="", seed=0))]
+    fn new(rom: &[u8], config: &str, seed: u64) -> PyResult<Self> {
+        let config = EnvConfig::parse(config).map_err(value_error)?;
+        let env = chip_core::env::Env::new(rom, config, seed).map_err(value_error)?;
+        Ok(Self { env })
+    }
+
+    /// The number of actions, which are numbered from 0.
+    #[getter]
+    fn action_count(&self) -> usize {
+        self.env.action_count()
+    }
+
+    /// Starts a new episode and returns the first observation, a (32, 64)
+    /// bool array.
+    fn reset<'py>(&mut self, py: Python<'py>) -> Bound<'py, PyArray2<bool>> {
+        let observation = self.env.reset();
+        observe(py, &observation.screen)
+    }
+
+    /// Plays an action and returns `(observation, reward, done)`. The
+    /// frames run without holding the GIL.
+    fn step<'py>(
+        &mut self,
+        py: Python<'py>,
+        action: usize,
+    ) -> PyResult<(Bound<'py, PyArray2<bool>>, f32, bool)> {
+        if action >= self.env.action_count() {
+            return Err(PyValueError::new_err(format!("no action {}", action)));
+        }
+        let env = &mut self.env;
+        let (observation, reward, done) = py.detach(|| env.step(action));
+        Ok((observe(py, &observation.screen), reward, done))
+    }
+
+    /// Returns a copy of the machine, for reading its RAM or registers.
+    fn emulator(&self) -> Emulator {
+        let source = self.env.emulator();
+        let mut emulator = chip_core::Emulator::new();
+        emulator.set_ticks_per_frame(source.ticks_per_frame());
+        emulator.set_quirks(source.quirks());
+        // A state saved by an emulator always loads.
+        let _ = emulator.load_state(&source.save_state());
+        Emulator { emulator }
+    }
+}
+
+/// Copies an observation into a new (32, 64) array.
+fn observe<'py>(py: Python<'py>, screen: &[bool]) -> Bound<'py, PyArray2<bool>> {
+    PyArray1::from_slice(py, screen)
+        .reshape([SCREEN_HEIGHT, SCREEN_WIDTH])
+        .expect("the display is SCREEN_HEIGHT rows of SCREEN_WIDTH pixels")
+}
+
+/// Python bindings for the CHIP-8 emulator.
+#[pymodule]
+fn chip8(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Emulator>()?;
+    m.add_class::<Env>()?;
+    m.add("STATE_SIZE", STATE_SIZE)?;
+    Ok(())
+}
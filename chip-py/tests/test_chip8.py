@@ -0,0 +1,139 @@
+"""Tests of the Python bindings. Build them first with `maturin develop`."""
+
+import threading
+
+import numpy as np
+import pytest
+
+import chip8
+
+# 6005: V0 = 5, F029: I = font character V0, D005: draw it at (V0, V0),
+# 1206: loop forever.
+DRAW_FIVE = bytes([0x60, 0x05, 0xF0, 0x29, 0xD0, 0x05, 0x12, 0x06])
+
+# 7101: V1 += 1, 1200: jump back.
+COUNTER = bytes([0x71, 0x01, 0x12, 0x00])
+
+
+def test_screen_is_a_live_view():
+    emulator = chip8.Emulator(seed=1)
+    emulator.load_rom(DRAW_FIVE)
+    screen = emulator.screen
+    assert screen.shape == (32, 64)
+    assert screen.dtype == np.bool_
+    assert not screen.any()
+
+    emulator.run_frame()
+    # The top row of the font's 5 is 1111 at (5, 5).
+    assert screen[5, 5:9].all()
+    assert screen.sum() == 14
+    with pytest.raises(ValueError):
+        screen[0, 0] = True
+
+
+def test_ram_and_registers():
+    emulator = chip8.Emulator()
+    emulator.load_rom(COUNTER)
+    ram = emulator.ram
+    assert ram.shape == (4096,)
+    assert ram.dtype == np.uint8
+    assert bytes(ram[0x200:0x204]) == COUNTER
+
+    emulator.write_ram(0x300, b"\x12\x34")
+    assert list(ram[0x300:0x302]) == [0x12, 0x34]
+    for _ in range(4):
+        emulator.tick()
+    assert emulator.registers[1] == 2
+    assert emulator.program_counter == 0x200
+
+    with pytest.raises(ValueError):
+        emulator.write_ram(0xFFF, b"\x00\x00")
+    with pytest.raises(ValueError):
+        emulator.load_rom(bytes(4096))
+
+
+def test_keys_and_timers():
+    emulator = chip8.Emulator()
+    # F00A: wait for a key into V0, then loop.
+    emulator.load_rom(bytes([0xF0, 0x0A, 0x12, 0x02]))
+    emulator.run_frames(2)
+    assert emulator.program_counter == 0x200
+    emulator.keypress(7, True)
+    emulator.tick()
+    assert emulator.registers[0] == 7
+    emulator.set_keys(0)
+    with pytest.raises(ValueError):
+        emulator.keypress(16, True)
+
+    # 6003: V0 = 3, F015: delay timer = V0.
+    emulator.reset()
+    emulator.load_rom(bytes([0x60, 0x03, 0xF0, 0x15, 0x12, 0x04]))
+    emulator.tick()
+    emulator.tick()
+    assert emulator.delay_timer == 3
+    emulator.timer_tick()
+    assert emulator.delay_timer == 2
+    emulator.ticks_per_frame = 20
+    assert emulator.ticks_per_frame == 20
+
+
+def test_save_states():
+    emulator = chip8.Emulator(seed=3)
+    emulator.load_rom(COUNTER)
+    emulator.run_frames(5)
+    state = emulator.save_state()
+    assert isinstance(state, bytes)
+    assert len(state) == chip8.STATE_SIZE
+    registers = emulator.registers.copy()
+
+    emulator.run_frames(5)
+    assert not np.array_equal(emulator.registers, registers)
+    emulator.load_state(state)
+    assert np.array_equal(emulator.registers, registers)
+
+    with pytest.raises(ValueError):
+        emulator.load_state(state[:-1])
+
+
+def test_faults():
+    emulator = chip8.Emulator()
+    emulator.load_rom(bytes([0x00, 0xEE]))
+    assert emulator.fault is None
+    emulator.tick()
+    assert "empty stack" in emulator.fault
+
+
+def test_frames_release_the_gil():
+    emulators = [chip8.Emulator(seed=seed) for seed in range(4)]
+    for emulator in emulators:
+        emulator.load_rom(COUNTER)
+    threads = [
+        threading.Thread(target=emulator.run_frames, args=(200,)) for emulator in emulators
+    ]
+    for thread in threads:
+        thread.start()
+    for thread in threads:
+        thread.join()
+    for emulator in emulators:
+        assert emulator.registers[1] == 1000 % 256
+
+
+def test_env():
+    config = "\n".join(["action = none", "action = 5", "reward = V1", "max_frames = 3"])
+    env = chip8.Env(COUNTER, config, seed=9)
+    assert env.action_count == 2
+    observation = env.reset()
+    assert observation.shape == (32, 64)
+
+    observation, reward, done = env.step(1)
+    assert reward == 5.0
+    assert not done
+    env.step(0)
+    _, _, done = env.step(0)
+    assert done
+    assert env.emulator().registers[1] == 15
+
+    with pytest.raises(ValueError):
+        env.step(2)
+    with pytest.raises(ValueError):
+        chip8.Env(COUNTER, "speed = 3")